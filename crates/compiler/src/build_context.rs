@@ -3,6 +3,8 @@ use std::{
     process::Command,
 };
 
+use indexmap::IndexMap;
+
 use crate::codegen::RuneVersion;
 
 /// Inputs used during the compilation process.
@@ -21,6 +23,21 @@ pub struct BuildContext {
     pub verbosity: Verbosity,
     /// The version of Rune being used.
     pub rune_version: Option<RuneVersion>,
+    /// Report the value of every intermediate tensor to the runtime (see
+    /// `rune debug`).
+    #[serde(default)]
+    pub debug_tensors: bool,
+    /// Values for the Runefile's `variables`. These take precedence over
+    /// the environment and the selected profile (see `rune build --var`).
+    #[serde(default)]
+    pub variables: IndexMap<String, String>,
+    /// The profile to use when resolving the Runefile's `variables`.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Always run `cargo build`, even if an identical project was compiled
+    /// before (see `rune build --no-cache`).
+    #[serde(default)]
+    pub no_cache: bool,
 }
 
 impl BuildContext {
@@ -56,6 +73,10 @@ impl BuildContext {
             rune_version: Some(RuneVersion {
                 version: env!("CARGO_PKG_VERSION").to_string(),
             }),
+            debug_tensors: false,
+            variables: IndexMap::new(),
+            profile: None,
+            no_cache: false,
         })
     }
 
@@ -71,6 +92,10 @@ impl BuildContext {
             rune_version: Some(RuneVersion {
                 version: env!("CARGO_PKG_VERSION").to_string(),
             }),
+            debug_tensors: false,
+            variables: IndexMap::new(),
+            profile: None,
+            no_cache: false,
         }
     }
 }
@@ -112,6 +137,7 @@ impl Verbosity {
 #[derive(Debug, Clone, PartialEq)]
 pub struct FeatureFlags {
     pub(crate) rune_repo_dir: Option<PathBuf>,
+    pub(crate) probe_proc_blocks: bool,
 }
 
 impl FeatureFlags {
@@ -124,12 +150,14 @@ impl FeatureFlags {
 
         FeatureFlags {
             rune_repo_dir: hotg_repo_dir,
+            probe_proc_blocks: false,
         }
     }
 
     pub const fn production() -> Self {
         FeatureFlags {
             rune_repo_dir: None,
+            probe_proc_blocks: false,
         }
     }
 
@@ -142,6 +170,16 @@ impl FeatureFlags {
         self.rune_repo_dir = hotg_repo_dir.into();
         self
     }
+
+    /// Compile each proc block on its own so the transforms it supports can
+    /// be checked against the Runefile (disabled by default).
+    ///
+    /// This requires building the proc block for WebAssembly, although
+    /// descriptors for proc blocks pinned to a version are cached.
+    pub fn set_probe_proc_blocks(&mut self, probe: bool) -> &mut Self {
+        self.probe_proc_blocks = probe;
+        self
+    }
 }
 
 impl Default for FeatureFlags {
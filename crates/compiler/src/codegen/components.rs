@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fmt::{self, Display, Formatter},
     ops::Deref,
@@ -7,7 +8,8 @@ use std::{
 };
 
 use hotg_rune_core::Shape;
-use serde::Serialize;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
 
 use crate::{
     lowering::{Name, Resource, SinkKind, SourceKind},
@@ -115,6 +117,11 @@ pub struct RuneGraph {
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
     pub outputs: HashMap<Name, OutputSummary>,
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub delays: HashMap<Name, DelaySummary>,
+    /// The stages run by each named pipeline.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub pipelines: HashMap<Name, Vec<Name>>,
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
     pub resources: HashMap<Name, Resource>,
     #[serde(skip_serializing_if = "HashMap::is_empty", default)]
     pub tensors: HashMap<TensorId, Shape<'static>>,
@@ -138,6 +145,127 @@ pub struct ModelSummary {
     pub args: HashMap<String, ResourceOrString>,
     pub inputs: Vec<TensorId>,
     pub outputs: Vec<TensorId>,
+    /// The model's size in bytes, if it was loaded from disk at compile time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    /// A [`Fingerprint`] of the model's contents, if it was loaded from disk
+    /// at compile time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+}
+
+/// The SHA-256 digest of a model's contents, used to detect when a model
+/// changes and to find it again inside a compiled Rune.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    digest: [u8; 32],
+    /// A rolling checksum of the same bytes.
+    ///
+    /// The digest can't be updated as we slide a window over a Rune's data
+    /// segments, so this is used to find candidates which are then confirmed
+    /// against the digest.
+    checksum: u64,
+}
+
+impl Fingerprint {
+    const CHECKSUM_BASE: u64 = 257;
+
+    pub fn of(data: &[u8]) -> Self {
+        Fingerprint {
+            digest: Sha256::digest(data).into(),
+            checksum: checksum(data),
+        }
+    }
+
+    /// Find the offset of the first `len`-byte slice of `haystack` which has
+    /// this [`Fingerprint`].
+    pub fn find_in(self, haystack: &[u8], len: usize) -> Option<usize> {
+        if len == 0 || haystack.len() < len {
+            return None;
+        }
+
+        // The weight given to the first byte in the window
+        let leading_weight = (1..len).fold(1_u64, |weight, _| {
+            weight.wrapping_mul(Fingerprint::CHECKSUM_BASE)
+        });
+
+        let mut rolling = checksum(&haystack[..len]);
+
+        for start in 0..=haystack.len() - len {
+            if rolling == self.checksum
+                && Fingerprint::of(&haystack[start..start + len]) == self
+            {
+                return Some(start);
+            }
+
+            if let Some(&next) = haystack.get(start + len) {
+                let first = u64::from(haystack[start]);
+                rolling = rolling
+                    .wrapping_sub(first.wrapping_mul(leading_weight))
+                    .wrapping_mul(Fingerprint::CHECKSUM_BASE)
+                    .wrapping_add(u64::from(next));
+            }
+        }
+
+        None
+    }
+}
+
+/// A polynomial (Rabin-Karp) checksum which can be updated in constant time
+/// as a window slides along a buffer.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0_u64, |hash, &byte| {
+        hash.wrapping_mul(Fingerprint::CHECKSUM_BASE)
+            .wrapping_add(u64::from(byte))
+    })
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for byte in &self.digest {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How a [`Fingerprint`] is stored in the Rune graph.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedFingerprint<'a> {
+    sha256: Cow<'a, str>,
+    checksum: Cow<'a, str>,
+}
+
+impl Serialize for Fingerprint {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        SerializedFingerprint {
+            sha256: Cow::Owned(self.to_string()),
+            checksum: Cow::Owned(format!("{:016x}", self.checksum)),
+        }
+        .serialize(ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fingerprint {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let SerializedFingerprint { sha256, checksum } =
+            SerializedFingerprint::deserialize(de)?;
+
+        let mut digest = [0_u8; 32];
+        if sha256.len() != digest.len() * 2 {
+            return Err(D::Error::custom("Expected a 64 character SHA-256"));
+        }
+        for (i, byte) in digest.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&sha256[2 * i..2 * i + 2], 16)
+                .map_err(D::Error::custom)?;
+        }
+
+        let checksum =
+            u64::from_str_radix(&checksum, 16).map_err(D::Error::custom)?;
+
+        Ok(Fingerprint { digest, checksum })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -155,6 +283,14 @@ pub struct OutputSummary {
     pub inputs: Vec<TensorId>,
 }
 
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DelaySummary {
+    /// The value every element starts with.
+    pub initial: f64,
+    pub inputs: Vec<TensorId>,
+    pub outputs: Vec<TensorId>,
+}
+
 #[derive(
     Debug,
     Clone,
@@ -185,3 +321,50 @@ impl RuneGraph {
         CustomSection::from_json(GRAPH_CUSTOM_SECTION, self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_a_fingerprinted_slice() {
+        let haystack = b"some padding, the model, and more padding";
+        let model = b"the model";
+        let fingerprint = Fingerprint::of(model);
+
+        let got = fingerprint.find_in(haystack, model.len()).unwrap();
+
+        assert_eq!(&haystack[got..got + model.len()], model);
+    }
+
+    #[test]
+    fn checksum_collisions_are_rejected() {
+        let haystack = b"some padding, the model, and more padding";
+        let model = b"the model";
+        // Pretend some other slice of the haystack had the same checksum
+        let mut fingerprint = Fingerprint::of(model);
+        fingerprint.checksum = checksum(&haystack[..model.len()]);
+
+        assert_eq!(fingerprint.find_in(haystack, model.len()), None);
+    }
+
+    #[test]
+    fn fingerprints_are_displayed_as_a_sha256_digest() {
+        let fingerprint = Fingerprint::of(b"");
+
+        assert_eq!(
+            fingerprint.to_string(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn fingerprints_round_trip_through_json() {
+        let fingerprint = Fingerprint::of(b"Hello, World!");
+
+        let json = serde_json::to_string(&fingerprint).unwrap();
+        let got: Fingerprint = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(got, fingerprint);
+    }
+}
@@ -87,6 +87,50 @@ where
     }
 }
 
+/// Generate the manifest for a tiny crate that does nothing but re-export a
+/// single proc block, letting us compile it on its own and read its
+/// [`hotg_rune_proc_blocks::ProcBlockDescriptor`].
+pub(crate) fn probe_manifest(
+    proc_block: &ProcBlock,
+    current_dir: &Path,
+    rune_repo_dir: Option<&Path>,
+) -> Manifest {
+    let product = Product {
+        path: Some("lib.rs".to_string()),
+        edition: Some(Edition::E2018),
+        crate_type: Some(vec!["cdylib".to_string()]),
+        ..Default::default()
+    };
+
+    let mut dependencies = DepsSet::new();
+    dependencies.insert(
+        proc_block.name().to_string(),
+        Dependency::Detailed(proc_block_dependency(
+            &proc_block.path,
+            current_dir,
+        )),
+    );
+
+    let mut manifest = Manifest {
+        package: Some(package(&format!("probe-{}", proc_block.name()))),
+        lib: Some(product),
+        dependencies,
+        workspace: Some(Workspace {
+            members: vec![String::from(".")],
+            default_members: vec![String::from(".")],
+            exclude: Vec::new(),
+            metadata: None,
+        }),
+        ..empty_manifest()
+    };
+
+    if let Some(hotg_repo_dir) = rune_repo_dir {
+        patch_hotg_dependencies(hotg_repo_dir, &mut manifest);
+    }
+
+    manifest
+}
+
 fn package(name: &str) -> Package {
     Package {
         name: name.into(),
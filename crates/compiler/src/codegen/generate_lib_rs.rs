@@ -9,11 +9,12 @@ use quote::{quote, ToTokens};
 use crate::{
     codegen::{CustomSection, File},
     lowering::{
-        Inputs, Mimetype, Model, ModelFile, Name, Outputs, PipelineNode,
-        ProcBlock, Resource, ResourceData, ResourceOrString, Sink, SinkKind,
-        Source, Tensor,
+        Condition, Delay, EntryPoint, Inputs, Mimetype, Model, ModelFile, Name,
+        Outputs, PipelineNode, ProcBlock, Resource, ResourceData,
+        ResourceOrString, Sink, SinkKind, Source, Tensor,
     },
-    parse::ResourceType,
+    parse::{Comparison, ResourceType},
+    BuildContext,
 };
 
 /// Generate the entire `lib.rs` file.
@@ -26,6 +27,7 @@ use crate::{
 pub(crate) fn run(
     cmd: &mut CommandBuffer,
     world: &SubWorld,
+    #[resource] ctx: &BuildContext,
     sections: &mut Query<&CustomSection>,
     models: &mut Query<(&Name, &Model, &Mimetype, &Inputs, &Outputs)>,
     names: &mut Query<&Name>,
@@ -42,6 +44,9 @@ pub(crate) fn run(
         Option<&Outputs>,
         &PipelineNode,
     )>,
+    conditions: &mut Query<(Entity, &Condition)>,
+    delays: &mut Query<(Entity, &Name, &Delay, &Outputs)>,
+    entry_points: &mut Query<&EntryPoint>,
 ) {
     let models: Vec<_> = models.iter(world).collect();
     let sections: Vec<_> = sections.iter(world).collect();
@@ -51,6 +56,12 @@ pub(crate) fn run(
     let outputs: Vec<_> = outputs.iter(world).collect();
     let pipeline_nodes: Vec<_> = pipeline_nodes.iter(world).collect();
     let tensors: Vec<_> = tensors.iter(world).collect();
+    let conditions: HashMap<_, _> = conditions
+        .iter(world)
+        .map(|(&ent, condition)| (ent, condition))
+        .collect();
+    let delays: Vec<_> = delays.iter(world).collect();
+    let entry_points: Vec<_> = entry_points.iter(world).collect();
 
     let lib_rs = generate_lib_rs(
         &sections,
@@ -61,6 +72,10 @@ pub(crate) fn run(
         &outputs,
         &pipeline_nodes,
         &tensors,
+        &conditions,
+        &delays,
+        &entry_points,
+        ctx.debug_tensors,
         |ent| names.get(world, ent).ok(),
         |ent| tensor_by_ent.get(world, ent).ok(),
     );
@@ -84,6 +99,10 @@ fn generate_lib_rs<'world>(
     outputs: &[(&Name, &Sink)],
     pipeline_nodes: &[Node<'_>],
     tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+    conditions: &HashMap<Entity, &Condition>,
+    delays: &[DelayNode<'_>],
+    entry_points: &[&EntryPoint],
+    debug_tensors: bool,
     mut get_name: impl FnMut(Entity) -> Option<&'world Name>,
     mut get_tensor: impl FnMut(Entity) -> Option<&'world Tensor>,
 ) -> TokenStream {
@@ -101,10 +120,14 @@ fn generate_lib_rs<'world>(
         outputs,
         pipeline_nodes,
         tensors,
+        conditions,
+        delays,
+        entry_points,
+        debug_tensors,
         &mut get_name,
         &mut get_tensor,
     );
-    let call = generate_call_function();
+    let call = generate_call_function(entry_points);
 
     quote! {
         #prelude
@@ -119,6 +142,11 @@ fn generate_lib_rs<'world>(
 /// Generate a `manifest()` function that initializes the various nodes in
 /// our pipeline then turns it into a closure that gets stored in the
 /// `PIPELINE` static variable.
+///
+/// The closure owns each delay's previous value, so it persists between
+/// calls. It is given the index of the [`EntryPoint`] to run, where `0` runs
+/// the entire pipeline and `i + 1` runs the `i`'th entry point, so every
+/// entry point shares the same nodes.
 fn generate_manifest_function<'world, F, T>(
     models: &[(&Name, &Model, &Mimetype, &Inputs, &Outputs)],
     capabilities: &[(&Name, &Source, &Outputs)],
@@ -126,6 +154,10 @@ fn generate_manifest_function<'world, F, T>(
     outputs: &[(&Name, &Sink)],
     pipeline_nodes: &[Node<'_>],
     tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+    conditions: &HashMap<Entity, &Condition>,
+    delays: &[DelayNode<'_>],
+    entry_points: &[&EntryPoint],
+    debug_tensors: bool,
     get_name: &mut F,
     get_tensor: &mut T,
 ) -> TokenStream
@@ -143,7 +175,36 @@ where
         })
         .collect();
     let outputs = initialize_outputs(outputs);
-    let pipeline = execute_pipeline(pipeline_nodes, tensors);
+    let delay_states = initialize_delays(delays, get_tensor);
+    let pipeline = execute_pipeline(
+        pipeline_nodes,
+        tensors,
+        conditions,
+        delays,
+        debug_tensors,
+    );
+    let entry_points = entry_points.iter().enumerate().map(|(i, entry)| {
+        let index = i + 1;
+        let pipeline_nodes: Vec<_> = pipeline_nodes
+            .iter()
+            .copied()
+            .filter(|(ent, ..)| entry.nodes.contains(ent))
+            .collect();
+        let delays: Vec<_> = delays
+            .iter()
+            .copied()
+            .filter(|(ent, ..)| entry.nodes.contains(ent))
+            .collect();
+        let pipeline = execute_pipeline(
+            &pipeline_nodes,
+            tensors,
+            conditions,
+            &delays,
+            debug_tensors,
+        );
+
+        quote! { #index => { #pipeline } }
+    });
 
     quote! {
         #[no_mangle]
@@ -153,10 +214,16 @@ where
             #proc_blocks
             #models
             #outputs
+            #delay_states
 
-            let pipeline = move || {
+            let pipeline = move |entry_point: usize| {
                 let _guard = hotg_runicos_base_wasm::PipelineGuard::default();
-                #pipeline
+
+                match entry_point {
+                    0 => { #pipeline }
+                    #( #entry_points )*
+                    _ => unreachable!(),
+                }
             };
 
             unsafe {
@@ -177,25 +244,105 @@ fn execute_pipeline(
         &PipelineNode,
     )],
     tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+    conditions: &HashMap<Entity, &Condition>,
+    delays: &[DelayNode<'_>],
+    debug_tensors: bool,
 ) -> TokenStream {
     let ExecutionOrder {
         order,
         tensor_names,
         pipeline_nodes,
+        tensor_inputs,
         ..
-    } = ExecutionOrder::calculate(pipeline_nodes, tensors);
+    } = ExecutionOrder::calculate(pipeline_nodes, tensors, conditions, delays);
+
+    // Tensors produced by a node which might be skipped
+    let mut maybe_absent: HashSet<Entity> = HashSet::new();
+    // Delays which haven't stored their input for the next call yet
+    let mut pending_updates: Vec<Entity> =
+        delays.iter().map(|(&ent, ..)| ent).collect();
+    let mut executed: HashSet<Entity> = HashSet::new();
+    let mut pipeline = TokenStream::new();
+
+    for entity in &order {
+        let (name, inputs, outputs) = pipeline_nodes[entity];
+        let condition = conditions.get(entity).copied();
+
+        let node = if delays.iter().any(|(&ent, ..)| ent == *entity) {
+            let outputs = outputs.expect("Delays should always have an output");
+            let mut execute =
+                execute_delay(name, outputs, &tensor_names, tensors);
+            if debug_tensors {
+                execute.extend(report_intermediate(
+                    name,
+                    outputs,
+                    &tensor_names,
+                ));
+            }
+            execute
+        } else {
+            let absent_inputs: Vec<Entity> = inputs
+                .iter()
+                .flat_map(|i| i.tensors.iter())
+                .chain(condition.map(|c| &c.tensor))
+                .copied()
+                .filter(|t| maybe_absent.contains(t))
+                .fold(Vec::new(), |mut tensors, t| {
+                    if !tensors.contains(&t) {
+                        tensors.push(t);
+                    }
+                    tensors
+                });
+
+            if condition.is_none() && absent_inputs.is_empty() {
+                execute_pipeline_node(
+                    entity,
+                    &pipeline_nodes,
+                    &tensor_names,
+                    tensors,
+                    debug_tensors,
+                )
+            } else {
+                maybe_absent
+                    .extend(outputs.iter().flat_map(|o| o.tensors.iter()));
+
+                execute_pipeline_node_conditionally(
+                    entity,
+                    &pipeline_nodes,
+                    condition,
+                    &absent_inputs,
+                    &tensor_names,
+                    tensors,
+                    debug_tensors,
+                )
+            }
+        };
+        pipeline.extend(node);
+        executed.insert(*entity);
+
+        // Once a delay has read its previous value and its input has been
+        // computed, it can remember the input for the next call
+        pending_updates.retain(|delay| {
+            let (name, inputs, _) = pipeline_nodes[delay];
+            let input = inputs
+                .and_then(|i| i.tensors.first())
+                .expect("Delays should always have an input");
+            let ready = executed.contains(delay)
+                && tensor_inputs[input].iter().all(|n| executed.contains(n));
+
+            if ready {
+                pipeline.extend(update_delay(
+                    name,
+                    &tensor_names[input],
+                    maybe_absent.contains(input),
+                ));
+            }
 
-    order
-        .iter()
-        .map(|entity| {
-            execute_pipeline_node(
-                entity,
-                &pipeline_nodes,
-                &tensor_names,
-                tensors,
-            )
-        })
-        .collect()
+            !ready
+        });
+    }
+
+    pipeline
 }
 
 fn execute_pipeline_node(
@@ -206,12 +353,151 @@ fn execute_pipeline_node(
     >,
     tensor_names: &HashMap<Entity, Ident>,
     tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+    debug_tensors: bool,
 ) -> TokenStream {
     let (name, inputs, outputs) = pipeline_nodes
         .get(node)
         .copied()
         .expect("This pipeline node always be present");
 
+    let execute = execute_node(name, inputs, outputs, tensor_names, tensors);
+
+    match outputs {
+        Some(outputs) if debug_tensors => {
+            let report = report_intermediate(name, outputs, tensor_names);
+            quote! {
+                #execute
+                #report
+            }
+        },
+        _ => execute,
+    }
+}
+
+/// Execute a node which may be skipped, either because its [`Condition`]
+/// doesn't hold or because one of its inputs is absent.
+///
+/// The node's outputs are wrapped in an `Option`, with `None` meaning the
+/// node was skipped.
+fn execute_pipeline_node_conditionally(
+    node: &Entity,
+    pipeline_nodes: &HashMap<
+        Entity,
+        (&Name, Option<&Inputs>, Option<&Outputs>),
+    >,
+    condition: Option<&Condition>,
+    absent_inputs: &[Entity],
+    tensor_names: &HashMap<Entity, Ident>,
+    tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+    debug_tensors: bool,
+) -> TokenStream {
+    let (name, inputs, outputs) = pipeline_nodes
+        .get(node)
+        .copied()
+        .expect("This pipeline node always be present");
+
+    let mut execute =
+        execute_node(name, inputs, outputs, tensor_names, tensors);
+    let skip_msg = format!("Skipping \"{}\"", name);
+    let mut skip = quote!(log::debug!(#skip_msg););
+
+    if let Some(outputs) = outputs {
+        if debug_tensors {
+            let report = report_intermediate(name, outputs, tensor_names);
+            let name = name.as_str();
+            execute.extend(report);
+            skip.extend(quote! {
+                if hotg_runicos_base_wasm::tensor_output::report_intermediate(#name, ()) {
+                    return;
+                }
+            });
+        }
+
+        let names: Vec<_> =
+            outputs.tensors.iter().map(|t| &tensor_names[t]).collect();
+        let (present, absent) = match names.as_slice() {
+            [tensor] => (quote!(Some(#tensor)), quote!(None)),
+            names => {
+                let absent = names.iter().map(|_| quote!(None));
+                (quote!((#( Some(#names) ),*)), quote!((#(#absent),*)))
+            },
+        };
+        execute.extend(present);
+        skip.extend(absent);
+    }
+
+    let predicate = condition.map(|c| condition_predicate(c, tensor_names));
+    let branches = match (absent_inputs, predicate) {
+        ([], Some(predicate)) => quote! {
+            if #predicate {
+                #execute
+            } else {
+                #skip
+            }
+        },
+        ([], None) => unreachable!(
+            "The \"{}\" pipeline node should always be executed",
+            name
+        ),
+        (absent_inputs, predicate) => {
+            let names: Vec<_> =
+                absent_inputs.iter().map(|t| &tensor_names[t]).collect();
+            let guard = predicate.map(|p| quote!(if #p));
+
+            quote! {
+                match (#( &#names, )*) {
+                    (#( Some(#names), )*) #guard => {
+                        #execute
+                    },
+                    _ => {
+                        #skip
+                    },
+                }
+            }
+        },
+    };
+
+    match outputs {
+        Some(outputs) => {
+            let names = tensor_name_or_tuple(&outputs.tensors, tensor_names);
+            let types = optional_tensor_types(&outputs.tensors, tensors);
+            quote! {
+                let #names: #types = #branches;
+            }
+        },
+        None => branches,
+    }
+}
+
+/// Check whether at least one element in the [`Condition`]'s tensor satisfies
+/// the comparison.
+fn condition_predicate(
+    condition: &Condition,
+    tensor_names: &HashMap<Entity, Ident>,
+) -> TokenStream {
+    let tensor = &tensor_names[&condition.tensor];
+    let comparison = match condition.comparison {
+        Comparison::GreaterThan => quote!(>),
+        Comparison::GreaterThanOrEqual => quote!(>=),
+        Comparison::LessThan => quote!(<),
+        Comparison::LessThanOrEqual => quote!(<=),
+        Comparison::Equal => quote!(==),
+        Comparison::NotEqual => quote!(!=),
+    };
+    let value = Literal::f64_suffixed(condition.value);
+
+    quote! {
+        #tensor.elements().iter().any(|&element| (element as f64) #comparison #value)
+    }
+}
+
+fn execute_node(
+    name: &Name,
+    inputs: Option<&Inputs>,
+    outputs: Option<&Outputs>,
+    tensor_names: &HashMap<Entity, Ident>,
+    tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+) -> TokenStream {
     match (inputs, outputs) {
         (Some(inputs), Some(outputs)) => execute_model_or_proc_block(
             name,
@@ -233,6 +519,30 @@ fn execute_pipeline_node(
     }
 }
 
+/// Send a node's outputs to the runtime so they can be inspected, stopping
+/// the pipeline early if the runtime asks us to.
+fn report_intermediate(
+    name: &Name,
+    outputs: &Outputs,
+    tensor_names: &HashMap<Entity, Ident>,
+) -> TokenStream {
+    let name = name.as_str();
+    let names: Vec<_> =
+        outputs.tensors.iter().map(|t| &tensor_names[t]).collect();
+
+    let tensors = match names.as_slice() {
+        [] => unreachable!("Expected 1 or more tensors"),
+        [tensor] => quote!(&#tensor),
+        names => quote!((#( &#names ),*)),
+    };
+
+    quote! {
+        if hotg_runicos_base_wasm::tensor_output::report_intermediate(#name, #tensors) {
+            return;
+        }
+    }
+}
+
 fn execute_output(
     name: &Name,
     inputs: &Inputs,
@@ -304,8 +614,36 @@ fn tensor_types(
     }
 }
 
+fn optional_tensor_types(
+    tensors: &[Entity],
+    all_tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+) -> TokenStream {
+    let mut types = Vec::new();
+
+    for ent in tensors {
+        let (_, Tensor(shape), _, _) = all_tensors
+            .iter()
+            .copied()
+            .find(|(e, _, _, _)| ent == *e)
+            .unwrap();
+
+        let ty = shape_to_tensor_type(shape);
+        types.push(quote!(Option<#ty>));
+    }
+
+    match types.as_slice() {
+        [single] => single.clone(),
+        many => quote!((#(#many),*)),
+    }
+}
+
 fn shape_to_tensor_type(shape: &Shape) -> TokenStream {
-    let element_type = match shape.element_type() {
+    let element_type = rust_element_type(shape.element_type());
+    quote!(Tensor<#element_type>)
+}
+
+fn rust_element_type(element_type: ElementType) -> TokenStream {
+    match element_type {
         ElementType::U8 => quote!(u8),
         ElementType::I8 => quote!(i8),
         ElementType::U16 => quote!(u16),
@@ -317,8 +655,7 @@ fn shape_to_tensor_type(shape: &Shape) -> TokenStream {
         ElementType::I64 => quote!(i64),
         ElementType::F64 => quote!(f64),
         ElementType::String => quote!(alloc::borrow::Cow<'static, str>),
-    };
-    quote!(Tensor<#element_type>)
+    }
 }
 
 fn tensor_name_or_tuple(
@@ -334,6 +671,42 @@ fn tensor_name_or_tuple(
     }
 }
 
+fn execute_delay(
+    name: &Name,
+    outputs: &Outputs,
+    tensor_names: &HashMap<Entity, Ident>,
+    tensors: &[(&Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)],
+) -> TokenStream {
+    let name = Ident::new(name, Span::call_site());
+    let output_types = tensor_types(&outputs.tensors, tensors);
+    let outputs = tensor_name_or_tuple(&outputs.tensors, tensor_names);
+
+    let msg = format!("Reading the previous value of \"{}\"", name);
+
+    quote! {
+        log::debug!(#msg);
+        let #outputs: #output_types = #name.clone();
+    }
+}
+
+/// Store a delay's input so it can be read on the next call.
+fn update_delay(name: &Name, input: &Ident, maybe_absent: bool) -> TokenStream {
+    let name = Ident::new(name, Span::call_site());
+
+    if maybe_absent {
+        // Keep the previous value when the input was skipped
+        quote! {
+            if let Some(#input) = &#input {
+                #name = #input.clone();
+            }
+        }
+    } else {
+        quote! {
+            #name = #input.clone();
+        }
+    }
+}
+
 fn execute_capability(
     name: &Name,
     outputs: &Outputs,
@@ -367,6 +740,8 @@ struct ExecutionOrder<'world> {
         ),
     >,
     tensor_inputs: HashMap<Entity, &'world [Entity]>,
+    conditions: HashMap<Entity, Entity>,
+    delays: HashSet<Entity>,
 }
 
 type Node<'world> = (
@@ -377,14 +752,21 @@ type Node<'world> = (
     &'world PipelineNode,
 );
 
+type DelayNode<'world> =
+    (&'world Entity, &'world Name, &'world Delay, &'world Outputs);
+
 impl<'world> ExecutionOrder<'world> {
     /// Given a set of pipeline nodes, determine the order they should be
     /// executed in and variable names for the various tensors involved.
     ///
     /// # Notes
     ///
-    /// This assumes the pipeline nodes define a directed acyclic graph, and may
-    /// not return if it contains cycles.
+    /// This assumes the pipeline nodes define a directed acyclic graph once
+    /// the edges going into delays are removed, and may not return if it
+    /// contains any other cycles.
+    ///
+    /// Delays are always executed first so they can read the value from the
+    /// previous call before anything overwrites it.
     ///
     /// This does [a topological sort][topo] using a modified depth-first
     /// search.
@@ -398,6 +780,8 @@ impl<'world> ExecutionOrder<'world> {
             Option<&'world Inputs>,
             Option<&'world Outputs>,
         )],
+        conditions: &HashMap<Entity, &Condition>,
+        delays: &[DelayNode<'_>],
     ) -> Self {
         let mut order = ExecutionOrder {
             order: Vec::new(),
@@ -422,10 +806,20 @@ impl<'world> ExecutionOrder<'world> {
                     )
                 })
                 .collect(),
+            conditions: conditions
+                .iter()
+                .map(|(&node, condition)| (node, condition.tensor))
+                .collect(),
+            delays: delays.iter().map(|(&ent, ..)| ent).collect(),
         };
 
-        for (entity, ..) in pipeline_nodes.iter().copied() {
-            order.visit(*entity);
+        let (delays, others): (Vec<_>, Vec<_>) = pipeline_nodes
+            .iter()
+            .map(|(&entity, ..)| entity)
+            .partition(|entity| order.delays.contains(entity));
+
+        for entity in delays.into_iter().chain(others) {
+            order.visit(entity);
         }
 
         order
@@ -440,16 +834,31 @@ impl<'world> ExecutionOrder<'world> {
 
         let (name, inputs, outputs) = self.pipeline_nodes[&entity];
 
-        // We need to make sure all the inputs have been initialized first
-        if let Some(inputs) = inputs {
-            for input in &inputs.tensors {
-                let previous_nodes =
-                    self.tensor_inputs.get(input).copied().expect(
-                        "All tensors must have a node that created them",
-                    );
-                for &previous_node in previous_nodes {
-                    self.visit(previous_node);
-                }
+        // A delay outputs the value its input had on the previous call, so it
+        // doesn't need to wait for the input to be computed
+        let inputs = if self.delays.contains(&entity) {
+            None
+        } else {
+            inputs
+        };
+
+        // We need to make sure all the inputs (and the tensor our condition
+        // checks) have been initialized first
+        let condition = self.conditions.get(&entity).copied();
+        let dependencies: Vec<Entity> = inputs
+            .iter()
+            .flat_map(|i| i.tensors.iter().copied())
+            .chain(condition)
+            .collect();
+
+        for input in &dependencies {
+            let previous_nodes = self
+                .tensor_inputs
+                .get(input)
+                .copied()
+                .expect("All tensors must have a node that created them");
+            for &previous_node in previous_nodes {
+                self.visit(previous_node);
             }
         }
 
@@ -470,6 +879,50 @@ impl<'world> ExecutionOrder<'world> {
     }
 }
 
+fn initialize_delays<'world, T>(
+    delays: &[DelayNode<'_>],
+    get_tensor: &mut T,
+) -> TokenStream
+where
+    T: FnMut(Entity) -> Option<&'world Tensor>,
+{
+    delays
+        .iter()
+        .copied()
+        .map(|(_, name, delay, outputs)| {
+            initialize_delay(name, delay, outputs, get_tensor)
+        })
+        .collect()
+}
+
+fn initialize_delay<'world, T>(
+    name: &Name,
+    delay: &Delay,
+    outputs: &Outputs,
+    get_tensor: &mut T,
+) -> TokenStream
+where
+    T: FnMut(Entity) -> Option<&'world Tensor>,
+{
+    let Tensor(shape) = match outputs.tensors.as_slice() {
+        [tensor] => get_tensor(*tensor).unwrap(),
+        _ => unreachable!("Delays should only have one output"),
+    };
+    let ty = shape_to_tensor_type(shape);
+    let element_type = rust_element_type(shape.element_type());
+    let dimensions = shape.dimensions();
+    let initial = Literal::f64_suffixed(delay.initial);
+
+    let name = Ident::new(name, Span::call_site());
+
+    quote! {
+        let mut #name: #ty = Tensor::filled_with(
+            alloc::vec![#(#dimensions),*],
+            || #initial as #element_type
+        );
+    }
+}
+
 fn initialize_outputs(outputs: &[(&Name, &Sink)]) -> TokenStream {
     outputs
         .iter()
@@ -774,13 +1227,31 @@ fn generate_prelude() -> TokenStream {
         use hotg_rune_core::PixelFormat;
         use hotg_rune_proc_blocks::*;
 
-        static mut PIPELINE: Option<Box<dyn FnMut()>> = None;
+        static mut PIPELINE: Option<Box<dyn FnMut(usize)>> = None;
     }
 }
 
 /// The `call()` function - a simple function which invokes the `PIPELINE`
-/// constructed by [`generate_manifest_function()`].
-fn generate_call_function() -> TokenStream {
+/// constructed by [`generate_manifest_function()`], plus a function for each
+/// [`EntryPoint`] which only runs its part of the pipeline.
+fn generate_call_function(entry_points: &[&EntryPoint]) -> TokenStream {
+    let entry_points = entry_points.iter().enumerate().map(|(i, entry)| {
+        let index = i + 1;
+        let export_name =
+            format!("{}{}", hotg_rune_core::PIPELINE_EXPORT_PREFIX, entry.name);
+        let ident = Ident::new(
+            &format!("call_entry_point_{}", index),
+            Span::call_site(),
+        );
+
+        quote! {
+            #[export_name = #export_name]
+            pub extern "C" fn #ident() -> i32 {
+                call_pipeline(#index)
+            }
+        }
+    });
+
     quote! {
         #[no_mangle]
         pub extern "C" fn _call(
@@ -788,10 +1259,16 @@ fn generate_call_function() -> TokenStream {
             _input_type: i32,
             _capability_idx: i32,
         ) -> i32 {
+            call_pipeline(0)
+        }
+
+        #( #entry_points )*
+
+        fn call_pipeline(entry_point: usize) -> i32 {
             unsafe {
                 let pipeline = PIPELINE.as_mut()
                     .expect("The rune hasn't been initialized");
-                pipeline();
+                pipeline(entry_point);
 
                 0
             }
@@ -1022,6 +1499,43 @@ mod tests {
         assert_quote_eq!(got, should_be);
     }
 
+    #[test]
+    fn export_a_function_for_each_entry_point() {
+        let fast = EntryPoint {
+            name: "fast".into(),
+            nodes: Vec::new(),
+        };
+        let should_be = quote! {
+            #[no_mangle]
+            pub extern "C" fn _call(
+                _capability_type: i32,
+                _input_type: i32,
+                _capability_idx: i32,
+            ) -> i32 {
+                call_pipeline(0)
+            }
+
+            #[export_name = "_call_pipeline_fast"]
+            pub extern "C" fn call_entry_point_1() -> i32 {
+                call_pipeline(1usize)
+            }
+
+            fn call_pipeline(entry_point: usize) -> i32 {
+                unsafe {
+                    let pipeline = PIPELINE.as_mut()
+                        .expect("The rune hasn't been initialized");
+                    pipeline(entry_point);
+
+                    0
+                }
+            }
+        };
+
+        let got = generate_call_function(&[&fast]);
+
+        assert_quote_eq!(got, should_be);
+    }
+
     #[test]
     fn simple_linear_execution_order() {
         let mut world = World::default();
@@ -1076,7 +1590,12 @@ mod tests {
             order,
             tensor_names,
             ..
-        } = ExecutionOrder::calculate(&pipeline_nodes, &tensors);
+        } = ExecutionOrder::calculate(
+            &pipeline_nodes,
+            &tensors,
+            &HashMap::new(),
+            &[],
+        );
 
         let order_should_be = vec![first, second, third];
         assert_eq!(order, order_should_be);
@@ -1089,6 +1608,295 @@ mod tests {
         assert_eq!(tensor_names, tensor_names_should_be);
     }
 
+    #[test]
+    fn conditions_are_executed_before_the_node_they_gate() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let first_output = cmd.push((Tensor("f32[1]".parse().unwrap()),));
+        let second_output = cmd.push((Tensor("f32[1]".parse().unwrap()),));
+        // Note: the gated node is visited first
+        let gated = cmd.push((
+            Name::from("gated"),
+            Inputs {
+                tensors: vec![first_output],
+            },
+            PipelineNode,
+        ));
+        let first = cmd.push((
+            Name::from("first"),
+            Outputs {
+                tensors: vec![first_output],
+            },
+            PipelineNode,
+        ));
+        let second = cmd.push((
+            Name::from("second"),
+            Outputs {
+                tensors: vec![second_output],
+            },
+            PipelineNode,
+        ));
+        cmd.add_component(
+            first_output,
+            Inputs {
+                tensors: vec![first],
+            },
+        );
+        cmd.add_component(
+            second_output,
+            Inputs {
+                tensors: vec![second],
+            },
+        );
+        cmd.flush(&mut world, &mut resources);
+        let condition = Condition {
+            tensor: second_output,
+            comparison: Comparison::GreaterThan,
+            value: 0.5,
+        };
+        let conditions: HashMap<_, _> =
+            vec![(gated, &condition)].into_iter().collect();
+
+        let pipeline_nodes: Vec<_> = <(
+            Entity,
+            &Name,
+            Option<&Inputs>,
+            Option<&Outputs>,
+            &PipelineNode,
+        )>::query()
+        .iter(&world)
+        .collect();
+        let tensors: Vec<_> =
+            <(Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)>::query()
+                .iter(&world)
+                .collect();
+
+        let ExecutionOrder { order, .. } = ExecutionOrder::calculate(
+            &pipeline_nodes,
+            &tensors,
+            &conditions,
+            &[],
+        );
+
+        assert_eq!(order, vec![first, second, gated]);
+    }
+
+    #[test]
+    fn feed_a_delay_back_into_the_pipeline() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let rand_output = cmd.push((Tensor("f32[1]".parse().unwrap()),));
+        let previous_output = cmd.push((Tensor("f32[1]".parse().unwrap()),));
+        let smoothed_output = cmd.push((Tensor("f32[1]".parse().unwrap()),));
+        let rand = cmd.push((
+            Name::from("rand"),
+            Outputs {
+                tensors: vec![rand_output],
+            },
+            PipelineNode,
+        ));
+        let smoothed = cmd.push((
+            Name::from("smoothed"),
+            Inputs {
+                tensors: vec![rand_output, previous_output],
+            },
+            Outputs {
+                tensors: vec![smoothed_output],
+            },
+            PipelineNode,
+        ));
+        let previous = cmd.push((
+            Name::from("previous"),
+            Inputs {
+                tensors: vec![smoothed_output],
+            },
+            Outputs {
+                tensors: vec![previous_output],
+            },
+            Delay { initial: 0.5 },
+            PipelineNode,
+        ));
+        cmd.push((
+            Name::from("serial"),
+            Inputs {
+                tensors: vec![smoothed_output],
+            },
+            PipelineNode,
+        ));
+        for (tensor, node) in [
+            (rand_output, rand),
+            (previous_output, previous),
+            (smoothed_output, smoothed),
+        ] {
+            cmd.add_component(
+                tensor,
+                Inputs {
+                    tensors: vec![node],
+                },
+            );
+        }
+        cmd.flush(&mut world, &mut resources);
+
+        let pipeline_nodes: Vec<_> = <(
+            Entity,
+            &Name,
+            Option<&Inputs>,
+            Option<&Outputs>,
+            &PipelineNode,
+        )>::query()
+        .iter(&world)
+        .collect();
+        let tensors: Vec<_> =
+            <(Entity, &Tensor, Option<&Inputs>, Option<&Outputs>)>::query()
+                .iter(&world)
+                .collect();
+        let delays: Vec<_> = <(Entity, &Name, &Delay, &Outputs)>::query()
+            .iter(&world)
+            .collect();
+
+        let got = execute_pipeline(
+            &pipeline_nodes,
+            &tensors,
+            &HashMap::new(),
+            &delays,
+            false,
+        );
+
+        let should_be = quote! {
+            log::debug!("Reading the previous value of \"previous\"");
+            let previous_0: Tensor<f32> = previous.clone();
+            log::debug!("Reading data from \"rand\"");
+            let rand_0: Tensor<f32> = rand.generate();
+            log::debug!("Executing \"smoothed\"");
+            let smoothed_0: Tensor<f32> = smoothed.transform((rand_0.clone(), previous_0.clone()));
+            previous = smoothed_0.clone();
+            log::debug!("Sending results to the \"serial\" output");
+            serial.consume(smoothed_0.clone());
+        };
+        assert_quote_eq!(got, should_be);
+
+        let state = initialize_delays(&delays, &mut |ent| {
+            <&Tensor>::query().get(&world, ent).ok()
+        });
+        let should_be = quote! {
+            let mut previous: Tensor<f32> = Tensor::filled_with(
+                alloc::vec![1usize],
+                || 0.5f64 as f32
+            );
+        };
+        assert_quote_eq!(state, should_be);
+    }
+
+    #[test]
+    fn execute_a_model_conditionally() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let model_output_tensor = Tensor("f32[1]".parse().unwrap());
+        let model_output = cmd.push((model_output_tensor.clone(),));
+        let model_input_tensor = Tensor("u8[1]".parse().unwrap());
+        let model_input = cmd.push((model_input_tensor.clone(),));
+        let model = cmd.push((Name::from("model"),));
+        cmd.flush(&mut world, &mut resources);
+        let name = Name::from("model");
+        let inputs = Inputs {
+            tensors: vec![model_input],
+        };
+        let outputs = Outputs {
+            tensors: vec![model_output],
+        };
+        let pipeline_nodes: HashMap<_, _> =
+            vec![(model, (&name, Some(&inputs), Some(&outputs)))]
+                .into_iter()
+                .collect();
+        let tensor_names: HashMap<_, _> = vec![
+            (model_output, Ident::new("model_output", Span::call_site())),
+            (model_input, Ident::new("model_input", Span::call_site())),
+        ]
+        .into_iter()
+        .collect();
+        let tensors = &[
+            (&model_output, &model_output_tensor, None, None),
+            (&model_input, &model_input_tensor, None, None),
+        ];
+        let condition = Condition {
+            tensor: model_input,
+            comparison: Comparison::GreaterThan,
+            value: 0.5,
+        };
+
+        let got = execute_pipeline_node_conditionally(
+            &model,
+            &pipeline_nodes,
+            Some(&condition),
+            &[],
+            &tensor_names,
+            tensors,
+            false,
+        );
+
+        let should_be = quote! {
+            let model_output: Option<Tensor<f32> > = if model_input.elements().iter().any(|&element| (element as f64) > 0.5f64) {
+                log::debug!("Executing \"model\"");
+                let model_output: Tensor<f32> = model.transform(model_input.clone());
+                Some(model_output)
+            } else {
+                log::debug!("Skipping \"model\"");
+                None
+            };
+        };
+        assert_quote_eq!(got, should_be);
+    }
+
+    #[test]
+    fn skip_outputs_when_their_inputs_are_absent() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let input_tensor = Tensor("u8[1]".parse().unwrap());
+        let input = cmd.push((input_tensor.clone(),));
+        let serial = cmd.push((Name::from("serial"),));
+        cmd.flush(&mut world, &mut resources);
+        let name = Name::from("serial");
+        let inputs = Inputs {
+            tensors: vec![input],
+        };
+        let pipeline_nodes: HashMap<_, _> =
+            vec![(serial, (&name, Some(&inputs), None))]
+                .into_iter()
+                .collect();
+        let tensor_names: HashMap<_, _> =
+            vec![(input, Ident::new("label_0", Span::call_site()))]
+                .into_iter()
+                .collect();
+        let tensors = &[(&input, &input_tensor, None, None)];
+
+        let got = execute_pipeline_node_conditionally(
+            &serial,
+            &pipeline_nodes,
+            None,
+            &[input],
+            &tensor_names,
+            tensors,
+            true,
+        );
+
+        let should_be = quote! {
+            match (&label_0,) {
+                (Some(label_0),) => {
+                    log::debug!("Sending results to the \"serial\" output");
+                    serial.consume(label_0.clone());
+                },
+                _ => {
+                    log::debug!("Skipping \"serial\"");
+                },
+            }
+        };
+        assert_quote_eq!(got, should_be);
+    }
+
     #[test]
     fn execute_a_capability() {
         let mut world = World::default();
@@ -1159,6 +1967,35 @@ mod tests {
         assert_quote_eq!(got, should_be);
     }
 
+    #[test]
+    fn report_intermediate_tensors_in_debug_builds() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let mut cmd = CommandBuffer::new(&world);
+        let first = cmd.push((Tensor("f32[1]".parse().unwrap()),));
+        let second = cmd.push((Tensor("u8[2]".parse().unwrap()),));
+        let name = Name::from("fft");
+        cmd.flush(&mut world, &mut resources);
+        let outputs = Outputs {
+            tensors: vec![first, second],
+        };
+        let tensor_names: HashMap<_, _> = vec![
+            (first, Ident::new("fft_0", Span::call_site())),
+            (second, Ident::new("fft_1", Span::call_site())),
+        ]
+        .into_iter()
+        .collect();
+
+        let got = report_intermediate(&name, &outputs, &tensor_names);
+
+        let should_be = quote! {
+            if hotg_runicos_base_wasm::tensor_output::report_intermediate("fft", (&fft_0, &fft_1)) {
+                return;
+            }
+        };
+        assert_quote_eq!(got, should_be);
+    }
+
     #[test]
     fn consume_multiple_outputs() {
         let mut world = World::default();
@@ -11,11 +11,12 @@ use legion::{
 use super::{CapabilitySummary, RuneSummary};
 use crate::{
     codegen::{
-        ModelSummary, OutputSummary, ProcBlockSummary, RuneGraph, TensorId,
+        DelaySummary, Fingerprint, ModelSummary, OutputSummary,
+        ProcBlockSummary, RuneGraph, TensorId,
     },
     lowering::{
-        self, Inputs, Model, ModelFile, Name, Outputs, ProcBlock, Resource,
-        Sink, Source, Tensor,
+        self, Delay, EntryPoint, Inputs, Model, ModelData, ModelFile, Name,
+        Outputs, ProcBlock, Resource, Sink, Source, Tensor,
     },
     parse::{ResourceName, ResourceOrString},
     BuildContext,
@@ -29,10 +30,13 @@ pub(crate) fn run(
     #[resource] ctx: &BuildContext,
     capabilities: &mut Query<(&Name, &Source, &Outputs)>,
     tensors: &mut Query<(Entity, &Tensor)>,
-    models: &mut Query<(&Name, &Model, &Inputs, &Outputs)>,
+    models: &mut Query<(&Name, &Model, &Inputs, &Outputs, Option<&ModelData>)>,
     proc_blocks: &mut Query<(&Name, &ProcBlock, &Inputs, &Outputs)>,
     outputs: &mut Query<(&Name, &Sink, &Inputs)>,
+    delays: &mut Query<(&Name, &Delay, &Inputs, &Outputs)>,
     resources: &mut Query<(&Name, &Resource)>,
+    entry_points: &mut Query<&EntryPoint>,
+    names: &mut Query<&Name>,
 ) {
     let canon = Canon::default();
     let mut resource_name = |ent: Entity| {
@@ -52,8 +56,8 @@ pub(crate) fn run(
             .collect(),
         models: models
             .iter(world)
-            .map(|(n, m, i, o)| {
-                model_summary(n, m, i, o, &mut resource_name, &canon)
+            .map(|(n, m, i, o, d)| {
+                model_summary(n, m, i, o, d, &mut resource_name, &canon)
             })
             .collect(),
         proc_blocks: proc_blocks
@@ -68,6 +72,22 @@ pub(crate) fn run(
                 output_summary(n, s, i, &canon, &mut resource_name)
             })
             .collect(),
+        delays: delays
+            .iter(world)
+            .map(|(n, d, i, o)| delay_summary(n, d, i, o, &canon))
+            .collect(),
+        pipelines: entry_points
+            .iter(world)
+            .map(|entry| {
+                let stages = entry
+                    .nodes
+                    .iter()
+                    .filter_map(|&ent| names.get(world, ent).ok())
+                    .cloned()
+                    .collect();
+                (entry.name.clone(), stages)
+            })
+            .collect(),
         resources: resources
             .iter(world)
             .map(|(name, res)| (name.clone(), res.clone()))
@@ -121,6 +141,7 @@ fn model_summary(
     model: &Model,
     inputs: &Inputs,
     outputs: &Outputs,
+    data: Option<&ModelData>,
     mut resources: impl FnMut(Entity) -> ResourceName,
     get_tensor: &Canon,
 ) -> (Name, ModelSummary) {
@@ -138,6 +159,8 @@ fn model_summary(
         args: convert_args(&model.args, resources),
         inputs: tensor_shapes(&inputs.tensors, get_tensor),
         outputs: tensor_shapes(&outputs.tensors, get_tensor),
+        size: data.map(|d| d.len()),
+        fingerprint: data.map(|d| Fingerprint::of(d)),
     };
 
     (name.clone(), summary)
@@ -191,6 +214,22 @@ fn output_summary(
     (name.clone(), summary)
 }
 
+fn delay_summary(
+    name: &Name,
+    delay: &Delay,
+    inputs: &Inputs,
+    outputs: &Outputs,
+    get_tensor: &Canon,
+) -> (Name, DelaySummary) {
+    let summary = DelaySummary {
+        initial: delay.initial,
+        inputs: tensor_shapes(&inputs.tensors, get_tensor),
+        outputs: tensor_shapes(&outputs.tensors, get_tensor),
+    };
+
+    (name.clone(), summary)
+}
+
 fn convert_args(
     args: &IndexMap<String, lowering::ResourceOrString>,
     mut resources: impl FnMut(Entity) -> ResourceName,
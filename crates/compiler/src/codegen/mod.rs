@@ -17,6 +17,7 @@ mod generate_version_section;
 pub use components::*;
 use legion::Registry;
 
+pub(crate) use self::generate_cargo_toml::probe_manifest;
 use crate::{phases::Phase, serialize::RegistryExt};
 
 pub fn phase() -> Phase {
@@ -0,0 +1,309 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    codegen::{CustomSection, File},
+    compile::CompiledBinary,
+    lowering::ProcBlock,
+    BuildContext, FeatureFlags,
+};
+
+/// How many compiled Runes to keep around before the oldest ones are
+/// deleted.
+const MAX_ENTRIES: usize = 32;
+
+/// A directory of previously compiled Runes, keyed by a SHA-256 digest of
+/// everything that went into the generated project.
+///
+/// Only the [`MAX_ENTRIES`] most recently saved Runes are kept.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BuildCache {
+    path: PathBuf,
+}
+
+impl BuildCache {
+    /// Get the cache entry for the generated project, if it is safe to use
+    /// one.
+    pub(crate) fn for_project(
+        ctx: &BuildContext,
+        features: &FeatureFlags,
+        files: &[&File],
+        sections: &[&CustomSection],
+        proc_blocks: &[&ProcBlock],
+    ) -> Option<BuildCache> {
+        if ctx.no_cache {
+            log::debug!("Build caching was disabled");
+            return None;
+        }
+
+        // Note: the generated project only contains the *path* to local
+        // crates, so we have no way of knowing when their code changes.
+        if features.rune_repo_dir.is_some()
+            || proc_blocks.iter().any(|p| p.path.base.starts_with('.'))
+        {
+            log::debug!(
+                "Not caching the build because it depends on local crates"
+            );
+            return None;
+        }
+
+        let key = cache_key(ctx, files, sections);
+        let path = ctx
+            .working_directory
+            .join("target")
+            .join("rune-cache")
+            .join(key)
+            .with_extension("rune");
+
+        Some(BuildCache { path })
+    }
+
+    /// Load a previously compiled Rune, if there is one.
+    pub(crate) fn lookup(&self) -> Option<CompiledBinary> {
+        match std::fs::read(&self.path) {
+            Ok(binary) => Some(CompiledBinary::from(binary)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!(
+                    "Unable to read the cached build at \"{}\": {}",
+                    self.path.display(),
+                    e
+                );
+                None
+            },
+        }
+    }
+
+    /// Save a freshly compiled Rune so it can be reused next time.
+    pub(crate) fn save(&self, binary: &CompiledBinary) {
+        let result = self
+            .path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&self.path, &binary.0));
+
+        match result {
+            Ok(_) => {
+                log::debug!("Cached the build at \"{}\"", self.path.display())
+            },
+            Err(e) => log::warn!(
+                "Unable to save the build to \"{}\": {}",
+                self.path.display(),
+                e
+            ),
+        }
+
+        if let Some(dir) = self.path.parent() {
+            prune(dir);
+        }
+    }
+}
+
+/// Delete the oldest cached Runes so the cache doesn't grow forever.
+fn prune(dir: &Path) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!(
+                "Unable to read the build cache at \"{}\": {}",
+                dir.display(),
+                e
+            );
+            return;
+        },
+    };
+
+    let entries: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "rune"))
+        .filter_map(|path| {
+            let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+            Some((path, modified))
+        })
+        .collect();
+
+    for path in stale_entries(entries, MAX_ENTRIES) {
+        log::debug!("Removing \"{}\" from the build cache", path.display());
+
+        if let Err(e) = std::fs::remove_file(&path) {
+            log::warn!(
+                "Unable to remove \"{}\" from the build cache: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Figure out which entries should be removed so only the `keep` most
+/// recently modified ones are left.
+fn stale_entries(
+    mut entries: Vec<(PathBuf, SystemTime)>,
+    keep: usize,
+) -> Vec<PathBuf> {
+    entries.sort_by(|left, right| right.1.cmp(&left.1));
+
+    entries
+        .into_iter()
+        .skip(keep)
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Calculate a hex-encoded digest of everything that affects the compiled
+/// Rune.
+///
+/// The `rust-toolchain.toml` and `Cargo.toml` are both generated [`File`]s,
+/// so the toolchain and dependencies are covered too.
+fn cache_key(
+    ctx: &BuildContext,
+    files: &[&File],
+    sections: &[&CustomSection],
+) -> String {
+    let mut files = files.to_vec();
+    files.sort_by(|left, right| left.path.cmp(&right.path));
+    let mut sections = sections.to_vec();
+    sections.sort_by(|left, right| {
+        left.section_name
+            .cmp(&right.section_name)
+            .then_with(|| left.value.cmp(&right.value))
+    });
+
+    let mut hasher = Sha256::new();
+    let mut append = |data: &[u8]| {
+        // Note: length-prefix everything so adjacent fields can't be
+        // confused with each other
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(data);
+    };
+
+    append(ctx.name.as_bytes());
+    append(&[ctx.optimized as u8, ctx.debug_tensors as u8]);
+    let version = ctx.rune_version.as_ref().map(|v| v.version.as_str());
+    append(version.unwrap_or_default().as_bytes());
+
+    for file in files {
+        append(file.path.to_string_lossy().as_bytes());
+        append(&file.data);
+    }
+
+    for section in sections {
+        append(section.section_name.as_bytes());
+        append(&section.value);
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut key, byte| {
+            // Note: writing to a String can't fail
+            let _ = write!(key, "{:02x}", byte);
+            key
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Document;
+
+    fn ctx() -> BuildContext {
+        let doc =
+            Document::parse("version: 1\nimage: runicos/base\npipeline: {}")
+                .unwrap();
+        BuildContext::from_doc(doc)
+    }
+
+    #[test]
+    fn the_key_doesnt_depend_on_the_order_files_were_generated() {
+        let lib_rs = File::new("lib.rs", b"fn main() {}".to_vec());
+        let cargo_toml = File::new("Cargo.toml", b"[package]".to_vec());
+        let section = CustomSection::new(".rune_version", b"1.0".to_vec());
+
+        let first = cache_key(&ctx(), &[&lib_rs, &cargo_toml], &[&section]);
+        let second = cache_key(&ctx(), &[&cargo_toml, &lib_rs], &[&section]);
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn changing_any_input_changes_the_key() {
+        let lib_rs = File::new("lib.rs", b"fn main() {}".to_vec());
+        let section = CustomSection::new(".rune_version", b"1.0".to_vec());
+        let original = cache_key(&ctx(), &[&lib_rs], &[&section]);
+
+        let modified = File::new("lib.rs", b"fn main() { }".to_vec());
+        assert_ne!(cache_key(&ctx(), &[&modified], &[&section]), original);
+
+        let renamed = File::new("main.rs", b"fn main() {}".to_vec());
+        assert_ne!(cache_key(&ctx(), &[&renamed], &[&section]), original);
+
+        let other_section =
+            CustomSection::new(".rune_version", b"2.0".to_vec());
+        assert_ne!(cache_key(&ctx(), &[&lib_rs], &[&other_section]), original);
+
+        let optimized = BuildContext {
+            optimized: true,
+            ..ctx()
+        };
+        assert_ne!(cache_key(&optimized, &[&lib_rs], &[&section]), original);
+    }
+
+    #[test]
+    fn the_key_is_a_hex_encoded_sha256_digest() {
+        let lib_rs = File::new("lib.rs", b"fn main() {}".to_vec());
+
+        let key = cache_key(&ctx(), &[&lib_rs], &[]);
+
+        assert_eq!(key.len(), 64);
+        assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn only_the_newest_entries_are_kept() {
+        let now = SystemTime::now();
+        let entry = |name: &str, age: u64| {
+            (
+                PathBuf::from(name),
+                now - std::time::Duration::from_secs(age),
+            )
+        };
+        let entries = vec![
+            entry("middle.rune", 10),
+            entry("oldest.rune", 100),
+            entry("newest.rune", 1),
+            entry("older.rune", 50),
+        ];
+
+        let got = stale_entries(entries, 2);
+
+        assert_eq!(
+            got,
+            vec![PathBuf::from("older.rune"), PathBuf::from("oldest.rune")]
+        );
+    }
+
+    #[test]
+    fn no_cache_disables_the_cache() {
+        let ctx = BuildContext {
+            no_cache: true,
+            ..ctx()
+        };
+
+        let cache = BuildCache::for_project(
+            &ctx,
+            &FeatureFlags::production(),
+            &[],
+            &[],
+            &[],
+        );
+
+        assert!(cache.is_none());
+    }
+}
@@ -4,15 +4,28 @@ use std::{
     sync::Mutex,
 };
 
-use legion::systems::CommandBuffer;
+use legion::{systems::CommandBuffer, world::SubWorld, Query};
 
 use crate::{
-    compile::{CompilationResult, CompileError, CompiledBinary},
-    BuildContext, Verbosity,
+    codegen::{CustomSection, File},
+    compile::{
+        build_cache::BuildCache, CompilationResult, CompileError,
+        CompiledBinary,
+    },
+    lowering::ProcBlock,
+    BuildContext, FeatureFlags, Verbosity,
 };
 
 #[legion::system]
-pub(crate) fn run(cmd: &mut CommandBuffer, #[resource] ctx: &BuildContext) {
+pub(crate) fn run(
+    world: &SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] ctx: &BuildContext,
+    #[resource] features: &FeatureFlags,
+    files: &mut Query<&File>,
+    sections: &mut Query<&CustomSection>,
+    proc_blocks: &mut Query<&ProcBlock>,
+) {
     let BuildContext {
         working_directory,
         optimized,
@@ -21,9 +34,34 @@ pub(crate) fn run(cmd: &mut CommandBuffer, #[resource] ctx: &BuildContext) {
         ..
     } = ctx;
 
-    rustfmt(working_directory);
+    let cache = BuildCache::for_project(
+        ctx,
+        features,
+        &files.iter(world).collect::<Vec<_>>(),
+        &sections.iter(world).collect::<Vec<_>>(),
+        &proc_blocks.iter(world).collect::<Vec<_>>(),
+    );
+
+    let result = match cache.as_ref().and_then(BuildCache::lookup) {
+        Some(binary) => {
+            log::info!(
+                "Reusing the cached build of \"{}\" because the generated \
+                 project hasn't changed",
+                name
+            );
+            Ok(binary)
+        },
+        None => {
+            rustfmt(working_directory);
+            let result = build(name, working_directory, *optimized, *verbosity);
 
-    let result = build(name, working_directory, *optimized, *verbosity);
+            if let (Some(cache), Ok(binary)) = (&cache, &result) {
+                cache.save(binary);
+            }
+
+            result
+        },
+    };
 
     // Note: the exec_mut() method takes a Fn() closure and not a FnOnce(), so
     // we need to use a Mutex<Option<_>> to move the result.
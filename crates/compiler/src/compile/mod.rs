@@ -1,3 +1,4 @@
+mod build_cache;
 mod cargo_build;
 mod components;
 mod write_project_to_disk;
@@ -1,10 +1,13 @@
 //! Callbacks that allow users to hook into the build process.
 
 use atomic_refcell::{AtomicRef, AtomicRefMut};
-use legion::{Resources, World};
+use legion::{IntoQuery, Resources, World};
 
 use crate::{
-    compile::CompilationResult, lowering::NameTable, parse::DocumentV1,
+    codegen::RuneGraph,
+    compile::CompilationResult,
+    lowering::NameTable,
+    parse::{DocumentV1, SourceMap},
     BuildContext, Diagnostics, FeatureFlags,
 };
 
@@ -123,6 +126,12 @@ pub trait AfterParseContext: Context {
     fn diagnostics_mut(&self) -> AtomicRefMut<'_, Diagnostics> {
         self.resources().get_mut().unwrap()
     }
+
+    /// The files the Runefile was assembled from, for rendering
+    /// [`Diagnostics`].
+    fn source_map(&self) -> AtomicRef<'_, SourceMap> {
+        self.resources().get().unwrap()
+    }
 }
 
 /// Context passed to the [`Hooks::after_lowering()`] method.
@@ -136,7 +145,12 @@ pub trait AfterLoweringContext: AfterParseContext {
 pub trait AfterTypeCheckingContext: AfterLoweringContext {}
 
 /// Context passed to the [`Hooks::after_codegen()`] method.
-pub trait AfterCodegenContext: AfterTypeCheckingContext {}
+pub trait AfterCodegenContext: AfterTypeCheckingContext {
+    /// Get the [`RuneGraph`] summarising the Rune's pipeline.
+    fn rune_graph(&self) -> Option<RuneGraph> {
+        <&RuneGraph>::query().iter(self.world()).next().cloned()
+    }
+}
 
 /// Context passed to the [`Hooks::after_compile()`] method.
 pub trait AfterCompileContext: AfterCodegenContext {
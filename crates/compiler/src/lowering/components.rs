@@ -13,7 +13,7 @@ use hotg_rune_core::Shape;
 use indexmap::IndexMap;
 use legion::Entity;
 
-use crate::parse::{Path, ResourceType};
+use crate::parse::{Comparison, Path, ResourceType};
 
 /// An output.
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -31,6 +31,16 @@ pub enum SinkKind {
     Other(String),
 }
 
+impl SinkKind {
+    pub const fn as_output_index(&self) -> Option<u32> {
+        match self {
+            SinkKind::Serial => Some(hotg_rune_core::outputs::SERIAL),
+            SinkKind::Tensor => Some(hotg_rune_core::outputs::TENSOR),
+            SinkKind::Other(_) => None,
+        }
+    }
+}
+
 impl Display for SinkKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -271,6 +281,38 @@ pub struct Inputs {
     pub tensors: Vec<Entity>,
 }
 
+/// A [`PipelineNode`] which should only be executed when at least one element
+/// of a [`Tensor`] satisfies a comparison.
+///
+/// When the node is skipped its [`Outputs`] are absent, so any nodes which
+/// depend on them will be skipped too.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Condition {
+    pub tensor: Entity,
+    pub comparison: Comparison,
+    pub value: f64,
+}
+
+/// A [`PipelineNode`] which outputs the value its input had on the previous
+/// call, starting with every element set to `initial`.
+///
+/// Edges going into a delay aren't counted when checking for cycles, which
+/// is what lets stages feed their outputs back into the pipeline.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Delay {
+    pub initial: f64,
+}
+
+/// A named part of the pipeline which can be run on its own.
+///
+/// The `nodes` are the [`PipelineNode`]s listed in the Runefile plus every
+/// node they depend on, in the order they were declared.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EntryPoint {
+    pub name: Name,
+    pub nodes: Vec<Entity>,
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct ResourceData(pub Arc<[u8]>);
 
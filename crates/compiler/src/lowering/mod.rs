@@ -3,6 +3,7 @@
 mod components;
 mod load_model_data;
 mod load_resource_data;
+mod register_entry_points;
 mod register_names;
 mod register_resources;
 mod register_stages;
@@ -23,12 +24,16 @@ pub fn phase() -> Phase {
     .and_then(register_resources::run_system)
     .and_then(register_stages::run_system)
     .and_then(register_tensors::run_system)
+    .and_then(register_entry_points::run_system)
     .and_then(load_resource_data::run_system)
     .and_then(load_model_data::run_system)
 }
 
 pub(crate) fn register_components(registry: &mut Registry<String>) {
     registry
+        .register_with_type_name::<Condition>()
+        .register_with_type_name::<Delay>()
+        .register_with_type_name::<EntryPoint>()
         .register_with_type_name::<Inputs>()
         .register_with_type_name::<Model>()
         .register_with_type_name::<ModelFile>()
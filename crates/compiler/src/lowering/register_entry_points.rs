@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use legion::{systems::CommandBuffer, world::SubWorld, Entity, Query};
+
+use crate::{
+    lowering::{
+        Condition, EntryPoint, Inputs, Name, NameTable, PipelineNode, Tensor,
+    },
+    parse::{DocumentSpans, DocumentV1},
+    Diagnostics,
+};
+
+/// Create an [`EntryPoint`] for each of the Runefile's named pipelines,
+/// containing the stages it lists and everything they depend on.
+#[legion::system]
+pub(crate) fn run(
+    cmd: &mut CommandBuffer,
+    world: &SubWorld,
+    #[resource] doc: &DocumentV1,
+    #[resource] spans: &DocumentSpans,
+    #[resource] names: &NameTable,
+    #[resource] diags: &mut Diagnostics,
+    nodes: &mut Query<(Option<&Inputs>, Option<&Condition>, &PipelineNode)>,
+    tensors: &mut Query<(&Tensor, &Inputs)>,
+) {
+    for (name, stages) in &doc.pipelines {
+        let mut pending = Vec::new();
+
+        for (i, stage) in stages.iter().enumerate() {
+            match names.get(stage.as_str()) {
+                Some(&ent) if doc.pipeline.contains_key(stage) => {
+                    pending.push(ent)
+                },
+                _ => diags.push(unknown_stage_diagnostic(
+                    name,
+                    stage,
+                    spans.pipeline_stage(name, i),
+                )),
+            }
+        }
+
+        let mut reachable = HashSet::new();
+
+        while let Some(node) = pending.pop() {
+            if !reachable.insert(node) {
+                continue;
+            }
+
+            let (inputs, condition, _) = match nodes.get(world, node) {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
+
+            let dependencies = inputs
+                .iter()
+                .flat_map(|i| i.tensors.iter())
+                .chain(condition.map(|c| &c.tensor));
+
+            for &tensor in dependencies {
+                if let Ok((_, producers)) = tensors.get(world, tensor) {
+                    pending.extend(producers.tensors.iter().copied());
+                }
+            }
+        }
+
+        // Note: keep the nodes in the order they were declared so the
+        // generated code is deterministic
+        let nodes: Vec<Entity> = doc
+            .pipeline
+            .keys()
+            .filter_map(|stage| names.get(stage.as_str()).copied())
+            .filter(|ent| reachable.contains(ent))
+            .collect();
+
+        cmd.push((EntryPoint {
+            name: Name::from(name),
+            nodes,
+        },));
+    }
+}
+
+fn unknown_stage_diagnostic(
+    pipeline: &str,
+    stage: &str,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" pipeline refers to an unknown stage, \"{}\"",
+            pipeline, stage
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+#[cfg(test)]
+mod tests {
+    use legion::{IntoQuery, Resources, World};
+
+    use super::*;
+    use crate::{parse::Document, BuildContext};
+
+    fn lower(src: &str) -> (Vec<(String, Vec<String>)>, Vec<Diagnostic<()>>) {
+        let doc: DocumentV1 = Document::parse(src).unwrap().to_v1();
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(BuildContext::from_doc(doc.into()));
+        crate::parse::phase().run(&mut world, &mut res);
+        crate::lowering::phase().run(&mut world, &mut res);
+
+        let mut names = <&Name>::query();
+        let entry_points = <&EntryPoint>::query()
+            .iter(&world)
+            .map(|e| {
+                let nodes = e
+                    .nodes
+                    .iter()
+                    .map(|&n| names.get(&world, n).unwrap().to_string())
+                    .collect();
+                (e.name.to_string(), nodes)
+            })
+            .collect();
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        (entry_points, diags.iter().cloned().collect())
+    }
+
+    #[test]
+    fn entry_points_include_their_dependencies() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  accelerometer:
+    capability: ACCEL
+    outputs: [{type: f32, dimensions: [1, 3]}]
+  gesture:
+    out: serial
+    inputs: [accelerometer]
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  samples:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [4]}]
+  serial:
+    out: serial
+    inputs: [samples]
+    when: "rand > 0.5"
+pipelines:
+  fast: [gesture]
+  slow: [serial]
+"#;
+
+        let (entry_points, diags) = lower(src);
+
+        assert!(diags.is_empty(), "{:?}", diags);
+        assert_eq!(entry_points.len(), 2);
+        assert!(entry_points.contains(&(
+            "fast".to_string(),
+            vec!["accelerometer".to_string(), "gesture".to_string()]
+        )));
+        assert!(entry_points.contains(&(
+            "slow".to_string(),
+            vec![
+                "rand".to_string(),
+                "samples".to_string(),
+                "serial".to_string()
+            ]
+        )));
+    }
+
+    #[test]
+    fn unknown_stages_are_reported() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+pipelines:
+  fast: [rand, missing]
+"#;
+
+        let (_, diags) = lower(src);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"fast\" pipeline refers to an unknown stage, \"missing\""
+        );
+    }
+}
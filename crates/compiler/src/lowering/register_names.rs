@@ -2,18 +2,22 @@ use legion::systems::CommandBuffer;
 
 use crate::{
     lowering::{Name, PipelineNode},
-    parse::DocumentV1,
+    parse::{DocumentSpans, DocumentV1},
 };
 
 /// Goes through and registers all the named items and their locations in the
 /// Runefile.
 #[legion::system]
-pub(crate) fn run(cmd: &mut CommandBuffer, #[resource] doc: &DocumentV1) {
-    for (name, stage) in &doc.pipeline {
-        cmd.push((Name::from(name), stage.span(), PipelineNode));
+pub(crate) fn run(
+    cmd: &mut CommandBuffer,
+    #[resource] doc: &DocumentV1,
+    #[resource] spans: &DocumentSpans,
+) {
+    for name in doc.pipeline.keys() {
+        cmd.push((Name::from(name), spans.stage(name), PipelineNode));
     }
 
-    for (name, decl) in &doc.resources {
-        cmd.push((Name::from(name), decl.span()));
+    for name in doc.resources.keys() {
+        cmd.push((Name::from(name), spans.resource(name)));
     }
 }
@@ -4,7 +4,7 @@ use legion::systems::CommandBuffer;
 
 use crate::{
     lowering::{NameTable, Resource, ResourceSource},
-    parse::DocumentV1,
+    parse::{DocumentSpans, DocumentV1},
     Diagnostics,
 };
 
@@ -14,6 +14,7 @@ pub(crate) fn run(
     cmd: &mut CommandBuffer,
     #[resource] diags: &mut Diagnostics,
     #[resource] doc: &mut DocumentV1,
+    #[resource] spans: &DocumentSpans,
     #[resource] names: &NameTable,
 ) {
     for (name, decl) in &doc.resources {
@@ -35,7 +36,7 @@ pub(crate) fn run(
             (Some(_), Some(_)) => {
                 diags.push(path_and_inline_defined_diagnostic(
                     name,
-                    decl.span(),
+                    spans.resource(name),
                 ));
 
                 continue;
@@ -83,7 +84,12 @@ mod tests {
         DocumentV1 {
             version: 1,
             image: "img".parse().unwrap(),
+            includes: Vec::new(),
+            variables: Default::default(),
+            profiles: Default::default(),
             pipeline: Default::default(),
+            pipelines: Default::default(),
+            templates: Default::default(),
             resources: map! {
                 inline_string: ResourceDeclaration {
                     inline: Some("inline".to_string()),
@@ -1,27 +1,29 @@
+use codespan::Span;
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 use indexmap::IndexMap;
 use legion::{systems::CommandBuffer, world::SubWorld, Entity, Query};
 
 use crate::{
     lowering::{
-        self, Mimetype, Model, ModelFile, NameTable, ProcBlock, Resource,
-        ResourceData, Sink, Source,
+        self, Delay, Mimetype, Model, ModelFile, NameTable, ProcBlock,
+        Resource, ResourceData, Sink, Source,
     },
     parse::{
-        self, CapabilityStage, DocumentV1, ModelStage, OutStage,
-        ProcBlockStage, ResourceName, ResourceType,
+        self, CapabilityStage, DelayStage, DocumentSpans, DocumentV1,
+        ModelStage, OutStage, ProcBlockStage, ResourceName, ResourceType,
     },
     Diagnostics,
 };
 
-/// Attach [`Model`], [`ProcBlock`], [`Sink`], and [`Source`] components to
-/// each [`parse::Stage`] in the [`DocumentV1`].
+/// Attach [`Model`], [`ProcBlock`], [`Sink`], [`Source`], and [`Delay`]
+/// components to each [`parse::Stage`] in the [`DocumentV1`].
 #[legion::system]
 #[read_component(Resource)]
 pub(crate) fn run(
     cmd: &mut CommandBuffer,
     world: &SubWorld,
     #[resource] doc: &DocumentV1,
+    #[resource] spans: &DocumentSpans,
     #[resource] names: &NameTable,
     #[resource] diags: &mut Diagnostics,
     resources: &mut Query<(&Resource, Option<&ResourceData>)>,
@@ -32,7 +34,9 @@ pub(crate) fn run(
             None => continue,
         };
 
-        let args = match translate_args(stage.args(), names) {
+        let args = match translate_args(stage.args(), names, |arg| {
+            spans.argument(name, arg)
+        }) {
             Ok(a) => a,
             Err(diag) => {
                 diags.push(diag);
@@ -42,9 +46,14 @@ pub(crate) fn run(
 
         match stage {
             parse::Stage::Model(ModelStage { model, .. }) => {
-                match register_model(names, name, model, &args, |e: Entity| {
-                    resources.get(world, e).ok()
-                }) {
+                match register_model(
+                    names,
+                    name,
+                    spans,
+                    model,
+                    &args,
+                    |e: Entity| resources.get(world, e).ok(),
+                ) {
                     Ok((model, mimetype)) => {
                         cmd.add_component(ent, model);
                         cmd.add_component(ent, mimetype);
@@ -55,7 +64,9 @@ pub(crate) fn run(
             parse::Stage::ProcBlock(ProcBlockStage { proc_block, .. }) => {
                 if proc_block.version.is_none() {
                     let diag = warn_on_unversioned_proc_block_diagnostic(
-                        name, proc_block,
+                        name,
+                        proc_block,
+                        spans.field(name, "proc-block"),
                     );
                     diags.push(diag);
                 }
@@ -84,6 +95,11 @@ pub(crate) fn run(
                     args,
                 },
             ),
+            parse::Stage::Delay(DelayStage { initial, .. }) => {
+                cmd.add_component(ent, Delay { initial: *initial })
+            },
+            // Templates are expanded into ordinary stages while parsing
+            parse::Stage::Template(_) => {},
         }
     }
 }
@@ -91,6 +107,7 @@ pub(crate) fn run(
 fn warn_on_unversioned_proc_block_diagnostic(
     name: &str,
     proc_block: &parse::Path,
+    span: Span,
 ) -> Diagnostic<()> {
     let msg = format!(
         "The \"{}\" proc block used by \"{}\" should have a version specifier",
@@ -103,6 +120,7 @@ fn warn_on_unversioned_proc_block_diagnostic(
 
     Diagnostic::warning()
         .with_message(msg)
+        .with_labels(vec![Label::primary((), span)])
         .with_notes(vec![format!(
             "hint: change it to something like \"{}\"",
             versioned
@@ -112,6 +130,7 @@ fn warn_on_unversioned_proc_block_diagnostic(
 fn translate_args(
     args: &IndexMap<String, parse::Argument>,
     names: &NameTable,
+    arg_span: impl Fn(&str) -> Span,
 ) -> Result<IndexMap<String, lowering::ResourceOrString>, Diagnostic<()>> {
     let mut translated = IndexMap::new();
 
@@ -122,7 +141,9 @@ fn translate_args(
                 .copied()
             {
                 Some(entity) => lowering::ResourceOrString::Resource(entity),
-                None => return Err(not_a_resource_diagnostic(r)),
+                None => {
+                    return Err(not_a_resource_diagnostic(r, arg_span(name)))
+                },
             },
             parse::ResourceOrString::String(s) => {
                 lowering::ResourceOrString::String(s.clone())
@@ -138,18 +159,22 @@ fn translate_args(
 fn register_model<'a>(
     names: &NameTable,
     node_name: &str,
+    spans: &DocumentSpans,
     model: &parse::ResourceOrString,
     args: &IndexMap<String, lowering::ResourceOrString>,
     mut get_resource: impl FnMut(Entity) -> Option<(&'a Resource, Option<&'a ResourceData>)>
         + 'a,
 ) -> Result<(Model, Mimetype), Diagnostic<()>> {
-    let (mimetype, args) = model_format_and_args(node_name, args, |e| {
-        get_resource(e).and_then(|r| r.1).cloned()
-    })?;
+    let format_span = spans.argument(node_name, "format");
+    let (mimetype, args) =
+        model_format_and_args(node_name, args, format_span, |e| {
+            get_resource(e).and_then(|r| r.1).cloned()
+        })?;
 
     let model_file = match model {
         parse::ResourceOrString::Resource(resource_name) => {
-            resource_model(resource_name, names, |e| {
+            let span = spans.field(node_name, "model");
+            resource_model(resource_name, span, names, |e| {
                 get_resource(e).map(|r| r.0)
             })?
         },
@@ -162,6 +187,7 @@ fn register_model<'a>(
 fn model_format_and_args(
     node_name: &str,
     args: &IndexMap<String, lowering::ResourceOrString>,
+    span: Span,
     get_resource_data: impl FnOnce(Entity) -> Option<ResourceData>,
 ) -> Result<
     (Mimetype, IndexMap<String, lowering::ResourceOrString>),
@@ -171,14 +197,16 @@ fn model_format_and_args(
 
     let mimetype = match args.remove("format") {
         Some(lowering::ResourceOrString::String(format)) => {
-            mimetype_for_known_format(&format)?
+            mimetype_for_known_format(&format, span)?
         },
         Some(lowering::ResourceOrString::Resource(entity)) => {
             match get_resource_data(entity) {
                 Some(data) => match std::str::from_utf8(&data) {
-                    Ok(format) => mimetype_for_known_format(format)?,
+                    Ok(format) => mimetype_for_known_format(format, span)?,
                     Err(e) => {
-                        return Err(invalid_mimetype_diagnostic(node_name, e))
+                        return Err(invalid_mimetype_diagnostic(
+                            node_name, e, span,
+                        ))
                     },
                 },
                 None => {
@@ -195,13 +223,19 @@ fn model_format_and_args(
 fn invalid_mimetype_diagnostic(
     node_name: &str,
     e: std::str::Utf8Error,
+    span: Span,
 ) -> Diagnostic<()> {
     let msg = format!("Invalid format for \"{}\": {}", node_name, e);
 
-    Diagnostic::error().with_message(msg)
+    Diagnostic::error()
+        .with_message(msg)
+        .with_labels(vec![Label::primary((), span)])
 }
 
-fn mimetype_for_known_format(format: &str) -> Result<Mimetype, Diagnostic<()>> {
+fn mimetype_for_known_format(
+    format: &str,
+    span: Span,
+) -> Result<Mimetype, Diagnostic<()>> {
     let known_formats = [
         ("onnx", hotg_rune_core::ONNX_MIMETYPE),
         ("tensorflow", hotg_rune_core::TF_MIMETYPE),
@@ -217,6 +251,7 @@ fn mimetype_for_known_format(format: &str) -> Result<Mimetype, Diagnostic<()>> {
             unknown_format_diagnostic(
                 &format,
                 known_formats.iter().copied().map(|(f, _)| f),
+                span,
             )
         })
 }
@@ -224,15 +259,16 @@ fn mimetype_for_known_format(format: &str) -> Result<Mimetype, Diagnostic<()>> {
 fn unknown_format_diagnostic(
     format: &str,
     expected: impl Iterator<Item = &'static str>,
+    span: Span,
 ) -> Diagnostic<()> {
-    // TODO: use span information to tell the user where the error came from
-
     let msg = format!(
         "Expected the format to be one of {}, but found {:?}",
         join(expected, ", "),
         format
     );
-    Diagnostic::error().with_message(msg)
+    Diagnostic::error()
+        .with_message(msg)
+        .with_labels(vec![Label::primary((), span)])
 }
 
 fn join<'a>(items: impl Iterator<Item = &'a str>, separator: &str) -> String {
@@ -251,21 +287,25 @@ fn join<'a>(items: impl Iterator<Item = &'a str>, separator: &str) -> String {
 
 fn resource_model<'a>(
     resource_name: &parse::ResourceName,
+    span: Span,
     names: &NameTable,
     get_resource: impl FnOnce(Entity) -> Option<&'a Resource> + 'a,
 ) -> Result<ModelFile, Diagnostic<()>> {
     let ent = match names.get(resource_name.as_str()) {
         Some(&e) => e,
-        None => return Err(unknown_resource_diagnostic(resource_name)),
+        None => return Err(unknown_resource_diagnostic(resource_name, span)),
     };
 
     let res = match get_resource(ent) {
         Some(r) => r,
-        None => return Err(not_a_resource_diagnostic(resource_name)),
+        None => return Err(not_a_resource_diagnostic(resource_name, span)),
     };
 
     if res.ty != ResourceType::Binary {
-        return Err(model_resource_should_be_binary_diagnostic(resource_name));
+        return Err(model_resource_should_be_binary_diagnostic(
+            resource_name,
+            span,
+        ));
     }
 
     Ok(ModelFile::Resource(ent))
@@ -273,25 +313,32 @@ fn resource_model<'a>(
 
 fn model_resource_should_be_binary_diagnostic(
     resource_name: &ResourceName,
+    span: Span,
 ) -> Diagnostic<()> {
     Diagnostic::error()
         .with_message(format!(
             "\"{}\" should be a binary resource",
             resource_name
         ))
-        .with_labels(vec![Label::primary((), resource_name.span())])
+        .with_labels(vec![Label::primary((), span)])
 }
 
-fn not_a_resource_diagnostic(resource_name: &ResourceName) -> Diagnostic<()> {
+fn not_a_resource_diagnostic(
+    resource_name: &ResourceName,
+    span: Span,
+) -> Diagnostic<()> {
     Diagnostic::error()
         .with_message(format!("\"{}\" is not a resource", resource_name))
-        .with_labels(vec![Label::primary((), resource_name.span())])
+        .with_labels(vec![Label::primary((), span)])
 }
 
-fn unknown_resource_diagnostic(resource_name: &ResourceName) -> Diagnostic<()> {
+fn unknown_resource_diagnostic(
+    resource_name: &ResourceName,
+    span: Span,
+) -> Diagnostic<()> {
     Diagnostic::error()
         .with_message(format!("No definition for \"{}\"", resource_name))
-        .with_labels(vec![Label::primary((), resource_name.span())])
+        .with_labels(vec![Label::primary((), span)])
 }
 
 #[cfg(test)]
@@ -311,6 +358,9 @@ mod tests {
         DocumentV1 {
             version: 1,
             image: "img".parse().unwrap(),
+            includes: Vec::new(),
+            variables: IndexMap::new(),
+            profiles: IndexMap::new(),
             pipeline: map! {
                 cap: Stage::Capability(CapabilityStage {
                     capability: "SOUND".to_string(),
@@ -326,43 +376,57 @@ mod tests {
                     },
                     inputs: Vec::new(),
                     outputs: Vec::new(),
+                    when: None,
                 }),
                 model_from_disk: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::String("model.tflite".into()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    when: None,
                 }),
                 model_from_resource: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::Resource("$MODEL_FILE".parse().unwrap()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    when: None,
                 }),
                 model_with_not_a_resource: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::Resource("$cap".parse().unwrap()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    when: None,
                 }),
                 model_with_missing_resource: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::Resource("$NON_EXISTENT".parse().unwrap()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    when: None,
                 }),
                 model_with_string_resource: Stage::Model(ModelStage {
                     model: parse::ResourceOrString::Resource("$STRING_RESOURCE".parse().unwrap()),
                     inputs: Vec::new(),
                     outputs: Vec::new(),
                     args: IndexMap::new(),
+                    when: None,
                 }),
                 serial: Stage::Out(OutStage {
                     out: "SERIAL".to_string(),
                     args: Default::default(),
                     inputs: Vec::new(),
+                    when: None,
+                }),
+                previous: Stage::Delay(DelayStage {
+                    initial: 0.5,
+                    inputs: Vec::new(),
+                    outputs: Vec::new(),
                 }),
             },
+            pipelines: IndexMap::new(),
+            templates: IndexMap::new(),
             resources: map! {
                 MODEL_FILE: ResourceDeclaration {
                     inline: None,
@@ -395,6 +459,7 @@ mod tests {
 
         let diags = res.get::<Diagnostics>().unwrap();
         let diags: Vec<_> = diags.iter().collect();
+        let spans = res.get::<DocumentSpans>().unwrap();
         assert_eq!(diags.len(), 4);
         assert_eq!(
             diags[0],
@@ -403,6 +468,10 @@ mod tests {
                     "The \"my-proc-block\" proc block used by \"transform\" \
                      should have a version specifier"
                 )
+                .with_labels(vec![Label::primary(
+                    (),
+                    spans.field("transform", "proc-block")
+                )])
                 .with_notes(vec![format!(
                     "hint: change it to something like \"my-proc-block@{}\"",
                     env!("CARGO_PKG_VERSION").to_string()
@@ -415,6 +484,12 @@ mod tests {
             diags[3].message,
             "\"$STRING_RESOURCE\" should be a binary resource"
         );
+        // Errors about a model's resource should point at the "model" field
+        let runefile = &res.get::<BuildContext>().unwrap().runefile;
+        for diag in &diags[1..] {
+            let text = &runefile[diag.labels[0].range.clone()];
+            assert!(text.trim_matches('"').starts_with('$'), "{:?}", diag);
+        }
 
         let proc_blocks_should_be = vec![(
             Name::from("transform"),
@@ -485,5 +560,13 @@ mod tests {
             .map(|(n, s)| (n.clone(), s.clone()))
             .collect();
         assert_eq!(got, sinks_should_be);
+
+        let delays_should_be =
+            vec![(Name::from("previous"), Delay { initial: 0.5 })];
+        let got: Vec<_> = <(&Name, &Delay)>::query()
+            .iter(&world)
+            .map(|(n, d)| (n.clone(), d.clone()))
+            .collect();
+        assert_eq!(got, delays_should_be);
     }
 }
@@ -1,27 +1,31 @@
 use std::collections::HashMap;
 
-use codespan_reporting::diagnostic::Diagnostic;
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
 use hotg_rune_core::{ElementType, Shape};
 use legion::{systems::CommandBuffer, Entity};
 
 use crate::{
-    lowering::{Inputs, NameTable, Outputs, Tensor},
-    parse::{self, DocumentV1},
+    lowering::{Condition, Inputs, NameTable, Outputs, Tensor},
+    parse::{self, DocumentSpans, DocumentV1},
     Diagnostics,
 };
 
 /// Register all [`Tensor`]s and associate them as node [`Inputs`] or
-/// [`Outputs`].
+/// [`Outputs`], attaching a [`Condition`] to any nodes that need one.
 #[legion::system]
 pub(crate) fn run(
     cmd: &mut CommandBuffer,
     #[resource] names: &NameTable,
     #[resource] doc: &DocumentV1,
+    #[resource] spans: &DocumentSpans,
     #[resource] diags: &mut Diagnostics,
 ) {
-    let node_outputs = register_node_outputs(cmd, names, doc, diags);
+    let node_outputs = register_node_outputs(cmd, names, doc, spans, diags);
     let node_inputs =
-        register_node_inputs(doc, names, &node_outputs, cmd, diags);
+        register_node_inputs(doc, spans, names, &node_outputs, cmd, diags);
+    let conditions =
+        register_conditions(doc, spans, names, &node_outputs, cmd, diags);
 
     for (&node, outputs) in &node_outputs {
         for &tensor in &outputs.tensors {
@@ -29,7 +33,7 @@ pub(crate) fn run(
                 tensors: vec![node],
             };
 
-            let outputs: Vec<_> = node_inputs
+            let mut outputs: Vec<_> = node_inputs
                 .iter()
                 .filter_map(|(&ent, inputs)| {
                     if inputs.tensors.contains(&tensor) {
@@ -40,6 +44,14 @@ pub(crate) fn run(
                 })
                 .collect();
 
+            // Nodes that only look at the tensor in their condition still
+            // need it to be computed first
+            for (&ent, condition) in &conditions {
+                if condition.tensor == tensor && !outputs.contains(&ent) {
+                    outputs.push(ent);
+                }
+            }
+
             cmd.add_component(tensor, inputs);
             cmd.add_component(tensor, Outputs { tensors: outputs });
         }
@@ -48,6 +60,7 @@ pub(crate) fn run(
 
 fn register_node_inputs(
     doc: &DocumentV1,
+    spans: &DocumentSpans,
     names: &NameTable,
     output_tensors_by_node: &HashMap<Entity, Outputs>,
     cmd: &mut CommandBuffer,
@@ -64,6 +77,7 @@ fn register_node_inputs(
         match register_stage_inputs(
             name,
             stage.inputs(),
+            spans,
             names,
             output_tensors_by_node,
         ) {
@@ -79,18 +93,59 @@ fn register_node_inputs(
     outputs
 }
 
+fn register_conditions(
+    doc: &DocumentV1,
+    spans: &DocumentSpans,
+    names: &NameTable,
+    output_tensors_by_node: &HashMap<Entity, Outputs>,
+    cmd: &mut CommandBuffer,
+    diags: &mut Diagnostics,
+) -> HashMap<Entity, Condition> {
+    let mut conditions = HashMap::new();
+
+    for (name, stage) in &doc.pipeline {
+        let (ent, condition) = match (names.get(name), stage.condition()) {
+            (Some(&e), Some(c)) => (e, c),
+            _ => continue,
+        };
+
+        match get_input_tensor(
+            name,
+            &condition.input,
+            spans.field(name, "when"),
+            names,
+            output_tensors_by_node,
+        ) {
+            Ok(tensor) => {
+                let condition = Condition {
+                    tensor,
+                    comparison: condition.comparison,
+                    value: condition.value,
+                };
+                cmd.add_component(ent, condition.clone());
+                conditions.insert(ent, condition);
+            },
+            Err(diag) => diags.push(diag),
+        }
+    }
+
+    conditions
+}
+
 fn register_stage_inputs(
     parent_name: &str,
     inputs: &[parse::Input],
+    spans: &DocumentSpans,
     names: &NameTable,
     output_tensors_by_node: &HashMap<Entity, Outputs>,
 ) -> Result<Inputs, Diagnostic<()>> {
     let mut tensors = Vec::new();
 
-    for input in inputs {
+    for (i, input) in inputs.iter().enumerate() {
         let tensor = get_input_tensor(
             parent_name,
             input,
+            spans.input(parent_name, i),
             names,
             output_tensors_by_node,
         )?;
@@ -103,62 +158,75 @@ fn register_stage_inputs(
 fn get_input_tensor(
     parent_name: &str,
     input: &parse::Input,
+    span: Span,
     names: &NameTable,
     output_tensors_by_node: &HashMap<Entity, Outputs>,
 ) -> Result<Entity, Diagnostic<()>> {
     // Find the node this "Input" refers to
-    let input_node = names
-        .get(&input.name)
-        .copied()
-        .ok_or_else(|| unknown_input_name_diagnostic(parent_name, input))?;
+    let input_node = names.get(&input.name).copied().ok_or_else(|| {
+        unknown_input_name_diagnostic(parent_name, input, span)
+    })?;
 
     // Then get its set of Outputs
-    let output_tensors = output_tensors_by_node
-        .get(&input_node)
-        .ok_or_else(|| node_has_no_outputs_diagnostic(parent_name, input))?;
+    let output_tensors =
+        output_tensors_by_node.get(&input_node).ok_or_else(|| {
+            node_has_no_outputs_diagnostic(parent_name, input, span)
+        })?;
 
     // Finally, get the Entity for the index'th item
     let tensor = output_tensors
         .tensors
         .get(input.index.unwrap_or(0))
         .copied()
-        .ok_or_else(|| no_such_output_diagnostic(input))?;
+        .ok_or_else(|| no_such_output_diagnostic(input, span))?;
 
     Ok(tensor)
 }
 
-fn no_such_output_diagnostic(input: &parse::Input) -> Diagnostic<()> {
-    Diagnostic::error().with_message(format!(
-        "The \"{}\" node has no {}'th output",
-        input.name,
-        input.index.unwrap_or(0)
-    ))
+fn no_such_output_diagnostic(
+    input: &parse::Input,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" node has no {}'th output",
+            input.name,
+            input.index.unwrap_or(0)
+        ))
+        .with_labels(vec![Label::primary((), span)])
 }
 
 fn node_has_no_outputs_diagnostic(
     parent_name: &str,
     input: &parse::Input,
+    span: Span,
 ) -> Diagnostic<()> {
-    Diagnostic::error().with_message(format!(
-        "The \"{}\" in {}'s \"{}\" input has no inputs",
-        input.name, parent_name, input,
-    ))
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" in {}'s \"{}\" input has no inputs",
+            input.name, parent_name, input,
+        ))
+        .with_labels(vec![Label::primary((), span)])
 }
 
 fn unknown_input_name_diagnostic(
     parent_name: &str,
     input: &parse::Input,
+    span: Span,
 ) -> Diagnostic<()> {
-    Diagnostic::error().with_message(format!(
-        "Unable to find \"{}\" to use as an input for \"{}\"",
-        input, parent_name,
-    ))
+    Diagnostic::error()
+        .with_message(format!(
+            "Unable to find \"{}\" to use as an input for \"{}\"",
+            input, parent_name,
+        ))
+        .with_labels(vec![Label::primary((), span)])
 }
 
 fn register_node_outputs(
     cmd: &mut CommandBuffer,
     names: &NameTable,
     doc: &DocumentV1,
+    spans: &DocumentSpans,
     diags: &mut Diagnostics,
 ) -> HashMap<Entity, Outputs> {
     let mut node_to_output_tensors = HashMap::new();
@@ -169,7 +237,9 @@ fn register_node_outputs(
             None => continue,
         };
 
-        match allocate_output_tensors(cmd, stage.output_types()) {
+        match allocate_output_tensors(cmd, stage.output_types(), |i| {
+            spans.output(name, i)
+        }) {
             Ok(outputs) if outputs.tensors.is_empty() => {},
             Ok(outputs) => {
                 node_to_output_tensors.insert(ent, outputs.clone());
@@ -186,23 +256,24 @@ fn register_node_outputs(
 fn allocate_output_tensors(
     cmd: &mut CommandBuffer,
     output_types: &[parse::Type],
+    output_span: impl Fn(usize) -> Span,
 ) -> Result<Outputs, Diagnostic<()>> {
     let mut outputs = Vec::new();
 
-    for ty in output_types {
-        let tensor = shape(ty)?;
+    for (i, ty) in output_types.iter().enumerate() {
+        let tensor = shape(ty, output_span(i))?;
         outputs.push(cmd.push((tensor,)));
     }
 
     Ok(Outputs { tensors: outputs })
 }
 
-fn shape(ty: &parse::Type) -> Result<Tensor, Diagnostic<()>> {
+fn shape(ty: &parse::Type, span: Span) -> Result<Tensor, Diagnostic<()>> {
     let element_type: ElementType = ty
         .name
         .to_lowercase()
         .parse()
-        .map_err(|_| unknown_element_type_diagnostic(&ty.name))?;
+        .map_err(|_| unknown_element_type_diagnostic(&ty.name, span))?;
 
     Ok(Tensor::from(Shape::new(
         element_type,
@@ -210,9 +281,10 @@ fn shape(ty: &parse::Type) -> Result<Tensor, Diagnostic<()>> {
     )))
 }
 
-fn unknown_element_type_diagnostic(name: &str) -> Diagnostic<()> {
+fn unknown_element_type_diagnostic(name: &str, span: Span) -> Diagnostic<()> {
     Diagnostic::error()
         .with_message(format!("Unknown element type, \"{}\"", name))
+        .with_labels(vec![Label::primary((), span)])
 }
 
 #[cfg(test)]
@@ -233,6 +305,9 @@ mod tests {
         DocumentV1 {
             version: 1,
             image: "image".parse().unwrap(),
+            includes: Vec::new(),
+            variables: Default::default(),
+            profiles: Default::default(),
             pipeline: map! {
                 rand: parse::Stage::Capability(CapabilityStage {
                     capability: "RAND".to_string(),
@@ -251,6 +326,7 @@ mod tests {
                         ty!(u8[2]),
                     ],
                     args: map! {},
+                    when: None,
                 }),
                 output: parse::Stage::Out(OutStage {
                     out: "SERIAL".to_string(),
@@ -259,8 +335,11 @@ mod tests {
                         "transform.0".parse().unwrap(),
                     ],
                     args: map! {},
+                    when: None,
                 })
             },
+            pipelines: Default::default(),
+            templates: Default::default(),
             resources: map! {},
         }
     }
@@ -317,4 +396,70 @@ mod tests {
             assert_eq!(input_tensor, output_tensor);
         }
     }
+
+    #[test]
+    fn conditions_are_attached_to_their_node() {
+        let mut doc = doc();
+        if let parse::Stage::Out(out) = doc.pipeline.get_mut("output").unwrap()
+        {
+            out.when = Some("rand > 0.5".parse().unwrap());
+        }
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(BuildContext::from_doc(doc.into()));
+        res.insert(NameTable::default());
+        crate::parse::phase().run(&mut world, &mut res);
+
+        Phase::new()
+            .and_then(lowering::register_names::run_system)
+            .and_then(lowering::update_nametable::run_system)
+            .and_then(lowering::register_stages::run_system)
+            .and_then(run_system)
+            .run(&mut world, &mut res);
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        assert!(diags.is_empty());
+        let names = res.get::<NameTable>().unwrap();
+        let output = names["output"];
+        let condition = <&Condition>::query().get(&world, output).unwrap();
+        assert_eq!(condition.comparison, parse::Comparison::GreaterThan);
+        assert_eq!(condition.value, 0.5);
+        let rand_outputs = <&Outputs>::query()
+            .get(&world, names["rand"])
+            .unwrap()
+            .clone();
+        assert_eq!(rand_outputs.tensors, vec![condition.tensor]);
+        let consumers =
+            <&Outputs>::query().get(&world, condition.tensor).unwrap();
+        assert!(consumers.tensors.contains(&output));
+    }
+
+    #[test]
+    fn unknown_inputs_point_at_the_input() {
+        let mut doc = doc();
+        let output = doc.pipeline.get_mut("output").unwrap();
+        output.inputs_mut().unwrap()[1] = "missing".parse().unwrap();
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(BuildContext::from_doc(doc.into()));
+        res.insert(NameTable::default());
+        crate::parse::phase().run(&mut world, &mut res);
+
+        Phase::new()
+            .and_then(lowering::register_names::run_system)
+            .and_then(lowering::update_nametable::run_system)
+            .and_then(run_system)
+            .run(&mut world, &mut res);
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        let diags: Vec<_> = diags.iter().collect();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "Unable to find \"missing\" to use as an input for \"output\""
+        );
+        let runefile = &res.get::<BuildContext>().unwrap().runefile;
+        let label = &diags[0].labels[0];
+        assert_eq!(&runefile[label.range.clone()], "missing");
+    }
 }
@@ -0,0 +1,1495 @@
+//! Expand a Runefile's `includes` and template instantiations into a single
+//! [`DocumentV1`] containing only ordinary stages.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use indexmap::IndexMap;
+
+use crate::{
+    parse::{
+        parse_failed_diagnostic, Argument, DocumentSpans, DocumentV1, Fragment,
+        Input, PipelineSpans, ResourceDeclaration, ResourceOrString, SourceMap,
+        Stage, StageSpans, Template, TemplateSpans, TemplateStage,
+    },
+    Diagnostics,
+};
+
+/// Load everything a Runefile includes and replace each [`TemplateStage`]
+/// with the stages from its [`Template`].
+///
+/// Stages from an instantiated template are prefixed with the name of the
+/// [`TemplateStage`] that instantiated them (e.g. the `fft` stage in a
+/// `preprocess` stage becomes `preprocess__fft`). A named pipeline which
+/// lists a [`TemplateStage`] will run every stage it was expanded into.
+///
+/// The `read` function is given paths relative to the Runefile's directory.
+pub(crate) fn expand(
+    doc: DocumentV1,
+    mut spans: DocumentSpans,
+    sources: &mut SourceMap,
+    diags: &mut Diagnostics,
+    read: impl FnMut(&Path) -> std::io::Result<String>,
+) -> (DocumentV1, DocumentSpans) {
+    let mut loader = Loader {
+        sources,
+        read,
+        seen: HashSet::new(),
+        fragments: Vec::new(),
+        diags: Vec::new(),
+    };
+    loader.load(&doc.includes, &spans.includes, Path::new(""));
+
+    let Loader {
+        fragments,
+        diags: mut reported,
+        ..
+    } = loader;
+
+    let DocumentV1 {
+        version,
+        image,
+        includes: _,
+        variables,
+        profiles,
+        pipeline,
+        pipelines,
+        templates,
+        resources,
+    } = doc;
+    // Note: only the Runefile can declare variables and named pipelines
+    let variable_spans = std::mem::take(&mut spans.variables);
+    let profile_spans = std::mem::take(&mut spans.profiles);
+    let mut pipeline_spans = std::mem::take(&mut spans.pipelines);
+    let runefile = Fragment {
+        includes: Vec::new(),
+        pipeline,
+        templates,
+        resources,
+    };
+
+    let mut merged = Merged::default();
+    for (fragment, spans) in fragments
+        .into_iter()
+        .chain(std::iter::once((runefile, spans)))
+    {
+        merged.add(fragment, spans, &mut reported);
+    }
+
+    let mut expander = Expander {
+        templates: &merged.templates,
+        template_spans: &merged.template_spans,
+        pipeline: IndexMap::new(),
+        spans: IndexMap::new(),
+        diags: reported,
+    };
+    let scope = Scope::new(String::new(), &merged.pipeline, None);
+    expander.expand(
+        &merged.pipeline,
+        &merged.stage_spans,
+        scope,
+        &mut Vec::new(),
+    );
+
+    let Expander {
+        pipeline,
+        spans: stage_spans,
+        diags: reported,
+        ..
+    } = expander;
+
+    for diag in reported {
+        diags.push(diag);
+    }
+
+    let pipelines = expand_pipelines(
+        pipelines,
+        &mut pipeline_spans,
+        &merged.pipeline,
+        &pipeline,
+    );
+
+    let doc = DocumentV1 {
+        version,
+        image,
+        includes: Vec::new(),
+        variables,
+        profiles,
+        pipeline,
+        pipelines,
+        templates: IndexMap::new(),
+        resources: merged.resources,
+    };
+    let spans = DocumentSpans {
+        includes: Vec::new(),
+        variables: variable_spans,
+        profiles: profile_spans,
+        stages: stage_spans,
+        pipelines: pipeline_spans,
+        templates: IndexMap::new(),
+        resources: merged.resource_spans,
+    };
+
+    (doc, spans)
+}
+
+/// Replace each template instantiation listed by a named pipeline with the
+/// stages it was expanded into.
+///
+/// The span of each replacement is the span of the instantiation's name, so
+/// the spans stay in sync with the list of stages.
+fn expand_pipelines(
+    pipelines: IndexMap<String, Vec<String>>,
+    spans: &mut IndexMap<String, PipelineSpans>,
+    original: &IndexMap<String, Stage>,
+    expanded: &IndexMap<String, Stage>,
+) -> IndexMap<String, Vec<String>> {
+    pipelines
+        .into_iter()
+        .map(|(name, stages)| {
+            let mut names = Vec::new();
+            let mut stage_spans = Vec::new();
+            let pipeline_spans = spans.get(&name).cloned().unwrap_or_default();
+
+            for (i, stage) in stages.into_iter().enumerate() {
+                let span = pipeline_spans
+                    .stages
+                    .get(i)
+                    .copied()
+                    .unwrap_or(pipeline_spans.name);
+
+                if let Some(Stage::Template(_)) = original.get(&stage) {
+                    let prefix = format!("{}__", stage);
+                    for instantiated in
+                        expanded.keys().filter(|s| s.starts_with(&prefix))
+                    {
+                        names.push(instantiated.clone());
+                        stage_spans.push(span);
+                    }
+                } else {
+                    names.push(stage);
+                    stage_spans.push(span);
+                }
+            }
+
+            if let Some(s) = spans.get_mut(&name) {
+                s.stages = stage_spans;
+            }
+
+            (name, names)
+        })
+        .collect()
+}
+
+/// Push a [`Diagnostic`], ignoring it if it has already been reported (e.g.
+/// because a broken template was instantiated multiple times).
+fn report(diags: &mut Vec<Diagnostic<()>>, diag: Diagnostic<()>) {
+    if !diags.contains(&diag) {
+        diags.push(diag);
+    }
+}
+
+struct Loader<'a, F> {
+    sources: &'a mut SourceMap,
+    read: F,
+    /// Every file we've loaded so far, so each file is only included once.
+    seen: HashSet<PathBuf>,
+    fragments: Vec<(Fragment, DocumentSpans)>,
+    diags: Vec<Diagnostic<()>>,
+}
+
+impl<'a, F> Loader<'a, F>
+where
+    F: FnMut(&Path) -> std::io::Result<String>,
+{
+    fn load(&mut self, includes: &[String], spans: &[Span], directory: &Path) {
+        for (i, include) in includes.iter().enumerate() {
+            let span = spans.get(i).copied().unwrap_or_default();
+            let path = normalize(&directory.join(include));
+
+            if !self.seen.insert(path.clone()) {
+                continue;
+            }
+
+            let src = match (self.read)(&path) {
+                Ok(src) => src,
+                Err(e) => {
+                    report(
+                        &mut self.diags,
+                        read_failed_diagnostic(&path, &e, span),
+                    );
+                    continue;
+                },
+            };
+
+            let offset = self.sources.add(path.display().to_string(), &*src);
+
+            let mut fragment: Fragment = match serde_yaml::from_str(&src) {
+                Ok(f) => f,
+                Err(e) => {
+                    let diag = parse_failed_diagnostic(&src, e);
+                    report(&mut self.diags, shift(diag, offset));
+                    continue;
+                },
+            };
+            let fragment_spans = DocumentSpans::parse_at(&src, offset)
+                .unwrap_or_else(|e| {
+                    log::warn!(
+                        "Unable to determine source locations for \"{}\": {}",
+                        path.display(),
+                        e
+                    );
+                    DocumentSpans::default()
+                });
+
+            let directory = path.parent().unwrap_or_else(|| Path::new(""));
+            make_paths_relative_to(&mut fragment, directory);
+
+            // Note: a file's includes are added before the file itself
+            let includes = std::mem::take(&mut fragment.includes);
+            self.load(&includes, &fragment_spans.includes, directory);
+
+            self.fragments.push((fragment, fragment_spans));
+        }
+    }
+}
+
+/// Resolve `.` and `..` without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir
+                if matches!(
+                    normalized.components().last(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            },
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+/// Paths in an included file are relative to that file, so we need to update
+/// them to be relative to the Runefile.
+fn make_paths_relative_to(fragment: &mut Fragment, directory: &Path) {
+    if directory.as_os_str().is_empty() {
+        return;
+    }
+
+    let relative = |path: &str| {
+        // Note: variables are substituted later on, so a path starting with
+        // one will usually be absolute or relative to the Runefile already
+        if Path::new(path).is_absolute() || path.starts_with("${") {
+            path.to_string()
+        } else {
+            normalize(&directory.join(path)).display().to_string()
+        }
+    };
+
+    let stages = fragment.pipeline.values_mut().chain(
+        fragment
+            .templates
+            .values_mut()
+            .flat_map(|t| t.pipeline.values_mut()),
+    );
+
+    for stage in stages {
+        match stage {
+            Stage::Model(m) => {
+                if let ResourceOrString::String(path) = &mut m.model {
+                    *path = relative(path);
+                }
+            },
+            // Note: codegen assumes proc-blocks starting with a "." are
+            // local paths
+            Stage::ProcBlock(p) if p.proc_block.base.starts_with('.') => {
+                let path = relative(&p.proc_block.base);
+                p.proc_block.base = if path.starts_with('.') {
+                    path
+                } else {
+                    format!("./{}", path)
+                };
+            },
+            _ => {},
+        }
+    }
+
+    for resource in fragment.resources.values_mut() {
+        if let Some(path) = &mut resource.path {
+            *path = relative(path);
+        }
+    }
+}
+
+/// The stages, templates, and resources from every file.
+#[derive(Default)]
+struct Merged {
+    pipeline: IndexMap<String, Stage>,
+    stage_spans: IndexMap<String, StageSpans>,
+    templates: IndexMap<String, Template>,
+    template_spans: IndexMap<String, TemplateSpans>,
+    resources: IndexMap<String, ResourceDeclaration>,
+    resource_spans: IndexMap<String, Span>,
+}
+
+impl Merged {
+    fn add(
+        &mut self,
+        fragment: Fragment,
+        spans: DocumentSpans,
+        diags: &mut Vec<Diagnostic<()>>,
+    ) {
+        let Fragment {
+            pipeline,
+            templates,
+            resources,
+            ..
+        } = fragment;
+
+        for (name, stage) in pipeline {
+            let span = spans.stages.get(&name).cloned().unwrap_or_default();
+
+            match self.stage_spans.get(&name) {
+                Some(original) => report(
+                    diags,
+                    duplicate_diagnostic(
+                        "stage",
+                        &name,
+                        original.name,
+                        span.name,
+                    ),
+                ),
+                None => {
+                    self.pipeline.insert(name.clone(), stage);
+                    self.stage_spans.insert(name, span);
+                },
+            }
+        }
+
+        for (name, template) in templates {
+            let span = spans.templates.get(&name).cloned().unwrap_or_default();
+
+            match self.template_spans.get(&name) {
+                Some(original) => report(
+                    diags,
+                    duplicate_diagnostic(
+                        "template",
+                        &name,
+                        original.name,
+                        span.name,
+                    ),
+                ),
+                None => {
+                    self.templates.insert(name.clone(), template);
+                    self.template_spans.insert(name, span);
+                },
+            }
+        }
+
+        for (name, resource) in resources {
+            let span = spans.resource(&name);
+
+            match self.resource_spans.get(&name) {
+                Some(&original) => report(
+                    diags,
+                    duplicate_diagnostic("resource", &name, original, span),
+                ),
+                None => {
+                    self.resources.insert(name.clone(), resource);
+                    self.resource_spans.insert(name, span);
+                },
+            }
+        }
+    }
+}
+
+/// What a name refers to within a pipeline.
+#[derive(Debug, Clone)]
+enum Binding {
+    /// An ordinary stage, with its (possibly prefixed) name.
+    Stage(String),
+    /// A template instantiation and the tensors it outputs.
+    Instance(Vec<Input>),
+    /// A template instantiation we weren't able to expand.
+    Broken,
+    /// One of the template's inputs.
+    Placeholder(Input),
+}
+
+/// Everything needed to resolve names within a pipeline.
+struct Scope {
+    /// The prefix added to every stage name.
+    prefix: String,
+    bindings: HashMap<String, Binding>,
+    /// The template being expanded, if any.
+    template: Option<String>,
+    /// Values for the template's parameters.
+    params: IndexMap<String, Argument>,
+}
+
+impl Scope {
+    fn new(
+        prefix: String,
+        pipeline: &IndexMap<String, Stage>,
+        template: Option<String>,
+    ) -> Self {
+        let bindings = pipeline
+            .iter()
+            .filter(|(_, stage)| !matches!(stage, Stage::Template(_)))
+            .map(|(name, _)| {
+                (name.clone(), Binding::Stage(format!("{}{}", prefix, name)))
+            })
+            .collect();
+
+        Scope {
+            prefix,
+            bindings,
+            template,
+            params: IndexMap::new(),
+        }
+    }
+
+    fn substitute(&self, value: &mut ResourceOrString) {
+        if let ResourceOrString::Resource(name) = value {
+            if let Some(Argument(replacement)) = self.params.get(name.as_str())
+            {
+                *value = replacement.clone();
+            }
+        }
+    }
+}
+
+struct Expander<'a> {
+    templates: &'a IndexMap<String, Template>,
+    template_spans: &'a IndexMap<String, TemplateSpans>,
+    pipeline: IndexMap<String, Stage>,
+    spans: IndexMap<String, StageSpans>,
+    diags: Vec<Diagnostic<()>>,
+}
+
+impl<'a> Expander<'a> {
+    /// Copy a pipeline's stages into the expanded pipeline, instantiating
+    /// any templates along the way.
+    fn expand(
+        &mut self,
+        pipeline: &IndexMap<String, Stage>,
+        spans: &IndexMap<String, StageSpans>,
+        mut scope: Scope,
+        stack: &mut Vec<String>,
+    ) {
+        // We need to know what every instance outputs before we can resolve
+        // anything's inputs
+        for (name, stage) in pipeline {
+            if let Stage::Template(t) = stage {
+                let binding = match self.instance_outputs(
+                    &t.template,
+                    field_span(spans, name, "template"),
+                    stack,
+                ) {
+                    Some(outputs) => Binding::Instance(
+                        outputs
+                            .into_iter()
+                            .map(|output| {
+                                Input::new(
+                                    format!(
+                                        "{}{}__{}",
+                                        scope.prefix, name, output.name
+                                    ),
+                                    output.index,
+                                )
+                            })
+                            .collect(),
+                    ),
+                    None => Binding::Broken,
+                };
+                scope.bindings.insert(name.clone(), binding);
+            }
+        }
+
+        for (name, stage) in pipeline {
+            let stage_spans = spans.get(name).cloned().unwrap_or_default();
+
+            match stage {
+                Stage::Template(t) => {
+                    self.instantiate(name, t, &stage_spans, &scope, stack)
+                },
+                _ => {
+                    let mut stage = stage.clone();
+                    self.resolve_stage(&mut stage, &stage_spans, &scope);
+
+                    let name = format!("{}{}", scope.prefix, name);
+                    self.pipeline.insert(name.clone(), stage);
+                    self.spans.insert(name, stage_spans);
+                },
+            }
+        }
+    }
+
+    fn resolve_stage(
+        &mut self,
+        stage: &mut Stage,
+        spans: &StageSpans,
+        scope: &Scope,
+    ) {
+        if let Stage::Model(m) = stage {
+            scope.substitute(&mut m.model);
+        }
+
+        for Argument(value) in
+            stage.args_mut().into_iter().flat_map(|a| a.values_mut())
+        {
+            scope.substitute(value);
+        }
+
+        if let Some(inputs) = stage.inputs_mut() {
+            for (i, input) in inputs.iter_mut().enumerate() {
+                let span = spans.inputs.get(i).copied().unwrap_or(spans.name);
+
+                if let Some(resolved) = self.resolve_input(input, span, scope) {
+                    *input = resolved;
+                }
+            }
+        }
+
+        if let Some(condition) = stage.condition_mut() {
+            let span = spans.fields.get("when").copied().unwrap_or(spans.name);
+
+            if let Some(resolved) =
+                self.resolve_input(&condition.input, span, scope)
+            {
+                condition.input = resolved;
+            }
+        }
+    }
+
+    /// Figure out which tensor an input refers to, returning `None` if it
+    /// should be left as-is.
+    fn resolve_input(
+        &mut self,
+        input: &Input,
+        span: Span,
+        scope: &Scope,
+    ) -> Option<Input> {
+        match scope.bindings.get(&input.name) {
+            Some(Binding::Stage(name)) => {
+                Some(Input::new(name.clone(), input.index))
+            },
+            Some(Binding::Instance(outputs)) => {
+                let index = input.index.unwrap_or(0);
+
+                match outputs.get(index) {
+                    Some(output) => Some(output.clone()),
+                    None => {
+                        report(
+                            &mut self.diags,
+                            missing_output_diagnostic(input, span),
+                        );
+                        None
+                    },
+                }
+            },
+            Some(Binding::Placeholder(_)) if input.index.is_some() => {
+                report(
+                    &mut self.diags,
+                    indexed_placeholder_diagnostic(input, span),
+                );
+                None
+            },
+            Some(Binding::Placeholder(bound)) => Some(bound.clone()),
+            Some(Binding::Broken) => None,
+            // Unknown names at the top level are reported while lowering
+            None => {
+                if let Some(template) = &scope.template {
+                    report(
+                        &mut self.diags,
+                        unknown_input_diagnostic(template, input, span),
+                    );
+                }
+                None
+            },
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        name: &str,
+        stage: &TemplateStage,
+        spans: &StageSpans,
+        scope: &Scope,
+        stack: &mut Vec<String>,
+    ) {
+        let templates = self.templates;
+        let template = match templates.get(&stage.template) {
+            Some(t) => t,
+            // Already reported by instance_outputs()
+            None => return,
+        };
+
+        if stack.contains(&stage.template) {
+            // Also reported by instance_outputs()
+            return;
+        }
+
+        if stage.inputs.len() != template.inputs.len() {
+            report(
+                &mut self.diags,
+                input_count_diagnostic(name, stage, template, spans.name),
+            );
+            return;
+        }
+
+        let mut inner = Scope::new(
+            format!("{}{}__", scope.prefix, name),
+            &template.pipeline,
+            Some(stage.template.clone()),
+        );
+
+        for (i, (placeholder, input)) in
+            template.inputs.iter().zip(&stage.inputs).enumerate()
+        {
+            let span = spans.inputs.get(i).copied().unwrap_or(spans.name);
+            let bound = self
+                .resolve_input(input, span, scope)
+                .unwrap_or_else(|| input.clone());
+            inner
+                .bindings
+                .insert(placeholder.clone(), Binding::Placeholder(bound));
+        }
+
+        for key in stage.args.keys() {
+            if !template.args.contains_key(key) {
+                let span = spans.args.get(key).copied().unwrap_or(spans.name);
+                report(
+                    &mut self.diags,
+                    unknown_argument_diagnostic(
+                        name, stage, template, key, span,
+                    ),
+                );
+            }
+        }
+
+        for (key, default) in &template.args {
+            let mut value = match stage.args.get(key).or(default.as_ref()) {
+                Some(value) => value.clone(),
+                None => {
+                    report(
+                        &mut self.diags,
+                        missing_argument_diagnostic(
+                            name,
+                            &stage.template,
+                            key,
+                            spans.name,
+                        ),
+                    );
+                    continue;
+                },
+            };
+
+            // Defaults may only refer to resources, but values passed in by
+            // the caller are evaluated in the caller's scope
+            if stage.args.contains_key(key) {
+                scope.substitute(&mut value.0);
+            }
+            inner.params.insert(key.clone(), value);
+        }
+
+        let template_spans = self
+            .template_spans
+            .get(&stage.template)
+            .map(|t| t.stages.clone())
+            .unwrap_or_default();
+
+        stack.push(stage.template.clone());
+        self.expand(&template.pipeline, &template_spans, inner, stack);
+        stack.pop();
+    }
+
+    /// Figure out which of a template's stages provide its outputs, returning
+    /// names relative to the instance (i.e. without the instance's prefix).
+    fn instance_outputs(
+        &mut self,
+        template_name: &str,
+        span: Span,
+        stack: &mut Vec<String>,
+    ) -> Option<Vec<Input>> {
+        let templates = self.templates;
+        let template = match templates.get(template_name) {
+            Some(t) => t,
+            None => {
+                report(
+                    &mut self.diags,
+                    unknown_template_diagnostic(template_name, templates, span),
+                );
+                return None;
+            },
+        };
+
+        if stack.iter().any(|t| t == template_name) {
+            report(
+                &mut self.diags,
+                recursive_template_diagnostic(template_name, stack, span),
+            );
+            return None;
+        }
+
+        let spans = self.template_spans.get(template_name);
+        let mut outputs = Vec::new();
+
+        stack.push(template_name.to_string());
+
+        for (i, output) in template.outputs.iter().enumerate() {
+            let output_span = spans
+                .and_then(|s| s.outputs.get(i).copied())
+                .unwrap_or_default();
+
+            match template.pipeline.get(&output.name) {
+                Some(Stage::Template(nested)) => {
+                    let nested_span = spans
+                        .and_then(|s| s.stages.get(&output.name))
+                        .and_then(|s| s.fields.get("template").copied())
+                        .unwrap_or(output_span);
+                    let nested_outputs = self.instance_outputs(
+                        &nested.template,
+                        nested_span,
+                        stack,
+                    );
+
+                    match nested_outputs {
+                        Some(nested_outputs) => {
+                            match nested_outputs.get(output.index.unwrap_or(0))
+                            {
+                                Some(o) => outputs.push(Input::new(
+                                    format!("{}__{}", output.name, o.name),
+                                    o.index,
+                                )),
+                                None => report(
+                                    &mut self.diags,
+                                    missing_output_diagnostic(
+                                        output,
+                                        output_span,
+                                    ),
+                                ),
+                            }
+                        },
+                        None => {
+                            stack.pop();
+                            return None;
+                        },
+                    }
+                },
+                Some(_) => outputs.push(output.clone()),
+                None => report(
+                    &mut self.diags,
+                    invalid_output_diagnostic(
+                        template_name,
+                        output,
+                        output_span,
+                    ),
+                ),
+            }
+        }
+
+        stack.pop();
+
+        Some(outputs)
+    }
+}
+
+fn field_span(
+    spans: &IndexMap<String, StageSpans>,
+    stage: &str,
+    field: &str,
+) -> Span {
+    spans
+        .get(stage)
+        .map(|s| s.fields.get(field).copied().unwrap_or(s.name))
+        .unwrap_or_default()
+}
+
+/// Move all of a [`Diagnostic`]'s labels `offset` bytes further into the
+/// [`SourceMap`].
+fn shift(mut diag: Diagnostic<()>, offset: usize) -> Diagnostic<()> {
+    for label in &mut diag.labels {
+        label.range = label.range.start + offset..label.range.end + offset;
+    }
+
+    diag
+}
+
+fn read_failed_diagnostic(
+    path: &Path,
+    error: &std::io::Error,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!("Unable to read \"{}\"", path.display()))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![error.to_string()])
+}
+
+fn duplicate_diagnostic(
+    kind: &str,
+    name: &str,
+    original: Span,
+    duplicate: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" {} is defined more than once",
+            name, kind
+        ))
+        .with_labels(vec![
+            Label::primary((), duplicate),
+            Label::secondary((), original).with_message("first defined here"),
+        ])
+}
+
+fn unknown_template_diagnostic(
+    name: &str,
+    templates: &IndexMap<String, Template>,
+    span: Span,
+) -> Diagnostic<()> {
+    let hint = if templates.is_empty() {
+        "hint: templates are defined in the \"templates\" section".to_string()
+    } else {
+        let names: Vec<_> = templates.keys().map(|s| s.as_str()).collect();
+        format!("hint: the known templates are {}", names.join(", "))
+    };
+
+    Diagnostic::error()
+        .with_message(format!("There is no template called \"{}\"", name))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![hint])
+}
+
+fn recursive_template_diagnostic(
+    name: &str,
+    stack: &[String],
+    span: Span,
+) -> Diagnostic<()> {
+    let chain: Vec<_> = stack
+        .iter()
+        .skip_while(|t| *t != name)
+        .map(|t| t.as_str())
+        .chain(std::iter::once(name))
+        .collect();
+
+    Diagnostic::error()
+        .with_message(format!("The \"{}\" template instantiates itself", name))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![chain.join(" -> ")])
+}
+
+fn input_count_diagnostic(
+    name: &str,
+    stage: &TemplateStage,
+    template: &Template,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" template expects {} inputs, but \"{}\" passes {}",
+            stage.template,
+            template.inputs.len(),
+            name,
+            stage.inputs.len(),
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn unknown_argument_diagnostic(
+    name: &str,
+    stage: &TemplateStage,
+    template: &Template,
+    key: &str,
+    span: Span,
+) -> Diagnostic<()> {
+    let hint = if template.args.is_empty() {
+        format!(
+            "hint: the \"{}\" template doesn't accept any arguments",
+            stage.template
+        )
+    } else {
+        let names: Vec<_> = template.args.keys().map(|s| s.as_str()).collect();
+        format!(
+            "hint: the \"{}\" template accepts {}",
+            stage.template,
+            names.join(", ")
+        )
+    };
+
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage passes an unknown \"{}\" argument to the \"{}\" \
+             template",
+            name, key, stage.template
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![hint])
+}
+
+fn missing_argument_diagnostic(
+    name: &str,
+    template: &str,
+    key: &str,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage is missing the \"{}\" argument required by the \
+             \"{}\" template",
+            name, key, template
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn missing_output_diagnostic(input: &Input, span: Span) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" node has no {}'th output",
+            input.name,
+            input.index.unwrap_or(0)
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn indexed_placeholder_diagnostic(input: &Input, span: Span) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" template input can't be indexed",
+            input.name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![format!("hint: use \"{}\" instead", input.name)])
+}
+
+fn unknown_input_diagnostic(
+    template: &str,
+    input: &Input,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" template has no stage or input called \"{}\"",
+            template, input.name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn invalid_output_diagnostic(
+    template: &str,
+    output: &Input,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" template outputs \"{}\", but it has no stage with \
+             that name",
+            template, output
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use codespan_reporting::files::Files;
+
+    use super::*;
+    use crate::parse::Document;
+
+    struct Expanded {
+        doc: DocumentV1,
+        sources: SourceMap,
+        diags: Vec<Diagnostic<()>>,
+    }
+
+    impl Expanded {
+        fn stage_names(&self) -> Vec<&str> {
+            self.doc.pipeline.keys().map(|s| s.as_str()).collect()
+        }
+
+        fn inputs(&self, stage: &str) -> Vec<String> {
+            self.doc.pipeline[stage]
+                .inputs()
+                .iter()
+                .map(|i| i.to_string())
+                .collect()
+        }
+
+        fn messages(&self) -> Vec<&str> {
+            self.diags.iter().map(|d| d.message.as_str()).collect()
+        }
+    }
+
+    fn expand_files(runefile: &str, files: &[(&str, &str)]) -> Expanded {
+        let doc = Document::parse(runefile).unwrap().to_v1();
+        let spans = DocumentSpans::parse(runefile).unwrap();
+        let mut sources = SourceMap::new("Runefile.yml", runefile);
+        let mut diags = Diagnostics::new();
+        let files: HashMap<PathBuf, &str> =
+            files.iter().map(|&(k, v)| (PathBuf::from(k), v)).collect();
+
+        let (doc, _) = expand(doc, spans, &mut sources, &mut diags, |path| {
+            files
+                .get(path)
+                .map(|s| s.to_string())
+                .ok_or_else(|| ErrorKind::NotFound.into())
+        });
+
+        Expanded {
+            doc,
+            sources,
+            diags: diags.into_iter().collect(),
+        }
+    }
+
+    const TEMPLATES: &str = r#"
+templates:
+  noise:
+    args:
+      amount: 1
+    outputs: [rand]
+    pipeline:
+      rand:
+        capability: RAND
+        outputs: [{type: f32, dimensions: [1]}]
+        args:
+          amount: $amount
+  scale:
+    inputs: [input]
+    args:
+      factor: ~
+    outputs: [mul, mul.1]
+    pipeline:
+      mul:
+        proc-block: "./multiply"
+        inputs: [input]
+        outputs: [{type: f32, dimensions: [1]}, {type: f32, dimensions: [1]}]
+        args:
+          factor: $factor
+          model: $MODEL
+"#;
+
+    #[test]
+    fn instantiate_templates() {
+        let runefile = format!(
+            r#"
+version: 1
+image: runicos/base
+pipeline:
+  input:
+    template: noise
+    args:
+      amount: 4
+  scaled:
+    template: scale
+    inputs: [input]
+    args:
+      factor: $FACTOR
+  serial:
+    out: SERIAL
+    inputs: [scaled, scaled.1]
+{}"#,
+            TEMPLATES
+        );
+
+        let got = expand_files(&runefile, &[]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        assert_eq!(
+            got.stage_names(),
+            vec!["input__rand", "scaled__mul", "serial"]
+        );
+        assert_eq!(got.inputs("scaled__mul"), vec!["input__rand"]);
+        assert_eq!(got.inputs("serial"), vec!["scaled__mul", "scaled__mul.1"]);
+        let rand_args = got.doc.pipeline["input__rand"].args();
+        assert_eq!(rand_args["amount"], Argument::from("4"));
+        let mul_args = got.doc.pipeline["scaled__mul"].args();
+        assert_eq!(mul_args["factor"].to_string(), "$FACTOR");
+        assert_eq!(mul_args["model"].to_string(), "$MODEL");
+        assert!(got.doc.templates.is_empty());
+    }
+
+    #[test]
+    fn conditions_can_use_template_outputs() {
+        let runefile = format!(
+            r#"
+version: 1
+image: runicos/base
+pipeline:
+  input:
+    template: noise
+  scaled:
+    template: scale
+    inputs: [input]
+    args:
+      factor: 2
+  serial:
+    out: SERIAL
+    inputs: [scaled]
+    when: "scaled.1 > 0.5"
+{}"#,
+            TEMPLATES
+        );
+
+        let got = expand_files(&runefile, &[]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        let condition = got.doc.pipeline["serial"].condition().unwrap();
+        assert_eq!(condition.to_string(), "scaled__mul.1 > 0.5");
+    }
+
+    #[test]
+    fn named_pipelines_can_list_template_instances() {
+        let runefile = format!(
+            r#"
+version: 1
+image: runicos/base
+pipeline:
+  input:
+    template: noise
+  scaled:
+    template: scale
+    inputs: [input]
+    args:
+      factor: 2
+  serial:
+    out: SERIAL
+    inputs: [scaled]
+pipelines:
+  fast: [scaled, serial]
+{}"#,
+            TEMPLATES
+        );
+
+        let got = expand_files(&runefile, &[]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        assert_eq!(got.doc.pipelines["fast"], vec!["scaled__mul", "serial"]);
+    }
+
+    #[test]
+    fn nested_templates() {
+        let runefile = format!(
+            r#"
+version: 1
+image: runicos/base
+pipeline:
+  outer:
+    template: wrapper
+  serial:
+    out: SERIAL
+    inputs: [outer]
+{}
+  wrapper:
+    outputs: [inner]
+    pipeline:
+      inner:
+        template: noise
+        args:
+          amount: 2
+"#,
+            TEMPLATES
+        );
+
+        let got = expand_files(&runefile, &[]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        assert_eq!(got.stage_names(), vec!["outer__inner__rand", "serial"]);
+        assert_eq!(got.inputs("serial"), vec!["outer__inner__rand"]);
+    }
+
+    #[test]
+    fn load_included_files() {
+        let runefile = r#"
+version: 1
+image: runicos/base
+includes: [common/templates.yml]
+pipeline:
+  input:
+    template: noise
+  serial:
+    out: SERIAL
+    inputs: [input]
+"#;
+        let common = format!(
+            "includes: [../shared.yml]\n{}\nresources:\n  WORDS:\n    path: \
+             ./words.txt\n",
+            TEMPLATES
+        );
+        let shared = r#"
+pipeline:
+  sine:
+    model: ./sine.tflite
+    inputs: [input]
+    outputs: [{type: f32, dimensions: [1]}]
+"#;
+
+        let got = expand_files(
+            runefile,
+            &[("common/templates.yml", &common), ("shared.yml", shared)],
+        );
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        assert_eq!(got.stage_names(), vec!["sine", "input__rand", "serial"]);
+        assert_eq!(
+            got.doc.resources["WORDS"].path.as_deref(),
+            Some("common/words.txt")
+        );
+        assert_eq!(got.inputs("sine"), vec!["input__rand"]);
+        assert_eq!(got.sources.name(1).unwrap(), "common/templates.yml");
+        assert_eq!(got.sources.name(2).unwrap(), "shared.yml");
+    }
+
+    #[test]
+    fn paths_in_included_templates_are_rewritten() {
+        let runefile = r#"
+version: 1
+image: runicos/base
+includes: [common/templates.yml]
+pipeline:
+  input:
+    template: noise
+  scaled:
+    template: scale
+    inputs: [input]
+    args:
+      factor: 2
+"#;
+
+        let got =
+            expand_files(runefile, &[("common/templates.yml", TEMPLATES)]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        match &got.doc.pipeline["scaled__mul"] {
+            Stage::ProcBlock(p) => {
+                assert_eq!(p.proc_block.base, "./common/multiply")
+            },
+            other => panic!("Expected a proc-block, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_included_file() {
+        let runefile = r#"
+version: 1
+image: runicos/base
+includes: [common.yml]
+pipeline:
+  input:
+    template: noise
+"#;
+        let common =
+            "templates:\n  noise:\n    outputs: [rnd]\n    pipeline: {}\n";
+
+        let got = expand_files(runefile, &[("common.yml", common)]);
+
+        assert_eq!(
+            got.messages(),
+            vec![
+                "The \"noise\" template outputs \"rnd\", but it has no stage \
+                 with that name"
+            ]
+        );
+        let (file_id, range) =
+            got.sources.locate(got.diags[0].labels[0].range.clone());
+        assert_eq!(got.sources.name(file_id).unwrap(), "common.yml");
+        assert_eq!(&common[range], "rnd");
+    }
+
+    #[test]
+    fn unknown_template() {
+        let runefile = format!(
+            "version: 1\nimage: runicos/base\npipeline:\n  input:\n    \
+             template: nose\n{}",
+            TEMPLATES
+        );
+
+        let got = expand_files(&runefile, &[]);
+
+        assert_eq!(
+            got.messages(),
+            vec!["There is no template called \"nose\""]
+        );
+        assert_eq!(
+            got.diags[0].notes,
+            vec!["hint: the known templates are noise, scale"]
+        );
+    }
+
+    #[test]
+    fn bad_template_arguments() {
+        let runefile = format!(
+            r#"
+version: 1
+image: runicos/base
+pipeline:
+  input:
+    template: noise
+    args:
+      seed: 42
+  scaled:
+    template: scale
+    inputs: [input]
+{}"#,
+            TEMPLATES
+        );
+
+        let got = expand_files(&runefile, &[]);
+
+        assert_eq!(
+            got.messages(),
+            vec![
+                "The \"input\" stage passes an unknown \"seed\" argument to \
+                 the \"noise\" template",
+                "The \"scaled\" stage is missing the \"factor\" argument \
+                 required by the \"scale\" template",
+            ]
+        );
+        assert_eq!(
+            got.diags[0].notes,
+            vec!["hint: the \"noise\" template accepts amount"]
+        );
+    }
+
+    #[test]
+    fn wrong_number_of_inputs() {
+        let runefile = format!(
+            "version: 1\nimage: runicos/base\npipeline:\n  scaled:\n    \
+             template: scale\n    args: {{factor: 2}}\n{}",
+            TEMPLATES
+        );
+
+        let got = expand_files(&runefile, &[]);
+
+        assert_eq!(
+            got.messages(),
+            vec![
+                "The \"scale\" template expects 1 inputs, but \"scaled\" \
+                 passes 0"
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_names_inside_a_template() {
+        let runefile = r#"
+version: 1
+image: runicos/base
+pipeline:
+  first:
+    template: broken
+  second:
+    template: broken
+templates:
+  broken:
+    pipeline:
+      serial:
+        out: SERIAL
+        inputs: [nonexistent]
+"#;
+
+        let got = expand_files(runefile, &[]);
+
+        // Note: the error is only reported once, even though the template
+        // was instantiated twice
+        assert_eq!(
+            got.messages(),
+            vec![
+                "The \"broken\" template has no stage or input called \
+                 \"nonexistent\""
+            ]
+        );
+    }
+
+    #[test]
+    fn templates_cant_instantiate_themselves() {
+        let runefile = r#"
+version: 1
+image: runicos/base
+pipeline:
+  input:
+    template: forever
+templates:
+  forever:
+    outputs: [again]
+    pipeline:
+      again:
+        template: forever
+"#;
+
+        let got = expand_files(runefile, &[]);
+
+        assert_eq!(
+            got.messages(),
+            vec!["The \"forever\" template instantiates itself"]
+        );
+        assert_eq!(got.diags[0].notes, vec!["forever -> forever"]);
+    }
+
+    #[test]
+    fn duplicate_definitions() {
+        let runefile = r#"
+version: 1
+image: runicos/base
+includes: [common.yml]
+pipeline:
+  input:
+    capability: RAND
+resources:
+  MODEL:
+    path: ./model.tflite
+"#;
+        let common = "pipeline:\n  input:\n    capability: RAND\n";
+
+        let got = expand_files(runefile, &[("common.yml", common)]);
+
+        assert_eq!(
+            got.messages(),
+            vec!["The \"input\" stage is defined more than once"]
+        );
+        let labels: Vec<_> = got.diags[0]
+            .labels
+            .iter()
+            .map(|l| got.sources.locate(l.range.clone()).0)
+            .collect();
+        assert_eq!(labels, vec![0, 1]);
+    }
+
+    #[test]
+    fn missing_include() {
+        let runefile = "version: 1\nimage: runicos/base\nincludes: \
+                        [missing.yml]\npipeline: {}\n";
+
+        let got = expand_files(runefile, &[]);
+
+        assert_eq!(got.messages(), vec!["Unable to read \"missing.yml\""]);
+    }
+
+    #[test]
+    fn files_are_only_included_once() {
+        let runefile = "version: 1\nimage: runicos/base\nincludes: [a.yml, \
+                        b.yml]\npipeline: {}\n";
+        let a = "includes: [b.yml]\nresources:\n  A:\n    inline: a\n";
+        let b = "includes: [./a.yml]\nresources:\n  B:\n    inline: b\n";
+
+        let got = expand_files(runefile, &[("a.yml", a), ("b.yml", b)]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        let resources: Vec<_> = got.doc.resources.keys().collect();
+        assert_eq!(resources, vec!["B", "A"]);
+    }
+}
@@ -0,0 +1,1777 @@
+//! A formatter which rewrites a Runefile in a canonical form.
+//!
+//! The canonical form uses a fixed key order for the document and each stage,
+//! inline dimension lists, lowercase element types, and the names the runtime
+//! uses for capabilities and outputs. Stage and resource order is preserved,
+//! as are any comments.
+
+use std::{borrow::Cow, fmt::Display};
+
+use hotg_rune_core::ElementType;
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{
+    lowering::{SinkKind, SourceKind},
+    parse::{
+        upgrade, Argument, CapabilityStage, Condition, DelayStage, Document,
+        DocumentV1, DocumentV2, Image, Input, ModelStage, OutStage,
+        ProcBlockStage, ResourceDeclaration, ResourceOrString, ResourceType,
+        Stage, StageKind, StageV2, Template, TemplateStage, Type, UpgradeError,
+    },
+};
+
+/// Parse a Runefile and rewrite it in the canonical form.
+pub fn format_runefile(src: &str) -> Result<String, serde_yaml::Error> {
+    let doc = Document::parse(src)?;
+    let comments = Comments::extract(src);
+
+    let mut printer = Printer::new(comments);
+    printer.document(&doc);
+
+    Ok(printer.finish())
+}
+
+/// Parse a Runefile, [`upgrade()`] it to version 2 if necessary, and write it
+/// in the canonical form.
+pub fn upgrade_runefile(src: &str) -> Result<String, UpgradeError> {
+    let doc = match Document::parse(src)? {
+        Document::V1(v1) => Document::V2(upgrade(v1)?),
+        v2 => v2,
+    };
+    let comments = Comments::extract(src);
+
+    let mut printer = Printer::new(comments);
+    printer.document(&doc);
+
+    Ok(printer.finish())
+}
+
+type Path = Vec<String>;
+
+fn path(segments: &[&str]) -> Path {
+    segments.iter().map(|s| s.to_string()).collect()
+}
+
+fn child(parent: &[String], segment: impl Into<String>) -> Path {
+    let mut path = parent.to_vec();
+    path.push(segment.into());
+    path
+}
+
+/// The comments in a Runefile, keyed by the "path" to the item they are
+/// attached to (e.g. `["pipeline", "audio", "args", "hz"]`).
+#[derive(Debug, Default, PartialEq)]
+struct Comments {
+    /// Comments at the very top of the file.
+    header: Vec<String>,
+    /// Comments on the lines immediately before an item.
+    leading: IndexMap<Path, Vec<String>>,
+    /// A comment at the end of an item's line.
+    trailing: IndexMap<Path, String>,
+    /// Comments after the last item in the file.
+    footer: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Frame {
+    indent: usize,
+    segment: String,
+    is_item: bool,
+}
+
+impl Comments {
+    /// Do a line-based scan over the Runefile's source text, figuring out
+    /// which item each comment belongs to.
+    ///
+    /// This only understands the subset of YAML used by Runefiles (block
+    /// mappings and sequences, flow collections on a single line, and block
+    /// scalars), which is enough to keep comments close to where the user
+    /// wrote them.
+    fn extract(src: &str) -> Self {
+        let mut comments = Comments::default();
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut item_counts: IndexMap<Path, usize> = IndexMap::new();
+        let mut pending: Vec<String> = Vec::new();
+        let mut seen_first_item = false;
+        let mut block_scalar_indent = None;
+
+        for line in src.lines() {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+
+            if let Some(parent_indent) = block_scalar_indent {
+                if trimmed.is_empty() || indent > parent_indent {
+                    // this line is part of a multi-line string
+                    continue;
+                }
+                block_scalar_indent = None;
+            }
+
+            if trimmed.is_empty() || trimmed == "---" {
+                continue;
+            }
+
+            if trimmed.starts_with('#') {
+                pending.push(trimmed.trim_end().to_string());
+                continue;
+            }
+
+            let (content, trailing) = split_trailing_comment(trimmed);
+
+            let (path, value, key_indent) = match push_item(
+                &mut stack,
+                &mut item_counts,
+                indent,
+                content,
+            ) {
+                Some(item) => item,
+                // Probably a continuation line (e.g. a flow sequence
+                // split across lines), keep the comments for the next
+                // item.
+                None => continue,
+            };
+
+            if value.starts_with('|') || value.starts_with('>') {
+                block_scalar_indent = Some(key_indent);
+            }
+
+            if !pending.is_empty() {
+                let pending = std::mem::take(&mut pending);
+
+                if seen_first_item {
+                    comments
+                        .leading
+                        .entry(path.clone())
+                        .or_default()
+                        .extend(pending);
+                } else {
+                    comments.header.extend(pending);
+                }
+            }
+
+            if let Some(trailing) = trailing {
+                comments.trailing.insert(path, trailing.to_string());
+            }
+
+            seen_first_item = true;
+        }
+
+        comments.footer = pending;
+
+        comments
+    }
+
+    fn take_leading(&mut self, path: &[String]) -> Vec<String> {
+        self.leading.shift_remove(path).unwrap_or_default()
+    }
+
+    fn take_trailing(&mut self, path: &[String]) -> Option<String> {
+        self.trailing.shift_remove(path)
+    }
+
+    /// Is there a comment attached to something nested inside `path`?
+    fn has_descendants(&self, path: &[String]) -> bool {
+        let is_descendant =
+            |p: &Path| p.len() > path.len() && p.starts_with(path);
+
+        self.leading.keys().any(is_descendant)
+            || self.trailing.keys().any(is_descendant)
+    }
+}
+
+/// Update the stack of parent items based on the current line, returning the
+/// path to the item on this line, its value, and the indentation used by its
+/// key.
+fn push_item<'a>(
+    stack: &mut Vec<Frame>,
+    item_counts: &mut IndexMap<Path, usize>,
+    indent: usize,
+    content: &'a str,
+) -> Option<(Path, &'a str, usize)> {
+    if content == "-" || content.starts_with("- ") {
+        // A sequence item. Note that sequences are allowed to have the same
+        // indentation as their parent key.
+        while let Some(top) = stack.last() {
+            if top.indent > indent || (top.indent == indent && top.is_item) {
+                stack.pop();
+            } else {
+                break;
+            }
+        }
+
+        let parent: Path = stack.iter().map(|f| f.segment.clone()).collect();
+        let count = item_counts.entry(parent).or_default();
+        let index = *count;
+        *count += 1;
+
+        stack.push(Frame {
+            indent,
+            segment: index.to_string(),
+            is_item: true,
+        });
+
+        let rest = content[1..].trim_start();
+        let rest_indent = indent + (content.len() - rest.len());
+
+        match split_key(rest) {
+            Some((key, value)) => {
+                stack.push(Frame {
+                    indent: rest_indent,
+                    segment: key.to_string(),
+                    is_item: false,
+                });
+                Some((current_path(stack), value, rest_indent))
+            },
+            None => Some((current_path(stack), rest, indent)),
+        }
+    } else {
+        let (key, value) = split_key(content)?;
+
+        while let Some(top) = stack.last() {
+            if top.indent >= indent {
+                stack.pop();
+            } else {
+                break;
+            }
+        }
+
+        stack.push(Frame {
+            indent,
+            segment: key.to_string(),
+            is_item: false,
+        });
+
+        Some((current_path(stack), value, indent))
+    }
+}
+
+fn current_path(stack: &[Frame]) -> Path {
+    stack.iter().map(|f| f.segment.clone()).collect()
+}
+
+static KEY_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?x)
+        ^(?:
+            "(?P<double>[^"]*)"
+            | '(?P<single>[^']*)'
+            | (?P<plain>[^\s"'\#\[\{\-][^\#]*?)
+        )
+        \s*:(?:\s+(?P<value>.*)|\s*)$
+        "#,
+    )
+    .unwrap()
+});
+
+/// Split a `key: value` line into its key and value.
+fn split_key(line: &str) -> Option<(&str, &str)> {
+    let captures = KEY_PATTERN.captures(line)?;
+
+    let key = captures
+        .name("double")
+        .or_else(|| captures.name("single"))
+        .or_else(|| captures.name("plain"))?
+        .as_str();
+    let value = captures.name("value").map(|m| m.as_str()).unwrap_or("");
+
+    Some((key, value))
+}
+
+/// Split a line into its content and a trailing comment (if there is one).
+fn split_trailing_comment(line: &str) -> (&str, Option<&str>) {
+    let mut in_single_quotes = false;
+    let mut in_double_quotes = false;
+    let mut previous = ' ';
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '\'' if !in_double_quotes => in_single_quotes = !in_single_quotes,
+            '"' if !in_single_quotes && previous != '\\' => {
+                in_double_quotes = !in_double_quotes
+            },
+            '#' if !in_single_quotes
+                && !in_double_quotes
+                && previous.is_whitespace() =>
+            {
+                return (line[..i].trim_end(), Some(line[i..].trim_end()));
+            },
+            _ => {},
+        }
+
+        previous = c;
+    }
+
+    (line.trim_end(), None)
+}
+
+const INDENT: usize = 2;
+
+struct Printer {
+    buffer: String,
+    comments: Comments,
+}
+
+impl Printer {
+    fn new(comments: Comments) -> Self {
+        Printer {
+            buffer: String::new(),
+            comments,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        // Make sure comments we couldn't find a home for aren't lost.
+        let mut leftovers = Vec::new();
+        for (_, comments) in self.comments.leading.drain(..) {
+            leftovers.extend(comments);
+        }
+        leftovers.extend(self.comments.trailing.drain(..).map(|(_, c)| c));
+        leftovers.append(&mut self.comments.footer);
+
+        if !leftovers.is_empty() {
+            self.blank_line();
+            for comment in leftovers {
+                self.buffer.push_str(&comment);
+                self.buffer.push('\n');
+            }
+        }
+
+        self.buffer
+    }
+
+    fn blank_line(&mut self) {
+        if !self.buffer.is_empty() && !self.buffer.ends_with("\n\n") {
+            self.buffer.push('\n');
+        }
+    }
+
+    /// Write a line of text, including any comments attached to the items
+    /// at `paths`.
+    fn line(&mut self, indent: usize, paths: &[Path], text: &str) {
+        let mut trailing = Vec::new();
+
+        for path in paths {
+            for comment in self.comments.take_leading(path) {
+                self.write_indented(indent, &comment);
+            }
+            trailing.extend(self.comments.take_trailing(path));
+        }
+
+        let mut text = text.to_string();
+        for comment in trailing {
+            text.push(' ');
+            text.push_str(&comment);
+        }
+
+        self.write_indented(indent, &text);
+    }
+
+    fn write_indented(&mut self, indent: usize, text: &str) {
+        for _ in 0..indent {
+            self.buffer.push(' ');
+        }
+        self.buffer.push_str(text);
+        self.buffer.push('\n');
+    }
+
+    fn document(&mut self, doc: &Document) {
+        for comment in std::mem::take(&mut self.comments.header) {
+            self.write_indented(0, &comment);
+        }
+
+        match doc {
+            Document::V1(v1) => self.document_v1(v1),
+            Document::V2(v2) => self.document_v2(v2),
+        }
+    }
+
+    fn document_v1(&mut self, doc: &DocumentV1) {
+        let DocumentV1 {
+            version,
+            image,
+            includes,
+            variables,
+            profiles,
+            pipeline,
+            pipelines,
+            templates,
+            resources,
+        } = doc;
+
+        self.version_and_image(*version, image);
+
+        if !includes.is_empty() {
+            self.blank_line();
+            self.list(0, &[], "includes", includes);
+        }
+
+        self.variables_and_profiles(variables, profiles);
+
+        self.blank_line();
+        self.pipeline(0, &[], pipeline);
+        self.named_pipelines(pipelines);
+
+        if !templates.is_empty() {
+            self.blank_line();
+            let templates_path = path(&["templates"]);
+            self.line(0, &[templates_path.clone()], "templates:");
+
+            for (i, (name, template)) in templates.iter().enumerate() {
+                if i > 0 {
+                    self.blank_line();
+                }
+                self.template(
+                    &child(&templates_path, name.as_str()),
+                    name,
+                    template,
+                );
+            }
+        }
+
+        self.resources(resources);
+    }
+
+    fn document_v2(&mut self, doc: &DocumentV2) {
+        let DocumentV2 {
+            version,
+            image,
+            variables,
+            profiles,
+            pipeline,
+            pipelines,
+            resources,
+        } = doc;
+
+        self.version_and_image(*version, image);
+        self.variables_and_profiles(variables, profiles);
+
+        self.blank_line();
+        let pipeline_path = path(&["pipeline"]);
+
+        if pipeline.is_empty() {
+            self.line(0, &[pipeline_path], "pipeline: {}");
+        } else {
+            self.line(0, &[pipeline_path.clone()], "pipeline:");
+
+            for (i, (name, stage)) in pipeline.iter().enumerate() {
+                if i > 0 {
+                    self.blank_line();
+                }
+                self.stage_v2(
+                    &child(&pipeline_path, name.as_str()),
+                    name,
+                    stage,
+                );
+            }
+        }
+
+        self.named_pipelines(pipelines);
+        self.resources(resources);
+    }
+
+    fn version_and_image(&mut self, version: usize, image: &Image) {
+        self.line(0, &[path(&["version"])], &format!("version: {}", version));
+        self.line(
+            0,
+            &[path(&["image"])],
+            &format!("image: {}", scalar(&image.0.to_string())),
+        );
+    }
+
+    fn variables_and_profiles(
+        &mut self,
+        variables: &IndexMap<String, Option<Argument>>,
+        profiles: &IndexMap<String, IndexMap<String, Argument>>,
+    ) {
+        if !variables.is_empty() {
+            self.blank_line();
+            self.variables(0, &path(&["variables"]), variables);
+        }
+
+        if !profiles.is_empty() {
+            self.blank_line();
+            let profiles_path = path(&["profiles"]);
+            self.line(0, &[profiles_path.clone()], "profiles:");
+
+            for (name, values) in profiles {
+                let profile_path = child(&profiles_path, name.as_str());
+                self.line(
+                    INDENT,
+                    &[profile_path.clone()],
+                    &format!("{}:", scalar(name)),
+                );
+
+                for (key, Argument(value)) in values {
+                    let value_path = child(&profile_path, key.as_str());
+                    self.argument(INDENT * 2, value_path, key, value);
+                }
+            }
+        }
+    }
+
+    fn named_pipelines(&mut self, pipelines: &IndexMap<String, Vec<String>>) {
+        if pipelines.is_empty() {
+            return;
+        }
+
+        self.blank_line();
+        let pipelines_path = path(&["pipelines"]);
+        self.line(0, &[pipelines_path.clone()], "pipelines:");
+
+        for (name, stages) in pipelines {
+            let pipeline_path = child(&pipelines_path, name.as_str());
+
+            if stages.is_empty() {
+                self.line(
+                    INDENT,
+                    &[pipeline_path],
+                    &format!("{}: []", scalar(name)),
+                );
+                continue;
+            }
+
+            self.line(
+                INDENT,
+                &[pipeline_path.clone()],
+                &format!("{}:", scalar(name)),
+            );
+
+            for (i, stage) in stages.iter().enumerate() {
+                self.line(
+                    INDENT * 2,
+                    &[child(&pipeline_path, i.to_string())],
+                    &format!("- {}", scalar(stage)),
+                );
+            }
+        }
+    }
+
+    fn resources(&mut self, resources: &IndexMap<String, ResourceDeclaration>) {
+        if resources.is_empty() {
+            return;
+        }
+
+        self.blank_line();
+        let resources_path = path(&["resources"]);
+        self.line(0, &[resources_path.clone()], "resources:");
+
+        for (i, (name, decl)) in resources.iter().enumerate() {
+            if i > 0 {
+                self.blank_line();
+            }
+            self.resource(&child(&resources_path, name.as_str()), name, decl);
+        }
+    }
+
+    fn pipeline(
+        &mut self,
+        indent: usize,
+        parent: &[String],
+        pipeline: &IndexMap<String, Stage>,
+    ) {
+        let pipeline_path = child(parent, "pipeline");
+
+        if pipeline.is_empty() {
+            self.line(indent, &[pipeline_path], "pipeline: {}");
+            return;
+        }
+
+        self.line(indent, &[pipeline_path.clone()], "pipeline:");
+
+        for (i, (name, stage)) in pipeline.iter().enumerate() {
+            if i > 0 {
+                self.blank_line();
+            }
+            self.stage(
+                indent + INDENT,
+                &child(&pipeline_path, name.as_str()),
+                name,
+                stage,
+            );
+        }
+    }
+
+    fn variables(
+        &mut self,
+        indent: usize,
+        variables_path: &[String],
+        variables: &IndexMap<String, Option<Argument>>,
+    ) {
+        self.line(indent, &[variables_path.to_vec()], "variables:");
+
+        for (name, default) in variables {
+            let variable_path = child(variables_path, name.as_str());
+
+            match default {
+                Some(Argument(value)) => {
+                    self.argument(indent + INDENT, variable_path, name, value)
+                },
+                // Variables without a default are written as null
+                None => self.line(
+                    indent + INDENT,
+                    &[variable_path],
+                    &format!("{}: ~", scalar(name)),
+                ),
+            }
+        }
+    }
+
+    fn template(
+        &mut self,
+        template_path: &[String],
+        name: &str,
+        template: &Template,
+    ) {
+        let Template {
+            inputs,
+            outputs,
+            args,
+            pipeline,
+        } = template;
+
+        self.line(
+            INDENT,
+            &[template_path.to_vec()],
+            &format!("{}:", scalar(name)),
+        );
+
+        let indent = INDENT * 2;
+
+        self.list(indent, template_path, "inputs", inputs);
+        let outputs: Vec<_> = outputs.iter().map(|o| o.to_string()).collect();
+        self.list(indent, template_path, "outputs", &outputs);
+
+        if !args.is_empty() {
+            let args_path = child(template_path, "args");
+            self.line(indent, &[args_path.clone()], "args:");
+
+            for (key, default) in args {
+                let arg_path = child(&args_path, key.as_str());
+
+                match default {
+                    Some(Argument(value)) => {
+                        self.argument(indent + INDENT, arg_path, key, value)
+                    },
+                    // Parameters without a default are written as null
+                    None => self.line(
+                        indent + INDENT,
+                        &[arg_path],
+                        &format!("{}: ~", scalar(key)),
+                    ),
+                }
+            }
+        }
+
+        self.pipeline(indent, template_path, pipeline);
+    }
+
+    fn stage(
+        &mut self,
+        indent: usize,
+        stage_path: &[String],
+        name: &str,
+        stage: &Stage,
+    ) {
+        self.line(
+            indent,
+            &[stage_path.to_vec()],
+            &format!("{}:", scalar(name)),
+        );
+
+        let indent = indent + INDENT;
+
+        match stage {
+            Stage::Capability(CapabilityStage {
+                capability,
+                outputs,
+                args,
+            }) => {
+                let capability = canonical_capability(capability);
+                self.field(indent, stage_path, "capability", &capability);
+                self.outputs(indent, stage_path, outputs);
+                self.args(indent, stage_path, args);
+            },
+            Stage::Model(ModelStage {
+                model,
+                inputs,
+                outputs,
+                args,
+                when,
+            }) => {
+                self.field(indent, stage_path, "model", &model.to_string());
+                self.inputs(indent, stage_path, inputs);
+                self.when(indent, stage_path, when.as_ref());
+                self.outputs(indent, stage_path, outputs);
+                self.args(indent, stage_path, args);
+            },
+            Stage::ProcBlock(ProcBlockStage {
+                proc_block,
+                inputs,
+                outputs,
+                args,
+                when,
+            }) => {
+                self.field(
+                    indent,
+                    stage_path,
+                    "proc-block",
+                    &proc_block.to_string(),
+                );
+                self.inputs(indent, stage_path, inputs);
+                self.when(indent, stage_path, when.as_ref());
+                self.outputs(indent, stage_path, outputs);
+                self.args(indent, stage_path, args);
+            },
+            Stage::Out(OutStage {
+                out,
+                inputs,
+                args,
+                when,
+            }) => {
+                let out = canonical_sink(out);
+                self.field(indent, stage_path, "out", &out);
+                self.inputs(indent, stage_path, inputs);
+                self.when(indent, stage_path, when.as_ref());
+                self.args(indent, stage_path, args);
+            },
+            Stage::Delay(DelayStage {
+                initial,
+                inputs,
+                outputs,
+            }) => {
+                // Note: the value needs to stay a number, so it isn't quoted
+                self.line(
+                    indent,
+                    &[child(stage_path, "delay")],
+                    &format!("delay: {}", initial),
+                );
+                self.inputs(indent, stage_path, inputs);
+                self.outputs(indent, stage_path, outputs);
+            },
+            Stage::Template(TemplateStage {
+                template,
+                inputs,
+                args,
+            }) => {
+                self.field(indent, stage_path, "template", template);
+                self.inputs(indent, stage_path, inputs);
+                self.args(indent, stage_path, args);
+            },
+        }
+    }
+
+    fn stage_v2(&mut self, stage_path: &[String], name: &str, stage: &StageV2) {
+        let StageV2 {
+            kind,
+            uses,
+            inputs,
+            outputs,
+            args,
+            when,
+        } = stage;
+
+        self.line(
+            INDENT,
+            &[stage_path.to_vec()],
+            &format!("{}:", scalar(name)),
+        );
+
+        let indent = INDENT * 2;
+
+        let uses = match kind {
+            StageKind::Capability => canonical_capability(&uses.to_string()),
+            StageKind::Out => canonical_sink(&uses.to_string()),
+            StageKind::Model | StageKind::ProcBlock | StageKind::Delay => {
+                uses.to_string()
+            },
+        };
+        self.field(indent, stage_path, "kind", &kind.to_string());
+        // A delay's initial value needs to stay a number
+        if *kind == StageKind::Delay {
+            self.line(
+                indent,
+                &[child(stage_path, "uses")],
+                &format!("uses: {}", argument_scalar(&uses)),
+            );
+        } else {
+            self.field(indent, stage_path, "uses", &uses);
+        }
+
+        if !inputs.is_empty() {
+            let inputs_path = child(stage_path, "inputs");
+            self.line(indent, &[inputs_path.clone()], "inputs:");
+
+            for (port, reference) in inputs {
+                self.field(
+                    indent + INDENT,
+                    &inputs_path,
+                    port,
+                    &reference.to_string(),
+                );
+            }
+        }
+
+        self.when(indent, stage_path, when.as_ref());
+
+        if !outputs.is_empty() {
+            let outputs_path = child(stage_path, "outputs");
+            self.line(indent, &[outputs_path.clone()], "outputs:");
+
+            for (port, ty) in outputs {
+                let port_path = child(&outputs_path, port.as_str());
+                self.line(
+                    indent + INDENT,
+                    &[port_path.clone()],
+                    &format!("{}:", scalar(port)),
+                );
+                self.field(
+                    indent + INDENT * 2,
+                    &port_path,
+                    "type",
+                    &canonical_element_type(&ty.name),
+                );
+                self.dimensions(
+                    indent + INDENT * 2,
+                    &port_path,
+                    &ty.dimensions,
+                );
+            }
+        }
+
+        self.args(indent, stage_path, args);
+    }
+
+    fn field(
+        &mut self,
+        indent: usize,
+        parent: &[String],
+        key: &str,
+        value: &str,
+    ) {
+        self.line(
+            indent,
+            &[child(parent, key)],
+            &format!("{}: {}", key, scalar(value)),
+        );
+    }
+
+    fn when<I: Display>(
+        &mut self,
+        indent: usize,
+        parent: &[String],
+        condition: Option<&Condition<I>>,
+    ) {
+        if let Some(condition) = condition {
+            self.field(indent, parent, "when", &condition.to_string());
+        }
+    }
+
+    fn inputs(&mut self, indent: usize, parent: &[String], inputs: &[Input]) {
+        let inputs: Vec<_> = inputs.iter().map(|i| i.to_string()).collect();
+        self.list(indent, parent, "inputs", &inputs);
+    }
+
+    /// Write a list of scalars, skipping it entirely when empty.
+    fn list(
+        &mut self,
+        indent: usize,
+        parent: &[String],
+        key: &str,
+        items: &[String],
+    ) {
+        if items.is_empty() {
+            return;
+        }
+
+        let list_path = child(parent, key);
+        self.line(indent, &[list_path.clone()], &format!("{}:", key));
+
+        for (i, item) in items.iter().enumerate() {
+            self.line(
+                indent + INDENT,
+                &[child(&list_path, i.to_string())],
+                &format!("- {}", scalar(item)),
+            );
+        }
+    }
+
+    fn outputs(&mut self, indent: usize, parent: &[String], outputs: &[Type]) {
+        if outputs.is_empty() {
+            return;
+        }
+
+        let outputs_path = child(parent, "outputs");
+        self.line(indent, &[outputs_path.clone()], "outputs:");
+
+        for (i, ty) in outputs.iter().enumerate() {
+            let item_path = child(&outputs_path, i.to_string());
+            self.output_type(indent + INDENT, &item_path, ty);
+        }
+    }
+
+    fn output_type(&mut self, indent: usize, item_path: &[String], ty: &Type) {
+        let type_path = child(item_path, "type");
+        self.line(
+            indent,
+            &[item_path.to_vec(), type_path],
+            &format!("- type: {}", scalar(&canonical_element_type(&ty.name))),
+        );
+
+        self.dimensions(indent + INDENT, item_path, &ty.dimensions);
+    }
+
+    fn dimensions(
+        &mut self,
+        indent: usize,
+        parent: &[String],
+        dimensions: &[usize],
+    ) {
+        if dimensions.is_empty() {
+            return;
+        }
+
+        let dimensions_path = child(parent, "dimensions");
+
+        if self.comments.has_descendants(&dimensions_path) {
+            // Keep each dimension on its own line so its comments stay
+            // exactly where they were written
+            self.line(indent, &[dimensions_path.clone()], "dimensions:");
+
+            for (i, dimension) in dimensions.iter().enumerate() {
+                self.line(
+                    indent + INDENT,
+                    &[child(&dimensions_path, i.to_string())],
+                    &format!("- {}", dimension),
+                );
+            }
+
+            return;
+        }
+
+        let dimensions: Vec<_> =
+            dimensions.iter().map(|d| d.to_string()).collect();
+        self.line(
+            indent,
+            &[dimensions_path],
+            &format!("dimensions: [{}]", dimensions.join(", ")),
+        );
+    }
+
+    fn args(
+        &mut self,
+        indent: usize,
+        parent: &[String],
+        args: &IndexMap<String, Argument>,
+    ) {
+        if args.is_empty() {
+            return;
+        }
+
+        let args_path = child(parent, "args");
+        self.line(indent, &[args_path.clone()], "args:");
+
+        for (key, Argument(value)) in args {
+            let arg_path = child(&args_path, key.as_str());
+            self.argument(indent + INDENT, arg_path, key, value);
+        }
+    }
+
+    fn argument(
+        &mut self,
+        indent: usize,
+        arg_path: Path,
+        key: &str,
+        value: &ResourceOrString,
+    ) {
+        let key = scalar(key);
+
+        match value {
+            ResourceOrString::String(s) if s.contains('\n') => {
+                self.block_scalar(indent, &arg_path, &key, s);
+            },
+            ResourceOrString::String(s) => {
+                self.line(
+                    indent,
+                    &[arg_path],
+                    &format!("{}: {}", key, argument_scalar(s)),
+                );
+            },
+            ResourceOrString::Resource(r) => {
+                self.line(indent, &[arg_path], &format!("{}: {}", key, r));
+            },
+        }
+    }
+
+    fn resource(
+        &mut self,
+        resource_path: &[String],
+        name: &str,
+        decl: &ResourceDeclaration,
+    ) {
+        let ResourceDeclaration { inline, path, ty } = decl;
+
+        self.line(
+            INDENT,
+            &[resource_path.to_vec()],
+            &format!("{}:", scalar(name)),
+        );
+
+        let indent = INDENT * 2;
+
+        if let Some(inline) = inline {
+            let inline_path = child(resource_path, "inline");
+
+            if inline.contains('\n') {
+                self.block_scalar(indent, &inline_path, "inline", inline);
+            } else {
+                self.line(
+                    indent,
+                    &[inline_path],
+                    &format!("inline: {}", scalar(inline)),
+                );
+            }
+        }
+
+        if let Some(path) = path {
+            self.field(indent, resource_path, "path", path);
+        }
+
+        let ty = match ty {
+            ResourceType::String => "string",
+            ResourceType::Binary => "binary",
+        };
+        self.field(indent, resource_path, "type", ty);
+    }
+
+    /// Write a multi-line string using YAML's `|` syntax.
+    fn block_scalar(
+        &mut self,
+        indent: usize,
+        item_path: &[String],
+        key: &str,
+        value: &str,
+    ) {
+        let first_line_is_indented =
+            value.lines().next().map_or(false, |l| l.starts_with(' '));
+
+        if first_line_is_indented {
+            // We'd need an explicit indentation indicator, so fall back to a
+            // normal quoted string.
+            self.line(
+                indent,
+                &[item_path.to_vec()],
+                &format!("{}: {}", key, quoted(value)),
+            );
+            return;
+        }
+
+        let body = value.trim_end_matches('\n');
+        let chomping = match value.len() - body.len() {
+            0 => "|-",
+            1 => "|",
+            _ => "|+",
+        };
+
+        self.line(
+            indent,
+            &[item_path.to_vec()],
+            &format!("{}: {}", key, chomping),
+        );
+
+        for line in body.split('\n') {
+            if line.is_empty() {
+                self.buffer.push('\n');
+            } else {
+                self.write_indented(indent + INDENT, line);
+            }
+        }
+
+        // With "keep" chomping the trailing newlines are part of the value
+        for _ in 1..value.len() - body.len() {
+            self.buffer.push('\n');
+        }
+    }
+}
+
+/// Capabilities are written using the name the runtime knows them by (e.g.
+/// `SOUND`).
+fn canonical_capability(capability: &str) -> String {
+    match SourceKind::from(capability).as_capability_name() {
+        Some(name) => name.to_string(),
+        None => capability.to_string(),
+    }
+}
+
+/// Sinks use the same rule as capabilities, so they are written using the
+/// name the runtime knows them by (e.g. `SERIAL`).
+fn canonical_sink(out: &str) -> String {
+    match SinkKind::from(out) {
+        SinkKind::Serial => "SERIAL".to_string(),
+        SinkKind::Tensor => "TENSOR".to_string(),
+        SinkKind::Other(other) => other,
+    }
+}
+
+fn canonical_element_type(name: &str) -> String {
+    match name.to_lowercase().parse::<ElementType>() {
+        Ok(element_type) => element_type.rune_name().to_string(),
+        Err(_) => name.to_string(),
+    }
+}
+
+/// Can this string be written without quotes?
+fn is_plain(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    let first_char_is_valid = match chars.next() {
+        Some(c) => c.is_alphanumeric() || "._/$".contains(c),
+        None => false,
+    };
+
+    first_char_is_valid
+        && chars.all(|c| c.is_alphanumeric() || "._-/@$".contains(c))
+}
+
+/// Format a string, only adding quotes when they are needed.
+fn scalar(s: &str) -> Cow<'_, str> {
+    let round_trips = || {
+        serde_yaml::from_str::<serde_yaml::Value>(s)
+            .map(|v| v.as_str() == Some(s))
+            .unwrap_or(false)
+    };
+
+    if is_plain(s) && round_trips() {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(quoted(s))
+    }
+}
+
+/// Format a stage argument. Unlike [`scalar()`], this lets numbers be written
+/// without quotes.
+fn argument_scalar(s: &str) -> Cow<'_, str> {
+    let round_trips = || {
+        serde_yaml::from_str::<ResourceOrString>(s)
+            .map(|v| v == ResourceOrString::String(s.to_string()))
+            .unwrap_or(false)
+    };
+
+    if is_plain(s) && !s.starts_with('$') && round_trips() {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(quoted(s))
+    }
+}
+
+fn quoted(s: &str) -> String {
+    // Note: JSON strings are also valid YAML double-quoted strings
+    serde_json::to_string(s).expect("Serializing a string never fails")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn extract_comments() {
+        let src = r#"
+# A header comment
+version: 1
+image: runicos/base
+
+pipeline:
+  # This is the audio stage
+  audio:
+    capability: SOUND # trailing
+    args:
+      labels: |
+        # not a comment
+        up
+  serial:
+    out: serial
+    inputs:
+    - audio # the only input
+# The end
+"#;
+
+        let got = Comments::extract(src);
+
+        assert_eq!(got.header, vec!["# A header comment"]);
+        assert_eq!(
+            got.leading,
+            vec![(
+                path(&["pipeline", "audio"]),
+                vec!["# This is the audio stage".to_string()]
+            )]
+            .into_iter()
+            .collect::<IndexMap<_, _>>()
+        );
+        assert_eq!(
+            got.trailing,
+            vec![
+                (
+                    path(&["pipeline", "audio", "capability"]),
+                    "# trailing".to_string()
+                ),
+                (
+                    path(&["pipeline", "serial", "inputs", "0"]),
+                    "# the only input".to_string()
+                ),
+            ]
+            .into_iter()
+            .collect::<IndexMap<_, _>>()
+        );
+        assert_eq!(got.footer, vec!["# The end"]);
+    }
+
+    #[test]
+    fn format_a_messy_runefile() {
+        let src = r#"
+# My Rune
+image: "runicos/base"
+version: 1
+pipeline:
+  audio:
+    outputs:
+      - type: I16
+        dimensions:
+          - 1
+          - 16000 # one second of audio
+    capability: sound
+    args:
+      hz: 16000
+  # Classify the audio
+  model:
+    outputs:
+    - type: F32
+      dimensions: [1, 6]
+    inputs: [audio]
+    model: "./model.tflite"
+  label:
+    proc-block: "hotg-ai/proc-blocks@v0.11.3#label"
+    inputs:
+      - model
+    outputs:
+      - type: UTF8
+        dimensions: [1]
+    args:
+      wordlist: $WORDS
+      format: "@PixelFormat::RGB"
+  serial:
+    inputs:
+      - label
+    out: SERIAL
+resources:
+  WORDS:
+    type: string
+    inline: |
+      up
+      down
+"#;
+        let should_be = r#"# My Rune
+version: 1
+image: runicos/base
+
+pipeline:
+  audio:
+    capability: SOUND
+    outputs:
+      - type: i16
+        dimensions:
+          - 1
+          - 16000 # one second of audio
+    args:
+      hz: 16000
+
+  # Classify the audio
+  model:
+    model: ./model.tflite
+    inputs:
+      - audio
+    outputs:
+      - type: f32
+        dimensions: [1, 6]
+
+  label:
+    proc-block: "hotg-ai/proc-blocks@v0.11.3#label"
+    inputs:
+      - model
+    outputs:
+      - type: utf8
+        dimensions: [1]
+    args:
+      wordlist: $WORDS
+      format: "@PixelFormat::RGB"
+
+  serial:
+    out: SERIAL
+    inputs:
+      - label
+
+resources:
+  WORDS:
+    inline: |
+      up
+      down
+    type: string
+"#;
+
+        let got = format_runefile(src).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn comments_on_dimensions_stay_where_they_were() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  image:
+    capability: image
+    outputs:
+      - type: u8
+        dimensions: # NHWC
+          # batch size
+          - 1
+          - 96 # height
+          - 96
+          - 3
+  raw:
+    out: tensor
+    inputs: [image]
+"#;
+        let should_be = r#"version: 1
+image: runicos/base
+
+pipeline:
+  image:
+    capability: IMAGE
+    outputs:
+      - type: u8
+        dimensions: # NHWC
+          # batch size
+          - 1
+          - 96 # height
+          - 96
+          - 3
+
+  raw:
+    out: TENSOR
+    inputs:
+      - image
+"#;
+
+        let got = format_runefile(src).unwrap();
+
+        assert_eq!(got, should_be);
+        assert_eq!(format_runefile(&got).unwrap(), got);
+    }
+
+    #[test]
+    fn format_includes_and_templates() {
+        let src = r#"
+version: 1
+image: runicos/base
+templates:
+  noise:
+    pipeline:
+      rand:
+        capability: rand
+        outputs: [{type: F32, dimensions: [1]}]
+        args: {amount: $amount}
+    args: {amount: ~, seed: 42}
+    outputs: [rand]
+pipeline:
+  input:
+    template: noise
+    args: {amount: 1}
+  serial:
+    out: SERIAL
+    inputs: [input]
+includes: [common/filters.yml]
+"#;
+        let should_be = r#"version: 1
+image: runicos/base
+
+includes:
+  - common/filters.yml
+
+pipeline:
+  input:
+    template: noise
+    args:
+      amount: 1
+
+  serial:
+    out: SERIAL
+    inputs:
+      - input
+
+templates:
+  noise:
+    outputs:
+      - rand
+    args:
+      amount: ~
+      seed: 42
+    pipeline:
+      rand:
+        capability: RAND
+        outputs:
+          - type: f32
+            dimensions: [1]
+        args:
+          amount: $amount
+"#;
+
+        let got = format_runefile(src).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn format_variables_and_profiles() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  sine:
+    model: ${MODEL_DIR}/sine.tflite
+    outputs: [{type: f32, dimensions: [1]}]
+    args: {threshold: "${THRESHOLD}"}
+profiles:
+  release: {THRESHOLD: 0.9, MODEL_DIR: /opt/models}
+variables:
+  # The directory containing all our models
+  MODEL_DIR: ./models
+  THRESHOLD: ~
+"#;
+        let should_be = r#"version: 1
+image: runicos/base
+
+variables:
+  # The directory containing all our models
+  MODEL_DIR: ./models
+  THRESHOLD: ~
+
+profiles:
+  release:
+    THRESHOLD: 0.9
+    MODEL_DIR: /opt/models
+
+pipeline:
+  sine:
+    model: "${MODEL_DIR}/sine.tflite"
+    outputs:
+      - type: f32
+        dimensions: [1]
+    args:
+      threshold: "${THRESHOLD}"
+"#;
+
+        let got = format_runefile(src).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn format_named_pipelines() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipelines:
+  # Runs whenever new samples arrive
+  fast: [serial]
+  idle: []
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  serial:
+    out: serial
+    inputs: [rand]
+"#;
+        let should_be = r#"version: 1
+image: runicos/base
+
+pipeline:
+  rand:
+    capability: RAND
+    outputs:
+      - type: f32
+        dimensions: [1]
+
+  serial:
+    out: SERIAL
+    inputs:
+      - rand
+
+pipelines:
+  # Runs whenever new samples arrive
+  fast:
+    - serial
+  idle: []
+"#;
+
+        let got = format_runefile(src).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn format_conditions() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  score:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  serial:
+    out: SERIAL
+    when: score>=0.5
+    inputs: [score]
+"#;
+        let should_be = r#"version: 1
+image: runicos/base
+
+pipeline:
+  score:
+    capability: RAND
+    outputs:
+      - type: f32
+        dimensions: [1]
+
+  serial:
+    out: SERIAL
+    inputs:
+      - score
+    when: "score >= 0.5"
+"#;
+
+        let got = format_runefile(src).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn format_delays() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  previous:
+    delay: 0.5
+    inputs: [smoothed]
+    outputs: [{type: f32, dimensions: [1]}]
+"#;
+        let should_be = r#"version: 1
+image: runicos/base
+
+pipeline:
+  previous:
+    delay: 0.5
+    inputs:
+      - smoothed
+    outputs:
+      - type: f32
+        dimensions: [1]
+"#;
+
+        let got = format_runefile(src).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn upgrade_a_v1_runefile() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  # Where the data comes from
+  rand:
+    capability: rand
+    outputs: [{type: F32, dimensions: [1]}]
+  split:
+    proc-block: "hotg-ai/rune#proc_blocks/split"
+    inputs: [rand]
+    outputs:
+      - {type: f32, dimensions: [1]}
+      - {type: f32, dimensions: [1]}
+  serial:
+    out: serial
+    inputs: [split.1, rand]
+"#;
+        let should_be = r#"version: 2
+image: runicos/base
+
+pipeline:
+  # Where the data comes from
+  rand:
+    kind: capability
+    uses: RAND
+    outputs:
+      output:
+        type: f32
+        dimensions: [1]
+
+  split:
+    kind: proc-block
+    uses: "hotg-ai/rune#proc_blocks/split"
+    inputs:
+      rand: rand.output
+    outputs:
+      output_0:
+        type: f32
+        dimensions: [1]
+      output_1:
+        type: f32
+        dimensions: [1]
+
+  serial:
+    kind: out
+    uses: SERIAL
+    inputs:
+      split: split.output_1
+      rand: rand.output
+"#;
+
+        let got = upgrade_runefile(src).unwrap();
+
+        assert_eq!(got, should_be);
+        assert_eq!(format_runefile(&got).unwrap(), got);
+        assert_eq!(upgrade_runefile(&got).unwrap(), got);
+    }
+
+    #[test]
+    fn runefiles_with_includes_cant_be_upgraded() {
+        let src = r#"
+version: 1
+image: runicos/base
+includes: [common.yml]
+pipeline: {}
+"#;
+
+        let err = upgrade_runefile(src).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Version 2 Runefiles don't support includes yet"
+        );
+    }
+
+    /// Apply the same normalisation to a [`Document`] that the formatter
+    /// does.
+    fn canonicalize(doc: Document) -> Document {
+        let mut doc = doc.to_v1();
+
+        for stage in doc.pipeline.values_mut() {
+            for input in stage.inputs_mut().into_iter().flatten() {
+                if input.index == Some(0) {
+                    input.index = None;
+                }
+            }
+
+            let outputs = match stage {
+                Stage::Capability(c) => {
+                    c.capability = canonical_capability(&c.capability);
+                    &mut c.outputs
+                },
+                Stage::Model(m) => &mut m.outputs,
+                Stage::ProcBlock(p) => &mut p.outputs,
+                Stage::Delay(d) => &mut d.outputs,
+                Stage::Out(o) => {
+                    o.out = canonical_sink(&o.out);
+                    continue;
+                },
+                Stage::Template(_) => continue,
+            };
+
+            for ty in outputs {
+                ty.name = canonical_element_type(&ty.name);
+            }
+        }
+
+        doc.into()
+    }
+
+    #[test]
+    fn formatting_is_idempotent_and_preserves_meaning() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("..")
+            .join("examples");
+
+        for entry in examples.read_dir().unwrap() {
+            let runefile = entry.unwrap().path().join("Runefile.yml");
+            if !runefile.exists() {
+                continue;
+            }
+            let src = std::fs::read_to_string(&runefile).unwrap();
+
+            let formatted = format_runefile(&src).unwrap();
+
+            assert_eq!(
+                canonicalize(Document::parse(&formatted).unwrap()),
+                canonicalize(Document::parse(&src).unwrap()),
+                "{}",
+                runefile.display()
+            );
+            assert_eq!(
+                format_runefile(&formatted).unwrap(),
+                formatted,
+                "{}",
+                runefile.display()
+            );
+            let comments_before = src.matches(" #").count()
+                + src.lines().filter(|l| l.trim().starts_with('#')).count();
+            let comments_after = formatted.matches(" #").count()
+                + formatted
+                    .lines()
+                    .filter(|l| l.trim().starts_with('#'))
+                    .count();
+            assert_eq!(comments_before, comments_after);
+        }
+    }
+
+    #[test]
+    fn upgrading_preserves_meaning() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("..")
+            .join("examples");
+
+        for entry in examples.read_dir().unwrap() {
+            let runefile = entry.unwrap().path().join("Runefile.yml");
+            if !runefile.exists() {
+                continue;
+            }
+            let src = std::fs::read_to_string(&runefile).unwrap();
+
+            let upgraded = match upgrade_runefile(&src) {
+                Ok(upgraded) => upgraded,
+                Err(UpgradeError::Unsupported(_)) => continue,
+                Err(e) => panic!("{}: {}", runefile.display(), e),
+            };
+
+            assert_eq!(
+                canonicalize(Document::parse(&upgraded).unwrap()),
+                canonicalize(Document::parse(&src).unwrap()),
+                "{}",
+                runefile.display()
+            );
+            assert_eq!(
+                format_runefile(&upgraded).unwrap(),
+                upgraded,
+                "{}",
+                runefile.display()
+            );
+        }
+    }
+}
@@ -1,15 +1,35 @@
 //! The parsing phase.
 //!
-//! This is a simple phase which just calls [`Document::parse()`] and stores
-//! the resulting [`DocumentV1`] in the global [`legion::Resources`].
+//! This phase calls [`Document::parse()`], converts version 2 Runefiles to
+//! version 1, loads any files it includes, expands template instantiations
+//! into ordinary stages, and substitutes the values of any `${VARIABLES}`.
+//! The resulting [`DocumentV1`] is stored in the global
+//! [`legion::Resources`], alongside the [`DocumentSpans`] that say where each
+//! item was defined and a [`SourceMap`] containing every file that was read.
 
+mod expand;
+mod format;
+mod source_map;
+mod spans;
+mod v2;
+mod variables;
 mod yaml;
 
+use std::path::Path;
+
 use codespan::Span;
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 use legion::{systems::CommandBuffer, Registry};
 
-pub use self::yaml::*;
+pub use self::{
+    format::{format_runefile, upgrade_runefile},
+    source_map::SourceMap,
+    spans::{
+        DocumentSpans, PipelineSpans, ProfileSpans, StageSpans, TemplateSpans,
+    },
+    v2::{upgrade, UpgradeError},
+    yaml::*,
+};
 use crate::{phases::Phase, serialize::RegistryExt, BuildContext, Diagnostics};
 
 pub fn phase() -> Phase {
@@ -25,26 +45,86 @@ fn run(
     #[resource] build_context: &BuildContext,
     #[resource] diags: &mut Diagnostics,
 ) {
+    let mut sources =
+        SourceMap::new("Runefile.yml", build_context.runefile.as_str());
+
+    let current_dir = &build_context.current_directory;
+    let loaded = load_runefile(build_context, &mut sources, diags, |path| {
+        std::fs::read_to_string(current_dir.join(path))
+    });
+
+    if let Some((doc, spans)) = loaded {
+        cmd.exec_mut(move |_, res| {
+            res.insert(doc.clone());
+            res.insert(spans.clone());
+        });
+    }
+
+    cmd.exec_mut(move |_, res| {
+        res.insert(sources.clone());
+    });
+}
+
+/// Parse the Runefile from a [`BuildContext`], load everything it includes,
+/// and substitute the values of its variables.
+///
+/// This is everything the parsing phase does, for tools that need to see the
+/// same [`DocumentV1`] as the compiler (e.g. to know which files a Rune
+/// depends on). The `read` function is given paths relative to the
+/// Runefile's directory and every file it reads is added to the
+/// [`SourceMap`].
+pub fn load_runefile(
+    build_context: &BuildContext,
+    sources: &mut SourceMap,
+    diags: &mut Diagnostics,
+    read: impl FnMut(&Path) -> std::io::Result<String>,
+) -> Option<(DocumentV1, DocumentSpans)> {
     let src = &build_context.runefile;
 
-    match Document::parse(src) {
-        Ok(d) => {
-            cmd.exec_mut(move |_, res| {
-                res.insert(d.clone().to_v1());
-            });
-        },
+    let d = match Document::parse(src) {
+        Ok(d) => d,
         Err(e) => {
-            diags.push(parse_failed_diagnostic(e));
+            diags.push(parse_failed_diagnostic(src, e));
+            return None;
         },
-    }
+    };
+
+    let spans = DocumentSpans::parse(src).unwrap_or_else(|e| {
+        log::warn!("Unable to determine source locations: {}", e);
+        DocumentSpans::default()
+    });
+
+    let (doc, spans) = match d {
+        Document::V1(v1) => (v1, spans),
+        Document::V2(v2) => v2::to_v1(v2, spans, diags),
+    };
+
+    let (mut doc, spans) = expand::expand(doc, spans, sources, diags, read);
+    variables::resolve(
+        &mut doc,
+        &spans,
+        &build_context.variables,
+        build_context.profile.as_deref(),
+        |name| std::env::var(name).ok(),
+        diags,
+    );
+
+    Some((doc, spans))
 }
 
-fn parse_failed_diagnostic(e: serde_yaml::Error) -> Diagnostic<()> {
+pub(crate) fn parse_failed_diagnostic(
+    src: &str,
+    e: serde_yaml::Error,
+) -> Diagnostic<()> {
     let msg = format!("Unable to parse the input: {}", e);
 
     let mut diag = Diagnostic::error().with_message(msg);
     if let Some(location) = e.location() {
-        let ix = location.index();
+        // Note: the location's index is in characters, not bytes
+        let ix = src
+            .char_indices()
+            .nth(location.index())
+            .map_or(src.len(), |(ix, _)| ix);
         diag = diag.with_labels(vec![Label::primary((), ix..ix)]);
     }
     diag
@@ -54,5 +134,6 @@ pub(crate) fn register_components(registry: &mut Registry<String>) {
     registry
         .register_with_type_name::<Document>()
         .register_with_type_name::<DocumentV1>()
+        .register_with_type_name::<DocumentSpans>()
         .register_with_type_name::<Span>();
 }
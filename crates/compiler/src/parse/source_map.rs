@@ -0,0 +1,176 @@
+use std::ops::Range;
+
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label},
+    files::{Error, Files, SimpleFile},
+};
+
+/// The files a Runefile was assembled from.
+///
+/// The rest of the compiler only knows about a single file, so every file is
+/// given its own region of one big "virtual" file, starting with the Runefile
+/// at offset `0`. [`SourceMap::resolve()`] can then be used to turn a
+/// [`Diagnostic`] which refers to the virtual file into one that refers to
+/// the actual files.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+#[derive(Debug, Clone)]
+struct SourceFile {
+    file: SimpleFile<String, String>,
+    start: usize,
+}
+
+impl SourceFile {
+    fn end(&self) -> usize { self.start + self.file.source().len() }
+}
+
+impl SourceMap {
+    /// Create a new [`SourceMap`] containing just the Runefile.
+    pub fn new(name: impl Into<String>, runefile: impl Into<String>) -> Self {
+        SourceMap {
+            files: vec![SourceFile {
+                file: SimpleFile::new(name.into(), runefile.into()),
+                start: 0,
+            }],
+        }
+    }
+
+    /// Add another file, returning the offset its spans should start at.
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        src: impl Into<String>,
+    ) -> usize {
+        // Note: we leave a gap so a span at the very end of one file can't
+        // be confused with the start of the next one.
+        let start = self.files.last().map(|f| f.end() + 1).unwrap_or(0);
+
+        self.files.push(SourceFile {
+            file: SimpleFile::new(name.into(), src.into()),
+            start,
+        });
+
+        start
+    }
+
+    /// Change the name the Runefile is reported with (e.g. to use the path
+    /// a user passed in on the command-line).
+    pub fn set_runefile_name(&mut self, name: impl Into<String>) {
+        let runefile = &mut self.files[0];
+        let src = runefile.file.source().clone();
+        runefile.file = SimpleFile::new(name.into(), src);
+    }
+
+    /// Find which file a range of the virtual file belongs to, and where it
+    /// is within that file.
+    pub fn locate(&self, range: Range<usize>) -> (usize, Range<usize>) {
+        let id = self
+            .files
+            .iter()
+            .rposition(|f| f.start <= range.start)
+            .unwrap_or(0);
+        let file = &self.files[id];
+
+        let start = range.start - file.start;
+        let end = range.end.max(range.start).min(file.end()) - file.start;
+
+        (id, start..end)
+    }
+
+    /// Convert a [`Diagnostic`] which refers to the virtual file into one
+    /// that can be rendered using this [`SourceMap`].
+    pub fn resolve(&self, diag: &Diagnostic<()>) -> Diagnostic<usize> {
+        let labels = diag
+            .labels
+            .iter()
+            .map(|label| {
+                let (id, range) = self.locate(label.range.clone());
+                Label::new(label.style, id, range)
+                    .with_message(label.message.clone())
+            })
+            .collect();
+
+        Diagnostic {
+            severity: diag.severity,
+            code: diag.code.clone(),
+            message: diag.message.clone(),
+            labels,
+            notes: diag.notes.clone(),
+        }
+    }
+
+    fn get(&self, id: usize) -> Result<&SimpleFile<String, String>, Error> {
+        self.files
+            .get(id)
+            .map(|f| &f.file)
+            .ok_or(Error::FileMissing)
+    }
+}
+
+impl<'a> Files<'a> for SourceMap {
+    type FileId = usize;
+    type Name = &'a str;
+    type Source = &'a str;
+
+    fn name(&'a self, id: usize) -> Result<&'a str, Error> {
+        Ok(self.get(id)?.name().as_str())
+    }
+
+    fn source(&'a self, id: usize) -> Result<&'a str, Error> {
+        Ok(self.get(id)?.source().as_str())
+    }
+
+    fn line_index(
+        &'a self,
+        id: usize,
+        byte_index: usize,
+    ) -> Result<usize, Error> {
+        self.get(id)?.line_index((), byte_index)
+    }
+
+    fn line_range(
+        &'a self,
+        id: usize,
+        line_index: usize,
+    ) -> Result<Range<usize>, Error> {
+        self.get(id)?.line_range((), line_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_spans_in_each_file() {
+        let mut map = SourceMap::new("Runefile.yml", "version: 1\n");
+        let offset = map.add("common.yml", "pipeline: {}\n");
+
+        assert_eq!(offset, 12);
+        assert_eq!(map.locate(0..7), (0, 0..7));
+        assert_eq!(map.locate(12..20), (1, 0..8));
+        assert_eq!(map.name(1).unwrap(), "common.yml");
+    }
+
+    #[test]
+    fn resolve_a_diagnostic() {
+        let mut map = SourceMap::new("Runefile.yml", "version: 1\n");
+        let offset = map.add("common.yml", "pipeline: {}\n");
+        let diag = Diagnostic::error().with_labels(vec![
+            Label::primary((), offset..offset + 8),
+            Label::secondary((), 0..7),
+        ]);
+
+        let got = map.resolve(&diag);
+
+        let locations: Vec<_> = got
+            .labels
+            .iter()
+            .map(|l| (l.file_id, l.range.clone()))
+            .collect();
+        assert_eq!(locations, vec![(1, 0..8), (0, 0..7)]);
+    }
+}
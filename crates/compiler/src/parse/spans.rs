@@ -0,0 +1,706 @@
+//! Source locations for the items in a Runefile.
+//!
+//! `serde_yaml` throws away location information when deserializing, so we
+//! make a second pass over the YAML with [`yaml_rust`]'s event parser and
+//! record where each stage, argument, input, and resource is.
+
+use codespan::Span;
+use indexmap::IndexMap;
+use yaml_rust::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::{Marker, ScanError, TScalarStyle},
+};
+
+/// The location of each item in a Runefile.
+///
+/// Lookups never fail. If an item can't be found we fall back to the
+/// location of its parent (e.g. the stage an argument belongs to) or an
+/// empty [`Span`].
+#[derive(
+    Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct DocumentSpans {
+    /// Each item in the `includes` list.
+    #[serde(default)]
+    pub includes: Vec<Span>,
+    /// The span of each variable's name.
+    #[serde(default)]
+    pub variables: IndexMap<String, Span>,
+    #[serde(default)]
+    pub profiles: IndexMap<String, ProfileSpans>,
+    pub stages: IndexMap<String, StageSpans>,
+    #[serde(default)]
+    pub pipelines: IndexMap<String, PipelineSpans>,
+    #[serde(default)]
+    pub templates: IndexMap<String, TemplateSpans>,
+    /// The span of each resource's name.
+    pub resources: IndexMap<String, Span>,
+}
+
+/// The location of a profile and the variables it sets.
+#[derive(
+    Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct ProfileSpans {
+    /// The profile's name.
+    pub name: Span,
+    /// The name of each variable.
+    pub variables: IndexMap<String, Span>,
+}
+
+/// The location of a named pipeline and the stages it lists.
+#[derive(
+    Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct PipelineSpans {
+    /// The pipeline's name.
+    pub name: Span,
+    /// Each item in the list of stages.
+    pub stages: Vec<Span>,
+}
+
+/// The location of a [`crate::parse::Template`] and its contents.
+#[derive(
+    Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct TemplateSpans {
+    /// The template's name.
+    pub name: Span,
+    /// The name of each argument.
+    pub args: IndexMap<String, Span>,
+    /// Each item in the `outputs` list.
+    pub outputs: Vec<Span>,
+    /// The template's stages.
+    pub stages: IndexMap<String, StageSpans>,
+}
+
+/// The location of a [`crate::parse::Stage`] and its contents.
+#[derive(
+    Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct StageSpans {
+    /// The stage's name.
+    pub name: Span,
+    /// The values for top-level fields like `model` or `proc-block`.
+    pub fields: IndexMap<String, Span>,
+    /// The value of each argument.
+    pub args: IndexMap<String, Span>,
+    /// Each item in the `inputs` list.
+    pub inputs: Vec<Span>,
+    /// Each item in the `outputs` list.
+    pub outputs: Vec<Span>,
+}
+
+impl DocumentSpans {
+    pub fn parse(src: &str) -> Result<Self, ScanError> {
+        let mut builder = TreeBuilder::new(src);
+        Parser::new(src.chars()).load(&mut builder, false)?;
+
+        let root = match builder.finish() {
+            Some(root) => root,
+            None => return Ok(DocumentSpans::default()),
+        };
+
+        let includes = root
+            .get("includes")
+            .map(|includes| includes.items().map(|n| n.span).collect())
+            .unwrap_or_default();
+
+        let variables =
+            root.get("variables").map(key_spans).unwrap_or_default();
+
+        let profiles = root
+            .get("profiles")
+            .map(|profiles| {
+                profiles
+                    .entries()
+                    .filter_map(|(key, value)| {
+                        let spans = ProfileSpans {
+                            name: key.span,
+                            variables: key_spans(value),
+                        };
+                        Some((key.as_str()?.to_string(), spans))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let stages = root.get("pipeline").map(stage_spans).unwrap_or_default();
+
+        let pipelines = root
+            .get("pipelines")
+            .map(|pipelines| {
+                pipelines
+                    .entries()
+                    .filter_map(|(key, value)| {
+                        let spans = PipelineSpans {
+                            name: key.span,
+                            stages: value.items().map(|n| n.span).collect(),
+                        };
+                        Some((key.as_str()?.to_string(), spans))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let templates = root
+            .get("templates")
+            .map(|templates| {
+                templates
+                    .entries()
+                    .filter_map(|(key, value)| {
+                        let name = key.as_str()?;
+                        Some((name.to_string(), TemplateSpans::new(key, value)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let resources = root
+            .get("resources")
+            .map(|resources| {
+                resources
+                    .entries()
+                    .filter_map(|(key, _)| {
+                        Some((key.as_str()?.to_string(), key.span))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(DocumentSpans {
+            includes,
+            variables,
+            profiles,
+            stages,
+            pipelines,
+            templates,
+            resources,
+        })
+    }
+
+    /// Parse the spans for a file whose contents start `offset` bytes into
+    /// the [`crate::parse::SourceMap`].
+    pub fn parse_at(src: &str, offset: usize) -> Result<Self, ScanError> {
+        let mut spans = DocumentSpans::parse(src)?;
+        spans.shift(offset as u32);
+        Ok(spans)
+    }
+
+    fn shift(&mut self, offset: u32) {
+        let shift = |span: &mut Span| shift_span(span, offset);
+
+        self.includes.iter_mut().for_each(shift);
+        self.variables.values_mut().for_each(shift);
+        self.resources.values_mut().for_each(shift);
+        self.stages.values_mut().for_each(|s| s.shift(offset));
+
+        for profile in self.profiles.values_mut() {
+            shift(&mut profile.name);
+            profile.variables.values_mut().for_each(shift);
+        }
+
+        for pipeline in self.pipelines.values_mut() {
+            shift(&mut pipeline.name);
+            pipeline.stages.iter_mut().for_each(shift);
+        }
+
+        for template in self.templates.values_mut() {
+            shift(&mut template.name);
+            template.args.values_mut().for_each(shift);
+            template.outputs.iter_mut().for_each(shift);
+            template.stages.values_mut().for_each(|s| s.shift(offset));
+        }
+    }
+
+    /// The span of a stage's name.
+    pub fn stage(&self, name: &str) -> Span {
+        self.stages.get(name).map(|s| s.name).unwrap_or_default()
+    }
+
+    /// The span of a top-level field's value (e.g. the `model` in a model
+    /// stage).
+    pub fn field(&self, stage: &str, field: &str) -> Span {
+        self.lookup(stage, |s| s.fields.get(field).copied())
+    }
+
+    /// The span of an argument's value.
+    pub fn argument(&self, stage: &str, arg: &str) -> Span {
+        self.lookup(stage, |s| s.args.get(arg).copied())
+    }
+
+    /// The span of the `index`'th item in a stage's `inputs`.
+    pub fn input(&self, stage: &str, index: usize) -> Span {
+        self.lookup(stage, |s| s.inputs.get(index).copied())
+    }
+
+    /// The span of the `index`'th item in a stage's `outputs`.
+    pub fn output(&self, stage: &str, index: usize) -> Span {
+        self.lookup(stage, |s| s.outputs.get(index).copied())
+    }
+
+    /// The span of a resource's name.
+    pub fn resource(&self, name: &str) -> Span {
+        self.resources.get(name).copied().unwrap_or_default()
+    }
+
+    /// The span of a variable's name.
+    pub fn variable(&self, name: &str) -> Span {
+        self.variables.get(name).copied().unwrap_or_default()
+    }
+
+    /// The span of a variable set by a profile, falling back to the
+    /// profile's name.
+    pub fn profile_variable(&self, profile: &str, name: &str) -> Span {
+        match self.profiles.get(profile) {
+            Some(p) => p.variables.get(name).copied().unwrap_or(p.name),
+            None => Span::default(),
+        }
+    }
+
+    /// The span of the `index`'th stage listed by a named pipeline, falling
+    /// back to the pipeline's name.
+    pub fn pipeline_stage(&self, pipeline: &str, index: usize) -> Span {
+        match self.pipelines.get(pipeline) {
+            Some(p) => p.stages.get(index).copied().unwrap_or(p.name),
+            None => Span::default(),
+        }
+    }
+
+    fn lookup(
+        &self,
+        stage: &str,
+        get: impl FnOnce(&StageSpans) -> Option<Span>,
+    ) -> Span {
+        match self.stages.get(stage) {
+            Some(s) => get(s).unwrap_or(s.name),
+            None => Span::default(),
+        }
+    }
+}
+
+impl TemplateSpans {
+    fn new(key: &Node, value: &Node) -> Self {
+        TemplateSpans {
+            name: key.span,
+            args: value
+                .get("args")
+                .map(|args| {
+                    args.entries()
+                        .filter_map(|(k, _)| {
+                            Some((k.as_str()?.to_string(), k.span))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            outputs: value
+                .get("outputs")
+                .map(|outputs| outputs.items().map(|n| n.span).collect())
+                .unwrap_or_default(),
+            stages: value.get("pipeline").map(stage_spans).unwrap_or_default(),
+        }
+    }
+}
+
+/// The span of each key in a mapping.
+fn key_spans(mapping: &Node) -> IndexMap<String, Span> {
+    mapping
+        .entries()
+        .filter_map(|(key, _)| Some((key.as_str()?.to_string(), key.span)))
+        .collect()
+}
+
+fn stage_spans(pipeline: &Node) -> IndexMap<String, StageSpans> {
+    pipeline
+        .entries()
+        .filter_map(|(key, value)| {
+            let name = key.as_str()?;
+            Some((name.to_string(), StageSpans::new(key, value)))
+        })
+        .collect()
+}
+
+impl StageSpans {
+    fn shift(&mut self, offset: u32) {
+        let shift = |span: &mut Span| shift_span(span, offset);
+
+        shift(&mut self.name);
+        self.fields.values_mut().for_each(shift);
+        self.args.values_mut().for_each(shift);
+        self.inputs.iter_mut().for_each(shift);
+        self.outputs.iter_mut().for_each(shift);
+    }
+
+    fn new(key: &Node, value: &Node) -> Self {
+        let mut spans = StageSpans {
+            name: key.span,
+            ..Default::default()
+        };
+
+        for (key, value) in value.entries() {
+            match key.as_str() {
+                Some("args") => {
+                    spans.args = value
+                        .entries()
+                        .filter_map(|(k, v)| {
+                            Some((k.as_str()?.to_string(), v.span))
+                        })
+                        .collect();
+                },
+                // Note: version 2 Runefiles use a mapping from port names to
+                // inputs and outputs
+                Some("inputs") => {
+                    spans.inputs = value
+                        .items()
+                        .chain(value.entries().map(|(_, v)| v))
+                        .map(|n| n.span)
+                        .collect();
+                },
+                Some("outputs") => {
+                    spans.outputs = value
+                        .items()
+                        .chain(value.entries().map(|(k, _)| k))
+                        .map(|n| n.span)
+                        .collect();
+                },
+                Some(field) => {
+                    spans.fields.insert(field.to_string(), value.span);
+                },
+                None => {},
+            }
+        }
+
+        spans
+    }
+}
+
+/// A YAML node and where it was defined.
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    span: Span,
+    kind: NodeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NodeKind {
+    Scalar(String),
+    Sequence(Vec<Node>),
+    Mapping(Vec<(Node, Node)>),
+    /// A mapping value we haven't seen yet.
+    Pending,
+}
+
+impl Node {
+    fn as_str(&self) -> Option<&str> {
+        match &self.kind {
+            NodeKind::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Node> {
+        self.entries()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .map(|(_, v)| v)
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (&Node, &Node)> + '_ {
+        let entries = match &self.kind {
+            NodeKind::Mapping(entries) => entries.as_slice(),
+            _ => &[],
+        };
+
+        entries.iter().map(|(k, v)| (k, v))
+    }
+
+    fn items(&self) -> impl Iterator<Item = &Node> + '_ {
+        let items = match &self.kind {
+            NodeKind::Sequence(items) => items.as_slice(),
+            _ => &[],
+        };
+
+        items.iter()
+    }
+}
+
+/// A [`MarkedEventReceiver`] which assembles parser events into a tree of
+/// [`Node`]s.
+struct TreeBuilder<'src> {
+    src: &'src str,
+    /// The byte offset of each character (plus one past the end), because
+    /// [`Marker::index()`] counts characters.
+    char_offsets: Vec<usize>,
+    stack: Vec<Node>,
+    root: Option<Node>,
+}
+
+impl<'src> TreeBuilder<'src> {
+    fn new(src: &'src str) -> Self {
+        let char_offsets = src
+            .char_indices()
+            .map(|(ix, _)| ix)
+            .chain(std::iter::once(src.len()))
+            .collect();
+
+        TreeBuilder {
+            src,
+            char_offsets,
+            stack: Vec::new(),
+            root: None,
+        }
+    }
+
+    fn finish(self) -> Option<Node> { self.root }
+
+    fn byte_offset(&self, mark: Marker) -> usize {
+        self.char_offsets
+            .get(mark.index())
+            .copied()
+            .unwrap_or(self.src.len())
+    }
+
+    fn scalar_span(
+        &self,
+        value: &str,
+        style: TScalarStyle,
+        start: usize,
+    ) -> Span {
+        let rest = &self.src[start..];
+
+        let len = match style {
+            TScalarStyle::Plain if rest.starts_with(value) => value.len(),
+            TScalarStyle::SingleQuoted => closing_quote(rest, '\''),
+            TScalarStyle::DoubleQuoted => closing_quote(rest, '"'),
+            // Empty values, block scalars, etc.
+            _ => 0,
+        };
+
+        span(start, start + len)
+    }
+
+    fn push(&mut self, node: Node) {
+        let parent = match self.stack.last_mut() {
+            Some(p) => p,
+            None => {
+                self.root = Some(node);
+                return;
+            },
+        };
+
+        match &mut parent.kind {
+            NodeKind::Sequence(items) => items.push(node),
+            NodeKind::Mapping(entries) => {
+                // Keys and values come in pairs, so we temporarily store the
+                // key with a placeholder value.
+                match entries.last_mut() {
+                    Some((_, value)) if value.kind == NodeKind::Pending => {
+                        *value = node;
+                    },
+                    _ => {
+                        let placeholder = Node {
+                            span: Span::default(),
+                            kind: NodeKind::Pending,
+                        };
+                        entries.push((node, placeholder));
+                    },
+                }
+            },
+            _ => unreachable!("Only collections are pushed onto the stack"),
+        }
+    }
+
+    fn end_collection(&mut self) {
+        if let Some(mut node) = self.stack.pop() {
+            // Note: The parser's markers for collections tend to be a token
+            // or two off, so we use the collection's contents instead.
+            let children: Vec<Span> = match &node.kind {
+                NodeKind::Sequence(items) => {
+                    items.iter().map(|n| n.span).collect()
+                },
+                NodeKind::Mapping(entries) => entries
+                    .iter()
+                    .flat_map(|(k, v)| vec![k.span, v.span])
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            if let (Some(first), Some(last)) =
+                (children.first(), children.last())
+            {
+                node.span = first.merge(*last);
+            }
+
+            self.push(node);
+        }
+    }
+}
+
+impl<'src> MarkedEventReceiver for TreeBuilder<'src> {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        let start = self.byte_offset(mark);
+
+        match ev {
+            Event::Scalar(value, style, _, _) => {
+                let span = self.scalar_span(&value, style, start);
+                self.push(Node {
+                    span,
+                    kind: NodeKind::Scalar(value),
+                });
+            },
+            // Note: we don't bother resolving aliases
+            Event::Alias(_) => self.push(Node {
+                span: span(start, start),
+                kind: NodeKind::Scalar(String::new()),
+            }),
+            Event::SequenceStart(_) => self.stack.push(Node {
+                span: span(start, start),
+                kind: NodeKind::Sequence(Vec::new()),
+            }),
+            Event::MappingStart(_) => self.stack.push(Node {
+                span: span(start, start),
+                kind: NodeKind::Mapping(Vec::new()),
+            }),
+            Event::SequenceEnd | Event::MappingEnd => self.end_collection(),
+            Event::Nothing
+            | Event::StreamStart
+            | Event::StreamEnd
+            | Event::DocumentStart
+            | Event::DocumentEnd => {},
+        }
+    }
+}
+
+/// Find the length of a quoted string, including its quotes.
+fn closing_quote(src: &str, quote: char) -> usize {
+    let mut chars = src.char_indices().skip(1);
+
+    while let Some((ix, c)) = chars.next() {
+        if c == '\\' && quote == '"' {
+            // skip the escaped character
+            chars.next();
+        } else if c == quote {
+            return ix + c.len_utf8();
+        }
+    }
+
+    0
+}
+
+fn shift_span(span: &mut Span, offset: u32) {
+    *span = Span::new(span.start().0 + offset, span.end().0 + offset);
+}
+
+fn span(start: usize, end: usize) -> Span {
+    Span::new(start as u32, end as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUNEFILE: &str = r#"version: 1
+image: runicos/base
+
+pipeline:
+  audio:
+    capability: SOUND
+    outputs:
+    - type: i16
+      dimensions: [16000]
+    args:
+      hz: 16000
+  "model":
+    model: $MODEL
+    inputs: [audio]
+    outputs:
+      - type: i8
+        dimensions: [6]
+  serial:
+    out: serial
+    inputs:
+    - model
+    - "audio"
+resources:
+  MODEL:
+    path: ./model.tflite
+    type: binary
+"#;
+
+    fn text(span: Span) -> &'static str {
+        &RUNEFILE[span.start().to_usize()..span.end().to_usize()]
+    }
+
+    #[test]
+    fn locate_stages_and_resources() {
+        let spans = DocumentSpans::parse(RUNEFILE).unwrap();
+
+        assert_eq!(text(spans.stage("audio")), "audio");
+        assert_eq!(text(spans.stage("model")), "\"model\"");
+        assert_eq!(text(spans.resource("MODEL")), "MODEL");
+        assert_eq!(text(spans.field("audio", "capability")), "SOUND");
+        assert_eq!(text(spans.field("model", "model")), "$MODEL");
+        assert_eq!(text(spans.argument("audio", "hz")), "16000");
+        assert_eq!(text(spans.input("model", 0)), "audio");
+        assert_eq!(text(spans.input("serial", 0)), "model");
+        assert_eq!(text(spans.input("serial", 1)), "\"audio\"");
+        assert!(text(spans.output("model", 0)).starts_with("type: i8"));
+    }
+
+    #[test]
+    fn missing_items_fall_back_to_their_stage() {
+        let spans = DocumentSpans::parse(RUNEFILE).unwrap();
+
+        assert_eq!(spans.input("serial", 42), spans.stage("serial"));
+        assert_eq!(spans.argument("model", "format"), spans.stage("model"));
+        assert_eq!(spans.stage("nonexistent"), Span::default());
+    }
+
+    #[test]
+    fn locate_templates() {
+        let src =
+            "includes: [common.yml]\ntemplates:\n  filter:\n    args:\n      \
+             hz: ~\n    outputs: [fft]\n    pipeline:\n      fft:\n        \
+             proc-block: fft\n";
+
+        let spans = DocumentSpans::parse_at(src, 100).unwrap();
+
+        let text = |span: Span| {
+            &src[span.start().to_usize() - 100..span.end().to_usize() - 100]
+        };
+        let template = &spans.templates["filter"];
+        assert_eq!(text(spans.includes[0]), "common.yml");
+        assert_eq!(text(template.name), "filter");
+        assert_eq!(text(template.args["hz"]), "hz");
+        assert_eq!(text(template.outputs[0]), "fft");
+        assert_eq!(text(template.stages["fft"].name), "fft");
+    }
+
+    #[test]
+    fn locate_variables_and_profiles() {
+        let src = "variables:\n  THRESHOLD: 0.5\n  MODEL: ~\nprofiles:\n  \
+                   release:\n    THRESHOLD: 0.9\n";
+
+        let spans = DocumentSpans::parse(src).unwrap();
+
+        let text =
+            |span: Span| &src[span.start().to_usize()..span.end().to_usize()];
+        assert_eq!(text(spans.variable("MODEL")), "MODEL");
+        assert_eq!(text(spans.profiles["release"].name), "release");
+        assert_eq!(
+            text(spans.profile_variable("release", "THRESHOLD")),
+            "THRESHOLD"
+        );
+        assert_eq!(text(spans.profile_variable("release", "MODEL")), "release");
+    }
+
+    #[test]
+    fn offsets_are_in_bytes() {
+        let src = "# ünïcödé\npipeline:\n  stage:\n    out: serial\n";
+
+        let spans = DocumentSpans::parse(src).unwrap();
+
+        let name = spans.stage("stage");
+        let name = &src[name.start().to_usize()..name.end().to_usize()];
+        assert_eq!(name, "stage");
+    }
+}
@@ -0,0 +1,811 @@
+//! Conversions between version 1 and version 2 Runefiles.
+//!
+//! The rest of the compiler only knows about [`DocumentV1`], so a
+//! [`DocumentV2`] is converted into one while parsing. Named ports become
+//! positional inputs and outputs, in the order they were declared.
+//!
+//! Going the other way ([`upgrade()`]) is what `rune fmt --upgrade` uses.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use indexmap::IndexMap;
+
+use crate::{
+    parse::{
+        variables::invalid_path_diagnostic, CapabilityStage, Condition,
+        DelayStage, DocumentSpans, DocumentV1, DocumentV2, Input, ModelStage,
+        OutStage, Path, PortReference, ProcBlockStage, ResourceOrString, Stage,
+        StageKind, StageV2,
+    },
+    Diagnostics,
+};
+
+/// Convert a [`DocumentV2`] into the equivalent [`DocumentV1`].
+///
+/// References to unknown stages are passed through untouched so they get
+/// reported alongside version 1 Runefiles, but references to unknown ports
+/// are reported here.
+pub(crate) fn to_v1(
+    doc: DocumentV2,
+    mut spans: DocumentSpans,
+    diags: &mut Diagnostics,
+) -> (DocumentV1, DocumentSpans) {
+    let DocumentV2 {
+        version: _,
+        image,
+        variables,
+        profiles,
+        pipeline,
+        pipelines,
+        resources,
+    } = doc;
+
+    let mut stages = IndexMap::new();
+
+    for (name, stage) in &pipeline {
+        let inputs = inputs(name, stage, &pipeline, &spans, diags);
+        let when = condition(name, stage, &pipeline, &spans, diags);
+
+        if let Some(converted) =
+            convert(name, stage, inputs, when, &spans, diags)
+        {
+            stages.insert(name.clone(), converted);
+        }
+
+        // Later phases look for the field named after the stage's kind
+        if let Some(stage_spans) = spans.stages.get_mut(name) {
+            if let Some(span) = stage_spans.fields.shift_remove("uses") {
+                stage_spans.fields.insert(stage.kind.to_string(), span);
+            }
+        }
+    }
+
+    let doc = DocumentV1 {
+        version: 1,
+        image,
+        includes: Vec::new(),
+        variables,
+        profiles,
+        pipeline: stages,
+        pipelines,
+        templates: IndexMap::new(),
+        resources,
+    };
+
+    (doc, spans)
+}
+
+fn inputs(
+    name: &str,
+    stage: &StageV2,
+    pipeline: &IndexMap<String, StageV2>,
+    spans: &DocumentSpans,
+    diags: &mut Diagnostics,
+) -> Vec<Input> {
+    let mut inputs = Vec::new();
+
+    for (i, reference) in stage.inputs.values().enumerate() {
+        match resolve(reference, pipeline) {
+            Ok(input) => inputs.push(input),
+            Err(upstream) => diags.push(unknown_port_diagnostic(
+                reference,
+                upstream,
+                spans.input(name, i),
+            )),
+        }
+    }
+
+    inputs
+}
+
+fn condition(
+    name: &str,
+    stage: &StageV2,
+    pipeline: &IndexMap<String, StageV2>,
+    spans: &DocumentSpans,
+    diags: &mut Diagnostics,
+) -> Option<Condition> {
+    let when = stage.when.as_ref()?;
+
+    match resolve(&when.input, pipeline) {
+        Ok(input) => Some(Condition::new(input, when.comparison, when.value)),
+        Err(upstream) => {
+            diags.push(unknown_port_diagnostic(
+                &when.input,
+                upstream,
+                spans.field(name, "when"),
+            ));
+            None
+        },
+    }
+}
+
+/// Turn a [`PortReference`] into the equivalent positional [`Input`],
+/// returning the upstream stage if it doesn't have the port.
+fn resolve<'a>(
+    reference: &PortReference,
+    pipeline: &'a IndexMap<String, StageV2>,
+) -> Result<Input, &'a StageV2> {
+    let upstream = match pipeline.get(&reference.stage) {
+        Some(s) => s,
+        None => return Ok(Input::new(&reference.stage, None)),
+    };
+
+    match upstream.outputs.get_index_of(&reference.port) {
+        Some(0) => Ok(Input::new(&reference.stage, None)),
+        Some(index) => Ok(Input::new(&reference.stage, index)),
+        None => Err(upstream),
+    }
+}
+
+fn convert(
+    name: &str,
+    stage: &StageV2,
+    inputs: Vec<Input>,
+    when: Option<Condition>,
+    spans: &DocumentSpans,
+    diags: &mut Diagnostics,
+) -> Option<Stage> {
+    let StageV2 {
+        kind,
+        uses,
+        outputs,
+        args,
+        ..
+    } = stage.clone();
+    let outputs = outputs.into_iter().map(|(_, ty)| ty).collect();
+    let uses_span = spans.field(name, "uses");
+
+    if kind == StageKind::Model {
+        return Some(Stage::Model(ModelStage {
+            model: uses,
+            inputs,
+            outputs,
+            args,
+            when,
+        }));
+    }
+
+    let uses = match uses {
+        ResourceOrString::String(s) => s,
+        ResourceOrString::Resource(_) => {
+            diags.push(resource_not_allowed_diagnostic(name, kind, uses_span));
+            return None;
+        },
+    };
+
+    match kind {
+        StageKind::Capability => {
+            if !stage.inputs.is_empty() {
+                diags.push(unexpected_ports_diagnostic(
+                    name,
+                    kind,
+                    "inputs",
+                    spans.input(name, 0),
+                ));
+            }
+            if stage.when.is_some() {
+                diags.push(unexpected_ports_diagnostic(
+                    name,
+                    kind,
+                    "a condition",
+                    spans.field(name, "when"),
+                ));
+            }
+
+            Some(Stage::Capability(CapabilityStage {
+                capability: uses,
+                outputs,
+                args,
+            }))
+        },
+        StageKind::ProcBlock => match uses.parse::<Path>() {
+            Ok(proc_block) if proc_block.to_string() == uses => {
+                Some(Stage::ProcBlock(ProcBlockStage {
+                    proc_block,
+                    inputs,
+                    outputs,
+                    args,
+                    when,
+                }))
+            },
+            _ => {
+                diags.push(invalid_path_diagnostic(name, &uses, uses_span));
+                None
+            },
+        },
+        StageKind::Out => {
+            if !stage.outputs.is_empty() {
+                diags.push(unexpected_ports_diagnostic(
+                    name,
+                    kind,
+                    "outputs",
+                    spans.output(name, 0),
+                ));
+            }
+
+            Some(Stage::Out(OutStage {
+                out: uses,
+                inputs,
+                args,
+                when,
+            }))
+        },
+        StageKind::Delay => {
+            if stage.when.is_some() {
+                diags.push(unexpected_ports_diagnostic(
+                    name,
+                    kind,
+                    "a condition",
+                    spans.field(name, "when"),
+                ));
+            }
+
+            match uses.trim().parse::<f64>() {
+                Ok(initial) => Some(Stage::Delay(DelayStage {
+                    initial,
+                    inputs,
+                    outputs,
+                })),
+                Err(_) => {
+                    diags.push(invalid_initial_value_diagnostic(
+                        name, &uses, uses_span,
+                    ));
+                    None
+                },
+            }
+        },
+        StageKind::Model => unreachable!("Handled above"),
+    }
+}
+
+fn invalid_initial_value_diagnostic(
+    name: &str,
+    value: &str,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" delay should start with a number, not \"{}\"",
+            name, value
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec!["hint: a delay's \"uses\" is the value it outputs \
+                          on the first call"
+            .to_string()])
+}
+
+fn unknown_port_diagnostic(
+    reference: &PortReference,
+    upstream: &StageV2,
+    span: Span,
+) -> Diagnostic<()> {
+    let hint = if upstream.outputs.is_empty() {
+        format!("hint: \"{}\" doesn't have any outputs", reference.stage)
+    } else {
+        let names: Vec<_> =
+            upstream.outputs.keys().map(|s| s.as_str()).collect();
+        format!(
+            "hint: the outputs of \"{}\" are {}",
+            reference.stage,
+            names.join(", ")
+        )
+    };
+
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage has no \"{}\" output",
+            reference.stage, reference.port
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![hint])
+}
+
+fn resource_not_allowed_diagnostic(
+    name: &str,
+    kind: StageKind,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage is a {}, so it can't use a resource",
+            name, kind
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec!["hint: only model stages can be loaded from a \
+                          resource"
+            .to_string()])
+}
+
+fn unexpected_ports_diagnostic(
+    name: &str,
+    kind: StageKind,
+    ports: &str,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage is a {}, so it can't have {}",
+            name, kind, ports
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+/// Convert a [`DocumentV1`] to the equivalent [`DocumentV2`].
+///
+/// A stage's outputs are called `output` when it only has one, or `output_0`,
+/// `output_1`, etc. when it has several. Inputs are named after the stage
+/// they come from.
+pub fn upgrade(doc: DocumentV1) -> Result<DocumentV2, UpgradeError> {
+    let DocumentV1 {
+        version: _,
+        image,
+        includes,
+        variables,
+        profiles,
+        pipeline,
+        pipelines,
+        templates,
+        resources,
+    } = doc;
+
+    if !includes.is_empty() {
+        return Err(UpgradeError::Unsupported("includes"));
+    }
+    if !templates.is_empty() {
+        return Err(UpgradeError::Unsupported("templates"));
+    }
+
+    let output_counts: HashMap<&str, usize> = pipeline
+        .iter()
+        .map(|(name, stage)| (name.as_str(), stage.output_types().len()))
+        .collect();
+
+    let mut stages = IndexMap::new();
+
+    for (name, stage) in &pipeline {
+        let when = stage.condition().cloned();
+        let (kind, uses, inputs, outputs, args) = match stage.clone() {
+            Stage::Capability(c) => (
+                StageKind::Capability,
+                c.capability.into(),
+                Vec::new(),
+                c.outputs,
+                c.args,
+            ),
+            Stage::Model(m) => {
+                (StageKind::Model, m.model, m.inputs, m.outputs, m.args)
+            },
+            Stage::ProcBlock(p) => (
+                StageKind::ProcBlock,
+                p.proc_block.to_string().into(),
+                p.inputs,
+                p.outputs,
+                p.args,
+            ),
+            Stage::Out(o) => {
+                (StageKind::Out, o.out.into(), o.inputs, Vec::new(), o.args)
+            },
+            Stage::Delay(d) => (
+                StageKind::Delay,
+                d.initial.to_string().into(),
+                d.inputs,
+                d.outputs,
+                IndexMap::new(),
+            ),
+            Stage::Template(_) => {
+                return Err(UpgradeError::Unsupported("templates"))
+            },
+        };
+
+        let reference = |input: &Input| {
+            let index = input.index.unwrap_or(0);
+
+            match output_counts.get(input.name.as_str()) {
+                Some(&count) if index < count => {
+                    let port = output_names(count).swap_remove(index);
+                    Ok(PortReference::new(&input.name, port))
+                },
+                _ => Err(UpgradeError::UnknownInput {
+                    stage: name.clone(),
+                    input: input.clone(),
+                }),
+            }
+        };
+
+        let references = inputs
+            .iter()
+            .map(reference)
+            .collect::<Result<Vec<_>, _>>()?;
+        let when = match when {
+            Some(c) => Some(Condition::new(
+                reference(&c.input)?,
+                c.comparison,
+                c.value,
+            )),
+            None => None,
+        };
+
+        let outputs = output_names(outputs.len()).into_iter().zip(outputs);
+
+        stages.insert(
+            name.clone(),
+            StageV2 {
+                kind,
+                uses,
+                inputs: input_names(&inputs)
+                    .into_iter()
+                    .zip(references)
+                    .collect(),
+                outputs: outputs.collect(),
+                args,
+                when,
+            },
+        );
+    }
+
+    Ok(DocumentV2 {
+        version: 2,
+        image,
+        variables,
+        profiles,
+        pipeline: stages,
+        pipelines,
+        resources,
+    })
+}
+
+fn output_names(count: usize) -> Vec<String> {
+    match count {
+        1 => vec!["output".to_string()],
+        _ => (0..count).map(|i| format!("output_{}", i)).collect(),
+    }
+}
+
+/// Name each input after the stage it comes from, falling back to something
+/// more specific when that would be ambiguous.
+fn input_names(inputs: &[Input]) -> Vec<String> {
+    let candidates: [fn(usize, &Input) -> String; 3] = [
+        |_, input| input.name.clone(),
+        |_, input| format!("{}_{}", input.name, input.index.unwrap_or(0)),
+        |i, _| format!("input_{}", i),
+    ];
+
+    for candidate in &candidates {
+        let names: Vec<String> = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, input)| candidate(i, input))
+            .collect();
+        let unique: HashSet<&String> = names.iter().collect();
+
+        if unique.len() == names.len() {
+            return names;
+        }
+    }
+
+    unreachable!("The positional names are always unique")
+}
+
+/// The reason a [`DocumentV1`] couldn't be [`upgrade()`]d.
+#[derive(Debug)]
+pub enum UpgradeError {
+    /// The Runefile couldn't be parsed.
+    Parse(serde_yaml::Error),
+    /// The Runefile uses something version 2 Runefiles don't support.
+    Unsupported(&'static str),
+    /// A stage's input refers to an output that doesn't exist.
+    UnknownInput { stage: String, input: Input },
+}
+
+impl Display for UpgradeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UpgradeError::Parse(_) => write!(f, "Unable to parse the Runefile"),
+            UpgradeError::Unsupported(feature) => {
+                write!(f, "Version 2 Runefiles don't support {} yet", feature)
+            },
+            UpgradeError::UnknownInput { stage, input } => write!(
+                f,
+                "The \"{}\" stage's \"{}\" input doesn't refer to a known \
+                 output",
+                stage, input
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpgradeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UpgradeError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for UpgradeError {
+    fn from(e: serde_yaml::Error) -> Self { UpgradeError::Parse(e) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{Comparison, Document};
+
+    const V2: &str = r#"
+version: 2
+image: runicos/base
+pipeline:
+  audio:
+    kind: capability
+    uses: SOUND
+    outputs:
+      samples: {type: i16, dimensions: [16000]}
+    args:
+      hz: 16000
+  split:
+    kind: proc-block
+    uses: hotg-ai/proc-blocks@v0.11.3#split
+    inputs:
+      audio: audio.samples
+    outputs:
+      left: {type: i16, dimensions: [8000]}
+      right: {type: i16, dimensions: [8000]}
+  model:
+    kind: model
+    uses: $MODEL
+    inputs:
+      left: split.left
+      right: split.right
+    outputs:
+      label: {type: u8, dimensions: [1]}
+  serial:
+    kind: out
+    uses: SERIAL
+    inputs:
+      label: model.label
+resources:
+  MODEL:
+    path: ./model.tflite
+"#;
+
+    fn convert(src: &str) -> (DocumentV1, DocumentSpans, Vec<Diagnostic<()>>) {
+        let doc = match Document::parse(src).unwrap() {
+            Document::V2(v2) => v2,
+            other => panic!("Expected a v2 document, found {:?}", other),
+        };
+        let spans = DocumentSpans::parse(src).unwrap();
+        let mut diags = Diagnostics::new();
+
+        let (doc, spans) = to_v1(doc, spans, &mut diags);
+
+        (doc, spans, diags.into_iter().collect())
+    }
+
+    #[test]
+    fn convert_ports_to_positions() {
+        let (doc, spans, diags) = convert(V2);
+
+        assert!(diags.is_empty(), "{:?}", diags);
+        let inputs: Vec<_> = doc.pipeline["model"]
+            .inputs()
+            .iter()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(inputs, vec!["split", "split.1"]);
+        assert_eq!(
+            doc.pipeline["model"],
+            Stage::Model(ModelStage {
+                model: ResourceOrString::Resource("$MODEL".parse().unwrap()),
+                inputs: vec![Input::new("split", None), Input::new("split", 1)],
+                outputs: vec![ty!(u8[1])],
+                args: IndexMap::new(),
+                when: None,
+            })
+        );
+        assert!(matches!(doc.pipeline["audio"], Stage::Capability(_)));
+        assert!(matches!(doc.pipeline["serial"], Stage::Out(_)));
+
+        let field = spans.field("split", "proc-block");
+        let text = &V2[field.start().to_usize()..field.end().to_usize()];
+        assert_eq!(text, "hotg-ai/proc-blocks@v0.11.3#split");
+        let input = spans.input("model", 1);
+        let text = &V2[input.start().to_usize()..input.end().to_usize()];
+        assert_eq!(text, "split.right");
+    }
+
+    #[test]
+    fn unknown_ports_are_reported() {
+        let src = V2.replace("split.right", "split.middle");
+
+        let (_, _, diags) = convert(&src);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"split\" stage has no \"middle\" output"
+        );
+        assert_eq!(
+            diags[0].notes,
+            vec!["hint: the outputs of \"split\" are left, right"]
+        );
+    }
+
+    #[test]
+    fn convert_conditions() {
+        let src = V2.replace(
+            "      label: model.label\n",
+            "      label: model.label\n    when: split.right >= 0.5\n",
+        );
+
+        let (doc, _, diags) = convert(&src);
+
+        assert!(diags.is_empty(), "{:?}", diags);
+        assert_eq!(
+            doc.pipeline["serial"].condition(),
+            Some(&Condition::new(
+                Input::new("split", 1),
+                Comparison::GreaterThanOrEqual,
+                0.5
+            ))
+        );
+
+        let upgraded = upgrade(doc).unwrap();
+        assert_eq!(
+            upgraded.pipeline["serial"]
+                .when
+                .as_ref()
+                .unwrap()
+                .to_string(),
+            "split.output_1 >= 0.5"
+        );
+    }
+
+    #[test]
+    fn conditions_with_unknown_ports_are_reported() {
+        let src = V2.replace(
+            "      label: model.label\n",
+            "      label: model.label\n    when: model.score > 0.5\n",
+        );
+
+        let (_, _, diags) = convert(&src);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"model\" stage has no \"score\" output"
+        );
+        let label = diags[0].labels[0].range.clone();
+        assert_eq!(&src[label], "model.score > 0.5");
+    }
+
+    const FEEDBACK: &str = r#"
+version: 2
+image: runicos/base
+pipeline:
+  previous:
+    kind: delay
+    uses: 0.5
+    inputs:
+      value: smoothed.output
+    outputs:
+      output: {type: f32, dimensions: [1]}
+  smoothed:
+    kind: proc-block
+    uses: hotg-ai/proc-blocks@v0.11.3#smooth
+    inputs:
+      previous: previous.output
+    outputs:
+      output: {type: f32, dimensions: [1]}
+"#;
+
+    #[test]
+    fn convert_delays() {
+        let (doc, _, diags) = convert(FEEDBACK);
+
+        assert!(diags.is_empty(), "{:?}", diags);
+        assert_eq!(
+            doc.pipeline["previous"],
+            Stage::Delay(DelayStage {
+                initial: 0.5,
+                inputs: vec![Input::new("smoothed", None)],
+                outputs: vec![ty!(f32[1])],
+            })
+        );
+
+        let upgraded = upgrade(doc).unwrap();
+        let previous = &upgraded.pipeline["previous"];
+        assert_eq!(previous.kind, StageKind::Delay);
+        assert_eq!(previous.uses.to_string(), "0.5");
+    }
+
+    #[test]
+    fn delays_must_start_with_a_number() {
+        let src = FEEDBACK.replace("uses: 0.5", "uses: zero");
+
+        let (_, _, diags) = convert(&src);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"previous\" delay should start with a number, not \"zero\""
+        );
+    }
+
+    #[test]
+    fn only_models_can_use_resources() {
+        let src = V2.replace("uses: SOUND", "uses: $SOUND");
+
+        let (_, _, diags) = convert(&src);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"audio\" stage is a capability, so it can't use a resource"
+        );
+    }
+
+    #[test]
+    fn upgrade_and_convert_back() {
+        let (v1, _, _) = convert(V2);
+
+        let upgraded = upgrade(v1.clone()).unwrap();
+
+        let names = |stage: &str| -> Vec<(String, String)> {
+            upgraded.pipeline[stage]
+                .inputs
+                .iter()
+                .map(|(port, r)| (port.clone(), r.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            names("model"),
+            vec![
+                ("split_0".to_string(), "split.output_0".to_string()),
+                ("split_1".to_string(), "split.output_1".to_string()),
+            ]
+        );
+        assert_eq!(
+            names("split"),
+            vec![("audio".to_string(), "audio.output".to_string())]
+        );
+        let (round_tripped, _) =
+            to_v1(upgraded, DocumentSpans::default(), &mut Diagnostics::new());
+        assert_eq!(round_tripped, v1);
+    }
+
+    #[test]
+    fn upgrade_errors() {
+        let src = "version: 1\nimage: runicos/base\npipeline:\n  serial:\n    \
+                   out: SERIAL\n    inputs: [audio.1]\n";
+        let doc = Document::parse(src).unwrap().to_v1();
+
+        let err = upgrade(doc).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "The \"serial\" stage's \"audio.1\" input doesn't refer to a \
+             known output"
+        );
+    }
+
+    #[test]
+    fn name_inputs() {
+        let inputs = |names: &[&str]| -> Vec<Input> {
+            names.iter().map(|n| n.parse().unwrap()).collect()
+        };
+
+        assert_eq!(input_names(&inputs(&["a", "b"])), vec!["a", "b"]);
+        assert_eq!(input_names(&inputs(&["a", "a.1"])), vec!["a_0", "a_1"]);
+        assert_eq!(
+            input_names(&inputs(&["a", "a"])),
+            vec!["input_0", "input_1"]
+        );
+    }
+}
@@ -0,0 +1,608 @@
+//! Substitute `${NAME}` references with the values of a Runefile's
+//! `variables`.
+//!
+//! A variable's value comes from (in order of increasing precedence)
+//!
+//! 1. Its default in the `variables` section
+//! 2. The selected profile
+//! 3. An environment variable with the same name
+//! 4. The [`BuildContext::variables`][crate::BuildContext::variables]
+//!    (i.e. `rune build --var NAME=value`)
+
+use std::ops::Range;
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use indexmap::IndexMap;
+
+use crate::{
+    parse::{
+        Argument, DocumentSpans, DocumentV1, Path, ResourceOrString, Stage,
+    },
+    Diagnostics,
+};
+
+/// Replace every `${NAME}` in the stage arguments, model paths, proc-block
+/// paths, and resources of a [`DocumentV1`].
+pub(crate) fn resolve(
+    doc: &mut DocumentV1,
+    spans: &DocumentSpans,
+    overrides: &IndexMap<String, String>,
+    profile: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+    diags: &mut Diagnostics,
+) {
+    let mut resolver = Resolver {
+        values: doc
+            .variables
+            .iter()
+            .map(|(name, default)| {
+                (name.clone(), default.as_ref().map(|Argument(v)| v.clone()))
+            })
+            .collect(),
+        spans,
+        diags: Vec::new(),
+    };
+
+    resolver.apply_profiles(&doc.profiles, profile);
+
+    for (name, value) in &mut resolver.values {
+        if let Some(v) = env(name) {
+            *value = Some(ResourceOrString::String(v));
+        }
+    }
+
+    for (name, value) in overrides {
+        match resolver.values.get_mut(name) {
+            Some(v) => *v = Some(ResourceOrString::String(value.clone())),
+            None => {
+                let diag = unknown_override_diagnostic(name, &doc.variables);
+                resolver.report(diag);
+            },
+        }
+    }
+
+    for (name, stage) in &mut doc.pipeline {
+        for (key, Argument(value)) in stage.args_mut().into_iter().flatten() {
+            resolver.substitute(value, spans.argument(name, key));
+        }
+
+        match stage {
+            Stage::Model(m) => {
+                resolver.substitute(&mut m.model, spans.field(name, "model"));
+            },
+            Stage::ProcBlock(p) => {
+                let span = spans.field(name, "proc-block");
+                resolver.substitute_path(name, &mut p.proc_block, span);
+            },
+            _ => {},
+        }
+    }
+
+    for (name, decl) in &mut doc.resources {
+        let span = spans.resource(name);
+        let fields = decl.path.iter_mut().chain(decl.inline.iter_mut());
+
+        for field in fields {
+            if let Some(value) = resolver.interpolate(field, span) {
+                *field = value.to_string();
+            }
+        }
+    }
+
+    for diag in resolver.diags {
+        diags.push(diag);
+    }
+}
+
+struct Resolver<'a> {
+    /// The value for each variable, or `None` if it doesn't have one.
+    values: IndexMap<String, Option<ResourceOrString>>,
+    spans: &'a DocumentSpans,
+    diags: Vec<Diagnostic<()>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn apply_profiles(
+        &mut self,
+        profiles: &IndexMap<String, IndexMap<String, Argument>>,
+        selected: Option<&str>,
+    ) {
+        // Note: we check every profile so mistakes aren't only noticed when
+        // someone finally selects it
+        for (profile, values) in profiles {
+            for name in values.keys() {
+                if !self.values.contains_key(name) {
+                    let span = self.spans.profile_variable(profile, name);
+                    let diag = unknown_profile_variable_diagnostic(
+                        profile,
+                        name,
+                        &self.values,
+                        span,
+                    );
+                    self.report(diag);
+                }
+            }
+        }
+
+        let selected = match selected {
+            Some(s) => s,
+            None => return,
+        };
+
+        match profiles.get(selected) {
+            Some(values) => {
+                for (name, Argument(value)) in values {
+                    if let Some(v) = self.values.get_mut(name) {
+                        *v = Some(value.clone());
+                    }
+                }
+            },
+            None => {
+                self.report(unknown_profile_diagnostic(selected, profiles));
+            },
+        }
+    }
+
+    /// Push a [`Diagnostic`], ignoring it if it has already been reported.
+    fn report(&mut self, diag: Diagnostic<()>) {
+        if !self.diags.contains(&diag) {
+            self.diags.push(diag);
+        }
+    }
+
+    fn substitute(&mut self, value: &mut ResourceOrString, span: Span) {
+        if let ResourceOrString::String(s) = value {
+            if let Some(new_value) = self.interpolate(s, span) {
+                *value = new_value;
+            }
+        }
+    }
+
+    fn substitute_path(&mut self, stage: &str, path: &mut Path, span: Span) {
+        let original = path.to_string();
+
+        let replaced = match self.interpolate(&original, span) {
+            Some(value) => value.to_string(),
+            None => return,
+        };
+
+        match replaced.parse::<Path>() {
+            Ok(p) if p.to_string() == replaced => *path = p,
+            _ => self.report(invalid_path_diagnostic(stage, &replaced, span)),
+        }
+    }
+
+    /// Replace the variables in a string, returning `None` if there was
+    /// nothing to replace or an error was reported.
+    ///
+    /// A string which is just a variable (e.g. `"${MODEL}"`) takes on that
+    /// variable's value, so a variable may refer to a resource.
+    fn interpolate(
+        &mut self,
+        text: &str,
+        span: Span,
+    ) -> Option<ResourceOrString> {
+        let references = match references(text) {
+            Ok(refs) if refs.is_empty() => return None,
+            Ok(refs) => refs,
+            Err(BadReference) => {
+                self.report(bad_reference_diagnostic(text, span));
+                return None;
+            },
+        };
+
+        let mut values = Vec::new();
+
+        for (_, name) in &references {
+            match self.values.get(*name) {
+                Some(Some(value)) => values.push(value.clone()),
+                Some(None) => {
+                    let declared = self.spans.variable(name);
+                    self.report(no_value_diagnostic(name, span, declared));
+                    return None;
+                },
+                None => {
+                    let diag =
+                        unknown_variable_diagnostic(name, &self.values, span);
+                    self.report(diag);
+                    return None;
+                },
+            }
+        }
+
+        if let [(range, _)] = references.as_slice() {
+            if *range == (0..text.len()) {
+                return values.pop();
+            }
+        }
+
+        let mut replaced = String::new();
+        let mut last_end = 0;
+
+        for ((range, _), value) in references.iter().zip(&values) {
+            replaced.push_str(&text[last_end..range.start]);
+            replaced.push_str(&value.to_string());
+            last_end = range.end;
+        }
+        replaced.push_str(&text[last_end..]);
+
+        Some(ResourceOrString::String(replaced))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct BadReference;
+
+/// Find each `${NAME}` in a string.
+fn references(text: &str) -> Result<Vec<(Range<usize>, &str)>, BadReference> {
+    let mut refs = Vec::new();
+    let mut rest = 0;
+
+    while let Some(start) = text[rest..].find("${").map(|ix| ix + rest) {
+        let name_start = start + 2;
+        let end = text[name_start..]
+            .find('}')
+            .map(|ix| ix + name_start)
+            .ok_or(BadReference)?;
+        let name = &text[name_start..end];
+
+        if !is_identifier(name) {
+            return Err(BadReference);
+        }
+
+        refs.push((start..end + 1, name));
+        rest = end + 1;
+    }
+
+    Ok(refs)
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    match chars.next() {
+        Some(first) if first == '_' || first.is_ascii_alphabetic() => {
+            chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+        },
+        _ => false,
+    }
+}
+
+fn known_variables<V>(values: &IndexMap<String, V>) -> String {
+    if values.is_empty() {
+        "hint: declare it in the Runefile's \"variables\" section".to_string()
+    } else {
+        let names: Vec<_> = values.keys().map(|s| s.as_str()).collect();
+        format!("hint: the known variables are {}", names.join(", "))
+    }
+}
+
+fn unknown_variable_diagnostic(
+    name: &str,
+    values: &IndexMap<String, Option<ResourceOrString>>,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!("There is no variable called \"{}\"", name))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![known_variables(values)])
+}
+
+fn no_value_diagnostic(
+    name: &str,
+    span: Span,
+    declared: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!("The \"{}\" variable doesn't have a value", name))
+        .with_labels(vec![
+            Label::primary((), span),
+            Label::secondary((), declared).with_message("declared here"),
+        ])
+        .with_notes(vec![format!(
+            "hint: give it a default, set it in a profile, set the \"{0}\" \
+             environment variable, or use \"--var {0}=...\"",
+            name
+        )])
+}
+
+fn bad_reference_diagnostic(text: &str, span: Span) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!("Invalid variable reference in \"{}\"", text))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![
+            "hint: variables are written like \"${NAME}\"".to_string()
+        ])
+}
+
+pub(super) fn invalid_path_diagnostic(
+    stage: &str,
+    path: &str,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage's proc-block, \"{}\", isn't a valid path",
+            stage, path
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn unknown_profile_diagnostic(
+    profile: &str,
+    profiles: &IndexMap<String, IndexMap<String, Argument>>,
+) -> Diagnostic<()> {
+    let hint = if profiles.is_empty() {
+        "hint: this Runefile doesn't define any profiles".to_string()
+    } else {
+        let names: Vec<_> = profiles.keys().map(|s| s.as_str()).collect();
+        format!("hint: the known profiles are {}", names.join(", "))
+    };
+
+    Diagnostic::error()
+        .with_message(format!("There is no profile called \"{}\"", profile))
+        .with_notes(vec![hint])
+}
+
+fn unknown_profile_variable_diagnostic(
+    profile: &str,
+    name: &str,
+    values: &IndexMap<String, Option<ResourceOrString>>,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" profile sets \"{}\", but there is no variable with \
+             that name",
+            profile, name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![known_variables(values)])
+}
+
+fn unknown_override_diagnostic(
+    name: &str,
+    variables: &IndexMap<String, Option<Argument>>,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "Unable to set \"{}\" because there is no variable with that name",
+            name
+        ))
+        .with_notes(vec![known_variables(variables)])
+}
+
+#[cfg(test)]
+mod tests {
+    use codespan_reporting::diagnostic::Severity;
+
+    use super::*;
+    use crate::parse::Document;
+
+    struct Resolved {
+        doc: DocumentV1,
+        diags: Vec<Diagnostic<()>>,
+    }
+
+    impl Resolved {
+        fn messages(&self) -> Vec<&str> {
+            self.diags.iter().map(|d| d.message.as_str()).collect()
+        }
+
+        fn arg(&self, stage: &str, key: &str) -> &ResourceOrString {
+            &self.doc.pipeline[stage].args()[key].0
+        }
+    }
+
+    fn resolve_with(
+        src: &str,
+        overrides: &[(&str, &str)],
+        profile: Option<&str>,
+        env: &[(&str, &str)],
+    ) -> Resolved {
+        let mut doc = Document::parse(src).unwrap().to_v1();
+        let spans = DocumentSpans::parse(src).unwrap();
+        let overrides = overrides
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let env = |name: &str| {
+            env.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        };
+        let mut diags = Diagnostics::new();
+
+        super::resolve(&mut doc, &spans, &overrides, profile, env, &mut diags);
+
+        Resolved {
+            doc,
+            diags: diags.into_iter().collect(),
+        }
+    }
+
+    const RUNEFILE: &str = r#"
+version: 1
+image: runicos/base
+variables:
+  MODEL_DIR: ./models
+  THRESHOLD: 0.5
+  VERSION: v0.11.3
+profiles:
+  release:
+    THRESHOLD: 0.9
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  sine:
+    model: ${MODEL_DIR}/sine.tflite
+    inputs: [rand]
+    outputs: [{type: f32, dimensions: [1]}]
+  filter:
+    proc-block: hotg-ai/proc-blocks@${VERSION}#threshold
+    inputs: [sine]
+    outputs: [{type: f32, dimensions: [1]}]
+    args:
+      threshold: ${THRESHOLD}
+      label: "above ${THRESHOLD}"
+  serial:
+    out: SERIAL
+    inputs: [filter]
+resources:
+  LABELS:
+    path: ${MODEL_DIR}/labels.txt
+"#;
+
+    #[test]
+    fn substitute_default_values() {
+        let got = resolve_with(RUNEFILE, &[], None, &[]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        assert_eq!(got.arg("filter", "threshold"), &"0.5".into());
+        assert_eq!(got.arg("filter", "label"), &"above 0.5".into());
+        match &got.doc.pipeline["sine"] {
+            Stage::Model(m) => {
+                assert_eq!(m.model, "./models/sine.tflite".into())
+            },
+            other => panic!("Expected a model stage, found {:?}", other),
+        }
+        match &got.doc.pipeline["filter"] {
+            Stage::ProcBlock(p) => assert_eq!(
+                p.proc_block.to_string(),
+                "hotg-ai/proc-blocks@v0.11.3#threshold"
+            ),
+            other => panic!("Expected a proc-block stage, found {:?}", other),
+        }
+        assert_eq!(
+            got.doc.resources["LABELS"].path.as_deref(),
+            Some("./models/labels.txt")
+        );
+    }
+
+    #[test]
+    fn profiles_override_defaults() {
+        let got = resolve_with(RUNEFILE, &[], Some("release"), &[]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        assert_eq!(got.arg("filter", "threshold"), &"0.9".into());
+    }
+
+    #[test]
+    fn environment_overrides_profiles() {
+        let got =
+            resolve_with(RUNEFILE, &[], Some("release"), &[("THRESHOLD", "1")]);
+
+        assert_eq!(got.arg("filter", "threshold"), &"1".into());
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let got = resolve_with(
+            RUNEFILE,
+            &[("THRESHOLD", "2")],
+            Some("release"),
+            &[("THRESHOLD", "1")],
+        );
+
+        assert_eq!(got.arg("filter", "threshold"), &"2".into());
+    }
+
+    #[test]
+    fn variables_can_refer_to_resources() {
+        let src = r#"
+version: 1
+image: runicos/base
+variables:
+  AMOUNT: $AMOUNT
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+    args:
+      amount: ${AMOUNT}
+resources:
+  AMOUNT:
+    inline: "4"
+"#;
+
+        let got = resolve_with(src, &[], None, &[]);
+
+        assert!(got.diags.is_empty(), "{:?}", got.diags);
+        assert_eq!(
+            got.arg("rand", "amount"),
+            &ResourceOrString::Resource("$AMOUNT".parse().unwrap())
+        );
+    }
+
+    const BROKEN: &str = r#"
+version: 1
+image: runicos/base
+variables:
+  API_KEY: ~
+profiles:
+  release:
+    APIKEY: secret
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+    args:
+      amount: ${AMOUNT}
+      key: ${API_KEY}
+      broken: ${oops
+"#;
+
+    #[test]
+    fn report_broken_variables() {
+        let got = resolve_with(BROKEN, &[], None, &[]);
+
+        assert!(got.diags.iter().all(|d| d.severity == Severity::Error));
+        assert_eq!(
+            got.messages(),
+            vec![
+                "The \"release\" profile sets \"APIKEY\", but there is no \
+                 variable with that name",
+                "There is no variable called \"AMOUNT\"",
+                "The \"API_KEY\" variable doesn't have a value",
+                "Invalid variable reference in \"${oops\"",
+            ]
+        );
+        assert_eq!(
+            got.diags[1].notes,
+            vec!["hint: the known variables are API_KEY"]
+        );
+    }
+
+    #[test]
+    fn unknown_profiles_and_overrides() {
+        let got =
+            resolve_with(BROKEN, &[("API-KEY", "secret")], Some("debug"), &[]);
+
+        assert_eq!(
+            &got.messages()[1..3],
+            &[
+                "There is no profile called \"debug\"",
+                "Unable to set \"API-KEY\" because there is no variable with \
+                 that name",
+            ]
+        );
+        assert_eq!(
+            got.diags[1].notes,
+            vec!["hint: the known profiles are release"]
+        );
+    }
+
+    #[test]
+    fn find_references() {
+        assert_eq!(references("plain"), Ok(Vec::new()));
+        assert_eq!(
+            references("${A}/b/${C_2}"),
+            Ok(vec![(0..4, "A"), (7..13, "C_2")])
+        );
+        assert_eq!(references("$A and $B"), Ok(Vec::new()));
+        assert_eq!(references("${A"), Err(BadReference));
+        assert_eq!(references("${not-valid}"), Err(BadReference));
+    }
+}
@@ -7,7 +7,7 @@ use std::{
     str::FromStr,
 };
 
-use codespan::Span;
+use hotg_rune_core::arguments::{self, ArgumentDescriptor, ArgumentType};
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -23,6 +23,8 @@ use serde::{
     ser::{Serialize, Serializer},
 };
 
+use crate::{parse::DocumentSpans, Diagnostics};
+
 static RESOURCE_NAME_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^\$[_a-zA-Z][_a-zA-Z0-9]*$").unwrap());
 
@@ -31,12 +33,26 @@ static RESOURCE_NAME_PATTERN: Lazy<Regex> =
 #[schemars(untagged)]
 pub enum Document {
     V1(DocumentV1),
+    V2(DocumentV2),
 }
 
 impl Document {
+    /// Convert the document to a [`DocumentV1`], which is what the rest of
+    /// the compiler works with.
+    ///
+    /// Any references between [`DocumentV2`] stages which can't be resolved
+    /// are dropped. The parse phase reports them as errors.
     pub fn to_v1(self) -> DocumentV1 {
         match self {
             Document::V1(d) => d,
+            Document::V2(d) => {
+                let (v1, _) = super::v2::to_v1(
+                    d,
+                    DocumentSpans::default(),
+                    &mut Diagnostics::new(),
+                );
+                v1
+            },
         }
     }
 }
@@ -45,6 +61,10 @@ impl From<DocumentV1> for Document {
     fn from(v1: DocumentV1) -> Self { Document::V1(v1) }
 }
 
+impl From<DocumentV2> for Document {
+    fn from(v2: DocumentV2) -> Self { Document::V2(v2) }
+}
+
 mod document_serde {
     use serde::de::Unexpected;
     use serde_yaml::Value;
@@ -69,6 +89,7 @@ mod document_serde {
         {
             match self {
                 Document::V1(v1) => Repr::new(1, v1).serialize(serializer),
+                Document::V2(v2) => Repr::new(2, v2).serialize(serializer),
             }
         }
     }
@@ -91,9 +112,14 @@ mod document_serde {
                         .map_err(D::Error::custom)?;
                     Ok(Document::V1(v1))
                 },
+                Some(2) => {
+                    let v2: DocumentV2 = serde_yaml::from_value(value)
+                        .map_err(D::Error::custom)?;
+                    Ok(Document::V2(v2))
+                },
                 Some(other) => Err(D::Error::invalid_value(
                     Unexpected::Unsigned(other),
-                    &"version to be 1",
+                    &"version to be 1 or 2",
                 )),
                 None => Err(D::Error::missing_field("version")),
             }
@@ -143,13 +169,280 @@ pub struct DocumentV1 {
     ///
     /// This should always be `"runicos/base"`.
     pub image: Image,
+    /// Other files containing stages, templates, and resources which should
+    /// be added to this Runefile (see [`Fragment`]).
+    ///
+    /// Paths are relative to the Runefile.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<String>,
+    /// Values which can be substituted into stage arguments and paths by
+    /// writing `${NAME}`.
+    ///
+    /// A variable without a default (`~`) must be given a value by a profile,
+    /// an environment variable with the same name, or `rune build --var`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub variables: IndexMap<String, Option<Argument>>,
+    /// Named sets of values which override the defaults in `variables` (e.g.
+    /// a `release` profile that uses a different model).
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub profiles: IndexMap<String, IndexMap<String, Argument>>,
     /// The various stages in the Runefile's pipeline.
     pub pipeline: IndexMap<String, Stage>,
+    /// Named parts of the pipeline which can be run on their own (e.g. a
+    /// fast accelerometer loop and a slow audio classifier).
+    ///
+    /// Each pipeline runs the stages it lists and every stage they depend on.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub pipelines: IndexMap<String, Vec<String>>,
+    /// Reusable sub-pipelines which can be instantiated by a
+    /// [`TemplateStage`].
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub templates: IndexMap<String, Template>,
     /// Any resources that can be accessed by pipeline stages.
     #[serde(default)]
     pub resources: IndexMap<String, ResourceDeclaration>,
 }
 
+/// Version 2 of the `Runefile.yml` format.
+///
+/// Unlike [`DocumentV1`], every stage is written the same way, the inputs and
+/// outputs of each stage have names, and stages refer to each other's outputs
+/// using `stage.port` (see [`PortReference`]).
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+pub struct DocumentV2 {
+    /// The version number. Must always be `"2"`.
+    #[schemars(required, range(min = 2, max = 2))]
+    pub version: usize,
+    /// The base image that defines the interface between a Rune and its
+    /// runtime.
+    ///
+    /// This should always be `"runicos/base"`.
+    pub image: Image,
+    /// Values which can be substituted into stage arguments and paths by
+    /// writing `${NAME}` (see [`DocumentV1::variables`]).
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub variables: IndexMap<String, Option<Argument>>,
+    /// Named sets of values which override the defaults in `variables`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub profiles: IndexMap<String, IndexMap<String, Argument>>,
+    /// The various stages in the Runefile's pipeline.
+    pub pipeline: IndexMap<String, StageV2>,
+    /// Named parts of the pipeline which can be run on their own (see
+    /// [`DocumentV1::pipelines`]).
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub pipelines: IndexMap<String, Vec<String>>,
+    /// Any resources that can be accessed by pipeline stages.
+    #[serde(default)]
+    pub resources: IndexMap<String, ResourceDeclaration>,
+}
+
+/// A stage in a [`DocumentV2`] pipeline.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(deny_unknown_fields)]
+pub struct StageV2 {
+    /// What sort of stage this is.
+    #[schemars(required)]
+    pub kind: StageKind,
+    /// The capability, model, proc-block, or output this stage uses (e.g.
+    /// `SOUND`, `./model.tflite`, or `hotg-ai/proc-blocks@v0.11.3#fft`).
+    ///
+    /// Models may also refer to a resource.
+    #[schemars(required)]
+    pub uses: ResourceOrString,
+    /// The name of each input, and the output it is connected to.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub inputs: IndexMap<String, PortReference>,
+    /// The name of each output, and the tensor it produces.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub outputs: IndexMap<String, Type>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub args: IndexMap<String, Argument>,
+    /// Only run this stage when the condition holds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition<PortReference>>,
+}
+
+/// The different kinds of [`StageV2`].
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum StageKind {
+    /// Read input from the runtime (see [`CapabilityStage`]).
+    Capability,
+    /// Run a ML model (see [`ModelStage`]).
+    Model,
+    /// Run a procedural block (see [`ProcBlockStage`]).
+    ProcBlock,
+    /// Pass outputs back to the runtime (see [`OutStage`]).
+    Out,
+    /// Output the value its input had on the previous call (see
+    /// [`DelayStage`]).
+    Delay,
+}
+
+impl Display for StageKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StageKind::Capability => write!(f, "capability"),
+            StageKind::Model => write!(f, "model"),
+            StageKind::ProcBlock => write!(f, "proc-block"),
+            StageKind::Out => write!(f, "out"),
+            StageKind::Delay => write!(f, "delay"),
+        }
+    }
+}
+
+/// A reference to one of a stage's named outputs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PortReference {
+    pub stage: String,
+    pub port: String,
+}
+
+impl_json_schema_via_regex!(
+    PortReference,
+    PORT_REFERENCE_PATTERN,
+    r#"
+One of a stage's outputs, written as "stage.port" (e.g. "fft.spectrum").
+"#
+);
+
+impl PortReference {
+    pub fn new(stage: impl Into<String>, port: impl Into<String>) -> Self {
+        PortReference {
+            stage: stage.into(),
+            port: port.into(),
+        }
+    }
+}
+
+static PORT_REFERENCE_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?P<stage>[a-zA-Z_][\w-]*)\.(?P<port>[a-zA-Z_][\w-]*)$")
+        .unwrap()
+});
+
+impl FromStr for PortReference {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let captures = PORT_REFERENCE_PATTERN
+            .captures(s)
+            .ok_or("Expected something like \"fft.spectrum\"")?;
+
+        Ok(PortReference::new(&captures["stage"], &captures["port"]))
+    }
+}
+
+impl Display for PortReference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.stage, self.port)
+    }
+}
+
+impl Serialize for PortReference {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PortReference {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = Cow::<str>::deserialize(deserializer)?;
+        PortReference::from_str(&raw)
+            .map_err(|e| D::Error::custom(e.to_string()))
+    }
+}
+
+/// A file which can be included by a Runefile so its stages, templates, and
+/// resources can be shared between Runes.
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(deny_unknown_fields)]
+pub struct Fragment {
+    /// Other files to include, relative to this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<String>,
+    /// Stages which will be added to the Runefile's pipeline.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub pipeline: IndexMap<String, Stage>,
+    /// Reusable sub-pipelines which can be instantiated by a
+    /// [`TemplateStage`].
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub templates: IndexMap<String, Template>,
+    /// Resources which will be added to the Runefile.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub resources: IndexMap<String, ResourceDeclaration>,
+}
+
+/// A reusable sub-pipeline.
+///
+/// Each time the template is instantiated by a [`TemplateStage`] its stages
+/// are copied into the Runefile's pipeline, with their names prefixed by the
+/// [`TemplateStage`]'s name (e.g. the `fft` stage in a `preprocess` stage
+/// becomes `preprocess__fft`).
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    /// Names the template's stages can use as inputs to refer to the tensors
+    /// passed in by a [`TemplateStage`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    /// The tensors this template produces, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<Input>,
+    /// Parameters which the template's stages can refer to like a resource
+    /// (e.g. `$hz`), plus their default values. Parameters without a default
+    /// value must be provided by every [`TemplateStage`].
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub args: IndexMap<String, Option<Argument>>,
+    /// The template's stages.
+    #[schemars(required)]
+    pub pipeline: IndexMap<String, Stage>,
+}
+
 impl Document {
     pub fn parse(yaml: &str) -> Result<Self, serde_yaml::Error> {
         serde_yaml::from_str(yaml)
@@ -241,9 +534,9 @@ impl Display for Path {
 static PATH_PATTERN: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
         r"(?x)
-        (?P<base>[\w\d:/_.-]+)
-        (?:@(?P<version>[\w\d./-]+))?
-        (?:\#(?P<sub_path>[\w\d._/-]+))?
+        (?P<base>[\w\d:/_.${}-]+)
+        (?:@(?P<version>[\w\d./${}-]+))?
+        (?:\#(?P<sub_path>[\w\d._/${}-]+))?
         ",
     )
     .unwrap()
@@ -320,6 +613,9 @@ pub struct ModelStage {
     pub outputs: Vec<Type>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub args: IndexMap<String, Argument>,
+    /// Only run this model when the condition holds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
 }
 
 /// A stage which executes a procedural block.
@@ -342,6 +638,30 @@ pub struct ProcBlockStage {
     pub outputs: Vec<Type>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub args: IndexMap<String, Argument>,
+    /// Only run this proc block when the condition holds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+}
+
+/// A stage which is replaced by the stages in a [`Template`].
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+pub struct TemplateStage {
+    /// The name of the [`Template`] to instantiate.
+    #[schemars(required)]
+    pub template: String,
+    /// The tensors to pass to each of the template's inputs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<Input>,
+    /// Values for the template's parameters.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub args: IndexMap<String, Argument>,
 }
 
 /// A stage which reads inputs from the runtime.
@@ -360,6 +680,7 @@ pub struct CapabilityStage {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub outputs: Vec<Type>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(schema_with = "capability_args_schema")]
     pub args: IndexMap<String, Argument>,
 }
 
@@ -379,7 +700,168 @@ pub struct OutStage {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub inputs: Vec<Input>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(schema_with = "out_args_schema")]
     pub args: IndexMap<String, Argument>,
+    /// Only pass the inputs to this output when the condition holds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+}
+
+/// A stage which outputs the value its input had the last time the Rune was
+/// called.
+///
+/// Delays are the only way to feed a stage's output back into the pipeline,
+/// so any cycle must pass through one.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+pub struct DelayStage {
+    /// The value every element is set to before the input has been computed
+    /// for the first time.
+    #[serde(rename = "delay")]
+    #[schemars(required)]
+    pub initial: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<Input>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<Type>,
+}
+
+fn capability_args_schema(gen: &mut SchemaGenerator) -> Schema {
+    let capabilities = hotg_rune_core::capabilities::all();
+    let known_args = capabilities.iter().filter_map(|&(kind, id)| {
+        arguments::capability_arguments(id).map(|args| (kind, args))
+    });
+
+    args_schema(gen, known_args)
+}
+
+fn out_args_schema(gen: &mut SchemaGenerator) -> Schema {
+    let outputs = hotg_rune_core::outputs::all();
+    let known_args = outputs.iter().filter_map(|&(kind, id)| {
+        arguments::output_arguments(id).map(|args| (kind, args))
+    });
+
+    args_schema(gen, known_args)
+}
+
+/// Generate the schema for a stage's `args`, documenting every argument the
+/// builtin capabilities or outputs know about while still allowing arbitrary
+/// arguments to be passed to third-party ones.
+fn args_schema<'a>(
+    gen: &mut SchemaGenerator,
+    known_args: impl Iterator<Item = (&'a str, &'a [ArgumentDescriptor])>,
+) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    };
+    schema.object().additional_properties =
+        Some(Box::new(gen.subschema_for::<Argument>()));
+
+    let mut descriptors: IndexMap<&str, (&ArgumentDescriptor, Vec<&str>)> =
+        IndexMap::new();
+
+    for (kind, args) in known_args {
+        for arg in args {
+            let (_, kinds) =
+                descriptors.entry(arg.name).or_insert((arg, Vec::new()));
+            kinds.push(kind);
+        }
+    }
+
+    for (name, (descriptor, kinds)) in descriptors {
+        let property = argument_schema(gen, descriptor, &kinds);
+        schema
+            .object()
+            .properties
+            .insert(name.to_string(), property);
+    }
+
+    schema.into()
+}
+
+fn argument_schema(
+    gen: &mut SchemaGenerator,
+    descriptor: &ArgumentDescriptor,
+    kinds: &[&str],
+) -> Schema {
+    let mut description =
+        format!("{} (used by {})", descriptor.description, kinds.join(", "));
+    if descriptor.required {
+        description.push_str(". This argument is required.");
+    }
+
+    // Any argument can be provided by a resource
+    let mut alternatives = vec![gen.subschema_for::<ResourceName>()];
+
+    if !descriptor.allowed_values.is_empty() {
+        let mut values = Vec::new();
+        for &value in descriptor.allowed_values {
+            values.push(serde_json::Value::from(value));
+            // YAML will parse unquoted numbers as integers
+            if let Ok(number) = value.parse::<u64>() {
+                values.push(serde_json::Value::from(number));
+            }
+        }
+
+        alternatives.push(
+            SchemaObject {
+                enum_values: Some(values),
+                ..Default::default()
+            }
+            .into(),
+        );
+    } else {
+        match descriptor.ty {
+            ArgumentType::Integer => {
+                let mut integer = SchemaObject {
+                    instance_type: Some(InstanceType::Integer.into()),
+                    ..Default::default()
+                };
+                integer.number().minimum = Some(0.0);
+                alternatives.push(integer.into());
+
+                // Strings starting with "@" are expressions which will be
+                // evaluated inside the Rune
+                let mut string = SchemaObject {
+                    instance_type: Some(InstanceType::String.into()),
+                    ..Default::default()
+                };
+                string.string().pattern = Some(r"^(\d+|@.*)$".to_string());
+                alternatives.push(string.into());
+            },
+            ArgumentType::String => {
+                alternatives.push(gen.subschema_for::<String>());
+            },
+        }
+    }
+
+    let default = descriptor.default.map(|value| {
+        match (descriptor.ty, value.parse::<u64>()) {
+            (ArgumentType::Integer, Ok(number)) => number.into(),
+            _ => value.into(),
+        }
+    });
+
+    SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some(description),
+            default,
+            ..Default::default()
+        })),
+        subschemas: Some(Box::new(SubschemaValidation {
+            any_of: Some(alternatives),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
 }
 
 /// A stage in the Rune's pipeline.
@@ -392,6 +874,10 @@ pub enum Stage {
     ProcBlock(ProcBlockStage),
     Capability(CapabilityStage),
     Out(OutStage),
+    Delay(DelayStage),
+    /// Instantiate a [`Template`]. These are expanded into ordinary stages
+    /// while parsing, so later phases will never see them.
+    Template(TemplateStage),
 }
 
 impl Stage {
@@ -399,7 +885,9 @@ impl Stage {
         match self {
             Stage::Model(ModelStage { inputs, .. })
             | Stage::ProcBlock(ProcBlockStage { inputs, .. })
-            | Stage::Out(OutStage { inputs, .. }) => inputs,
+            | Stage::Out(OutStage { inputs, .. })
+            | Stage::Delay(DelayStage { inputs, .. })
+            | Stage::Template(TemplateStage { inputs, .. }) => inputs,
             Stage::Capability(_) => &[],
         }
     }
@@ -408,11 +896,32 @@ impl Stage {
         match self {
             Stage::Model(ModelStage { inputs, .. })
             | Stage::ProcBlock(ProcBlockStage { inputs, .. })
-            | Stage::Out(OutStage { inputs, .. }) => Some(inputs),
+            | Stage::Out(OutStage { inputs, .. })
+            | Stage::Delay(DelayStage { inputs, .. })
+            | Stage::Template(TemplateStage { inputs, .. }) => Some(inputs),
             Stage::Capability(_) => None,
         }
     }
 
+    /// The [`Condition`] which must hold for this stage to run, if any.
+    pub fn condition(&self) -> Option<&Condition> {
+        match self {
+            Stage::Model(ModelStage { when, .. })
+            | Stage::ProcBlock(ProcBlockStage { when, .. })
+            | Stage::Out(OutStage { when, .. }) => when.as_ref(),
+            Stage::Capability(_) | Stage::Delay(_) | Stage::Template(_) => None,
+        }
+    }
+
+    pub fn condition_mut(&mut self) -> Option<&mut Condition> {
+        match self {
+            Stage::Model(ModelStage { when, .. })
+            | Stage::ProcBlock(ProcBlockStage { when, .. })
+            | Stage::Out(OutStage { when, .. }) => when.as_mut(),
+            Stage::Capability(_) | Stage::Delay(_) | Stage::Template(_) => None,
+        }
+    }
+
     pub fn output_type(&self) -> Option<&Type> {
         match self.output_types() {
             [] => None,
@@ -425,22 +934,36 @@ impl Stage {
         match self {
             Stage::Model(ModelStage { outputs, .. })
             | Stage::ProcBlock(ProcBlockStage { outputs, .. })
-            | Stage::Capability(CapabilityStage { outputs, .. }) => outputs,
+            | Stage::Capability(CapabilityStage { outputs, .. })
+            | Stage::Delay(DelayStage { outputs, .. }) => outputs,
             Stage::Out(OutStage { .. }) => &[],
+            // We won't know until the template is expanded
+            Stage::Template(TemplateStage { .. }) => &[],
         }
     }
 
-    pub fn span(&self) -> Span {
-        // TODO: Get span from serde_yaml
-        Span::default()
-    }
-
     pub fn args(&self) -> &IndexMap<String, Argument> {
+        static NO_ARGS: Lazy<IndexMap<String, Argument>> =
+            Lazy::new(IndexMap::new);
+
         match self {
             Stage::Model(m) => &m.args,
             Stage::ProcBlock(p) => &p.args,
             Stage::Capability(c) => &c.args,
             Stage::Out(out) => &out.args,
+            Stage::Template(t) => &t.args,
+            Stage::Delay(_) => &NO_ARGS,
+        }
+    }
+
+    pub fn args_mut(&mut self) -> Option<&mut IndexMap<String, Argument>> {
+        match self {
+            Stage::Model(m) => Some(&mut m.args),
+            Stage::ProcBlock(p) => Some(&mut p.args),
+            Stage::Capability(c) => Some(&mut c.args),
+            Stage::Out(out) => Some(&mut out.args),
+            Stage::Template(t) => Some(&mut t.args),
+            Stage::Delay(_) => None,
         }
     }
 }
@@ -528,7 +1051,8 @@ impl<'de> Deserialize<'de> for ResourceOrString {
             {
                 let v = v.trim();
 
-                if !v.starts_with('$') {
+                // Note: "${NAME}" is a variable, not a resource
+                if !v.starts_with('$') || v.starts_with("${") {
                     return Ok(ResourceOrString::String(v.to_string()));
                 }
 
@@ -697,6 +1221,168 @@ impl<'de> Deserialize<'de> for Input {
     }
 }
 
+/// A predicate which must hold for a stage to run, written as something like
+/// `"wake_word > 0.8"`.
+///
+/// The condition holds when at least one element of the tensor satisfies the
+/// comparison. When it doesn't, the stage is skipped and its outputs are
+/// absent, which means every stage that uses them will also be skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition<I = Input> {
+    /// The tensor being checked.
+    pub input: I,
+    pub comparison: Comparison,
+    pub value: f64,
+}
+
+impl<I> Condition<I> {
+    pub fn new(input: I, comparison: Comparison, value: f64) -> Self {
+        Condition {
+            input,
+            comparison,
+            value,
+        }
+    }
+}
+
+static CONDITION_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^\s*(?P<input>[\w.-]+)\s*(?P<comparison>>=|<=|==|!=|>|<)\s*(?P<value>[-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?)\s*$",
+    )
+    .unwrap()
+});
+
+impl<I> JsonSchema for Condition<I> {
+    fn schema_name() -> String { String::from("Condition") }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            format: Some(String::from("string")),
+            metadata: Some(Box::new(Metadata {
+                description: Some(String::from(
+                    "Only run this stage when at least one element of a \
+                     tensor satisfies a comparison (e.g. \"wake_word > 0.8\").",
+                )),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        schema.string().pattern = Some(CONDITION_PATTERN.to_string());
+
+        schema.into()
+    }
+}
+
+impl<I> FromStr for Condition<I>
+where
+    I: FromStr,
+    I::Err: Display,
+{
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let captures = CONDITION_PATTERN
+            .captures(s)
+            .ok_or("Expected something like \"wake_word > 0.8\"")?;
+
+        let input = captures["input"]
+            .parse()
+            .map_err(|e: I::Err| e.to_string())?;
+        let comparison = captures["comparison"].parse()?;
+        let value: f64 =
+            captures["value"].parse().expect("Guaranteed by the regex");
+
+        if !value.is_finite() {
+            return Err("The value is too large".into());
+        }
+
+        Ok(Condition::new(input, comparison, value))
+    }
+}
+
+impl<I: Display> Display for Condition<I> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.input, self.comparison, self.value)
+    }
+}
+
+impl<I: Display> Serialize for Condition<I> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de, I> Deserialize<'de> for Condition<I>
+where
+    I: FromStr,
+    I::Err: Display,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = Cow::<str>::deserialize(deserializer)?;
+        Condition::from_str(&raw).map_err(|e| D::Error::custom(e.to_string()))
+    }
+}
+
+/// How a [`Condition`] compares a tensor's elements with its value.
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Comparison {
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl FromStr for Comparison {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            ">" => Ok(Comparison::GreaterThan),
+            ">=" => Ok(Comparison::GreaterThanOrEqual),
+            "<" => Ok(Comparison::LessThan),
+            "<=" => Ok(Comparison::LessThanOrEqual),
+            "==" => Ok(Comparison::Equal),
+            "!=" => Ok(Comparison::NotEqual),
+            _ => Err(format!("Unknown comparison, \"{}\"", s).into()),
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Comparison::GreaterThan => ">",
+            Comparison::GreaterThanOrEqual => ">=",
+            Comparison::LessThan => "<",
+            Comparison::LessThanOrEqual => "<=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+        };
+
+        f.write_str(symbol)
+    }
+}
+
 /// The declaration for a resource, typically something like a wordlist or
 /// environment variable.
 #[derive(
@@ -718,13 +1404,6 @@ pub struct ResourceDeclaration {
     pub ty: ResourceType,
 }
 
-impl ResourceDeclaration {
-    pub fn span(&self) -> Span {
-        // TODO: Get span from serde_yaml
-        Span::default()
-    }
-}
-
 /// How the resource should be treated inside the Rune.
 #[derive(
     Debug,
@@ -761,13 +1440,6 @@ A reference to some [`ResourceDeclaration`]. It typically looks like
 "#
 );
 
-impl ResourceName {
-    pub fn span(&self) -> Span {
-        // TODO: Get span from serde_yaml
-        Span::default()
-    }
-}
-
 impl<S: Into<String>> From<S> for ResourceName {
     fn from(s: S) -> Self { ResourceName(s.into()) }
 }
@@ -882,6 +1554,59 @@ mod tests {
         assert_eq!(got.to_string(), src);
     }
 
+    #[test]
+    fn parse_conditions() {
+        let inputs = vec![
+            (
+                "wake_word > 0.8",
+                Condition::new(
+                    Input::new("wake_word", None),
+                    Comparison::GreaterThan,
+                    0.8,
+                ),
+            ),
+            (
+                "split.1<=-2",
+                Condition::new(
+                    Input::new("split", 1),
+                    Comparison::LessThanOrEqual,
+                    -2.0,
+                ),
+            ),
+            (
+                "  label == 3  ",
+                Condition::new(
+                    Input::new("label", None),
+                    Comparison::Equal,
+                    3.0,
+                ),
+            ),
+        ];
+
+        for (src, should_be) in inputs {
+            let got: Condition = src.parse().unwrap();
+            assert_eq!(got, should_be, "{}", src);
+
+            let round_tripped: Condition = got.to_string().parse().unwrap();
+            assert_eq!(round_tripped, should_be);
+        }
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        let inputs = vec![
+            "wake_word",
+            "wake_word > high",
+            "> 0.8",
+            "a => 1",
+            "a > 1e999",
+        ];
+
+        for src in inputs {
+            assert!(Condition::<Input>::from_str(src).is_err(), "{}", src);
+        }
+    }
+
     #[test]
     fn parse_paths() {
         let inputs = vec![
@@ -925,6 +1650,16 @@ mod tests {
                     "refs/heads/master".to_string(),
                 ),
             ),
+            // Variables are substituted after parsing, so they need to
+            // survive a round-trip
+            (
+                "hotg-ai/proc-blocks@${VERSION}#normalize",
+                Path::new(
+                    "hotg-ai/proc-blocks",
+                    "normalize".to_string(),
+                    "${VERSION}".to_string(),
+                ),
+            ),
         ];
 
         for (src, should_be) in inputs {
@@ -945,9 +1680,9 @@ mod tests {
     }
 
     #[test]
-    #[should_panic = "expected version to be 1"]
+    #[should_panic = "expected version to be 1 or 2"]
     fn other_versions_are_an_error() {
-        let src = "image: asdf\nversion: 2\npipeline:";
+        let src = "image: asdf\nversion: 3\npipeline:";
 
         let got = Document::parse(src).unwrap();
 
@@ -1058,6 +1793,7 @@ mod tests {
             )]
             .into_iter()
             .collect(),
+            when: None,
         });
 
         let got: IndexMap<String, Stage> = serde_yaml::from_str(src).unwrap();
@@ -1120,6 +1856,9 @@ pipeline:
         let should_be = Document::V1(DocumentV1 {
             version: 1,
             image: "runicos/base".parse().unwrap(),
+            includes: Vec::new(),
+            variables: IndexMap::new(),
+            profiles: IndexMap::new(),
             pipeline: map! {
                 audio: Stage::Capability(CapabilityStage {
                     capability: String::from("SOUND"),
@@ -1131,12 +1870,14 @@ pipeline:
                     inputs: vec!["audio".parse().unwrap()],
                     outputs: vec![ty!(i8[1960])],
                     args: IndexMap::new(),
+                    when: None,
                 }),
                 model: Stage::Model(ModelStage {
                     model: "./model.tflite".into(),
                     inputs: vec!["fft".parse().unwrap()],
                     outputs: vec![ty!(i8[6])],
                     args: IndexMap::new(),
+                    when: None,
                 }),
                 label: Stage::ProcBlock(ProcBlockStage {
                     proc_block: "hotg-ai/rune#proc_blocks/ohv_label".parse().unwrap(),
@@ -1145,13 +1886,17 @@ pipeline:
                     args: map! {
                         labels: "silence\nunknown\nup\ndown\nleft\nright".into()
                     },
+                    when: None,
                 }),
                 output: Stage::Out(OutStage {
                     out: String::from("SERIAL"),
                     args: IndexMap::new(),
                     inputs: vec!["label".parse().unwrap()],
+                    when: None,
                 }),
             },
+            pipelines: IndexMap::new(),
+            templates: IndexMap::new(),
             resources: map![],
         });
 
@@ -1184,6 +1929,48 @@ pipeline:
         assert_eq!(got, should_be);
     }
 
+    #[test]
+    fn parse_delay() {
+        let src = r#"
+              delay: 0
+              inputs:
+              - smoothed
+              outputs:
+              - type: f32
+                dimensions: [1]
+        "#;
+        let should_be = Stage::Delay(DelayStage {
+            initial: 0.0,
+            inputs: vec!["smoothed".parse().unwrap()],
+            outputs: vec![Type {
+                name: String::from("f32"),
+                dimensions: vec![1],
+            }],
+        });
+
+        let got: Stage = serde_yaml::from_str(src).unwrap();
+
+        assert_eq!(got, should_be);
+        assert!(got.args().is_empty());
+    }
+
+    #[test]
+    fn parse_named_pipelines() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline: {}
+pipelines:
+  fast: [accelerometer_out]
+  slow: [audio_out, wake_word]
+        "#;
+
+        let got = Document::parse(src).unwrap().to_v1();
+
+        assert_eq!(got.pipelines["fast"], vec!["accelerometer_out"]);
+        assert_eq!(got.pipelines["slow"], vec!["audio_out", "wake_word"]);
+    }
+
     #[test]
     fn schema_is_in_sync_with_version_on_disk() {
         let existing_schema = include_str!("../../runefile-schema.json");
@@ -0,0 +1,286 @@
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::arguments::{self, ArgumentDescriptor, InvalidArgument};
+use indexmap::IndexMap;
+use legion::{world::SubWorld, Query};
+
+use crate::{
+    lowering::{Name, ResourceOrString, Sink, Source},
+    parse::DocumentSpans,
+    Diagnostics,
+};
+
+/// Check the arguments passed to each capability and output against the
+/// [`ArgumentDescriptor`]s the runtime uses when reading them.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] spans: &DocumentSpans,
+    #[resource] diags: &mut Diagnostics,
+    sources: &mut Query<(&Name, &Span, &Source)>,
+    sinks: &mut Query<(&Name, &Span, &Sink)>,
+) {
+    sources.for_each(world, |(name, &span, source)| {
+        let index = source.kind.as_capability_index();
+
+        if let (Some(kind), Some(schema)) = (
+            index.and_then(hotg_rune_core::capabilities::name),
+            index.and_then(arguments::capability_arguments),
+        ) {
+            check(name, span, kind, schema, &source.parameters, spans, diags);
+        }
+    });
+
+    sinks.for_each(world, |(name, &span, sink)| {
+        let index = sink.kind.as_output_index();
+
+        if let (Some(kind), Some(schema)) = (
+            index.and_then(hotg_rune_core::outputs::name),
+            index.and_then(arguments::output_arguments),
+        ) {
+            check(name, span, kind, schema, &sink.args, spans, diags);
+        }
+    });
+}
+
+fn check(
+    name: &Name,
+    span: Span,
+    kind: &str,
+    schema: &[ArgumentDescriptor],
+    args: &IndexMap<String, ResourceOrString>,
+    spans: &DocumentSpans,
+    diags: &mut Diagnostics,
+) {
+    // Note: codegen converts "kebab-case" arguments to "snake_case"
+    let lookup = |arg: &str| {
+        args.iter()
+            .find(|(key, _)| key.replace('-', "_") == arg)
+            .map(|(key, value)| (key.as_str(), value))
+    };
+
+    for (key, _) in args {
+        let normalized = key.replace('-', "_");
+
+        if !schema.iter().any(|d| d.name == normalized) {
+            diags.push(unknown_argument_diagnostic(
+                name,
+                kind,
+                key,
+                schema,
+                spans.argument(name, key),
+            ));
+        }
+    }
+
+    for descriptor in schema {
+        match lookup(descriptor.name) {
+            Some((key, ResourceOrString::String(value))) => {
+                if let Err(e) = descriptor.validate(value) {
+                    diags.push(invalid_argument_diagnostic(
+                        name,
+                        key,
+                        value,
+                        e,
+                        spans.argument(name, key),
+                    ));
+                }
+            },
+            // Resources are only known at runtime
+            Some((_, ResourceOrString::Resource(_))) => {},
+            None if descriptor.required => {
+                diags.push(missing_argument_diagnostic(
+                    name, kind, descriptor, span,
+                ));
+            },
+            None => {},
+        }
+    }
+}
+
+fn unknown_argument_diagnostic(
+    name: &Name,
+    kind: &str,
+    key: &str,
+    schema: &[ArgumentDescriptor],
+    span: Span,
+) -> Diagnostic<()> {
+    let hint = if schema.is_empty() {
+        format!("hint: {} doesn't accept any arguments", kind)
+    } else {
+        let names: Vec<_> = schema.iter().map(|d| d.name).collect();
+        format!("hint: {} accepts {}", kind, names.join(", "))
+    };
+
+    Diagnostic::warning()
+        .with_message(format!(
+            "The \"{}\" stage passes an unknown \"{}\" argument to {}",
+            name, key, kind
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![hint])
+}
+
+fn invalid_argument_diagnostic(
+    name: &Name,
+    key: &str,
+    value: &str,
+    error: InvalidArgument,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "Invalid value for the \"{}\" argument of \"{}\"",
+            key, name
+        ))
+        .with_labels(vec![Label::primary((), span)
+            .with_message(format!("{}, but found {:?}", error, value))])
+}
+
+fn missing_argument_diagnostic(
+    name: &Name,
+    kind: &str,
+    descriptor: &ArgumentDescriptor,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage is missing the \"{}\" argument required by {}",
+            name, descriptor.name, kind
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![format!(
+            "{} ({}): {}",
+            descriptor.name, descriptor.ty, descriptor.description
+        )])
+}
+
+#[cfg(test)]
+mod tests {
+    use codespan_reporting::diagnostic::Severity;
+    use legion::{Resources, World};
+
+    use super::*;
+    use crate::{
+        lowering::{SinkKind, SourceKind},
+        phases::Phase,
+    };
+
+    fn check_source(
+        kind: SourceKind,
+        parameters: &[(&str, &str)],
+    ) -> Vec<Diagnostic<()>> {
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(DocumentSpans::default());
+        res.insert(Diagnostics::new());
+        let parameters = parameters
+            .iter()
+            .map(|&(k, v)| (k.to_string(), ResourceOrString::from(v)))
+            .collect();
+        world.push((
+            Name::from("input"),
+            Span::new(0, 5),
+            Source { kind, parameters },
+        ));
+
+        Phase::new().and_then(run_system).run(&mut world, &mut res);
+
+        res.get::<Diagnostics>().unwrap().iter().cloned().collect()
+    }
+
+    #[test]
+    fn valid_arguments() {
+        let diags = check_source(
+            SourceKind::Image,
+            &[
+                ("width", "384"),
+                ("height", "384"),
+                ("pixel-format", "@PixelFormat::RGB"),
+                ("source", "1"),
+            ],
+        );
+
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+
+    #[test]
+    fn missing_required_argument() {
+        let diags = check_source(SourceKind::Sound, &[("hz", "16000")]);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"input\" stage is missing the \"sample_duration_ms\" \
+             argument required by SOUND"
+        );
+    }
+
+    #[test]
+    fn invalid_value() {
+        let diags = check_source(
+            SourceKind::Image,
+            &[("width", "wide"), ("height", "384")],
+        );
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "Invalid value for the \"width\" argument of \"input\""
+        );
+        assert_eq!(
+            diags[0].labels[0].message,
+            "expected a non-negative integer, but found \"wide\""
+        );
+    }
+
+    #[test]
+    fn unknown_argument() {
+        let diags = check_source(SourceKind::Random, &[("seed", "42")]);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert_eq!(diags[0].notes, vec!["hint: RAND accepts amount"]);
+    }
+
+    #[test]
+    fn unknown_capabilities_are_ignored() {
+        let diags = check_source(
+            SourceKind::Other("CUSTOM".to_string()),
+            &[("anything", "goes")],
+        );
+
+        assert!(diags.is_empty());
+    }
+
+    #[test]
+    fn outputs_are_checked() {
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(DocumentSpans::default());
+        res.insert(Diagnostics::new());
+        let mut args = IndexMap::new();
+        args.insert("baud".to_string(), ResourceOrString::from("9600"));
+        world.push((
+            Name::from("serial"),
+            Span::new(0, 5),
+            Sink {
+                kind: SinkKind::Serial,
+                args,
+            },
+        ));
+
+        Phase::new().and_then(run_system).run(&mut world, &mut res);
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        let messages: Vec<_> =
+            diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "The \"serial\" stage passes an unknown \"baud\" argument to \
+                 SERIAL"
+            ]
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::ElementType;
+use legion::{world::SubWorld, Query};
+
+use crate::{
+    lowering::{Condition, Name, Tensor},
+    parse::DocumentSpans,
+    Diagnostics,
+};
+
+/// Make sure every [`Condition`] compares a numeric tensor.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] spans: &DocumentSpans,
+    #[resource] diags: &mut Diagnostics,
+    conditions: &mut Query<(&Name, &Condition)>,
+    tensors: &mut Query<&Tensor>,
+) {
+    conditions.for_each(world, |(name, condition)| {
+        let Tensor(shape) = match tensors.get(world, condition.tensor) {
+            Ok(t) => t,
+            Err(_) => return,
+        };
+
+        if shape.element_type() == ElementType::String {
+            diags.push(string_condition_diagnostic(
+                name,
+                spans.field(name, "when"),
+            ));
+        }
+    });
+}
+
+fn string_condition_diagnostic(name: &Name, span: Span) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The condition on \"{}\" can't compare a string tensor with a \
+             number",
+            name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![
+            "hint: conditions can only check numeric tensors".to_string()
+        ])
+}
+
+#[cfg(test)]
+mod tests {
+    use legion::{Resources, World};
+
+    use super::*;
+    use crate::{
+        parse::{Document, DocumentV1},
+        phases::Phase,
+        BuildContext,
+    };
+
+    fn check(src: &str) -> Vec<Diagnostic<()>> {
+        let doc: DocumentV1 = Document::parse(src).unwrap().to_v1();
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(BuildContext::from_doc(doc.into()));
+        crate::parse::phase().run(&mut world, &mut res);
+        crate::lowering::phase().run(&mut world, &mut res);
+
+        Phase::new().and_then(run_system).run(&mut world, &mut res);
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        diags.iter().cloned().collect()
+    }
+
+    #[test]
+    fn numeric_conditions_are_allowed() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  serial:
+    out: SERIAL
+    inputs: [rand]
+    when: rand > 0.5
+"#;
+
+        let diags = check(src);
+
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+
+    #[test]
+    fn string_conditions_are_an_error() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  label:
+    proc-block: hotg-ai/proc-blocks@v0.11.3#label
+    inputs: [rand]
+    outputs: [{type: utf8, dimensions: [1]}]
+  serial:
+    out: SERIAL
+    inputs: [rand]
+    when: label == 1
+"#;
+
+        let diags = check(src);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The condition on \"serial\" can't compare a string tensor with a \
+             number"
+        );
+    }
+}
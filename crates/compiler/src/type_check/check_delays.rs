@@ -0,0 +1,217 @@
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::{ElementType, Shape};
+use legion::{world::SubWorld, Query};
+
+use crate::{
+    lowering::{Delay, Inputs, Name, Outputs, Tensor},
+    parse::DocumentSpans,
+    Diagnostics,
+};
+
+/// Make sure every [`Delay`] passes a single numeric tensor through without
+/// changing its shape.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] spans: &DocumentSpans,
+    #[resource] diags: &mut Diagnostics,
+    delays: &mut Query<(&Name, &Delay, Option<&Inputs>, Option<&Outputs>)>,
+    tensors: &mut Query<&Tensor>,
+) {
+    delays.for_each(world, |(name, delay, inputs, outputs)| {
+        if !delay.initial.is_finite() {
+            diags.push(non_finite_initial_value_diagnostic(
+                name,
+                spans.field(name, "delay"),
+            ));
+        }
+
+        let (input, output) = match (inputs, outputs) {
+            (Some(i), Some(o))
+                if i.tensors.len() == 1 && o.tensors.len() == 1 =>
+            {
+                (i.tensors[0], o.tensors[0])
+            },
+            _ => {
+                diags.push(wrong_number_of_tensors_diagnostic(
+                    name,
+                    spans.stage(name),
+                ));
+                return;
+            },
+        };
+
+        let (Tensor(input), Tensor(output)) =
+            match (tensors.get(world, input), tensors.get(world, output)) {
+                (Ok(i), Ok(o)) => (i, o),
+                _ => return,
+            };
+
+        if input != output {
+            diags.push(shape_mismatch_diagnostic(
+                name,
+                input,
+                output,
+                spans.input(name, 0),
+                spans.output(name, 0),
+            ));
+        } else if output.element_type() == ElementType::String {
+            diags.push(string_delay_diagnostic(name, spans.output(name, 0)));
+        }
+    });
+}
+
+fn non_finite_initial_value_diagnostic(
+    name: &Name,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" delay should start with a finite number",
+            name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn wrong_number_of_tensors_diagnostic(
+    name: &Name,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" delay should have exactly one input and one output",
+            name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn shape_mismatch_diagnostic(
+    name: &Name,
+    input: &Shape<'_>,
+    output: &Shape<'_>,
+    input_span: Span,
+    output_span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" delay receives {} but declares its output as {}",
+            name, input, output
+        ))
+        .with_labels(vec![
+            Label::primary((), output_span)
+                .with_message(format!("should be {}", input)),
+            Label::secondary((), input_span),
+        ])
+        .with_notes(vec!["hint: a delay's output has the same shape as its \
+                          input"
+            .to_string()])
+}
+
+fn string_delay_diagnostic(name: &Name, span: Span) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" delay can't hold a string tensor",
+            name
+        ))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec!["hint: delays start with a number, so they can only \
+                          hold numeric tensors"
+            .to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+    use legion::{Resources, World};
+
+    use super::*;
+    use crate::{
+        parse::{Document, DocumentV1},
+        phases::Phase,
+        BuildContext,
+    };
+
+    fn check(src: &str) -> Vec<Diagnostic<()>> {
+        let doc: DocumentV1 = Document::parse(src).unwrap().to_v1();
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(BuildContext::from_doc(doc.into()));
+        crate::parse::phase().run(&mut world, &mut res);
+        crate::lowering::phase().run(&mut world, &mut res);
+
+        Phase::new().and_then(run_system).run(&mut world, &mut res);
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        diags.iter().cloned().collect()
+    }
+
+    #[test]
+    fn delays_pass_their_input_through() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  previous:
+    delay: 0
+    inputs: [rand]
+    outputs: [{type: f32, dimensions: [1]}]
+"#;
+
+        let diags = check(src);
+
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+
+    #[test]
+    fn delays_cant_change_the_shape() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  previous:
+    delay: 0
+    inputs: [rand]
+    outputs: [{type: u8, dimensions: [2]}]
+"#;
+
+        let diags = check(src);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"previous\" delay receives f32[1] but declares its output \
+             as u8[2]"
+        );
+    }
+
+    #[test]
+    fn delays_need_exactly_one_input() {
+        let src = r#"
+version: 1
+image: runicos/base
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  previous:
+    delay: 0
+    inputs: [rand, rand]
+    outputs: [{type: f32, dimensions: [1]}]
+"#;
+
+        let diags = check(src);
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"previous\" delay should have exactly one input and one \
+             output"
+        );
+    }
+}
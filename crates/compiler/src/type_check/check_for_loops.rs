@@ -6,7 +6,7 @@ use indexmap::IndexMap;
 use legion::{world::SubWorld, Entity, Query};
 
 use crate::{
-    lowering::{Name, Outputs},
+    lowering::{Delay, Name, Outputs},
     Diagnostics,
 };
 
@@ -16,12 +16,24 @@ pub(crate) fn run(
     #[resource] diags: &mut Diagnostics,
     names: &mut Query<(&Name, &Span)>,
     query: &mut Query<(Entity, &Outputs)>,
+    delays: &mut Query<(Entity, &Delay)>,
 ) {
+    // A delay only passes its input along on the next call, so the edges
+    // going into it can't form a cycle
+    let delays: HashSet<Entity> =
+        delays.iter(world).map(|(&ent, _)| ent).collect();
+
     // construct an adjacency graph where edges go from a node to its output.
     // Note: use an IndexMap so ordering is deterministic
     let mut outputs = IndexMap::new();
     query.for_each(world, |(&ent, out)| {
-        outputs.insert(ent, out.tensors.as_slice());
+        let next: Vec<Entity> = out
+            .tensors
+            .iter()
+            .copied()
+            .filter(|e| !delays.contains(e))
+            .collect();
+        outputs.insert(ent, next);
     });
 
     if let Some(cycle) = next_cycle(&outputs) {
@@ -60,11 +72,15 @@ fn cycle_detected_diagnostic(
         name
     );
     notes.push(closing_message);
+    notes.push(
+        "hint: use a \"delay\" stage to pass a value back to an earlier stage"
+            .to_string(),
+    );
 
     diag.with_notes(notes)
 }
 
-fn next_cycle(outputs: &IndexMap<Entity, &[Entity]>) -> Option<Vec<Entity>> {
+fn next_cycle(outputs: &IndexMap<Entity, Vec<Entity>>) -> Option<Vec<Entity>> {
     // https://www.geeksforgeeks.org/detect-cycle-in-a-graph/
     let mut stack = VecDeque::new();
     let mut visited = HashSet::new();
@@ -80,7 +96,7 @@ fn next_cycle(outputs: &IndexMap<Entity, &[Entity]>) -> Option<Vec<Entity>> {
 
 fn detect_cycles(
     ent: Entity,
-    outputs: &IndexMap<Entity, &[Entity]>,
+    outputs: &IndexMap<Entity, Vec<Entity>>,
     visited: &mut HashSet<Entity>,
     stack: &mut VecDeque<Entity>,
 ) -> bool {
@@ -99,9 +115,10 @@ fn detect_cycles(
     visited.insert(ent);
     stack.push_back(ent);
 
-    let outgoing_node = outputs.get(&ent).copied().unwrap_or_default();
+    let outgoing_nodes =
+        outputs.get(&ent).map(Vec::as_slice).unwrap_or_default();
 
-    for &outgoing_node in outgoing_node {
+    for &outgoing_node in outgoing_nodes {
         if detect_cycles(outgoing_node, outputs, visited, stack) {
             return true;
         }
@@ -0,0 +1,355 @@
+use std::collections::HashSet;
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use crate::{
+    parse::{DocumentSpans, DocumentV1, ResourceOrString, Stage},
+    Diagnostics,
+};
+
+/// Warn about stages, outputs, and resources which don't contribute to the
+/// Rune's outputs.
+#[legion::system]
+pub(crate) fn run(
+    #[resource] doc: &DocumentV1,
+    #[resource] spans: &DocumentSpans,
+    #[resource] diags: &mut Diagnostics,
+) {
+    let consumed: HashSet<(&str, usize)> = doc
+        .pipeline
+        .values()
+        .flat_map(|stage| {
+            stage
+                .inputs()
+                .iter()
+                .chain(stage.condition().map(|c| &c.input))
+        })
+        .map(|input| (input.name.as_str(), input.index.unwrap_or(0)))
+        .collect();
+
+    for (name, stage) in &doc.pipeline {
+        let outputs = stage.output_types().len();
+        let unused: Vec<usize> = (0..outputs)
+            .filter(|&i| !consumed.contains(&(name.as_str(), i)))
+            .collect();
+
+        if outputs == 0 {
+            // Out stages are where all the data ends up
+        } else if unused.len() == outputs {
+            let span = spans.stage(name);
+
+            match stage {
+                Stage::Capability(_) => {
+                    diags.push(unread_capability_diagnostic(name, span))
+                },
+                _ => diags.push(unused_stage_diagnostic(name, span)),
+            }
+        } else {
+            for index in unused {
+                diags.push(unused_output_diagnostic(
+                    name,
+                    index,
+                    spans.output(name, index),
+                ));
+            }
+        }
+    }
+
+    let referenced: HashSet<&str> = doc
+        .pipeline
+        .values()
+        .flat_map(|stage| {
+            let model = match stage {
+                Stage::Model(m) => Some(&m.model),
+                _ => None,
+            };
+            stage.args().values().map(|arg| &arg.0).chain(model)
+        })
+        .filter_map(|value| match value {
+            ResourceOrString::Resource(r) => Some(r.as_str()),
+            ResourceOrString::String(_) => None,
+        })
+        .collect();
+
+    for name in doc.resources.keys() {
+        if !referenced.contains(name.as_str()) {
+            diags.push(unused_resource_diagnostic(name, spans.resource(name)));
+        }
+    }
+}
+
+fn unread_capability_diagnostic(name: &str, span: Span) -> Diagnostic<()> {
+    Diagnostic::warning()
+        .with_message(format!("Nothing reads from the \"{}\" capability", name))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![format!(
+            "hint: add \"{}\" to another stage's inputs, or remove it",
+            name
+        )])
+}
+
+fn unused_stage_diagnostic(name: &str, span: Span) -> Diagnostic<()> {
+    Diagnostic::warning()
+        .with_message(format!("The output of \"{}\" is never used", name))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![format!(
+            "hint: pass \"{}\" to an out stage (e.g. SERIAL), or remove it",
+            name
+        )])
+}
+
+fn unused_output_diagnostic(
+    name: &str,
+    index: usize,
+    span: Span,
+) -> Diagnostic<()> {
+    let input = if index == 0 {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, index)
+    };
+
+    Diagnostic::warning()
+        .with_message(format!("Output {} of \"{}\" is never used", index, name))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![format!(
+            "hint: add \"{}\" to another stage's inputs if it is needed",
+            input
+        )])
+}
+
+fn unused_resource_diagnostic(name: &str, span: Span) -> Diagnostic<()> {
+    Diagnostic::warning()
+        .with_message(format!("The \"{}\" resource is never used", name))
+        .with_labels(vec![Label::primary((), span)])
+        .with_notes(vec![format!(
+            "hint: refer to it as \"${}\" in a stage's arguments, or remove it",
+            name
+        )])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use codespan_reporting::diagnostic::Severity;
+    use legion::{Resources, World};
+
+    use super::*;
+    use crate::{
+        parse::{
+            CapabilityStage, ModelStage, OutStage, ProcBlockStage,
+            ResourceDeclaration, ResourceName,
+        },
+        phases::Phase,
+        BuildContext,
+    };
+
+    fn check(doc: DocumentV1) -> Vec<Diagnostic<()>> {
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(BuildContext::from_doc(doc.into()));
+        crate::parse::phase().run(&mut world, &mut res);
+
+        Phase::new().and_then(run_system).run(&mut world, &mut res);
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        assert!(diags.iter().all(|d| d.severity == Severity::Warning));
+        diags.iter().cloned().collect()
+    }
+
+    fn messages(diags: &[Diagnostic<()>]) -> Vec<&str> {
+        diags.iter().map(|d| d.message.as_str()).collect()
+    }
+
+    fn rand() -> Stage {
+        Stage::Capability(CapabilityStage {
+            capability: "RAND".to_string(),
+            outputs: vec![ty!(f32[1])],
+            args: map! {},
+        })
+    }
+
+    fn serial(inputs: &[&str]) -> Stage {
+        Stage::Out(OutStage {
+            out: "SERIAL".to_string(),
+            inputs: inputs.iter().map(|i| i.parse().unwrap()).collect(),
+            args: map! {},
+            when: None,
+        })
+    }
+
+    fn doc(
+        pipeline: indexmap::IndexMap<String, Stage>,
+        resources: indexmap::IndexMap<String, ResourceDeclaration>,
+    ) -> DocumentV1 {
+        DocumentV1 {
+            version: 1,
+            image: "runicos/base".parse().unwrap(),
+            includes: Vec::new(),
+            variables: Default::default(),
+            profiles: Default::default(),
+            pipeline,
+            pipelines: Default::default(),
+            templates: Default::default(),
+            resources,
+        }
+    }
+
+    #[test]
+    fn everything_is_used() {
+        let doc = doc(
+            map! {
+                rand: rand(),
+                model: Stage::Model(ModelStage {
+                    model: ResourceOrString::Resource(
+                        "$MODEL".parse().unwrap(),
+                    ),
+                    inputs: vec!["rand".parse().unwrap()],
+                    outputs: vec![ty!(f32[1])],
+                    args: map! {},
+                    when: None,
+                }),
+                serial: serial(&["model"])
+            },
+            map! { MODEL: ResourceDeclaration::default() },
+        );
+
+        let diags = check(doc);
+
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+
+    #[test]
+    fn conditions_read_from_their_input() {
+        let mut serial = serial(&["rand"]);
+        if let Stage::Out(out) = &mut serial {
+            out.when = Some("score > 0.5".parse().unwrap());
+        }
+        let doc = doc(
+            map! {
+                rand: rand(),
+                score: rand(),
+                serial: serial
+            },
+            map! {},
+        );
+
+        let diags = check(doc);
+
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+
+    #[test]
+    fn unread_capability() {
+        let doc = doc(
+            map! {
+                rand: rand(),
+                unused: rand(),
+                serial: serial(&["rand"])
+            },
+            map! {},
+        );
+
+        let diags = check(doc);
+
+        assert_eq!(
+            messages(&diags),
+            vec!["Nothing reads from the \"unused\" capability"]
+        );
+        assert_eq!(
+            diags[0].notes,
+            vec![
+                "hint: add \"unused\" to another stage's inputs, or remove it"
+            ]
+        );
+    }
+
+    #[test]
+    fn stage_output_is_never_used() {
+        let doc = doc(
+            map! {
+                rand: rand(),
+                dead_end: Stage::ProcBlock(ProcBlockStage {
+                    proc_block: "proc-block@1.0".parse().unwrap(),
+                    inputs: vec!["rand".parse().unwrap()],
+                    outputs: vec![ty!(f32[1])],
+                    args: map! {},
+                    when: None,
+                }),
+                serial: serial(&["rand"])
+            },
+            map! {},
+        );
+
+        let diags = check(doc);
+
+        assert_eq!(
+            messages(&diags),
+            vec!["The output of \"dead_end\" is never used"]
+        );
+    }
+
+    #[test]
+    fn ignored_outputs() {
+        let doc = doc(
+            map! {
+                rand: rand(),
+                split: Stage::ProcBlock(ProcBlockStage {
+                    proc_block: "proc-block@1.0".parse().unwrap(),
+                    inputs: vec!["rand".parse().unwrap()],
+                    outputs: vec![ty!(f32[1]), ty!(f32[1]), ty!(f32[1])],
+                    args: map! {},
+                    when: None,
+                }),
+                serial: serial(&["split.1"])
+            },
+            map! {},
+        );
+
+        let diags = check(doc);
+
+        assert_eq!(
+            messages(&diags),
+            vec![
+                "Output 0 of \"split\" is never used",
+                "Output 2 of \"split\" is never used",
+            ]
+        );
+        assert_eq!(
+            diags[1].notes,
+            vec![
+                "hint: add \"split.2\" to another stage's inputs if it is \
+                 needed"
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_resource() {
+        let doc = doc(
+            map! {
+                rand: Stage::Capability(CapabilityStage {
+                    capability: "RAND".to_string(),
+                    outputs: vec![ty!(f32[1])],
+                    args: map! {
+                        amount: ResourceName::from_str("$AMOUNT").unwrap().into(),
+                    },
+                }),
+                serial: serial(&["rand"])
+            },
+            map! {
+                AMOUNT: ResourceDeclaration::default(),
+                UNUSED: ResourceDeclaration::default()
+            },
+        );
+
+        let diags = check(doc);
+
+        assert_eq!(
+            messages(&diags),
+            vec!["The \"UNUSED\" resource is never used"]
+        );
+    }
+}
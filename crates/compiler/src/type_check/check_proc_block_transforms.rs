@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::Shape;
+use hotg_rune_proc_blocks::{
+    ProcBlockDescriptor, TensorDescriptors, TransformDescriptor,
+};
+use legion::{systems::CommandBuffer, world::SubWorld, Entity, Query};
+
+use crate::{
+    lowering::{Inputs, Name, Outputs, ProcBlock, Tensor},
+    parse::DocumentSpans,
+    type_check::{
+        check_tensor_shapes::{describe, is_compatible},
+        probe, ExpectedInputs, ExpectedOutputs,
+    },
+    BuildContext, Diagnostics, FeatureFlags,
+};
+
+/// Compile each proc block on its own and make sure the inputs and outputs of
+/// every stage using it line up with one of the transforms listed in its
+/// [`ProcBlockDescriptor`].
+///
+/// The transform each stage uses is attached as [`ExpectedInputs`] and
+/// [`ExpectedOutputs`] so individual edges can be checked later on.
+#[legion::system]
+pub(crate) fn run(
+    cmd: &mut CommandBuffer,
+    world: &SubWorld,
+    #[resource] ctx: &BuildContext,
+    #[resource] features: &FeatureFlags,
+    #[resource] spans: &DocumentSpans,
+    #[resource] diags: &mut Diagnostics,
+    proc_blocks: &mut Query<(
+        Entity,
+        &Name,
+        &ProcBlock,
+        Option<&Inputs>,
+        Option<&Outputs>,
+    )>,
+    tensors: &mut Query<(Entity, &Tensor)>,
+) {
+    if !features.probe_proc_blocks {
+        return;
+    }
+
+    let shapes: HashMap<Entity, &Shape<'static>> = tensors
+        .iter(world)
+        .map(|(&ent, Tensor(shape))| (ent, shape))
+        .collect();
+
+    // Note: the same proc block may be used by multiple stages
+    let mut descriptors: HashMap<String, Option<ProcBlockDescriptor<'static>>> =
+        HashMap::new();
+
+    proc_blocks.for_each(world, |(&ent, name, proc_block, inputs, outputs)| {
+        let descriptor = descriptors
+            .entry(proc_block.path.to_string())
+            .or_insert_with(|| {
+                match probe(
+                    proc_block,
+                    &ctx.current_directory,
+                    features.rune_repo_dir.as_deref(),
+                    &ctx.working_directory.join("probes"),
+                ) {
+                    Ok(d) => Some(d),
+                    Err(e) => {
+                        log::warn!(
+                            "Unable to determine which transforms \"{}\" \
+                             supports: {}",
+                            proc_block.path,
+                            e
+                        );
+                        None
+                    },
+                }
+            });
+
+        let descriptor = match descriptor {
+            Some(d) if !d.available_transforms.is_empty() => d,
+            _ => return,
+        };
+
+        let inputs = lookup(&shapes, inputs.map(|i| &i.tensors[..]));
+        let outputs = lookup(&shapes, outputs.map(|o| &o.tensors[..]));
+
+        match expected_transform(
+            &descriptor.available_transforms,
+            &inputs,
+            &outputs,
+        ) {
+            Some(transform) => {
+                cmd.add_component(
+                    ent,
+                    ExpectedInputs {
+                        tensors: transform.inputs.iter().cloned().collect(),
+                    },
+                );
+                cmd.add_component(
+                    ent,
+                    ExpectedOutputs {
+                        tensors: transform.outputs.iter().cloned().collect(),
+                    },
+                );
+            },
+            None => diags.push(unsupported_transform_diagnostic(
+                name,
+                proc_block,
+                &inputs,
+                &outputs,
+                &descriptor.available_transforms,
+                spans.field(name, "proc-block"),
+            )),
+        }
+    });
+}
+
+fn lookup<'a>(
+    shapes: &HashMap<Entity, &'a Shape<'static>>,
+    tensors: Option<&[Entity]>,
+) -> Vec<&'a Shape<'static>> {
+    tensors
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|ent| shapes.get(ent).copied())
+        .collect()
+}
+
+/// Find the transform a stage is using.
+///
+/// If the proc block only supports a single transform, that's the one the
+/// stage must be using even if its tensors don't line up, so the mismatch can
+/// be reported against the offending inputs and outputs.
+fn expected_transform<'a>(
+    transforms: &'a [TransformDescriptor<'static>],
+    inputs: &[&Shape<'_>],
+    outputs: &[&Shape<'_>],
+) -> Option<&'a TransformDescriptor<'static>> {
+    match transforms {
+        [single] => Some(single),
+        _ => transforms.iter().find(|t| {
+            matches(&t.inputs, inputs) && matches(&t.outputs, outputs)
+        }),
+    }
+}
+
+fn matches(descriptors: &TensorDescriptors<'_>, shapes: &[&Shape<'_>]) -> bool {
+    descriptors.len() == shapes.len()
+        && descriptors
+            .iter()
+            .zip(shapes)
+            .all(|(descriptor, shape)| is_compatible(shape, descriptor))
+}
+
+fn unsupported_transform_diagnostic(
+    name: &Name,
+    proc_block: &ProcBlock,
+    inputs: &[&Shape<'_>],
+    outputs: &[&Shape<'_>],
+    transforms: &[TransformDescriptor<'_>],
+    span: Span,
+) -> Diagnostic<()> {
+    let msg = format!(
+        "The \"{}\" stage's inputs and outputs don't match any of the \
+         transforms supported by \"{}\"",
+        name, proc_block.path,
+    );
+
+    let actual = format!(
+        "{} -> {}",
+        tuple(inputs.iter().map(|s| s.to_string())),
+        tuple(outputs.iter().map(|s| s.to_string())),
+    );
+    let mut supported = String::from("supported transforms:");
+    for transform in transforms {
+        supported.push_str(&format!(
+            "\n  {} -> {}",
+            tuple(transform.inputs.iter().map(describe)),
+            tuple(transform.outputs.iter().map(describe)),
+        ));
+    }
+
+    Diagnostic::error()
+        .with_message(msg)
+        .with_labels(vec![Label::primary((), span)
+            .with_message(format!("used as {}", actual))])
+        .with_notes(vec![supported])
+}
+
+/// Format a list of tensors the same way `rune inspect` does.
+fn tuple(items: impl Iterator<Item = String>) -> String {
+    let items: Vec<_> = items.collect();
+
+    match items.as_slice() {
+        [single] => single.clone(),
+        _ => format!("({})", items.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hotg_rune_core::ElementType;
+    use hotg_rune_proc_blocks::{Dimension, Dimensions, TensorDescriptor};
+
+    use super::*;
+
+    fn tensor(
+        element_type: ElementType,
+        dimensions: Dimensions<'static>,
+    ) -> TensorDescriptor<'static> {
+        TensorDescriptor {
+            element_type,
+            dimensions,
+        }
+    }
+
+    fn transforms() -> Vec<TransformDescriptor<'static>> {
+        vec![
+            TransformDescriptor {
+                inputs: tensor(
+                    ElementType::F32,
+                    vec![Dimension::Value(1), Dimension::Any].into(),
+                )
+                .into(),
+                outputs: tensor(ElementType::U8, Dimensions::Arbitrary).into(),
+            },
+            TransformDescriptor {
+                inputs: vec![
+                    tensor(ElementType::I16, Dimensions::Arbitrary),
+                    tensor(ElementType::I16, Dimensions::Arbitrary),
+                ]
+                .into_iter()
+                .collect(),
+                outputs: tensor(
+                    ElementType::I8,
+                    vec![Dimension::Value(1960)].into(),
+                )
+                .into(),
+            },
+        ]
+    }
+
+    fn supported(inputs: &[&str], outputs: &[&str]) -> bool {
+        let inputs: Vec<_> =
+            inputs.iter().map(|s| Shape::from_str(s).unwrap()).collect();
+        let inputs: Vec<_> = inputs.iter().collect();
+        let outputs: Vec<_> = outputs
+            .iter()
+            .map(|s| Shape::from_str(s).unwrap())
+            .collect();
+        let outputs: Vec<_> = outputs.iter().collect();
+
+        transforms().iter().any(|t| {
+            matches(&t.inputs, &inputs) && matches(&t.outputs, &outputs)
+        })
+    }
+
+    #[test]
+    fn match_stages_against_transforms() {
+        assert!(supported(&["f32[1, 128]"], &["u8[1, 2, 3]"]));
+        assert!(supported(&["f32[1, 5]"], &["u8[4]"]));
+        assert!(supported(&["i16[16000]", "i16[1, 2]"], &["i8[1960]"]));
+
+        assert!(!supported(&["f32[2, 128]"], &["u8[4]"]));
+        assert!(!supported(&["f32[1, 128]"], &["i8[1960]"]));
+        assert!(!supported(&["i16[16000]"], &["i8[1960]"]));
+        assert!(!supported(&["i16[16000]", "i16[1, 2]"], &["i8[1, 1960]"]));
+    }
+
+    fn shapes(shapes: &[&str]) -> Vec<Shape<'static>> {
+        shapes.iter().map(|s| Shape::from_str(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn use_the_matching_transform_or_the_only_one() {
+        let transforms = transforms();
+        let inputs = shapes(&["i16[16000]", "i16[1, 2]"]);
+        let outputs = shapes(&["i8[1960]"]);
+
+        let got = expected_transform(
+            &transforms,
+            &inputs.iter().collect::<Vec<_>>(),
+            &outputs.iter().collect::<Vec<_>>(),
+        );
+
+        assert_eq!(got, Some(&transforms[1]));
+
+        let inputs = shapes(&["u8[1]"]);
+        let inputs: Vec<_> = inputs.iter().collect();
+        assert_eq!(expected_transform(&transforms, &inputs, &[]), None);
+        assert_eq!(
+            expected_transform(&transforms[..1], &inputs, &[]),
+            Some(&transforms[0])
+        );
+    }
+
+    #[test]
+    fn list_the_supported_transforms() {
+        let proc_block = ProcBlock {
+            path: "hotg-ai/proc-blocks@0.11#fft".parse().unwrap(),
+            parameters: Default::default(),
+        };
+        let input = Shape::from_str("f32[2]").unwrap();
+        let output = Shape::from_str("u8[1]").unwrap();
+
+        let diag = unsupported_transform_diagnostic(
+            &Name::from("fft"),
+            &proc_block,
+            &[&input],
+            &[&output],
+            &transforms(),
+            Span::new(1, 2),
+        );
+
+        assert_eq!(
+            diag.message,
+            "The \"fft\" stage's inputs and outputs don't match any of the \
+             transforms supported by \"hotg-ai/proc-blocks@0.11#fft\""
+        );
+        assert_eq!(diag.labels[0].message, "used as f32[2] -> u8[1]");
+        assert_eq!(
+            diag.notes,
+            vec![
+                "supported transforms:\n  f32[1, _] -> u8[..]\n  (i16[..], \
+                 i16[..]) -> i8[1960]"
+            ]
+        );
+    }
+}
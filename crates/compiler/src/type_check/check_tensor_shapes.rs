@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_core::Shape;
+use hotg_rune_proc_blocks::{Dimension, Dimensions, TensorDescriptor};
+use legion::{world::SubWorld, Entity, Query};
+
+use crate::{
+    lowering::{Inputs, Name, Outputs, Tensor},
+    parse::DocumentSpans,
+    type_check::{ExpectedInputs, ExpectedOutputs},
+    Diagnostics,
+};
+
+/// Compare the element type and dimensions of the tensor flowing along each
+/// edge in the pipeline with what the nodes at either end expect.
+#[legion::system]
+pub(crate) fn run(
+    world: &SubWorld,
+    #[resource] spans: &DocumentSpans,
+    #[resource] diags: &mut Diagnostics,
+    consumers: &mut Query<(&Name, &Inputs, &ExpectedInputs)>,
+    producers: &mut Query<(&Name, &Outputs, &ExpectedOutputs)>,
+    tensors: &mut Query<(Entity, &Tensor)>,
+    nodes: &mut Query<(&Name, &Outputs)>,
+) {
+    let shapes: HashMap<Entity, &Shape<'static>> = tensors
+        .iter(world)
+        .map(|(&ent, Tensor(shape))| (ent, shape))
+        .collect();
+
+    // Remember which node produced each tensor and which of its outputs it was
+    let mut origins = HashMap::new();
+    nodes.for_each(world, |(name, outputs)| {
+        for (i, &tensor) in outputs.tensors.iter().enumerate() {
+            origins.insert(tensor, (name, i));
+        }
+    });
+
+    consumers.for_each(world, |(name, inputs, expected)| {
+        if inputs.tensors.len() != expected.tensors.len() {
+            diags.push(input_count_mismatch_diagnostic(
+                name,
+                expected.tensors.len(),
+                inputs.tensors.len(),
+                spans.field(name, "inputs"),
+            ));
+            return;
+        }
+
+        for (i, (tensor, expected)) in
+            inputs.tensors.iter().zip(&expected.tensors).enumerate()
+        {
+            let (actual, &(upstream, index)) =
+                match (shapes.get(tensor), origins.get(tensor)) {
+                    (Some(shape), Some(origin)) => (shape, origin),
+                    _ => continue,
+                };
+
+            if !is_compatible(actual, expected) {
+                diags.push(input_mismatch_diagnostic(
+                    name,
+                    i,
+                    upstream,
+                    index,
+                    actual,
+                    expected,
+                    spans.input(name, i),
+                    spans.output(upstream, index),
+                ));
+            }
+        }
+    });
+
+    producers.for_each(world, |(name, outputs, expected)| {
+        if outputs.tensors.len() != expected.tensors.len() {
+            diags.push(output_count_mismatch_diagnostic(
+                name,
+                expected.tensors.len(),
+                outputs.tensors.len(),
+                spans.field(name, "outputs"),
+            ));
+            return;
+        }
+
+        for (i, (tensor, expected)) in
+            outputs.tensors.iter().zip(&expected.tensors).enumerate()
+        {
+            let actual = match shapes.get(tensor) {
+                Some(shape) => shape,
+                None => continue,
+            };
+
+            if !is_compatible(actual, expected) {
+                diags.push(output_mismatch_diagnostic(
+                    name,
+                    i,
+                    actual,
+                    expected,
+                    spans.output(name, i),
+                ));
+            }
+        }
+    });
+}
+
+/// Does a tensor with this [`Shape`] satisfy the [`TensorDescriptor`]?
+pub(crate) fn is_compatible(
+    shape: &Shape<'_>,
+    descriptor: &TensorDescriptor<'_>,
+) -> bool {
+    if shape.element_type() != descriptor.element_type {
+        return false;
+    }
+
+    match &descriptor.dimensions {
+        Dimensions::Arbitrary => true,
+        Dimensions::Finite(dimensions) => {
+            dimensions.len() == shape.dimensions().len()
+                && dimensions.iter().zip(shape.dimensions()).all(
+                    |(expected, &actual)| match *expected {
+                        Dimension::Any => true,
+                        Dimension::Value(d) => d == actual,
+                    },
+                )
+        },
+    }
+}
+
+/// Format a [`TensorDescriptor`] the same way we'd format a [`Shape`] (e.g.
+/// `f32[1, _, 3]` or `u8[..]`).
+pub(crate) fn describe(descriptor: &TensorDescriptor<'_>) -> String {
+    format!("{}[{}]", descriptor.element_type, descriptor.dimensions)
+}
+
+fn output_name(name: &Name, index: usize) -> String {
+    if index == 0 {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, index)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn input_mismatch_diagnostic(
+    name: &Name,
+    input_index: usize,
+    upstream: &Name,
+    output_index: usize,
+    actual: &Shape<'_>,
+    expected: &TensorDescriptor<'_>,
+    input_span: Span,
+    output_span: Span,
+) -> Diagnostic<()> {
+    let msg = format!(
+        "The \"{}\" stage expects input {} to be {}, but \"{}\" produces {}",
+        name,
+        input_index,
+        describe(expected),
+        output_name(upstream, output_index),
+        actual,
+    );
+
+    Diagnostic::error().with_message(msg).with_labels(vec![
+        Label::primary((), input_span)
+            .with_message(format!("expected {}", describe(expected))),
+        Label::secondary((), output_span)
+            .with_message(format!("\"{}\" declares {} here", upstream, actual)),
+    ])
+}
+
+fn output_mismatch_diagnostic(
+    name: &Name,
+    index: usize,
+    actual: &Shape<'_>,
+    expected: &TensorDescriptor<'_>,
+    span: Span,
+) -> Diagnostic<()> {
+    let msg = format!(
+        "The \"{}\" stage declares output {} as {}, but it actually produces \
+         {}",
+        name,
+        index,
+        actual,
+        describe(expected),
+    );
+
+    Diagnostic::error()
+        .with_message(msg)
+        .with_labels(vec![Label::primary((), span)
+            .with_message(format!("should be {}", describe(expected)))])
+}
+
+fn input_count_mismatch_diagnostic(
+    name: &Name,
+    expected: usize,
+    actual: usize,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage expects {}, but {} provided",
+            name,
+            count(expected, "input"),
+            count_with_verb(actual, "was", "were"),
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+fn output_count_mismatch_diagnostic(
+    name: &Name,
+    expected: usize,
+    actual: usize,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::error()
+        .with_message(format!(
+            "The \"{}\" stage produces {}, but {} declared",
+            name,
+            count(expected, "output"),
+            count_with_verb(actual, "was", "were"),
+        ))
+        .with_labels(vec![Label::primary((), span)])
+}
+
+/// Format a count with the noun pluralised to match (e.g. "1 input" or
+/// "2 inputs").
+fn count(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, noun)
+    } else {
+        format!("{} {}s", n, noun)
+    }
+}
+
+fn count_with_verb(n: usize, singular: &str, plural: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, singular)
+    } else {
+        format!("{} {}", n, plural)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hotg_rune_core::ElementType;
+    use legion::{Resources, World};
+
+    use super::*;
+    use crate::{
+        lowering::{self, NameTable},
+        parse::{self, CapabilityStage, DocumentV1, OutStage, ProcBlockStage},
+        phases::Phase,
+        BuildContext,
+    };
+
+    fn doc() -> DocumentV1 {
+        DocumentV1 {
+            version: 1,
+            image: "image".parse().unwrap(),
+            includes: Vec::new(),
+            variables: Default::default(),
+            profiles: Default::default(),
+            pipeline: map! {
+                rand: parse::Stage::Capability(CapabilityStage {
+                    capability: "RAND".to_string(),
+                    outputs: vec![
+                        ty!(f32[1, 128]),
+                    ],
+                    args: map! {},
+                }),
+                transform: parse::Stage::ProcBlock(ProcBlockStage {
+                    proc_block: "proc-block@1.0".parse().unwrap(),
+                    inputs: vec![
+                        "rand".parse().unwrap(),
+                    ],
+                    outputs: vec![
+                        ty!(u8[1]),
+                    ],
+                    args: map! {},
+                    when: None,
+                }),
+                output: parse::Stage::Out(OutStage {
+                    out: "SERIAL".to_string(),
+                    inputs: vec![
+                        "transform".parse().unwrap(),
+                    ],
+                    args: map! {},
+                    when: None,
+                })
+            },
+            pipelines: Default::default(),
+            templates: Default::default(),
+            resources: map! {},
+        }
+    }
+
+    fn descriptor(
+        element_type: ElementType,
+        dimensions: &[Dimension],
+    ) -> TensorDescriptor<'static> {
+        TensorDescriptor {
+            element_type,
+            dimensions: Dimensions::from(dimensions.to_vec()),
+        }
+    }
+
+    /// Lower the [`doc()`], giving the "transform" stage a set of expected
+    /// inputs and outputs before type checking.
+    fn check(
+        inputs: Vec<TensorDescriptor<'static>>,
+        outputs: Vec<TensorDescriptor<'static>>,
+    ) -> (Vec<Diagnostic<()>>, String) {
+        let mut world = World::default();
+        let mut res = Resources::default();
+        res.insert(BuildContext::from_doc(doc().into()));
+        res.insert(NameTable::default());
+        crate::parse::phase().run(&mut world, &mut res);
+
+        Phase::new()
+            .and_then(lowering::register_names::run_system)
+            .and_then(lowering::update_nametable::run_system)
+            .and_then(lowering::register_stages::run_system)
+            .and_then(lowering::register_tensors::run_system)
+            .run(&mut world, &mut res);
+
+        let transform = res.get::<NameTable>().unwrap()["transform"];
+        let mut entry = world.entry(transform).unwrap();
+        entry.add_component(ExpectedInputs { tensors: inputs });
+        entry.add_component(ExpectedOutputs { tensors: outputs });
+
+        Phase::new().and_then(run_system).run(&mut world, &mut res);
+
+        let diags = res.get::<Diagnostics>().unwrap();
+        let runefile = res.get::<BuildContext>().unwrap().runefile.clone();
+
+        (diags.iter().cloned().collect(), runefile)
+    }
+
+    #[test]
+    fn compatible_shapes() {
+        let shape = Shape::from_str("f32[1, 128]").unwrap();
+        let inputs = vec![
+            (ElementType::F32, Dimensions::Arbitrary, true),
+            (ElementType::U8, Dimensions::Arbitrary, false),
+            (
+                ElementType::F32,
+                Dimensions::from(vec![Dimension::Value(1), Dimension::Any]),
+                true,
+            ),
+            (
+                ElementType::F32,
+                Dimensions::from(vec![
+                    Dimension::Value(1),
+                    Dimension::Value(128),
+                ]),
+                true,
+            ),
+            (
+                ElementType::F32,
+                Dimensions::from(vec![Dimension::Value(128)]),
+                false,
+            ),
+            (
+                ElementType::F32,
+                Dimensions::from(vec![
+                    Dimension::Value(1),
+                    Dimension::Value(64),
+                ]),
+                false,
+            ),
+        ];
+
+        for (element_type, dimensions, should_be) in inputs {
+            let descriptor = TensorDescriptor {
+                element_type,
+                dimensions,
+            };
+
+            assert_eq!(
+                is_compatible(&shape, &descriptor),
+                should_be,
+                "{}",
+                describe(&descriptor)
+            );
+        }
+    }
+
+    #[test]
+    fn matching_shapes_are_fine() {
+        let (diags, _) = check(
+            vec![descriptor(
+                ElementType::F32,
+                &[Dimension::Any, Dimension::Value(128)],
+            )],
+            vec![descriptor(ElementType::U8, &[Dimension::Value(1)])],
+        );
+
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+
+    #[test]
+    fn mismatched_input() {
+        let (diags, runefile) = check(
+            vec![descriptor(
+                ElementType::I8,
+                &[Dimension::Value(1), Dimension::Value(128)],
+            )],
+            vec![descriptor(ElementType::U8, &[Dimension::Value(1)])],
+        );
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "The \"transform\" stage expects input 0 to be i8[1, 128], but \
+             \"rand\" produces f32[1, 128]"
+        );
+        let labels = &diags[0].labels;
+        assert_eq!(&runefile[labels[0].range.clone()], "rand");
+        assert!(runefile[labels[1].range.clone()].contains("f32"));
+    }
+
+    #[test]
+    fn mismatched_output() {
+        let (diags, _) = check(
+            vec![descriptor(ElementType::F32, &[])],
+            vec![descriptor(ElementType::U8, &[Dimension::Value(2)])],
+        );
+
+        let messages: Vec<_> = diags.iter().map(|d| &d.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "The \"transform\" stage expects input 0 to be f32[], but \
+                 \"rand\" produces f32[1, 128]",
+                "The \"transform\" stage declares output 0 as u8[1], but it \
+                 actually produces u8[2]",
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_number_of_inputs() {
+        let (diags, _) = check(
+            vec![
+                descriptor(ElementType::F32, &[]),
+                descriptor(ElementType::F32, &[]),
+            ],
+            Vec::new(),
+        );
+
+        let messages: Vec<_> = diags.iter().map(|d| &d.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "The \"transform\" stage expects 2 inputs, but 1 was provided",
+                "The \"transform\" stage produces 0 outputs, but 1 was \
+                 declared",
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_are_pluralised() {
+        assert_eq!(count(1, "input"), "1 input");
+        assert_eq!(count(0, "output"), "0 outputs");
+        assert_eq!(count_with_verb(1, "was", "were"), "1 was");
+        assert_eq!(count_with_verb(2, "was", "were"), "2 were");
+    }
+}
@@ -1 +1,20 @@
+use hotg_rune_proc_blocks::TensorDescriptor;
 
+/// The tensors a [`crate::lowering::PipelineNode`] expects to receive as
+/// input, as reported by something which knows more about the node than the
+/// Runefile does (e.g. a proc block's descriptor or the model file).
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct ExpectedInputs {
+    pub tensors: Vec<TensorDescriptor<'static>>,
+}
+
+/// The tensors a [`crate::lowering::PipelineNode`] will actually produce,
+/// which should line up with the `outputs` declared in the Runefile.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct ExpectedOutputs {
+    pub tensors: Vec<TensorDescriptor<'static>>,
+}
@@ -0,0 +1,142 @@
+//! Read the tensors a model accepts and produces from the model file itself.
+
+pub mod onnx;
+pub mod tflite;
+
+use std::fmt::{self, Display, Formatter};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hotg_rune_proc_blocks::TensorDescriptor;
+use legion::{systems::CommandBuffer, world::SubWorld, Entity, Query};
+
+use crate::{
+    lowering::{Mimetype, Model, ModelData, ModelFile, Name, ResourceData},
+    parse::DocumentSpans,
+    type_check::{ExpectedInputs, ExpectedOutputs},
+    Diagnostics,
+};
+
+/// Attach [`ExpectedInputs`] and [`ExpectedOutputs`] to every model we know
+/// how to inspect so they can be checked against the Runefile.
+#[legion::system]
+pub(crate) fn run(
+    cmd: &mut CommandBuffer,
+    world: &SubWorld,
+    #[resource] spans: &DocumentSpans,
+    #[resource] diags: &mut Diagnostics,
+    models: &mut Query<(Entity, &Name, &Model, &Mimetype, Option<&ModelData>)>,
+    resources: &mut Query<&ResourceData>,
+) {
+    models.for_each(world, |(&ent, name, model, mimetype, data)| {
+        let data: &[u8] = match (&model.model_file, data) {
+            (_, Some(data)) => data,
+            (&ModelFile::Resource(resource), None) => {
+                match resources.get(world, resource) {
+                    Ok(data) => data,
+                    // The resource's value will be provided at runtime
+                    Err(_) => return,
+                }
+            },
+            (ModelFile::FromDisk(_), None) => return,
+        };
+
+        match signature(mimetype, data) {
+            Ok(Signature { inputs, outputs }) => {
+                cmd.add_component(ent, ExpectedInputs { tensors: inputs });
+                cmd.add_component(ent, ExpectedOutputs { tensors: outputs });
+            },
+            Err(e) => diags.push(uninspectable_model_diagnostic(
+                name,
+                &e,
+                spans.stage(name),
+            )),
+        }
+    });
+}
+
+fn signature(mimetype: &str, data: &[u8]) -> Result<Signature, ModelError> {
+    match mimetype {
+        hotg_rune_core::TFLITE_MIMETYPE => tflite::signature(data),
+        hotg_rune_core::ONNX_MIMETYPE => onnx::signature(data),
+        _ => Err(ModelError::UnsupportedFormat),
+    }
+}
+
+fn uninspectable_model_diagnostic(
+    name: &Name,
+    error: &ModelError,
+    span: Span,
+) -> Diagnostic<()> {
+    Diagnostic::warning()
+        .with_message(format!(
+            "Unable to check the \"{}\" stage against its model",
+            name
+        ))
+        .with_labels(vec![
+            Label::primary((), span).with_message(error.to_string())
+        ])
+        .with_notes(vec![String::from(
+            "the stage's inputs and outputs will only be checked at runtime",
+        )])
+}
+
+/// The tensors passed to and from a model.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Signature {
+    inputs: Vec<TensorDescriptor<'static>>,
+    outputs: Vec<TensorDescriptor<'static>>,
+}
+
+/// The reasons a model's inputs and outputs can't be determined.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelError {
+    UnsupportedFormat,
+    Malformed,
+    UnsupportedElementType(i32),
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::UnsupportedFormat => {
+                f.write_str("Inspecting this kind of model isn't supported")
+            },
+            ModelError::Malformed => f.write_str("Unable to parse the model"),
+            ModelError::UnsupportedElementType(ty) => {
+                write!(f, "Unsupported element type ({})", ty)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+#[cfg(test)]
+mod tests {
+    use codespan_reporting::diagnostic::Severity;
+
+    use super::*;
+
+    #[test]
+    fn unsupported_models_are_reported() {
+        let err = signature("application/tfjs-model", b"...").unwrap_err();
+        assert_eq!(err, ModelError::UnsupportedFormat);
+
+        let diag = uninspectable_model_diagnostic(
+            &Name::from("model"),
+            &err,
+            Span::new(1, 2),
+        );
+
+        assert_eq!(diag.severity, Severity::Warning);
+        assert_eq!(
+            diag.message,
+            "Unable to check the \"model\" stage against its model"
+        );
+        assert_eq!(
+            diag.labels[0].message,
+            "Inspecting this kind of model isn't supported"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Just enough of the [ONNX protobuf schema][schema] to find a model's inputs
+//! and outputs.
+//!
+//! [schema]: https://github.com/onnx/onnx/blob/main/onnx/onnx.proto3
+
+use std::{collections::HashSet, convert::TryFrom};
+
+use hotg_rune_core::ElementType;
+use hotg_rune_proc_blocks::{Dimension, Dimensions, TensorDescriptor};
+use prost::Message;
+
+use super::{ModelError, Signature};
+
+/// The parts of an ONNX model's graph we care about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnnxModel {
+    pub inputs: Vec<OnnxTensor>,
+    pub outputs: Vec<OnnxTensor>,
+    /// The number of operations in the graph.
+    pub ops: usize,
+}
+
+/// A tensor passed to or from an [`OnnxModel`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnnxTensor {
+    pub name: String,
+    /// The tensor's `TensorProto.DataType`, if the value is a tensor.
+    pub elem_type: Option<i32>,
+    /// The tensor's dimensions (if known), where `None` is a dimension that
+    /// isn't known until runtime.
+    pub dims: Option<Vec<Option<usize>>>,
+}
+
+/// Does this look like an ONNX model?
+pub fn is_onnx(raw: &[u8]) -> bool {
+    match ModelProto::decode(raw) {
+        Ok(model) => model.ir_version > 0 && model.graph.is_some(),
+        Err(_) => false,
+    }
+}
+
+/// Read the inputs and outputs from an ONNX model.
+pub fn parse(raw: &[u8]) -> Result<OnnxModel, ModelError> {
+    let model = ModelProto::decode(raw).map_err(|_| ModelError::Malformed)?;
+    let graph = model.graph.ok_or(ModelError::Malformed)?;
+
+    // Older versions of ONNX list weights as inputs, so we need to skip over
+    // anything that has an initializer.
+    let initializers: HashSet<&str> =
+        graph.initializer.iter().map(|t| t.name.as_str()).collect();
+
+    let inputs = graph
+        .input
+        .iter()
+        .filter(|i| !initializers.contains(i.name.as_str()))
+        .map(tensor)
+        .collect();
+    let outputs = graph.output.iter().map(tensor).collect();
+
+    Ok(OnnxModel {
+        inputs,
+        outputs,
+        ops: graph.node.len(),
+    })
+}
+
+pub(crate) fn signature(raw: &[u8]) -> Result<Signature, ModelError> {
+    let model = parse(raw)?;
+
+    let inputs = model
+        .inputs
+        .iter()
+        .map(descriptor)
+        .collect::<Result<_, _>>()?;
+    let outputs = model
+        .outputs
+        .iter()
+        .map(descriptor)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Signature { inputs, outputs })
+}
+
+fn tensor(value: &ValueInfoProto) -> OnnxTensor {
+    let tensor_type =
+        value.r#type.as_ref().and_then(|t| t.tensor_type.as_ref());
+
+    let dims = tensor_type.and_then(|t| t.shape.as_ref()).map(|shape| {
+        shape
+            .dim
+            .iter()
+            .map(|d| match d.dim_value.map(usize::try_from) {
+                Some(Ok(value)) if value > 0 => Some(value),
+                _ => None,
+            })
+            .collect()
+    });
+
+    OnnxTensor {
+        name: value.name.clone(),
+        elem_type: tensor_type.map(|t| t.elem_type),
+        dims,
+    }
+}
+
+fn descriptor(
+    tensor: &OnnxTensor,
+) -> Result<TensorDescriptor<'static>, ModelError> {
+    let elem_type = tensor.elem_type.ok_or(ModelError::Malformed)?;
+    let element_type = element_type(elem_type)
+        .ok_or(ModelError::UnsupportedElementType(elem_type))?;
+
+    let dimensions = match &tensor.dims {
+        Some(dims) => dims
+            .iter()
+            .map(|d| match *d {
+                Some(value) => Dimension::Value(value),
+                None => Dimension::Any,
+            })
+            .collect::<Vec<_>>()
+            .into(),
+        None => Dimensions::Arbitrary,
+    };
+
+    Ok(TensorDescriptor {
+        element_type,
+        dimensions,
+    })
+}
+
+/// Convert a `TensorProto.DataType` to the equivalent [`ElementType`].
+pub fn element_type(data_type: i32) -> Option<ElementType> {
+    match data_type {
+        1 => Some(ElementType::F32),
+        2 => Some(ElementType::U8),
+        3 => Some(ElementType::I8),
+        4 => Some(ElementType::U16),
+        5 => Some(ElementType::I16),
+        6 => Some(ElementType::I32),
+        7 => Some(ElementType::I64),
+        8 => Some(ElementType::String),
+        11 => Some(ElementType::F64),
+        12 => Some(ElementType::U32),
+        13 => Some(ElementType::U64),
+        _ => None,
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ModelProto {
+    #[prost(int64, tag = "1")]
+    ir_version: i64,
+    #[prost(message, optional, tag = "7")]
+    graph: Option<GraphProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct GraphProto {
+    #[prost(message, repeated, tag = "1")]
+    node: Vec<NodeProto>,
+    #[prost(message, repeated, tag = "5")]
+    initializer: Vec<TensorProto>,
+    #[prost(message, repeated, tag = "11")]
+    input: Vec<ValueInfoProto>,
+    #[prost(message, repeated, tag = "12")]
+    output: Vec<ValueInfoProto>,
+}
+
+/// An operation in the graph. We only care about how many there are.
+#[derive(Clone, PartialEq, Message)]
+struct NodeProto {}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorProto {
+    #[prost(string, tag = "8")]
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ValueInfoProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, optional, tag = "2")]
+    r#type: Option<TypeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TypeProto {
+    #[prost(message, optional, tag = "1")]
+    tensor_type: Option<TensorType>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorType {
+    #[prost(int32, tag = "1")]
+    elem_type: i32,
+    #[prost(message, optional, tag = "2")]
+    shape: Option<TensorShapeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorShapeProto {
+    #[prost(message, repeated, tag = "1")]
+    dim: Vec<DimensionProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct DimensionProto {
+    /// A fixed dimension. This will be missing if the dimension is symbolic
+    /// (i.e. the `dim_param` field is set instead).
+    #[prost(int64, optional, tag = "1")]
+    dim_value: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(
+        name: &str,
+        elem_type: i32,
+        dims: &[Option<i64>],
+    ) -> ValueInfoProto {
+        ValueInfoProto {
+            name: name.to_string(),
+            r#type: Some(TypeProto {
+                tensor_type: Some(TensorType {
+                    elem_type,
+                    shape: Some(TensorShapeProto {
+                        dim: dims
+                            .iter()
+                            .map(|&dim_value| DimensionProto { dim_value })
+                            .collect(),
+                    }),
+                }),
+            }),
+        }
+    }
+
+    #[test]
+    fn inputs_and_outputs() {
+        let model = ModelProto {
+            ir_version: 7,
+            graph: Some(GraphProto {
+                node: vec![NodeProto {}, NodeProto {}],
+                initializer: vec![TensorProto {
+                    name: "weights".to_string(),
+                }],
+                input: vec![
+                    value("input", 1, &[None, Some(3), Some(224), Some(224)]),
+                    value("weights", 1, &[Some(64)]),
+                ],
+                output: vec![value("output", 7, &[Some(1), Some(1000)])],
+            }),
+        };
+        let raw = model.encode_to_vec();
+
+        assert!(is_onnx(&raw));
+        assert_eq!(parse(&raw).unwrap().ops, 2);
+        let got = signature(&raw).unwrap();
+
+        assert_eq!(
+            got,
+            Signature {
+                inputs: vec![TensorDescriptor {
+                    element_type: ElementType::F32,
+                    dimensions: vec![
+                        Dimension::Any,
+                        Dimension::Value(3),
+                        Dimension::Value(224),
+                        Dimension::Value(224),
+                    ]
+                    .into(),
+                }],
+                outputs: vec![TensorDescriptor {
+                    element_type: ElementType::I64,
+                    dimensions: vec![
+                        Dimension::Value(1),
+                        Dimension::Value(1000)
+                    ]
+                    .into(),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn unsupported_element_type() {
+        let model = ModelProto {
+            ir_version: 7,
+            graph: Some(GraphProto {
+                node: Vec::new(),
+                initializer: Vec::new(),
+                input: vec![value("input", 9, &[Some(1)])],
+                output: Vec::new(),
+            }),
+        };
+        let raw = model.encode_to_vec();
+
+        let err = signature(&raw).unwrap_err();
+
+        assert_eq!(err, ModelError::UnsupportedElementType(9));
+    }
+}
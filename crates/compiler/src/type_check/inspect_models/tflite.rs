@@ -0,0 +1,413 @@
+//! Just enough of the [TensorFlow Lite schema][schema] to find a model's
+//! inputs and outputs, reading the [flatbuffer][fb] by hand.
+//!
+//! [schema]: https://github.com/tensorflow/tensorflow/blob/master/tensorflow/lite/schema/schema.fbs
+//! [fb]: https://google.github.io/flatbuffers/flatbuffers_internals.html
+
+use std::convert::TryFrom;
+
+use hotg_rune_core::ElementType;
+use hotg_rune_proc_blocks::{Dimension, Dimensions, TensorDescriptor};
+
+use super::{ModelError, Signature};
+
+/// The file identifier embedded in every TensorFlow Lite flatbuffer.
+const FILE_IDENTIFIER: &[u8] = b"TFL3";
+
+// Field indices, taken from the schema
+const MODEL_SUBGRAPHS: usize = 2;
+const SUBGRAPH_TENSORS: usize = 0;
+const SUBGRAPH_INPUTS: usize = 1;
+const SUBGRAPH_OUTPUTS: usize = 2;
+const SUBGRAPH_OPERATORS: usize = 3;
+const TENSOR_SHAPE: usize = 0;
+const TENSOR_TYPE: usize = 1;
+const TENSOR_NAME: usize = 3;
+const TENSOR_SHAPE_SIGNATURE: usize = 7;
+
+/// The parts of a TensorFlow Lite model's main subgraph we care about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TfliteModel {
+    pub inputs: Vec<TfliteTensor>,
+    pub outputs: Vec<TfliteTensor>,
+    /// The number of operations in the subgraph.
+    pub ops: usize,
+}
+
+/// A tensor passed to or from a [`TfliteModel`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TfliteTensor {
+    pub name: String,
+    /// The tensor's `TensorType`.
+    pub tensor_type: i8,
+    /// The tensor's dimensions (if known), where `None` is a dimension that
+    /// isn't known until runtime.
+    pub dims: Option<Vec<Option<usize>>>,
+}
+
+/// Does this look like a TensorFlow Lite model?
+pub fn is_tflite(raw: &[u8]) -> bool { raw.get(4..8) == Some(FILE_IDENTIFIER) }
+
+/// Read the inputs and outputs from a TensorFlow Lite model.
+pub fn parse(raw: &[u8]) -> Result<TfliteModel, ModelError> {
+    if !is_tflite(raw) {
+        return Err(ModelError::Malformed);
+    }
+
+    let model = Buffer(raw).root().ok_or(ModelError::Malformed)?;
+    let subgraph = model
+        .vector(MODEL_SUBGRAPHS)
+        .and_then(|subgraphs| subgraphs.table(0))
+        .ok_or(ModelError::Malformed)?;
+    let tensors = subgraph
+        .vector(SUBGRAPH_TENSORS)
+        .ok_or(ModelError::Malformed)?;
+    let ops = subgraph.vector(SUBGRAPH_OPERATORS).map_or(0, |ops| ops.len);
+
+    Ok(TfliteModel {
+        inputs: tensors_at(&subgraph, SUBGRAPH_INPUTS, &tensors)?,
+        outputs: tensors_at(&subgraph, SUBGRAPH_OUTPUTS, &tensors)?,
+        ops,
+    })
+}
+
+pub(crate) fn signature(raw: &[u8]) -> Result<Signature, ModelError> {
+    let model = parse(raw)?;
+
+    let inputs = model
+        .inputs
+        .iter()
+        .map(descriptor)
+        .collect::<Result<_, _>>()?;
+    let outputs = model
+        .outputs
+        .iter()
+        .map(descriptor)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Signature { inputs, outputs })
+}
+
+/// Look up the tensors referenced by a subgraph's `inputs` or `outputs`.
+fn tensors_at(
+    subgraph: &Table<'_>,
+    field: usize,
+    tensors: &Vector<'_>,
+) -> Result<Vec<TfliteTensor>, ModelError> {
+    let indices = subgraph
+        .vector(field)
+        .and_then(|v| v.i32s())
+        .ok_or(ModelError::Malformed)?;
+
+    indices
+        .into_iter()
+        .map(|index| {
+            let tensor = usize::try_from(index)
+                .ok()
+                .and_then(|ix| tensors.table(ix))
+                .ok_or(ModelError::Malformed)?;
+            tensor_info(&tensor)
+        })
+        .collect()
+}
+
+fn tensor_info(tensor: &Table<'_>) -> Result<TfliteTensor, ModelError> {
+    let name = match tensor.field(TENSOR_NAME) {
+        Some(pos) => tensor.buffer.string(pos).ok_or(ModelError::Malformed)?,
+        None => String::new(),
+    };
+
+    // Note: the type is a byte which defaults to FLOAT32 when missing
+    let tensor_type = match tensor.field(TENSOR_TYPE) {
+        Some(pos) => tensor.buffer.u8(pos).ok_or(ModelError::Malformed)? as i8,
+        None => 0,
+    };
+
+    // Dynamic dimensions are only recorded in the shape signature (as -1)
+    let shape = tensor
+        .vector(TENSOR_SHAPE_SIGNATURE)
+        .or_else(|| tensor.vector(TENSOR_SHAPE));
+    let dims = match shape {
+        Some(shape) => Some(
+            shape
+                .i32s()
+                .ok_or(ModelError::Malformed)?
+                .into_iter()
+                .map(|d| usize::try_from(d).ok())
+                .collect(),
+        ),
+        None => None,
+    };
+
+    Ok(TfliteTensor {
+        name,
+        tensor_type,
+        dims,
+    })
+}
+
+fn descriptor(
+    tensor: &TfliteTensor,
+) -> Result<TensorDescriptor<'static>, ModelError> {
+    let element_type = element_type(tensor.tensor_type).ok_or(
+        ModelError::UnsupportedElementType(tensor.tensor_type.into()),
+    )?;
+
+    let dimensions = match &tensor.dims {
+        Some(dims) => dims
+            .iter()
+            .map(|d| match d {
+                Some(d) => Dimension::Value(*d),
+                None => Dimension::Any,
+            })
+            .collect::<Vec<_>>()
+            .into(),
+        None => Dimensions::Arbitrary,
+    };
+
+    Ok(TensorDescriptor {
+        element_type,
+        dimensions,
+    })
+}
+
+/// Convert a `TensorType` to the equivalent [`ElementType`].
+pub fn element_type(ty: i8) -> Option<ElementType> {
+    match ty {
+        0 => Some(ElementType::F32),
+        2 => Some(ElementType::I32),
+        3 => Some(ElementType::U8),
+        4 => Some(ElementType::I64),
+        5 => Some(ElementType::String),
+        7 => Some(ElementType::I16),
+        9 => Some(ElementType::I8),
+        10 => Some(ElementType::F64),
+        12 => Some(ElementType::U64),
+        15 => Some(ElementType::U32),
+        16 => Some(ElementType::U16),
+        _ => None,
+    }
+}
+
+/// A flatbuffer, with bounds-checked accessors for reading little-endian
+/// values.
+#[derive(Debug, Copy, Clone)]
+struct Buffer<'a>(&'a [u8]);
+
+impl<'a> Buffer<'a> {
+    fn bytes<const N: usize>(&self, pos: usize) -> Option<[u8; N]> {
+        let bytes = self.0.get(pos..pos.checked_add(N)?)?;
+        <[u8; N]>::try_from(bytes).ok()
+    }
+
+    fn u8(&self, pos: usize) -> Option<u8> { self.0.get(pos).copied() }
+
+    fn u16(&self, pos: usize) -> Option<u16> {
+        self.bytes(pos).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, pos: usize) -> Option<u32> {
+        self.bytes(pos).map(u32::from_le_bytes)
+    }
+
+    fn i32(&self, pos: usize) -> Option<i32> {
+        self.bytes(pos).map(i32::from_le_bytes)
+    }
+
+    /// Read the string that the offset stored at `pos` refers to.
+    fn string(&self, pos: usize) -> Option<String> {
+        let start = self.follow(pos)?;
+        let len = usize::try_from(self.u32(start)?).ok()?;
+        let bytes = self.0.get(start + 4..start.checked_add(4 + len)?)?;
+
+        std::str::from_utf8(bytes).ok().map(String::from)
+    }
+
+    /// Follow the offset stored at `pos` to whatever it refers to.
+    fn follow(&self, pos: usize) -> Option<usize> {
+        pos.checked_add(usize::try_from(self.u32(pos)?).ok()?)
+    }
+
+    fn root(self) -> Option<Table<'a>> {
+        Some(Table {
+            buffer: self,
+            pos: self.follow(0)?,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Table<'a> {
+    buffer: Buffer<'a>,
+    pos: usize,
+}
+
+impl<'a> Table<'a> {
+    /// Find where a field is stored, returning `None` if it isn't set.
+    fn field(&self, index: usize) -> Option<usize> {
+        // The table starts with a signed offset back to its vtable, which
+        // contains the vtable's length, the table's length, and then the
+        // offset of each field.
+        let vtable = i64::try_from(self.pos).ok()?
+            - i64::from(self.buffer.i32(self.pos)?);
+        let vtable = usize::try_from(vtable).ok()?;
+        let vtable_len = usize::from(self.buffer.u16(vtable)?);
+
+        let entry = 4 + 2 * index;
+        if entry + 2 > vtable_len {
+            return None;
+        }
+
+        match self.buffer.u16(vtable + entry)? {
+            0 => None,
+            offset => Some(self.pos + usize::from(offset)),
+        }
+    }
+
+    fn vector(&self, index: usize) -> Option<Vector<'a>> {
+        let pos = self.buffer.follow(self.field(index)?)?;
+        let len = usize::try_from(self.buffer.u32(pos)?).ok()?;
+
+        Some(Vector {
+            buffer: self.buffer,
+            start: pos + 4,
+            len,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Vector<'a> {
+    buffer: Buffer<'a>,
+    start: usize,
+    len: usize,
+}
+
+impl<'a> Vector<'a> {
+    fn table(&self, index: usize) -> Option<Table<'a>> {
+        if index >= self.len {
+            return None;
+        }
+
+        Some(Table {
+            buffer: self.buffer,
+            pos: self.buffer.follow(self.start + 4 * index)?,
+        })
+    }
+
+    fn i32s(&self) -> Option<Vec<i32>> {
+        (0..self.len)
+            .map(|i| self.buffer.i32(self.start + 4 * i))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tensor(
+        element_type: ElementType,
+        dimensions: &[usize],
+    ) -> TensorDescriptor<'static> {
+        TensorDescriptor {
+            element_type,
+            dimensions: dimensions
+                .iter()
+                .map(|&d| Dimension::Value(d))
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+
+    #[test]
+    fn sine_model() {
+        let raw =
+            include_bytes!("../../../../../examples/sine/sinemodel.tflite");
+
+        let got = signature(raw).unwrap();
+
+        assert_eq!(
+            got,
+            Signature {
+                inputs: vec![tensor(ElementType::F32, &[1, 1])],
+                outputs: vec![tensor(ElementType::F32, &[1, 1])],
+            }
+        );
+    }
+
+    #[test]
+    fn tensor_names_and_op_count() {
+        let raw =
+            include_bytes!("../../../../../examples/gesture/model.tflite");
+
+        let got = parse(raw).unwrap();
+
+        assert_eq!(
+            got,
+            TfliteModel {
+                inputs: vec![TfliteTensor {
+                    name: "conv2d_input".to_string(),
+                    tensor_type: 0,
+                    dims: Some(vec![None, Some(128), Some(3), Some(1)]),
+                }],
+                outputs: vec![TfliteTensor {
+                    name: "Identity".to_string(),
+                    tensor_type: 0,
+                    dims: Some(vec![None, Some(4)]),
+                }],
+                ops: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn quantized_model() {
+        let raw =
+            include_bytes!("../../../../../examples/microspeech/model.tflite");
+
+        let got = signature(raw).unwrap();
+
+        assert_eq!(
+            got,
+            Signature {
+                inputs: vec![tensor(ElementType::I8, &[1, 1960])],
+                outputs: vec![tensor(ElementType::I8, &[1, 6])],
+            }
+        );
+    }
+
+    #[test]
+    fn model_with_multiple_inputs() {
+        let raw = include_bytes!(
+            "../../../../../examples/style_transfer/style_transform.tflite"
+        );
+
+        let got = signature(raw).unwrap();
+
+        assert_eq!(
+            got,
+            Signature {
+                inputs: vec![
+                    tensor(ElementType::F32, &[1, 384, 384, 3]),
+                    tensor(ElementType::F32, &[1, 1, 1, 100]),
+                ],
+                outputs: vec![tensor(ElementType::F32, &[1, 384, 384, 3])],
+            }
+        );
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        let inputs: &[&[u8]] = &[
+            b"",
+            b"\x08\0\0\0TFL3",
+            b"\xff\xff\xff\xffTFL3\0\0\0\0",
+            b"Hello, World!",
+        ];
+
+        for &input in inputs {
+            assert_eq!(signature(input), Err(ModelError::Malformed));
+        }
+    }
+}
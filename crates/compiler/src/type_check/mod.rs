@@ -1,18 +1,39 @@
 //! The type checking phase.
 
+mod check_arguments;
+mod check_conditions;
+mod check_delays;
 mod check_for_loops;
+mod check_for_unused;
+mod check_proc_block_transforms;
+mod check_tensor_shapes;
 mod components;
+mod inspect_models;
 mod model_args_are_consumed;
+mod probe;
 
 pub use components::*;
+pub use inspect_models::{onnx, tflite, ModelError};
 use legion::Registry;
+pub use probe::{descriptor_from_wasm, probe, ProbeError};
 
-use crate::phases::Phase;
+use crate::{phases::Phase, serialize::RegistryExt};
 
 pub fn phase() -> Phase {
     Phase::new()
         .and_then(check_for_loops::run_system)
         .and_then(model_args_are_consumed::run_system)
+        .and_then(check_arguments::run_system)
+        .and_then(inspect_models::run_system)
+        .and_then(check_proc_block_transforms::run_system)
+        .and_then(check_tensor_shapes::run_system)
+        .and_then(check_conditions::run_system)
+        .and_then(check_delays::run_system)
+        .and_then(check_for_unused::run_system)
 }
 
-pub(crate) fn register_components(_registry: &mut Registry<String>) {}
+pub(crate) fn register_components(registry: &mut Registry<String>) {
+    registry
+        .register_with_type_name::<ExpectedInputs>()
+        .register_with_type_name::<ExpectedOutputs>();
+}
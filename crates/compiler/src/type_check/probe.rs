@@ -0,0 +1,306 @@
+//! Compile a proc block on its own so we can read its
+//! [`ProcBlockDescriptor`].
+
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Output},
+};
+
+use hotg_rune_proc_blocks::ProcBlockDescriptor;
+use wasmparser::{Parser, Payload};
+
+use crate::{codegen, lowering::ProcBlock};
+
+/// Compile a crate which re-exports the proc block and read the
+/// [`ProcBlockDescriptor`] from its custom section.
+///
+/// The crate is generated inside `probes_dir`. Descriptors for proc blocks
+/// pinned to a specific version are saved alongside it, so they only need to
+/// be compiled once.
+pub fn probe(
+    proc_block: &ProcBlock,
+    current_dir: &Path,
+    rune_repo_dir: Option<&Path>,
+    probes_dir: &Path,
+) -> Result<ProcBlockDescriptor<'static>, ProbeError> {
+    let dir = probes_dir.join(proc_block.name());
+    let cached = dir.join("descriptor.json");
+    let cacheable = is_cacheable(proc_block, rune_repo_dir);
+
+    if cacheable {
+        let descriptor = std::fs::read(&cached)
+            .ok()
+            .and_then(|json| cached_descriptor(&json, proc_block));
+
+        if let Some(descriptor) = descriptor {
+            log::debug!("Reusing the descriptor for \"{}\"", proc_block.path);
+            return Ok(descriptor);
+        }
+    }
+
+    let manifest =
+        codegen::probe_manifest(proc_block, current_dir, rune_repo_dir);
+    let manifest = toml::to_string_pretty(&manifest)
+        .expect("Serializing to a string should never fail");
+    write(&dir.join("Cargo.toml"), manifest)?;
+    write(
+        &dir.join("lib.rs"),
+        format!("pub use {}::*;\n", proc_block.name().replace("-", "_")),
+    )?;
+    write(
+        &dir.join("rust-toolchain.toml"),
+        crate::rust_toolchain().to_string(),
+    )?;
+
+    let target_dir = probes_dir.join("target");
+
+    let mut cmd = Command::new("cargo");
+    cmd.arg("build")
+        .arg("--manifest-path")
+        .arg(dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .arg("--target=wasm32-unknown-unknown")
+        .current_dir(&dir);
+
+    log::debug!("Executing {:?}", cmd);
+
+    let Output { status, stderr, .. } =
+        cmd.output().map_err(ProbeError::DidntStart)?;
+
+    if !status.success() {
+        return Err(ProbeError::BuildFailed {
+            status,
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        });
+    }
+
+    let wasm = target_dir
+        .join("wasm32-unknown-unknown")
+        .join("debug")
+        .join(format!("probe_{}", proc_block.name().replace("-", "_")))
+        .with_extension("wasm");
+    let wasm = std::fs::read(&wasm)
+        .map_err(|error| ProbeError::Io { path: wasm, error })?;
+
+    let descriptor = descriptor_from_wasm(&wasm)?;
+
+    if cacheable {
+        let entry = CachedDescriptor {
+            path: proc_block.path.to_string(),
+            descriptor: descriptor.clone(),
+        };
+        let json = serde_json::to_vec_pretty(&entry)
+            .expect("Serializing to JSON should never fail");
+        write(&cached, json)?;
+    }
+
+    Ok(descriptor)
+}
+
+/// The [`ProcBlockDescriptor`] for a proc block, as saved by [`probe()`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct CachedDescriptor {
+    path: String,
+    descriptor: ProcBlockDescriptor<'static>,
+}
+
+/// Will this proc block always have the same [`ProcBlockDescriptor`]?
+///
+/// Local proc blocks (or ones patched to use the Rune repo) may change at any
+/// time, as can git dependencies without a tag.
+fn is_cacheable(proc_block: &ProcBlock, rune_repo_dir: Option<&Path>) -> bool {
+    rune_repo_dir.is_none()
+        && !proc_block.path.base.starts_with('.')
+        && proc_block.path.version.is_some()
+}
+
+fn cached_descriptor(
+    json: &[u8],
+    proc_block: &ProcBlock,
+) -> Option<ProcBlockDescriptor<'static>> {
+    let entry: CachedDescriptor = serde_json::from_slice(json).ok()?;
+
+    if entry.path == proc_block.path.to_string() {
+        Some(entry.descriptor)
+    } else {
+        None
+    }
+}
+
+/// Read the [`ProcBlockDescriptor`] from a compiled proc block.
+pub fn descriptor_from_wasm(
+    wasm: &[u8],
+) -> Result<ProcBlockDescriptor<'static>, ProbeError> {
+    for payload in Parser::default().parse_all(wasm) {
+        let payload = payload.map_err(ProbeError::InvalidWasm)?;
+
+        if let Payload::CustomSection { name, data, .. } = payload {
+            if name == ProcBlockDescriptor::CUSTOM_SECTION_NAME {
+                return serde_json::from_slice(data)
+                    .map_err(ProbeError::InvalidDescriptor);
+            }
+        }
+    }
+
+    Err(ProbeError::MissingDescriptor)
+}
+
+fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), ProbeError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|error| ProbeError::Io {
+            path: parent.to_path_buf(),
+            error,
+        })?;
+    }
+
+    std::fs::write(path, contents).map_err(|error| ProbeError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+#[derive(Debug)]
+pub enum ProbeError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    DidntStart(std::io::Error),
+    BuildFailed {
+        status: ExitStatus,
+        stderr: String,
+    },
+    InvalidWasm(wasmparser::BinaryReaderError),
+    MissingDescriptor,
+    InvalidDescriptor(serde_json::Error),
+}
+
+impl Display for ProbeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Io { path, error } => {
+                write!(f, "Unable to access \"{}\": {}", path.display(), error)
+            },
+            ProbeError::DidntStart(e) => {
+                write!(f, "Unable to run cargo: {}", e)
+            },
+            ProbeError::BuildFailed { status, stderr } => {
+                write!(f, "Compilation failed ({})\n{}", status, stderr)
+            },
+            ProbeError::InvalidWasm(e) => {
+                write!(f, "Unable to parse the WebAssembly module: {}", e)
+            },
+            ProbeError::MissingDescriptor => {
+                f.write_str("The proc block doesn't contain any metadata")
+            },
+            ProbeError::InvalidDescriptor(e) => {
+                write!(f, "Unable to parse the proc block's metadata: {}", e)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use hotg_rune_core::ElementType;
+    use hotg_rune_proc_blocks::{
+        Dimensions, TensorDescriptor, TransformDescriptor,
+    };
+
+    use super::*;
+
+    fn descriptor() -> ProcBlockDescriptor<'static> {
+        let tensor = TensorDescriptor {
+            element_type: ElementType::F32,
+            dimensions: Dimensions::Arbitrary,
+        };
+
+        ProcBlockDescriptor {
+            type_name: Cow::Borrowed("Fft"),
+            description: Cow::Borrowed("An FFT"),
+            available_transforms: vec![TransformDescriptor {
+                inputs: tensor.clone().into(),
+                outputs: tensor.into(),
+            }]
+            .into(),
+        }
+    }
+
+    #[test]
+    fn read_the_descriptor_from_a_custom_section() {
+        let descriptor = descriptor();
+        let json = serde_json::to_vec(&descriptor).unwrap();
+        let name = ProcBlockDescriptor::CUSTOM_SECTION_NAME;
+
+        // An empty module followed by a single custom section
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        let payload_len = 1 + name.len() + json.len();
+        assert!(name.len() < 0x80 && payload_len < 0x4000);
+        wasm.push(0);
+        wasm.push((payload_len & 0x7f) as u8 | 0x80);
+        wasm.push((payload_len >> 7) as u8);
+        wasm.push(name.len() as u8);
+        wasm.extend_from_slice(name.as_bytes());
+        wasm.extend_from_slice(&json);
+
+        let got = descriptor_from_wasm(&wasm).unwrap();
+
+        assert_eq!(got, descriptor);
+    }
+
+    #[test]
+    fn missing_descriptor() {
+        let wasm = b"\0asm\x01\0\0\0";
+
+        let err = descriptor_from_wasm(wasm).unwrap_err();
+
+        assert!(matches!(err, ProbeError::MissingDescriptor));
+    }
+
+    fn proc_block(path: &str) -> ProcBlock {
+        ProcBlock {
+            path: path.parse().unwrap(),
+            parameters: Default::default(),
+        }
+    }
+
+    #[test]
+    fn only_pinned_proc_blocks_are_cached() {
+        let repo = Path::new("/rune");
+
+        assert!(is_cacheable(
+            &proc_block("hotg-ai/proc-blocks@v0.11#fft"),
+            None
+        ));
+        assert!(is_cacheable(&proc_block("normalize@0.11.0"), None));
+
+        assert!(!is_cacheable(&proc_block("hotg-ai/proc-blocks#fft"), None));
+        assert!(!is_cacheable(&proc_block("./fft@0.1"), None));
+        assert!(!is_cacheable(
+            &proc_block("hotg-ai/proc-blocks@v0.11#fft"),
+            Some(repo)
+        ));
+    }
+
+    #[test]
+    fn cached_descriptors_are_only_used_for_the_same_proc_block() {
+        let fft = proc_block("hotg-ai/proc-blocks@v0.11#fft");
+        let entry = CachedDescriptor {
+            path: fft.path.to_string(),
+            descriptor: descriptor(),
+        };
+        let json = serde_json::to_vec(&entry).unwrap();
+
+        assert_eq!(cached_descriptor(&json, &fft), Some(descriptor()));
+
+        let newer = proc_block("hotg-ai/proc-blocks@v0.12#fft");
+        assert_eq!(cached_descriptor(&json, &newer), None);
+        assert_eq!(cached_descriptor(b"not json", &fft), None);
+    }
+}
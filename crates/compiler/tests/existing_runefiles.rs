@@ -117,9 +117,15 @@ macro_rules! parse_and_analyse {
                     rune_version: Some(RuneVersion {
                         version: env!("CARGO_PKG_VERSION").to_string(),
                     }),
+                    debug_tensors: false,
+                    variables: Default::default(),
+                    profile: None,
+                    no_cache: false,
                 }
             }
 
+            fn features() -> FeatureFlags { FeatureFlags::development() }
+
             #[test]
             fn analyse() {
                 let file = SimpleFile::new("Runefile", SRC);
@@ -128,7 +134,7 @@ macro_rules! parse_and_analyse {
 
                 hotg_rune_compiler::build_with_hooks(
                     ctx,
-                    FeatureFlags::development(),
+                    features(),
                     &mut hooks,
                 );
 
@@ -143,7 +149,7 @@ macro_rules! parse_and_analyse {
 
                 hotg_rune_compiler::build_with_hooks(
                     ctx,
-                    FeatureFlags::development(),
+                    features(),
                     &mut hooks,
                 );
 
@@ -1,8 +1,9 @@
 use anyhow::Error;
 use env_logger::Env;
 use hotg_rune_cli::{
-    Build, ColorChoice, Format, Graph, Inspect, ModelInfo, Run, Unstable,
-    Version,
+    Build, Check, ColorChoice, Config, ConfigCommand, Debugger, Diff, Extract,
+    Fmt, Format, Graph, Inspect, Lsp, ModelInfo, Patch, Run, Test, Unstable,
+    Version, Watch,
 };
 use log::LevelFilter;
 use structopt::{clap::AppSettings, StructOpt};
@@ -29,11 +30,32 @@ fn main() -> Result<(), Error> {
         .filter_module("regalloc", LevelFilter::Warn)
         .init();
 
+    let config = match &cmd {
+        Some(cmd) => cmd.load_config()?,
+        None => Config::default(),
+    };
+    let unstable = unstable.with_config(&config);
+    unstable.validate()?;
+
     match cmd {
-        Some(Cmd::Build(build)) => build.execute(colour.into(), unstable),
-        Some(Cmd::Run(run)) => run.execute(),
-        Some(Cmd::Graph(graph)) => graph.execute(),
+        Some(Cmd::Build(build)) => {
+            build.with_config(&config).execute(colour.into(), unstable)
+        },
+        Some(Cmd::Check(check)) => check.execute(colour.into(), unstable),
+        Some(Cmd::Config(c)) => c.execute(config),
+        Some(Cmd::Debug(debug)) => debug.with_config(&config).execute(),
+        Some(Cmd::Diff(diff)) => diff.execute(),
+        Some(Cmd::Extract(extract)) => extract.execute(),
+        Some(Cmd::Fmt(fmt)) => fmt.execute(),
+        Some(Cmd::Lsp(lsp)) => lsp.execute(unstable),
+        Some(Cmd::Patch(patch)) => patch.execute(),
+        Some(Cmd::Run(run)) => run.with_config(&config).execute(),
+        Some(Cmd::Test(test)) => test.execute(unstable),
+        Some(Cmd::Graph(graph)) => graph.execute(colour.into(), unstable),
         Some(Cmd::Version(version)) => version.execute(),
+        Some(Cmd::Watch(watch)) => {
+            watch.with_config(&config).execute(colour.into(), unstable)
+        },
         Some(Cmd::ModelInfo(m)) => m.execute(),
         Some(Cmd::Inspect(i)) => i.execute(),
         None if version => {
@@ -77,15 +99,60 @@ pub struct Args {
 enum Cmd {
     /// Compile a Runefile into a Rune.
     Build(Build),
+    /// Check a Runefile for errors without compiling it.
+    Check(Check),
+    /// Inspect the settings loaded from "rune.toml" files.
+    Config(ConfigCommand),
+    /// Run a Rune and show the tensors produced by each node.
+    Debug(Debugger),
+    /// Show what changed between two compiled Runes.
+    Diff(Diff),
+    /// Extract the models and resources embedded in a compiled Rune.
+    Extract(Extract),
+    /// Rewrite Runefiles in a canonical form.
+    Fmt(Fmt),
+    /// Start a language server for editing Runefiles.
+    Lsp(Lsp),
+    /// Change the resources embedded in a compiled Rune.
+    Patch(Patch),
     /// Execute a Rune on the current device.
     Run(Run),
+    /// Compile and run every Rune in a directory, comparing their output
+    /// against the expected output.
+    Test(Test),
     /// Print version information about the rune CLI.
     Version(Version),
-    /// Load a TensorFlow Lite model and print information about it.
+    /// Load a TensorFlow Lite, ONNX, or TensorFlow model and print
+    /// information about it.
     #[structopt(name = "model-info")]
     ModelInfo(ModelInfo),
     /// Show which capabilities are used by a compiled Rune.
     Inspect(Inspect),
-    /// Visualise the flow of data through a Rune.
+    /// Visualise the flow of data through a Rune or Runefile.
     Graph(Graph),
+    /// Rebuild a Rune whenever its Runefile or dependencies change.
+    Watch(Watch),
+}
+
+impl Cmd {
+    /// Load the settings from "rune.toml" files for the commands which use
+    /// them.
+    fn load_config(&self) -> Result<Config, Error> {
+        match self {
+            Cmd::Build(_)
+            | Cmd::Config(_)
+            | Cmd::Debug(_)
+            | Cmd::Run(_)
+            | Cmd::Watch(_) => Config::discover(),
+            // Note: these commands only look at the unstable settings, so a
+            // broken "rune.toml" shouldn't stop them from working.
+            Cmd::Check(_) | Cmd::Lsp(_) | Cmd::Test(_) | Cmd::Graph(_) => {
+                Ok(Config::discover().unwrap_or_else(|e| {
+                    log::warn!("Ignoring the \"rune.toml\" settings: {:#}", e);
+                    Config::default()
+                }))
+            },
+            _ => Ok(Config::default()),
+        }
+    }
 }
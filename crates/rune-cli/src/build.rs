@@ -3,7 +3,6 @@ use std::path::{Path, PathBuf};
 use anyhow::{Context, Error};
 use codespan_reporting::{
     diagnostic::{Diagnostic, Severity},
-    files::SimpleFile,
     term::{
         termcolor::{ColorChoice, StandardStream},
         Config,
@@ -16,11 +15,13 @@ use hotg_rune_compiler::{
         AfterCodegenContext, AfterLoweringContext, AfterParseContext,
         AfterTypeCheckingContext, Continuation,
     },
+    parse::SourceMap,
     BuildContext, Verbosity,
 };
+use indexmap::IndexMap;
 use once_cell::sync::Lazy;
 
-use crate::Unstable;
+use crate::{patch::Assignment, Config, Unstable};
 
 #[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
 pub struct Build {
@@ -49,16 +50,43 @@ pub struct Build {
     /// Compile the Rune without optimisations.
     #[structopt(long)]
     debug: bool,
+    /// Make the Rune report every intermediate tensor so it can be inspected
+    /// with "rune debug".
+    #[structopt(long)]
+    debug_tensors: bool,
+    /// Set one of the Runefile's variables (e.g. "--var THRESHOLD=0.5"),
+    /// overriding its profile and environment variable.
+    #[structopt(long = "var", parse(try_from_str))]
+    vars: Vec<Assignment>,
+    /// Use the variables from one of the Runefile's profiles.
+    #[structopt(long)]
+    profile: Option<String>,
+    /// Compile each proc block on its own and check the Runefile against the
+    /// transforms it supports.
+    #[structopt(long)]
+    probe_proc_blocks: bool,
+    /// Always run cargo instead of reusing a cached build.
+    #[structopt(long)]
+    no_cache: bool,
 }
 
 impl Build {
+    /// Use defaults from a `rune.toml` file for anything that wasn't
+    /// specified on the command-line.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.cache_dir =
+            self.cache_dir.or_else(|| config.build.cache_dir.clone());
+        self
+    }
+
     pub fn execute(
         self,
         color: ColorChoice,
         unstable: Unstable,
     ) -> Result<(), Error> {
         let ctx = self.build_context()?;
-        let features = unstable.feature_flags();
+        let mut features = unstable.feature_flags();
+        features.set_probe_proc_blocks(self.probe_proc_blocks);
 
         log::debug!(
             "Compiling {} in \"{}\"",
@@ -66,9 +94,7 @@ impl Build {
             ctx.working_directory.display()
         );
 
-        let dest = self.output.unwrap_or_else(|| {
-            ctx.current_directory.join(&ctx.name).with_extension("rune")
-        });
+        let dest = self.rune_path()?;
 
         let mut hooks = Hooks::new(dest, color, self.runefile);
         hotg_rune_compiler::build_with_hooks(ctx, features, &mut hooks);
@@ -79,7 +105,21 @@ impl Build {
         }
     }
 
-    fn build_context(&self) -> Result<BuildContext, Error> {
+    /// Where the compiled Rune will be written.
+    pub(crate) fn rune_path(&self) -> Result<PathBuf, Error> {
+        if let Some(output) = &self.output {
+            return Ok(output.clone());
+        }
+
+        let current_directory = self.current_directory()?;
+        let name = self.name()?;
+
+        Ok(current_directory.join(name).with_extension("rune"))
+    }
+
+    pub(crate) fn runefile(&self) -> &Path { &self.runefile }
+
+    pub(crate) fn build_context(&self) -> Result<BuildContext, Error> {
         let verbosity =
             Verbosity::from_quiet_and_verbose(self.quiet, self.verbose)
                 .context(
@@ -92,7 +132,7 @@ impl Build {
         let working_directory = self
             .cache_dir
             .clone()
-            .unwrap_or_else(|| Path::new(&*DEFAULT_CACHE_DIR).join(&name));
+            .unwrap_or_else(|| default_working_directory(&name));
         let runefile =
             std::fs::read_to_string(&self.runefile).with_context(|| {
                 format!("Unable to read \"{}\"", self.runefile.display())
@@ -106,37 +146,75 @@ impl Build {
             working_directory,
             optimized: !self.debug,
             rune_version: Some(RuneVersion::new(env!("CARGO_PKG_VERSION"))),
+            debug_tensors: self.debug_tensors,
+            variables: variables(&self.vars),
+            profile: self.profile.clone(),
+            no_cache: self.no_cache,
         })
     }
 
-    fn current_directory(&self) -> Result<PathBuf, Error> {
-        if let Some(dir) = &self.current_dir {
-            return Ok(dir.clone());
-        }
+    pub(crate) fn current_directory(&self) -> Result<PathBuf, Error> {
+        current_directory(&self.runefile, self.current_dir.as_deref())
+    }
 
-        if let Some(parent) =
-            self.runefile.parent().and_then(|p| p.canonicalize().ok())
-        {
-            return Ok(parent);
-        }
+    fn name(&self) -> Result<String, Error> {
+        rune_name(
+            &self.runefile,
+            self.current_dir.as_deref(),
+            self.name.as_deref(),
+        )
+    }
+}
 
-        std::env::current_dir()
-            .context("Unable to determine the current directory")
+/// Collect `--var` assignments into the [`BuildContext::variables`].
+pub(crate) fn variables(vars: &[Assignment]) -> IndexMap<String, String> {
+    vars.iter()
+        .map(|Assignment { name, value }| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Figure out which directory paths in a Runefile should be resolved relative
+/// to, falling back to the Runefile's parent directory.
+pub(crate) fn current_directory(
+    runefile: &Path,
+    current_dir: Option<&Path>,
+) -> Result<PathBuf, Error> {
+    if let Some(dir) = current_dir {
+        return Ok(dir.to_path_buf());
     }
 
-    fn name(&self) -> Result<String, Error> {
-        if let Some(name) = &self.name {
-            return Ok(name.clone());
-        }
+    if let Some(parent) = runefile.parent().and_then(|p| p.canonicalize().ok())
+    {
+        return Ok(parent);
+    }
 
-        let current_dir = self.current_directory()?;
+    std::env::current_dir().context("Unable to determine the current directory")
+}
 
-        if let Some(name) = current_dir.file_name().and_then(|n| n.to_str()) {
-            return Ok(name.to_string());
-        }
+/// Determine the Rune's name, defaulting to the name of the directory
+/// containing the Runefile.
+pub(crate) fn rune_name(
+    runefile: &Path,
+    current_dir: Option<&Path>,
+    name: Option<&str>,
+) -> Result<String, Error> {
+    if let Some(name) = name {
+        return Ok(name.to_string());
+    }
 
-        Err(Error::msg("Unable to determine the Rune's name"))
+    let current_dir = current_directory(runefile, current_dir)?;
+
+    if let Some(name) = current_dir.file_name().and_then(|n| n.to_str()) {
+        return Ok(name.to_string());
     }
+
+    Err(Error::msg("Unable to determine the Rune's name"))
+}
+
+/// The directory `rune build` uses for a Rune's generated project when no
+/// `--cache-dir` is provided.
+pub(crate) fn default_working_directory(name: &str) -> PathBuf {
+    Path::new(&*DEFAULT_CACHE_DIR).join(name)
 }
 
 static DEFAULT_CACHE_DIR: Lazy<String> = Lazy::new(|| {
@@ -191,15 +269,13 @@ impl Hooks {
     fn check_diagnostics(
         &mut self,
         diags: impl Iterator<Item = Diagnostic<()>>,
-        ctx: &BuildContext,
+        sources: &SourceMap,
     ) -> Continuation {
         let mut writer = StandardStream::stderr(self.color);
         let config = Config::default();
 
-        let file = SimpleFile::new(
-            self.runefile_path.display().to_string(),
-            &ctx.runefile,
-        );
+        let mut sources = sources.clone();
+        sources.set_runefile_name(self.runefile_path.display().to_string());
 
         let mut errors = 0;
 
@@ -211,8 +287,8 @@ impl Hooks {
             match codespan_reporting::term::emit(
                 &mut writer,
                 &config,
-                &file,
-                &diag,
+                &sources,
+                &sources.resolve(&diag),
             )
             .context("Unable to print the diagnostic")
             {
@@ -244,37 +320,25 @@ impl hotg_rune_compiler::hooks::Hooks for Hooks {
         &mut self,
         ctx: &mut dyn AfterTypeCheckingContext,
     ) -> Continuation {
-        self.check_diagnostics(
-            ctx.diagnostics_mut().drain(),
-            &ctx.build_context(),
-        )
+        self.check_diagnostics(ctx.diagnostics_mut().drain(), &ctx.source_map())
     }
 
     fn after_parse(&mut self, ctx: &mut dyn AfterParseContext) -> Continuation {
-        self.check_diagnostics(
-            ctx.diagnostics_mut().drain(),
-            &ctx.build_context(),
-        )
+        self.check_diagnostics(ctx.diagnostics_mut().drain(), &ctx.source_map())
     }
 
     fn after_lowering(
         &mut self,
         ctx: &mut dyn AfterLoweringContext,
     ) -> Continuation {
-        self.check_diagnostics(
-            ctx.diagnostics_mut().drain(),
-            &ctx.build_context(),
-        )
+        self.check_diagnostics(ctx.diagnostics_mut().drain(), &ctx.source_map())
     }
 
     fn after_codegen(
         &mut self,
         ctx: &mut dyn AfterCodegenContext,
     ) -> Continuation {
-        self.check_diagnostics(
-            ctx.diagnostics_mut().drain(),
-            &ctx.build_context(),
-        )
+        self.check_diagnostics(ctx.diagnostics_mut().drain(), &ctx.source_map())
     }
 
     fn after_compile(
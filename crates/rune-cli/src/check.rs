@@ -0,0 +1,353 @@
+use std::{
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+use codespan_reporting::{
+    diagnostic::{Diagnostic, LabelStyle, Severity},
+    files::Files,
+    term::{
+        termcolor::{ColorChoice, StandardStream},
+        Config,
+    },
+};
+use hotg_rune_compiler::{
+    codegen::RuneVersion,
+    hooks::{
+        AfterLoweringContext, AfterParseContext, AfterTypeCheckingContext,
+        Continuation, Hooks,
+    },
+    parse::SourceMap,
+    BuildContext, Verbosity,
+};
+use indexmap::IndexMap;
+use strum::VariantNames;
+
+use crate::{build, patch::Assignment, Format, Unstable};
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct Check {
+    /// The Runefile to check.
+    #[structopt(parse(from_os_str), default_value = "Runefile.yml")]
+    runefile: PathBuf,
+    /// The directory that all paths are resolved relative to (Defaults to the
+    /// Runefile's directory)
+    #[structopt(short, long, env)]
+    current_dir: Option<PathBuf>,
+    /// The name of the Rune (defaults to the Runefile directory's name).
+    #[structopt(short, long)]
+    name: Option<String>,
+    #[structopt(
+        short,
+        long,
+        help = "The format to print diagnostics in",
+        default_value = "text",
+        possible_values = Format::VARIANTS,
+        parse(try_from_str)
+    )]
+    format: Format,
+    /// Set one of the Runefile's variables (e.g. "--var THRESHOLD=0.5").
+    #[structopt(long = "var", parse(try_from_str))]
+    vars: Vec<Assignment>,
+    /// Use the variables from one of the Runefile's profiles.
+    #[structopt(long)]
+    profile: Option<String>,
+    /// Compile each proc block on its own and check the Runefile against the
+    /// transforms it supports.
+    #[structopt(long)]
+    probe_proc_blocks: bool,
+}
+
+impl Check {
+    pub fn execute(
+        self,
+        color: ColorChoice,
+        unstable: Unstable,
+    ) -> Result<(), Error> {
+        let ctx = self.build_context()?;
+        let mut features = unstable.feature_flags();
+        features.set_probe_proc_blocks(self.probe_proc_blocks);
+
+        log::debug!("Checking \"{}\"", self.runefile.display());
+
+        let runefile = ctx.runefile.clone();
+        let mut hooks = CollectDiagnostics::default();
+        hotg_rune_compiler::build_with_hooks(ctx, features, &mut hooks);
+
+        let sources = hooks.sources(&self.runefile, &runefile);
+
+        match self.format {
+            Format::Text => print_text(&sources, &hooks.diagnostics, color)?,
+            Format::Json => print_json(&sources, &hooks.diagnostics)?,
+        }
+
+        let errors = hooks
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity >= Severity::Error)
+            .count();
+
+        match errors {
+            0 => Ok(()),
+            1 => Err(Error::msg("There was 1 error")),
+            n => Err(anyhow::anyhow!("There were {} errors", n)),
+        }
+    }
+
+    fn build_context(&self) -> Result<BuildContext, Error> {
+        let mut ctx = build_context(
+            &self.runefile,
+            self.current_dir.as_deref(),
+            self.name.as_deref(),
+        )?;
+        ctx.variables = build::variables(&self.vars);
+        ctx.profile = self.profile.clone();
+
+        if self.probe_proc_blocks {
+            // Note: probing compiles each proc block, so make sure that
+            // happens in the same place "rune build" would use
+            ctx.working_directory = build::default_working_directory(&ctx.name);
+        }
+
+        Ok(ctx)
+    }
+}
+
+/// Create a [`BuildContext`] which can be used to analyse a Runefile without
+/// compiling it.
+pub(crate) fn build_context(
+    runefile: &Path,
+    current_dir: Option<&Path>,
+    name: Option<&str>,
+) -> Result<BuildContext, Error> {
+    let src = std::fs::read_to_string(runefile).with_context(|| {
+        format!("Unable to read \"{}\"", runefile.display())
+    })?;
+
+    build_context_for_source(runefile, current_dir, name, src)
+}
+
+/// Create a [`BuildContext`] for analysing a Runefile's contents, where the
+/// contents may not have been saved to disk yet.
+pub(crate) fn build_context_for_source(
+    runefile: &Path,
+    current_dir: Option<&Path>,
+    name: Option<&str>,
+    src: String,
+) -> Result<BuildContext, Error> {
+    let current_directory = build::current_directory(runefile, current_dir)?;
+    let name = build::rune_name(runefile, current_dir, name)?;
+
+    Ok(BuildContext {
+        name,
+        runefile: src,
+        // Note: we never get as far as compiling the generated project so
+        // nothing should be written to the working directory.
+        working_directory: current_directory.clone(),
+        current_directory,
+        verbosity: Verbosity::Normal,
+        optimized: false,
+        rune_version: Some(RuneVersion::new(env!("CARGO_PKG_VERSION"))),
+        debug_tensors: false,
+        variables: IndexMap::new(),
+        profile: None,
+        no_cache: false,
+    })
+}
+
+/// [`Hooks`] which record every diagnostic from the parse, lowering, and type
+/// checking phases and halt before any code is generated.
+#[derive(Debug, Default)]
+pub(crate) struct CollectDiagnostics {
+    pub(crate) diagnostics: Vec<Diagnostic<()>>,
+    /// The files the Runefile was assembled from.
+    source_map: Option<SourceMap>,
+}
+
+impl CollectDiagnostics {
+    /// Record the [`SourceMap`] and any diagnostics from the parse phase.
+    pub(crate) fn collect_after_parse(
+        &mut self,
+        ctx: &mut dyn AfterParseContext,
+    ) -> Continuation {
+        self.source_map = Some(ctx.source_map().clone());
+        self.collect(ctx.diagnostics_mut().drain())
+    }
+
+    /// Get a [`SourceMap`] for rendering the collected diagnostics, naming the
+    /// Runefile after the path it was loaded from.
+    pub(crate) fn sources(&self, runefile: &Path, src: &str) -> SourceMap {
+        let name = runefile.display().to_string();
+
+        match &self.source_map {
+            Some(sources) => {
+                let mut sources = sources.clone();
+                sources.set_runefile_name(name);
+                sources
+            },
+            None => SourceMap::new(name, src),
+        }
+    }
+
+    pub(crate) fn collect(
+        &mut self,
+        diags: impl Iterator<Item = Diagnostic<()>>,
+    ) -> Continuation {
+        let len = self.diagnostics.len();
+        self.diagnostics.extend(diags);
+
+        let has_errors = self.diagnostics[len..]
+            .iter()
+            .any(|d| d.severity >= Severity::Error);
+
+        if has_errors {
+            Continuation::Halt
+        } else {
+            Continuation::Continue
+        }
+    }
+}
+
+impl Hooks for CollectDiagnostics {
+    fn after_parse(&mut self, ctx: &mut dyn AfterParseContext) -> Continuation {
+        self.collect_after_parse(ctx)
+    }
+
+    fn after_lowering(
+        &mut self,
+        ctx: &mut dyn AfterLoweringContext,
+    ) -> Continuation {
+        self.collect(ctx.diagnostics_mut().drain())
+    }
+
+    fn after_type_checking(
+        &mut self,
+        ctx: &mut dyn AfterTypeCheckingContext,
+    ) -> Continuation {
+        self.collect(ctx.diagnostics_mut().drain());
+
+        // We only care about analysis, so there's no need to go any further.
+        Continuation::Halt
+    }
+}
+
+pub(crate) fn print_text(
+    sources: &SourceMap,
+    diags: &[Diagnostic<()>],
+    color: ColorChoice,
+) -> Result<(), Error> {
+    let mut writer = StandardStream::stderr(color);
+    let config = Config::default();
+
+    for diag in diags {
+        let diag = sources.resolve(diag);
+        codespan_reporting::term::emit(&mut writer, &config, sources, &diag)
+            .context("Unable to print the diagnostic")?;
+    }
+
+    Ok(())
+}
+
+fn print_json(
+    sources: &SourceMap,
+    diags: &[Diagnostic<()>],
+) -> Result<(), Error> {
+    let diags: Vec<_> = diags
+        .iter()
+        .map(|d| DiagnosticInfo::new(sources, d))
+        .collect();
+
+    let mut stdout = std::io::stdout();
+    serde_json::to_writer_pretty(stdout.lock(), &diags)
+        .context("Unable to print to stdout")?;
+    writeln!(stdout)?;
+
+    Ok(())
+}
+
+/// A machine-readable version of a [`Diagnostic`], suitable for editors and
+/// CI tools.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct DiagnosticInfo {
+    pub(crate) severity: &'static str,
+    pub(crate) message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) code: Option<String>,
+    pub(crate) labels: Vec<LabelInfo>,
+    pub(crate) notes: Vec<String>,
+}
+
+impl DiagnosticInfo {
+    pub(crate) fn new(sources: &SourceMap, diag: &Diagnostic<()>) -> Self {
+        let diag = sources.resolve(diag);
+
+        DiagnosticInfo {
+            severity: severity_name(diag.severity),
+            message: diag.message.clone(),
+            code: diag.code.clone(),
+            labels: diag
+                .labels
+                .iter()
+                .map(|label| LabelInfo {
+                    primary: label.style == LabelStyle::Primary,
+                    message: label.message.clone(),
+                    file: sources
+                        .name(label.file_id)
+                        .unwrap_or_default()
+                        .to_string(),
+                    start: location(sources, label.file_id, label.range.start),
+                    end: location(sources, label.file_id, label.range.end),
+                    range: label.range.clone(),
+                })
+                .collect(),
+            notes: diag.notes.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct LabelInfo {
+    pub(crate) primary: bool,
+    pub(crate) message: String,
+    /// The file this label points into.
+    pub(crate) file: String,
+    /// The byte range this label points at.
+    pub(crate) range: Range<usize>,
+    pub(crate) start: Location,
+    pub(crate) end: Location,
+}
+
+/// A 1-based line and column number.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct Location {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+fn location(
+    sources: &SourceMap,
+    file_id: usize,
+    byte_index: usize,
+) -> Location {
+    let line_index =
+        sources.line_index(file_id, byte_index).unwrap_or_default();
+
+    Location {
+        line: sources.line_number(file_id, line_index).unwrap_or(1),
+        column: sources
+            .column_number(file_id, line_index, byte_index)
+            .unwrap_or(1),
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
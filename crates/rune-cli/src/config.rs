@@ -0,0 +1,280 @@
+//! Default settings loaded from `rune.toml` files.
+//!
+//! Settings are merged with the following precedence (highest first):
+//!
+//! 1. Command-line arguments
+//! 2. Environment variables
+//! 3. The project's `rune.toml` (found by searching the current directory and
+//!    its parents)
+//! 4. The user's `rune.toml` (e.g. `~/.config/rune/rune.toml` on Linux)
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+use strum::VariantNames;
+
+use crate::{run::Engine, Format};
+
+/// The name of the configuration file.
+pub const CONFIG_FILE: &str = "rune.toml";
+
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub build: BuildConfig,
+    pub run: RunConfig,
+    pub unstable: UnstableConfig,
+    /// The files these settings were loaded from, in order of increasing
+    /// precedence.
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+
+/// Defaults for `rune build`.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BuildConfig {
+    /// The directory to use when caching builds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Defaults for `rune run`.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RunConfig {
+    /// The WebAssembly engine to use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) engine: Option<Engine>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub image: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sound: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub accelerometer: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub raw: Vec<PathBuf>,
+    /// Seed the runtime's random number generator.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random: Option<u64>,
+    /// Resources which should be set to a string value.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub resources: BTreeMap<String, String>,
+    /// Resources which should be loaded from a file.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_resources: BTreeMap<String, PathBuf>,
+}
+
+/// Defaults for the unstable flags.
+#[derive(
+    Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UnstableConfig {
+    /// Enable unstable features.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// A path to the Rune repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rune_repo_dir: Option<PathBuf>,
+}
+
+impl Config {
+    /// Load the user's and current project's configuration files, if they
+    /// exist.
+    pub fn discover() -> Result<Self, Error> {
+        let current_dir = std::env::current_dir()
+            .context("Unable to determine the current directory")?;
+
+        let mut config = Config::default();
+
+        let mut candidates: Vec<_> = user_config_file()
+            .into_iter()
+            .chain(project_config_file(&current_dir))
+            .collect();
+        // The current directory might be inside the user's config directory
+        candidates.dedup();
+
+        for path in candidates {
+            let file = Config::from_file(&path)?;
+            config = config.merge(file);
+        }
+
+        Ok(config)
+    }
+
+    /// Load a config file, resolving any paths relative to the directory it
+    /// is in.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let src = std::fs::read_to_string(path).with_context(|| {
+            format!("Unable to read \"{}\"", path.display())
+        })?;
+        let mut config: Config = toml::from_str(&src).with_context(|| {
+            format!("Unable to parse \"{}\"", path.display())
+        })?;
+
+        if let Some(base) = path.parent() {
+            config.resolve_paths(base);
+        }
+        config.sources.push(path.to_path_buf());
+
+        Ok(config)
+    }
+
+    /// Merge two sets of settings, where values in `other` take precedence.
+    pub fn merge(self, other: Config) -> Config {
+        let Config {
+            build,
+            run,
+            unstable,
+            mut sources,
+        } = self;
+
+        let mut resources = run.resources;
+        resources.extend(other.run.resources);
+        let mut file_resources = run.file_resources;
+        file_resources.extend(other.run.file_resources);
+
+        sources.extend(other.sources);
+
+        Config {
+            build: BuildConfig {
+                cache_dir: other.build.cache_dir.or(build.cache_dir),
+            },
+            run: RunConfig {
+                engine: other.run.engine.or(run.engine),
+                image: non_empty_or(other.run.image, run.image),
+                sound: non_empty_or(other.run.sound, run.sound),
+                accelerometer: non_empty_or(
+                    other.run.accelerometer,
+                    run.accelerometer,
+                ),
+                raw: non_empty_or(other.run.raw, run.raw),
+                random: other.run.random.or(run.random),
+                resources,
+                file_resources,
+            },
+            unstable: UnstableConfig {
+                enabled: other.unstable.enabled.or(unstable.enabled),
+                rune_repo_dir: other
+                    .unstable
+                    .rune_repo_dir
+                    .or(unstable.rune_repo_dir),
+            },
+            sources,
+        }
+    }
+
+    /// Override any settings that were also set via environment variables.
+    fn with_env(mut self) -> Self {
+        // Note: these need to match the "env" attributes on Build and Unstable
+        if let Some(cache_dir) = std::env::var_os("CACHE_DIR") {
+            self.build.cache_dir = Some(cache_dir.into());
+        }
+        if let Some(repo_dir) = std::env::var_os("RUNE_REPO_DIR") {
+            self.unstable.rune_repo_dir = Some(repo_dir.into());
+        }
+
+        self
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |path: &mut PathBuf| *path = base.join(&*path);
+
+        self.build.cache_dir.iter_mut().for_each(resolve);
+        self.run
+            .image
+            .iter_mut()
+            .chain(&mut self.run.sound)
+            .chain(&mut self.run.accelerometer)
+            .chain(&mut self.run.raw)
+            .chain(self.run.file_resources.values_mut())
+            .for_each(resolve);
+        self.unstable.rune_repo_dir.iter_mut().for_each(resolve);
+    }
+}
+
+fn non_empty_or<T>(preferred: Vec<T>, fallback: Vec<T>) -> Vec<T> {
+    if preferred.is_empty() {
+        fallback
+    } else {
+        preferred
+    }
+}
+
+fn user_config_file() -> Option<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join("rune").join(CONFIG_FILE))
+        .filter(|path| path.is_file())
+}
+
+fn project_config_file(current_dir: &Path) -> Option<PathBuf> {
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub enum ConfigCommand {
+    /// Show the settings that will be used after merging every "rune.toml"
+    /// and environment variable.
+    Show {
+        #[structopt(
+            short,
+            long,
+            help = "The format to print output in",
+            default_value = "text",
+            possible_values = Format::VARIANTS,
+            parse(try_from_str)
+        )]
+        format: Format,
+    },
+}
+
+impl ConfigCommand {
+    pub fn execute(self, config: Config) -> Result<(), Error> {
+        match self {
+            ConfigCommand::Show { format } => show(config.with_env(), format),
+        }
+    }
+}
+
+fn show(config: Config, format: Format) -> Result<(), Error> {
+    match format {
+        Format::Text => {
+            if config.sources.is_empty() {
+                println!("# No \"{}\" files were found", CONFIG_FILE);
+            } else {
+                println!("# Loaded from:");
+                for source in &config.sources {
+                    println!("#   {}", source.display());
+                }
+            }
+
+            let settings = toml::to_string(&config)
+                .context("Unable to serialize the settings")?;
+            print!("{}", settings);
+        },
+        Format::Json => {
+            let settings = serde_json::json!({
+                "sources": &config.sources,
+                "settings": &config,
+            });
+            let settings = serde_json::to_string_pretty(&settings)
+                .context("Unable to serialize the settings")?;
+            println!("{}", settings);
+        },
+    }
+
+    Ok(())
+}
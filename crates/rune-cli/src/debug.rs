@@ -0,0 +1,133 @@
+use anyhow::{Context, Error};
+use hotg_rune_runtime::OutputTensor;
+use strum::VariantNames;
+
+use crate::{Config, Format, Run};
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct Debugger {
+    /// Stop executing the pipeline once this node has run.
+    #[structopt(long)]
+    stop_after: Option<String>,
+    #[structopt(
+        short,
+        long,
+        help = "The format to print output in",
+        default_value = "text",
+        possible_values = Format::VARIANTS,
+        parse(try_from_str)
+    )]
+    format: Format,
+    #[structopt(flatten)]
+    run: Run,
+}
+
+impl Debugger {
+    /// Use defaults from a `rune.toml` file for anything that wasn't
+    /// specified on the command-line.
+    pub fn with_config(self, config: &Config) -> Self {
+        Debugger {
+            run: self.run.with_config(config),
+            ..self
+        }
+    }
+
+    pub fn execute(self) -> Result<(), Error> {
+        let Debugger {
+            stop_after,
+            format,
+            run,
+        } = self;
+
+        let mut runtime = run.prepare()?;
+        runtime.stop_after(stop_after.clone());
+
+        runtime.predict().context("Prediction failed")?;
+
+        let nodes = runtime.intermediate_tensors();
+
+        if nodes.is_empty() {
+            anyhow::bail!(
+                "The Rune didn't report any intermediate tensors. Make sure \
+                 it was compiled with \"rune build --debug-tensors\""
+            );
+        }
+
+        if let Some(stop_after) = &stop_after {
+            if !nodes.iter().any(|(name, _)| name == stop_after) {
+                log::warn!(
+                    "The pipeline never executed a node called \"{}\"",
+                    stop_after
+                );
+            }
+        }
+
+        match format {
+            Format::Text => print_text(nodes)?,
+            Format::Json => print_json(nodes)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn print_text(nodes: &[(String, Vec<OutputTensor>)]) -> Result<(), Error> {
+    for (name, tensors) in nodes {
+        if tensors.is_empty() {
+            println!("{}: skipped", name);
+            continue;
+        }
+
+        for (i, tensor) in tensors.iter().enumerate() {
+            let label = if tensors.len() == 1 {
+                name.clone()
+            } else {
+                format!("{}.{}", name, i)
+            };
+
+            let (shape, elements) = match tensor {
+                OutputTensor::Tensor(t) => {
+                    let value = serde_json::to_value(t.serializable())?;
+                    (t.shape().to_string(), value["elements"].clone())
+                },
+                OutputTensor::StringTensor {
+                    dimensions,
+                    strings,
+                } => (
+                    format!("utf8{:?}", dimensions),
+                    serde_json::to_value(strings)?,
+                ),
+            };
+
+            println!("{}: {}", label, shape);
+            println!("  {}", elements);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_json(nodes: &[(String, Vec<OutputTensor>)]) -> Result<(), Error> {
+    #[derive(serde::Serialize)]
+    struct Node<'a> {
+        name: &'a str,
+        skipped: bool,
+        outputs: &'a [OutputTensor],
+    }
+
+    let nodes: Vec<_> = nodes
+        .iter()
+        .map(|(name, outputs)| Node {
+            name,
+            skipped: outputs.is_empty(),
+            outputs,
+        })
+        .collect();
+
+    let stdout = std::io::stdout();
+    serde_json::to_writer_pretty(stdout.lock(), &nodes)
+        .context("Unable to print to stdout")?;
+    println!();
+
+    Ok(())
+}
@@ -0,0 +1,643 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+use hotg_rune_compiler::{
+    codegen::{Fingerprint, RuneGraph},
+    parse::{ResourceOrString, ResourceType},
+};
+use strum::VariantNames;
+
+use crate::{
+    graph::{self, NodeType, PipelineNode},
+    inspect::Metadata,
+    Format,
+};
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct Diff {
+    #[structopt(
+        short,
+        long,
+        help = "The format to use when printing output",
+        default_value = "text",
+        possible_values = Format::VARIANTS,
+        parse(try_from_str)
+    )]
+    format: Format,
+    /// Exit with an error if the two Runes are different.
+    #[structopt(long)]
+    exit_code: bool,
+    /// The original Rune.
+    #[structopt(parse(from_os_str))]
+    old: PathBuf,
+    /// The Rune to compare against.
+    #[structopt(parse(from_os_str))]
+    new: PathBuf,
+}
+
+impl Diff {
+    pub fn execute(self) -> Result<(), Error> {
+        let old = load(&self.old)?;
+        let new = load(&self.new)?;
+
+        let changes = diff(&old, &new)?;
+
+        match self.format {
+            Format::Json => {
+                let s = serde_json::to_string_pretty(&changes)
+                    .context("Unable to format the changes as JSON")?;
+                println!("{}", s);
+            },
+            Format::Text => {
+                for change in &changes {
+                    println!("{}", change);
+                }
+            },
+        }
+
+        if self.exit_code && !changes.is_empty() {
+            match changes.len() {
+                1 => anyhow::bail!("Found 1 change"),
+                n => anyhow::bail!("Found {} changes", n),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The parts of a compiled Rune we can compare.
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
+    version: Option<String>,
+    graph: RuneGraph,
+    resources: BTreeMap<String, Vec<u8>>,
+}
+
+fn load(path: &Path) -> Result<Snapshot, Error> {
+    let wasm = std::fs::read(path)
+        .with_context(|| format!("Unable to read \"{}\"", path.display()))?;
+
+    let Metadata {
+        version,
+        rune,
+        resources,
+    } = Metadata::from_wasm_binary(&wasm).with_context(|| {
+        format!("Unable to parse metadata from \"{}\"", path.display())
+    })?;
+
+    let graph = rune.with_context(|| {
+        format!(
+            "\"{}\" doesn't contain a Rune graph custom section",
+            path.display()
+        )
+    })?;
+
+    Ok(Snapshot {
+        version: version.map(|v| v.to_string()),
+        graph,
+        resources,
+    })
+}
+
+/// A single difference between two Runes.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Change {
+    VersionChanged {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    NodeAdded {
+        name: String,
+        kind: &'static str,
+        qualifier: String,
+    },
+    NodeRemoved {
+        name: String,
+        kind: &'static str,
+        qualifier: String,
+    },
+    KindChanged {
+        name: String,
+        old: &'static str,
+        new: &'static str,
+    },
+    QualifierChanged {
+        name: String,
+        old: String,
+        new: String,
+    },
+    ProcBlockVersionChanged {
+        name: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    ArgAdded {
+        node: String,
+        arg: String,
+        value: String,
+    },
+    ArgRemoved {
+        node: String,
+        arg: String,
+        value: String,
+    },
+    ArgChanged {
+        node: String,
+        arg: String,
+        old: String,
+        new: String,
+    },
+    InputChanged {
+        node: String,
+        index: usize,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    OutputChanged {
+        node: String,
+        index: usize,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    ModelChanged {
+        name: String,
+        old_size: Option<usize>,
+        new_size: Option<usize>,
+        old_fingerprint: Option<Fingerprint>,
+        new_fingerprint: Option<Fingerprint>,
+    },
+    ResourceAdded {
+        name: String,
+    },
+    ResourceRemoved {
+        name: String,
+    },
+    ResourceTypeChanged {
+        name: String,
+        old: &'static str,
+        new: &'static str,
+    },
+    ResourceValueChanged {
+        name: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::VersionChanged { old, new } => {
+                write!(f, "~ compiled by: {} -> {}", or_none(old), or_none(new))
+            },
+            Change::NodeAdded {
+                name,
+                kind,
+                qualifier,
+            } => write!(f, "+ {} \"{}\" ({})", kind, name, qualifier),
+            Change::NodeRemoved {
+                name,
+                kind,
+                qualifier,
+            } => write!(f, "- {} \"{}\" ({})", kind, name, qualifier),
+            Change::KindChanged { name, old, new } => {
+                write!(f, "~ \"{}\": {} -> {}", name, old, new)
+            },
+            Change::QualifierChanged { name, old, new } => {
+                write!(f, "~ \"{}\": {} -> {}", name, old, new)
+            },
+            Change::ProcBlockVersionChanged { name, old, new } => write!(
+                f,
+                "~ \"{}\": version {} -> {}",
+                name,
+                or_none(old),
+                or_none(new)
+            ),
+            Change::ArgAdded { node, arg, value } => {
+                write!(f, "+ \"{}\": arg {} = {:?}", node, arg, value)
+            },
+            Change::ArgRemoved { node, arg, value } => {
+                write!(f, "- \"{}\": arg {} = {:?}", node, arg, value)
+            },
+            Change::ArgChanged {
+                node,
+                arg,
+                old,
+                new,
+            } => {
+                write!(f, "~ \"{}\": arg {}: {:?} -> {:?}", node, arg, old, new)
+            },
+            Change::InputChanged {
+                node,
+                index,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ \"{}\": input {}: {} -> {}",
+                node,
+                index,
+                or_none(old),
+                or_none(new)
+            ),
+            Change::OutputChanged {
+                node,
+                index,
+                old,
+                new,
+            } => write!(
+                f,
+                "~ \"{}\": output {}: {} -> {}",
+                node,
+                index,
+                or_none(old),
+                or_none(new)
+            ),
+            Change::ModelChanged {
+                name,
+                old_size,
+                new_size,
+                old_fingerprint,
+                new_fingerprint,
+            } => write!(
+                f,
+                "~ \"{}\": model {} ({} bytes) -> {} ({} bytes)",
+                name,
+                or_none(old_fingerprint),
+                or_none(old_size),
+                or_none(new_fingerprint),
+                or_none(new_size),
+            ),
+            Change::ResourceAdded { name } => write!(f, "+ resource ${}", name),
+            Change::ResourceRemoved { name } => {
+                write!(f, "- resource ${}", name)
+            },
+            Change::ResourceTypeChanged { name, old, new } => {
+                write!(f, "~ resource ${}: {} -> {}", name, old, new)
+            },
+            Change::ResourceValueChanged { name, old, new } => write!(
+                f,
+                "~ resource ${}: {} -> {}",
+                name,
+                or_none(old),
+                or_none(new)
+            ),
+        }
+    }
+}
+
+fn or_none<T: Display>(value: &Option<T>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => String::from("(none)"),
+    }
+}
+
+fn diff(old: &Snapshot, new: &Snapshot) -> Result<Vec<Change>, Error> {
+    let mut changes = Vec::new();
+
+    if old.version != new.version {
+        changes.push(Change::VersionChanged {
+            old: old.version.clone(),
+            new: new.version.clone(),
+        });
+    }
+
+    diff_nodes(&old.graph, &new.graph, &mut changes)?;
+    diff_resources(old, new, &mut changes);
+
+    Ok(changes)
+}
+
+fn diff_nodes(
+    old: &RuneGraph,
+    new: &RuneGraph,
+    changes: &mut Vec<Change>,
+) -> Result<(), Error> {
+    let old_nodes = Nodes::new(old).context("The old Rune is malformed")?;
+    let new_nodes = Nodes::new(new).context("The new Rune is malformed")?;
+
+    let names: BTreeSet<&str> = old_nodes
+        .nodes
+        .keys()
+        .chain(new_nodes.nodes.keys())
+        .copied()
+        .collect();
+
+    for name in names {
+        match (old_nodes.nodes.get(name), new_nodes.nodes.get(name)) {
+            (Some(node), None) => changes.push(Change::NodeRemoved {
+                name: name.to_string(),
+                kind: node.specifics.kind(),
+                qualifier: node.specifics.qualifier(),
+            }),
+            (None, Some(node)) => changes.push(Change::NodeAdded {
+                name: name.to_string(),
+                kind: node.specifics.kind(),
+                qualifier: node.specifics.qualifier(),
+            }),
+            (Some(old_node), Some(new_node)) => diff_node(
+                name,
+                (old_node, &old_nodes),
+                (new_node, &new_nodes),
+                changes,
+            ),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    Ok(())
+}
+
+/// The nodes in a [`RuneGraph`] and the edges between them.
+struct Nodes<'a> {
+    nodes: HashMap<&'a str, PipelineNode<'a>>,
+    /// A description of each node's inputs (shape and where it came from).
+    inputs: HashMap<&'a str, Vec<String>>,
+    /// The shape of each node's outputs.
+    outputs: HashMap<&'a str, Vec<String>>,
+}
+
+impl<'a> Nodes<'a> {
+    fn new(rune: &'a RuneGraph) -> Result<Self, Error> {
+        let nodes = graph::pipeline_nodes(rune);
+        let mut inputs: HashMap<&str, Vec<String>> = HashMap::new();
+
+        for edge in graph::edges(&nodes, &rune.tensors)? {
+            let node_inputs = inputs.entry(edge.to).or_default();
+            if node_inputs.len() <= edge.to_input {
+                node_inputs.resize(edge.to_input + 1, String::new());
+            }
+            node_inputs[edge.to_input] = format!(
+                "{} from {}.{}",
+                edge.shape, edge.from, edge.from_output
+            );
+        }
+
+        let outputs = nodes
+            .iter()
+            .map(|node| {
+                let shapes = node
+                    .outputs
+                    .iter()
+                    .map(|t| {
+                        graph::shape(&rune.tensors, t).map(|s| s.to_string())
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Ok((node.name, shapes))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Nodes {
+            nodes: nodes.into_iter().map(|n| (n.name, n)).collect(),
+            inputs,
+            outputs,
+        })
+    }
+}
+
+fn diff_node(
+    name: &str,
+    (old, old_nodes): (&PipelineNode<'_>, &Nodes<'_>),
+    (new, new_nodes): (&PipelineNode<'_>, &Nodes<'_>),
+    changes: &mut Vec<Change>,
+) {
+    if old.specifics.kind() != new.specifics.kind() {
+        changes.push(Change::KindChanged {
+            name: name.to_string(),
+            old: old.specifics.kind(),
+            new: new.specifics.kind(),
+        });
+        return;
+    }
+
+    match (old.specifics, new.specifics) {
+        (NodeType::ProcBlock(old_pb), NodeType::ProcBlock(new_pb))
+            if old_pb.path.base == new_pb.path.base
+                && old_pb.path.sub_path == new_pb.path.sub_path =>
+        {
+            if old_pb.path.version != new_pb.path.version {
+                changes.push(Change::ProcBlockVersionChanged {
+                    name: name.to_string(),
+                    old: old_pb.path.version.clone(),
+                    new: new_pb.path.version.clone(),
+                });
+            }
+        },
+        _ => {
+            let old_qualifier = old.specifics.qualifier();
+            let new_qualifier = new.specifics.qualifier();
+
+            if old_qualifier != new_qualifier {
+                changes.push(Change::QualifierChanged {
+                    name: name.to_string(),
+                    old: old_qualifier,
+                    new: new_qualifier,
+                });
+            }
+        },
+    }
+
+    if let (NodeType::Model(old_model), NodeType::Model(new_model)) =
+        (old.specifics, new.specifics)
+    {
+        if old_model.size != new_model.size
+            || old_model.fingerprint != new_model.fingerprint
+        {
+            changes.push(Change::ModelChanged {
+                name: name.to_string(),
+                old_size: old_model.size,
+                new_size: new_model.size,
+                old_fingerprint: old_model.fingerprint,
+                new_fingerprint: new_model.fingerprint,
+            });
+        }
+    }
+
+    let no_args = HashMap::new();
+    diff_args(
+        name,
+        args(old.specifics).unwrap_or(&no_args),
+        args(new.specifics).unwrap_or(&no_args),
+        changes,
+    );
+
+    diff_tensors(
+        old_nodes.inputs.get(name),
+        new_nodes.inputs.get(name),
+        |index, old, new| Change::InputChanged {
+            node: name.to_string(),
+            index,
+            old,
+            new,
+        },
+        changes,
+    );
+    diff_tensors(
+        old_nodes.outputs.get(name),
+        new_nodes.outputs.get(name),
+        |index, old, new| Change::OutputChanged {
+            node: name.to_string(),
+            index,
+            old,
+            new,
+        },
+        changes,
+    );
+}
+
+fn args<'a>(
+    node: NodeType<'a>,
+) -> Option<&'a HashMap<String, ResourceOrString>> {
+    match node {
+        NodeType::Capability(c) => Some(&c.args),
+        NodeType::Model(m) => Some(&m.args),
+        NodeType::ProcBlock(p) => Some(&p.args),
+        NodeType::Output(o) => Some(&o.args),
+        NodeType::Delay(_) => None,
+    }
+}
+
+fn diff_args(
+    node: &str,
+    old: &HashMap<String, ResourceOrString>,
+    new: &HashMap<String, ResourceOrString>,
+    changes: &mut Vec<Change>,
+) {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    for key in keys {
+        let change = match (old.get(key), new.get(key)) {
+            (Some(value), None) => Change::ArgRemoved {
+                node: node.to_string(),
+                arg: key.clone(),
+                value: value.to_string(),
+            },
+            (None, Some(value)) => Change::ArgAdded {
+                node: node.to_string(),
+                arg: key.clone(),
+                value: value.to_string(),
+            },
+            (Some(old_value), Some(new_value)) if old_value != new_value => {
+                Change::ArgChanged {
+                    node: node.to_string(),
+                    arg: key.clone(),
+                    old: old_value.to_string(),
+                    new: new_value.to_string(),
+                }
+            },
+            _ => continue,
+        };
+
+        changes.push(change);
+    }
+}
+
+fn diff_tensors(
+    old: Option<&Vec<String>>,
+    new: Option<&Vec<String>>,
+    mut change: impl FnMut(usize, Option<String>, Option<String>) -> Change,
+    changes: &mut Vec<Change>,
+) {
+    let old = old.map(Vec::as_slice).unwrap_or_default();
+    let new = new.map(Vec::as_slice).unwrap_or_default();
+
+    for index in 0..std::cmp::max(old.len(), new.len()) {
+        let old = old.get(index);
+        let new = new.get(index);
+
+        if old != new {
+            changes.push(change(index, old.cloned(), new.cloned()));
+        }
+    }
+}
+
+fn diff_resources(old: &Snapshot, new: &Snapshot, changes: &mut Vec<Change>) {
+    let old_declared: BTreeMap<&str, ResourceType> = old
+        .graph
+        .resources
+        .iter()
+        .map(|(name, res)| (name.as_str(), res.ty))
+        .collect();
+    let new_declared: BTreeMap<&str, ResourceType> = new
+        .graph
+        .resources
+        .iter()
+        .map(|(name, res)| (name.as_str(), res.ty))
+        .collect();
+
+    let names: BTreeSet<&str> = old_declared
+        .keys()
+        .chain(new_declared.keys())
+        .copied()
+        .chain(old.resources.keys().map(String::as_str))
+        .chain(new.resources.keys().map(String::as_str))
+        .collect();
+
+    for name in names {
+        let (old_ty, new_ty) =
+            match (old_declared.get(name), new_declared.get(name)) {
+                (Some(_), None) => {
+                    changes.push(Change::ResourceRemoved {
+                        name: name.to_string(),
+                    });
+                    continue;
+                },
+                (None, Some(_)) => {
+                    changes.push(Change::ResourceAdded {
+                        name: name.to_string(),
+                    });
+                    continue;
+                },
+                (old_ty, new_ty) => (old_ty.copied(), new_ty.copied()),
+            };
+
+        if let (Some(old_ty), Some(new_ty)) = (old_ty, new_ty) {
+            if old_ty != new_ty {
+                changes.push(Change::ResourceTypeChanged {
+                    name: name.to_string(),
+                    old: resource_type_name(old_ty),
+                    new: resource_type_name(new_ty),
+                });
+            }
+        }
+
+        let old_value = old.resources.get(name);
+        let new_value = new.resources.get(name);
+
+        if old_value != new_value {
+            changes.push(Change::ResourceValueChanged {
+                name: name.to_string(),
+                old: old_value.map(|v| describe_value(v)),
+                new: new_value.map(|v| describe_value(v)),
+            });
+        }
+    }
+}
+
+fn resource_type_name(ty: ResourceType) -> &'static str {
+    match ty {
+        ResourceType::String => "string",
+        ResourceType::Binary => "binary",
+    }
+}
+
+/// Get a human-friendly description of a resource's value.
+fn describe_value(value: &[u8]) -> String {
+    const MAX_LEN: usize = 64;
+
+    match std::str::from_utf8(value) {
+        Ok(s) if s.len() <= MAX_LEN => format!("{:?}", s),
+        _ => format!(
+            "{} bytes (fingerprint {})",
+            value.len(),
+            Fingerprint::of(value)
+        ),
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Error};
+use hotg_rune_compiler::{codegen::ModelSummary, parse::ResourceOrString};
+use wasmparser::{Parser, Payload};
+
+use crate::inspect::Metadata;
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct Extract {
+    /// The directory to write everything to (defaults to
+    /// "<rune-name>-extracted").
+    #[structopt(short, long, parse(from_os_str))]
+    output_dir: Option<PathBuf>,
+    /// The compiled Rune to extract data from.
+    #[structopt(parse(from_os_str))]
+    rune: PathBuf,
+}
+
+impl Extract {
+    pub fn execute(self) -> Result<(), Error> {
+        let wasm = std::fs::read(&self.rune).with_context(|| {
+            format!("Unable to read \"{}\"", self.rune.display())
+        })?;
+        let meta = Metadata::from_wasm_binary(&wasm)
+            .context("Unable to parse metadata from the WebAssembly module")?;
+
+        // Note: the names come from the Rune, so we need to make sure a
+        // malicious Rune can't write outside the output directory.
+        for name in meta.resources.keys() {
+            ensure_file_name(name, "resource")?;
+        }
+        if let Some(rune) = &meta.rune {
+            for name in rune.models.keys() {
+                ensure_file_name(name, "model")?;
+            }
+        }
+
+        let output_dir = self.output_dir()?;
+        log::info!("Extracting to \"{}\"", output_dir.display());
+
+        let metadata_json = serde_json::to_vec_pretty(&meta)
+            .context("Unable to format the metadata as JSON")?;
+        write(&output_dir.join("metadata.json"), &metadata_json)?;
+
+        for (name, value) in &meta.resources {
+            write(&output_dir.join("resources").join(name), value)?;
+        }
+
+        if let Some(rune) = &meta.rune {
+            let data_segments = data_segments(&wasm)
+                .context("Unable to read the WebAssembly data segments")?;
+
+            for (name, model) in &rune.models {
+                match find_model(model, &meta, &data_segments) {
+                    Some(data) => {
+                        write(&output_dir.join("models").join(name), data)?
+                    },
+                    None => log::warn!(
+                        "Unable to find the data for the \"{}\" model",
+                        name
+                    ),
+                }
+            }
+        } else {
+            log::warn!("Unable to find the Rune graph custom section");
+        }
+
+        Ok(())
+    }
+
+    fn output_dir(&self) -> Result<PathBuf, Error> {
+        if let Some(dir) = &self.output_dir {
+            return Ok(dir.clone());
+        }
+
+        let stem = self
+            .rune
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .context("Unable to determine the Rune's name")?;
+
+        Ok(PathBuf::from(format!("{}-extracted", stem)))
+    }
+}
+
+/// Find the bytes for a model that was embedded in the Rune.
+fn find_model<'a>(
+    model: &ModelSummary,
+    meta: &'a Metadata,
+    data_segments: &[&'a [u8]],
+) -> Option<&'a [u8]> {
+    if let ResourceOrString::Resource(resource) = &model.file {
+        // Models loaded from a resource are stored alongside all the other
+        // resources.
+        return meta.resources.get(&resource.0).map(|v| v.as_slice());
+    }
+
+    // Otherwise, the model was compiled into the Rune's data segment and we
+    // need to use its fingerprint to find it.
+    let size = model.size?;
+    let fingerprint = model.fingerprint?;
+
+    data_segments.iter().find_map(|segment| {
+        fingerprint
+            .find_in(segment, size)
+            .map(|start| &segment[start..start + size])
+    })
+}
+
+/// Make sure a name read from the Rune can be used as a file name without
+/// escaping the directory it is joined onto.
+fn ensure_file_name(name: &str, kind: &str) -> Result<(), Error> {
+    let mut components = Path::new(name).components();
+    let is_plain = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) && !name.contains(|c| c == '/' || c == '\\');
+
+    anyhow::ensure!(
+        is_plain,
+        "Refusing to extract the \"{}\" {} because its name isn't a valid \
+         file name",
+        name,
+        kind
+    );
+
+    Ok(())
+}
+
+fn data_segments(wasm: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    let mut segments = Vec::new();
+
+    for payload in Parser::default().parse_all(wasm) {
+        if let Payload::DataSection(reader) = payload? {
+            for data in reader {
+                segments.push(data?.data);
+            }
+        }
+    }
+
+    Ok(segments)
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| {
+            format!("Unable to create the \"{}\" directory", parent.display())
+        })?;
+    }
+
+    log::debug!("Writing {} bytes to \"{}\"", data.len(), path.display());
+
+    std::fs::write(path, data)
+        .with_context(|| format!("Unable to write to \"{}\"", path.display()))
+}
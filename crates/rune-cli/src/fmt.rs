@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Error};
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct Fmt {
+    /// Don't write the formatted Runefiles back to disk, instead exit with an
+    /// error if any of them aren't formatted.
+    #[structopt(long)]
+    check: bool,
+    /// Upgrade version 1 Runefiles to the version 2 format, where stages
+    /// have named inputs and outputs.
+    #[structopt(long)]
+    upgrade: bool,
+    /// The Runefiles to format.
+    #[structopt(parse(from_os_str), default_value = "Runefile.yml")]
+    runefiles: Vec<PathBuf>,
+}
+
+impl Fmt {
+    pub fn execute(self) -> Result<(), Error> {
+        let mut unformatted = Vec::new();
+
+        for runefile in &self.runefiles {
+            let src = std::fs::read_to_string(runefile).with_context(|| {
+                format!("Unable to read \"{}\"", runefile.display())
+            })?;
+
+            let formatted = if self.upgrade {
+                hotg_rune_compiler::parse::upgrade_runefile(&src).with_context(
+                    || format!("Unable to upgrade \"{}\"", runefile.display()),
+                )?
+            } else {
+                hotg_rune_compiler::parse::format_runefile(&src).with_context(
+                    || format!("Unable to parse \"{}\"", runefile.display()),
+                )?
+            };
+
+            if formatted == src {
+                log::debug!("\"{}\" is already formatted", runefile.display());
+                continue;
+            }
+
+            if self.check {
+                println!("{}", runefile.display());
+                unformatted.push(runefile);
+            } else {
+                log::info!("Formatting \"{}\"", runefile.display());
+                std::fs::write(runefile, formatted).with_context(|| {
+                    format!("Unable to write to \"{}\"", runefile.display())
+                })?;
+            }
+        }
+
+        match unformatted.len() {
+            0 => Ok(()),
+            1 => Err(Error::msg("1 Runefile needs to be formatted")),
+            n => Err(anyhow::anyhow!("{} Runefiles need to be formatted", n)),
+        }
+    }
+}
@@ -1,26 +1,97 @@
-use std::{collections::HashMap, fs::File, io::Write, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Error};
-use hotg_rune_compiler::codegen::{
-    CapabilitySummary, ModelSummary, OutputSummary, ProcBlockSummary,
-    RuneGraph, TensorId,
+use codespan_reporting::{diagnostic::Severity, term::termcolor::ColorChoice};
+use hotg_rune_compiler::{
+    codegen::{
+        CapabilitySummary, DelaySummary, ModelSummary, OutputSummary,
+        ProcBlockSummary, RuneGraph, TensorId,
+    },
+    hooks::{
+        AfterCodegenContext, AfterLoweringContext, AfterParseContext,
+        AfterTypeCheckingContext, Continuation, Hooks,
+    },
 };
 use hotg_rune_core::Shape;
+use strum::VariantNames;
 
-use crate::inspect::Metadata;
+use crate::{
+    check::{self, CollectDiagnostics},
+    inspect::Metadata,
+    Unstable,
+};
 
 #[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
 pub struct Graph {
     /// Where to write the generated file (stdout by default).
     #[structopt(short, long, parse(from_os_str))]
     output: Option<PathBuf>,
-    /// A compiled Rune to graph.
+    #[structopt(
+        short,
+        long,
+        help = "The format to render the graph in",
+        default_value = "dot",
+        possible_values = GraphFormat::VARIANTS,
+        parse(try_from_str)
+    )]
+    format: GraphFormat,
+    /// The directory that all paths are resolved relative to when graphing a
+    /// Runefile (Defaults to the Runefile's directory)
+    #[structopt(short, long, env)]
+    current_dir: Option<PathBuf>,
+    /// A compiled Rune or Runefile to graph.
     #[structopt(parse(from_os_str))]
     input: PathBuf,
 }
 
+#[derive(
+    Debug, Copy, Clone, PartialEq, strum::EnumVariantNames, strum::EnumString,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    Dot,
+    /// A Mermaid flowchart.
+    Mermaid,
+    /// A JSON list of nodes and edges.
+    Json,
+    /// A plain-text rendering of the pipeline.
+    Text,
+}
+
 impl Graph {
-    pub fn execute(self) -> Result<(), Error> {
+    pub fn execute(
+        self,
+        color: ColorChoice,
+        unstable: Unstable,
+    ) -> Result<(), Error> {
+        let rune = if is_runefile(&self.input) {
+            self.graph_runefile(color, unstable)?
+        } else {
+            self.graph_rune()?
+        };
+
+        let mut writer = self.writer()?;
+
+        let rendered = match self.format {
+            GraphFormat::Dot => render(&mut *writer, &rune),
+            GraphFormat::Mermaid => render_mermaid(&mut *writer, &rune),
+            GraphFormat::Json => render_json(&mut *writer, &rune),
+            GraphFormat::Text => render_text(&mut *writer, &rune),
+        };
+        rendered.context("Render failed")?;
+
+        writer.flush().context("Flush failed")?;
+
+        Ok(())
+    }
+
+    fn graph_rune(&self) -> Result<RuneGraph, Error> {
         let bytes = std::fs::read(&self.input).with_context(|| {
             format!("Unable to read \"{}\"", self.input.display())
         })?;
@@ -29,20 +100,46 @@ impl Graph {
             .context(
                 "Unable to extract metadata from the WebAssembly module",
             )?;
-        let rune =
-            rune.context("Unable to find the Rune graph custom section")?;
 
-        let mut writer = self.writer()?;
-        render(&mut *writer, &rune).context("Render failed")?;
-        writer.flush().context("Flush failed")?;
+        rune.context("Unable to find the Rune graph custom section")
+    }
 
-        Ok(())
+    fn graph_runefile(
+        &self,
+        color: ColorChoice,
+        unstable: Unstable,
+    ) -> Result<RuneGraph, Error> {
+        let ctx = check::build_context(
+            &self.input,
+            self.current_dir.as_deref(),
+            None,
+        )?;
+        let runefile = ctx.runefile.clone();
+
+        let features = unstable.feature_flags();
+
+        let mut hooks = GenerateGraph::default();
+        hotg_rune_compiler::build_with_hooks(ctx, features, &mut hooks);
+
+        let GenerateGraph { diags, graph } = hooks;
+        let has_errors = diags
+            .diagnostics
+            .iter()
+            .any(|d| d.severity >= Severity::Error);
+
+        if has_errors {
+            let sources = diags.sources(&self.input, &runefile);
+            check::print_text(&sources, &diags.diagnostics, color)?;
+            anyhow::bail!("Unable to analyse \"{}\"", self.input.display());
+        }
+
+        graph.context("The compiler didn't generate a Rune graph")
     }
 
     fn writer(&self) -> Result<Box<dyn Write>, Error> {
         match &self.output {
             Some(path) => {
-                let file = File::open(path).with_context(|| {
+                let file = File::create(path).with_context(|| {
                     format!("Unable to open \"{}\" for writing", path.display())
                 })?;
                 Ok(Box::new(file))
@@ -55,58 +152,86 @@ impl Graph {
     }
 }
 
+fn is_runefile(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("yml") | Some("yaml")
+    )
+}
+
+/// [`Hooks`] which run the compiler up to the point where the [`RuneGraph`]
+/// has been generated.
+#[derive(Debug, Default)]
+struct GenerateGraph {
+    diags: CollectDiagnostics,
+    graph: Option<RuneGraph>,
+}
+
+impl Hooks for GenerateGraph {
+    fn after_parse(&mut self, ctx: &mut dyn AfterParseContext) -> Continuation {
+        self.diags.collect_after_parse(ctx)
+    }
+
+    fn after_lowering(
+        &mut self,
+        ctx: &mut dyn AfterLoweringContext,
+    ) -> Continuation {
+        self.diags.collect(ctx.diagnostics_mut().drain())
+    }
+
+    fn after_type_checking(
+        &mut self,
+        ctx: &mut dyn AfterTypeCheckingContext,
+    ) -> Continuation {
+        self.diags.collect(ctx.diagnostics_mut().drain())
+    }
+
+    fn after_codegen(
+        &mut self,
+        ctx: &mut dyn AfterCodegenContext,
+    ) -> Continuation {
+        self.diags.collect(ctx.diagnostics_mut().drain());
+        self.graph = ctx.rune_graph();
+
+        // We've got everything we need, there's no need to compile anything.
+        Continuation::Halt
+    }
+}
+
 fn render(w: &mut dyn Write, rune: &RuneGraph) -> Result<(), Error> {
     writeln!(w, "digraph {{")?;
     writeln!(w, "  rankdir=TD;")?;
     writeln!(w, "  node [shape=plaintext];")?;
 
-    declare_nodes(w, rune)?;
-    declare_edges(w, rune)?;
+    let nodes = pipeline_nodes(rune);
+    declare_nodes(w, &nodes)?;
+    declare_edges(w, rune, &nodes)?;
 
     writeln!(w, "}}")?;
     Ok(())
 }
 
-fn declare_edges(w: &mut dyn Write, rune: &RuneGraph) -> Result<(), Error> {
-    let nodes: Vec<_> = pipeline_nodes(rune).collect();
-    for node in nodes.iter().copied() {
-        declare_input_edges(w, node, &rune.tensors, &nodes)?;
-    }
-
-    Ok(())
-}
-
-fn declare_input_edges(
+fn declare_edges(
     w: &mut dyn Write,
-    node: PipelineNode<'_>,
-    tensors: &HashMap<TensorId, Shape<'_>>,
+    rune: &RuneGraph,
     nodes: &[PipelineNode<'_>],
 ) -> Result<(), Error> {
-    for (i, tensor_id) in node.inputs.iter().enumerate() {
-        let shape = &tensors[tensor_id];
-
-        let (input_node, input_index) = nodes
-            .iter()
-            .find_map(|n| {
-                n.outputs
-                    .iter()
-                    .position(|t| t == tensor_id)
-                    .map(|i| (n, i))
-            })
-            .expect("The graph was malformed");
-
+    for edge in edges(nodes, &rune.tensors)? {
         writeln!(
             w,
             "  node_{}:output_{}:s -> node_{}:input_{}:n [label=\"{}\"];",
-            input_node.name, input_index, node.name, i, shape
+            edge.from, edge.from_output, edge.to, edge.to_input, edge.shape
         )?;
     }
 
     Ok(())
 }
 
-fn declare_nodes(w: &mut dyn Write, rune: &RuneGraph) -> Result<(), Error> {
-    for node in pipeline_nodes(rune) {
+fn declare_nodes(
+    w: &mut dyn Write,
+    nodes: &[PipelineNode<'_>],
+) -> Result<(), Error> {
+    for node in nodes {
         let colour = node_colour(node.specifics);
         write!(
             w,
@@ -127,6 +252,7 @@ fn node_colour(specifics: NodeType<'_>) -> &'static str {
         NodeType::Model(_) => "violet",
         NodeType::ProcBlock(_) => "tan1",
         NodeType::Output(_) => "indianred1",
+        NodeType::Delay(_) => "lightblue",
     }
 }
 
@@ -173,30 +299,236 @@ fn format_node_label(
     Ok(())
 }
 
+fn render_mermaid(w: &mut dyn Write, rune: &RuneGraph) -> Result<(), Error> {
+    writeln!(w, "flowchart TD")?;
+
+    let nodes = pipeline_nodes(rune);
+
+    for node in &nodes {
+        let label = mermaid_escape(&format!(
+            "{}: {}",
+            node.name,
+            node.specifics.qualifier()
+        ));
+        let id = mermaid_id(node.name);
+
+        match node.specifics {
+            NodeType::Capability(_) | NodeType::Output(_) => {
+                writeln!(w, "    {}([\"{}\"])", id, label)?
+            },
+            NodeType::Model(_) => writeln!(w, "    {}[[\"{}\"]]", id, label)?,
+            NodeType::ProcBlock(_) => writeln!(w, "    {}[\"{}\"]", id, label)?,
+            NodeType::Delay(_) => writeln!(w, "    {}[/\"{}\"/]", id, label)?,
+        }
+    }
+
+    for edge in edges(&nodes, &rune.tensors)? {
+        writeln!(
+            w,
+            "    {} -->|\"{}\"| {}",
+            mermaid_id(edge.from),
+            mermaid_escape(&edge.shape.to_string()),
+            mermaid_id(edge.to)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Mermaid IDs can only contain a limited set of characters, so we replace
+/// anything unusual with an underscore.
+fn mermaid_id(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("node_{}", sanitized)
+}
+
+fn mermaid_escape(text: &str) -> String { text.replace('"', "#quot;") }
+
+fn render_json(w: &mut dyn Write, rune: &RuneGraph) -> Result<(), Error> {
+    let nodes = pipeline_nodes(rune);
+    let shapes = |tensors: &[TensorId]| -> Result<Vec<String>, Error> {
+        tensors
+            .iter()
+            .map(|t| shape(&rune.tensors, t).map(|s| s.to_string()))
+            .collect()
+    };
+
+    let graph = JsonGraph {
+        name: &rune.rune.name,
+        nodes: nodes
+            .iter()
+            .map(|node| {
+                Ok(JsonNode {
+                    name: node.name,
+                    kind: node.specifics.kind(),
+                    qualifier: node.specifics.qualifier(),
+                    inputs: shapes(node.inputs)?,
+                    outputs: shapes(node.outputs)?,
+                })
+            })
+            .collect::<Result<_, Error>>()?,
+        edges: edges(&nodes, &rune.tensors)?
+            .into_iter()
+            .map(|edge| JsonEdge {
+                from: edge.from,
+                from_output: edge.from_output,
+                to: edge.to,
+                to_input: edge.to_input,
+                shape: edge.shape.to_string(),
+            })
+            .collect(),
+    };
+
+    serde_json::to_writer_pretty(&mut *w, &graph)?;
+    writeln!(w)?;
+
+    Ok(())
+}
+
+#[derive(Debug, serde::Serialize)]
+struct JsonGraph<'a> {
+    name: &'a str,
+    nodes: Vec<JsonNode<'a>>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct JsonNode<'a> {
+    name: &'a str,
+    kind: &'static str,
+    qualifier: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct JsonEdge<'a> {
+    from: &'a str,
+    from_output: usize,
+    to: &'a str,
+    to_input: usize,
+    shape: String,
+}
+
+fn render_text(w: &mut dyn Write, rune: &RuneGraph) -> Result<(), Error> {
+    let nodes = pipeline_nodes(rune);
+    let edges = edges(&nodes, &rune.tensors)?;
+
+    for node in &nodes {
+        writeln!(
+            w,
+            "{} ({}: {})",
+            node.name,
+            node.specifics.kind(),
+            node.specifics.qualifier()
+        )?;
+
+        for edge in edges.iter().filter(|e| e.from == node.name) {
+            write!(w, "  |-- ")?;
+            if node.outputs.len() > 1 {
+                write!(w, "{}: ", edge.from_output)?;
+            }
+            write!(w, "{} --> {}", edge.shape, edge.to)?;
+            if edge.to_input > 0 {
+                write!(w, " (input {})", edge.to_input)?;
+            }
+            writeln!(w)?;
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Copy, Clone)]
-enum NodeType<'a> {
+pub(crate) enum NodeType<'a> {
     Capability(&'a CapabilitySummary),
     Model(&'a ModelSummary),
     ProcBlock(&'a ProcBlockSummary),
     Output(&'a OutputSummary),
+    Delay(&'a DelaySummary),
 }
 
 #[derive(Debug, Copy, Clone)]
-struct PipelineNode<'a> {
-    name: &'a str,
-    specifics: NodeType<'a>,
-    inputs: &'a [TensorId],
-    outputs: &'a [TensorId],
+pub(crate) struct PipelineNode<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) specifics: NodeType<'a>,
+    pub(crate) inputs: &'a [TensorId],
+    pub(crate) outputs: &'a [TensorId],
 }
 
-fn pipeline_nodes(
-    rune: &RuneGraph,
-) -> impl Iterator<Item = PipelineNode<'_>> + '_ {
+/// A tensor being passed from one node's output to another node's input.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Edge<'a> {
+    pub(crate) from: &'a str,
+    pub(crate) from_output: usize,
+    pub(crate) to: &'a str,
+    pub(crate) to_input: usize,
+    pub(crate) shape: &'a Shape<'static>,
+}
+
+pub(crate) fn edges<'a>(
+    nodes: &[PipelineNode<'a>],
+    tensors: &'a HashMap<TensorId, Shape<'static>>,
+) -> Result<Vec<Edge<'a>>, Error> {
+    let mut edges = Vec::new();
+
+    for node in nodes {
+        for (to_input, tensor_id) in node.inputs.iter().enumerate() {
+            let (from, from_output) = nodes
+                .iter()
+                .find_map(|n| {
+                    n.outputs
+                        .iter()
+                        .position(|t| t == tensor_id)
+                        .map(|i| (n.name, i))
+                })
+                .with_context(|| {
+                    format!(
+                        "Input {} of \"{}\" uses the \"{}\" tensor, but no \
+                         node produces it",
+                        to_input, node.name, tensor_id.0
+                    )
+                })?;
+
+            edges.push(Edge {
+                from,
+                from_output,
+                to: node.name,
+                to_input,
+                shape: shape(tensors, tensor_id)?,
+            });
+        }
+    }
+
+    Ok(edges)
+}
+
+/// Look up a tensor's [`Shape`], failing if the Rune graph is malformed.
+pub(crate) fn shape<'a>(
+    tensors: &'a HashMap<TensorId, Shape<'static>>,
+    id: &TensorId,
+) -> Result<&'a Shape<'static>, Error> {
+    tensors.get(id).with_context(|| {
+        format!("The Rune graph doesn't declare the \"{}\" tensor", id.0)
+    })
+}
+
+/// Get all the nodes in the pipeline, in the order data flows through them.
+///
+/// Nodes are sorted by name where the order would otherwise be ambiguous so
+/// the rendered graph doesn't change from run to run.
+pub(crate) fn pipeline_nodes(rune: &RuneGraph) -> Vec<PipelineNode<'_>> {
     let RuneGraph {
         capabilities,
         models,
         proc_blocks,
         outputs,
+        delays,
         ..
     } = rune;
 
@@ -226,9 +558,45 @@ fn pipeline_nodes(
         inputs: out.inputs.as_slice(),
         outputs: EMPTY,
     });
+    let delays = delays.iter().map(|(name, delay)| PipelineNode {
+        name: name.as_str(),
+        specifics: NodeType::Delay(delay),
+        inputs: delay.inputs.as_slice(),
+        outputs: delay.outputs.as_slice(),
+    });
+
+    let mut remaining: Vec<_> = capabilities
+        .chain(models)
+        .chain(proc_blocks)
+        .chain(outputs)
+        .chain(delays)
+        .collect();
+    remaining.sort_by_key(|n| n.name);
+
+    let mut sorted = Vec::with_capacity(remaining.len());
+    let mut available: HashSet<&TensorId> = HashSet::new();
+
+    while !remaining.is_empty() {
+        let next = remaining
+            .iter()
+            .position(|n| {
+                // Delays output the previous call's value, so they never
+                // need to wait for their inputs
+                matches!(n.specifics, NodeType::Delay(_))
+                    || n.inputs.iter().all(|t| available.contains(t))
+            })
+            // There must be a cycle, so just take things in alphabetical
+            // order.
+            .unwrap_or(0);
+
+        let node = remaining.remove(next);
+        available.extend(node.outputs);
+        sorted.push(node);
+    }
 
-    capabilities.chain(models).chain(proc_blocks).chain(outputs)
+    sorted
 }
+
 impl NodeType<'_> {
     pub(crate) fn qualifier(self) -> String {
         match self {
@@ -236,6 +604,17 @@ impl NodeType<'_> {
             NodeType::Model(model) => model.file.to_string(),
             NodeType::ProcBlock(pb) => pb.path.to_string(),
             NodeType::Output(out) => out.kind.to_string(),
+            NodeType::Delay(delay) => format!("initially {}", delay.initial),
+        }
+    }
+
+    pub(crate) fn kind(self) -> &'static str {
+        match self {
+            NodeType::Capability(_) => "capability",
+            NodeType::Model(_) => "model",
+            NodeType::ProcBlock(_) => "proc-block",
+            NodeType::Output(_) => "output",
+            NodeType::Delay(_) => "delay",
         }
     }
 }
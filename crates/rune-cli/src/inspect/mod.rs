@@ -6,7 +6,7 @@ use std::path::PathBuf;
 use anyhow::Error;
 use strum::VariantNames;
 
-pub(crate) use self::rune::{wasm_custom_sections, Metadata};
+pub(crate) use self::rune::Metadata;
 use crate::Format;
 
 #[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
@@ -1,10 +1,13 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
 use anyhow::{Context, Error};
 use hotg_rune_compiler::{
     codegen::{
-        CapabilitySummary, ModelSummary, OutputSummary, ProcBlockSummary,
-        RuneGraph, RuneVersion, TensorId,
+        CapabilitySummary, DelaySummary, ModelSummary, OutputSummary,
+        ProcBlockSummary, RuneGraph, RuneVersion, TensorId,
     },
     lowering::{Name, Resource},
     parse::{ResourceOrString, ResourceType},
@@ -49,6 +52,8 @@ fn print_rune(rune: &RuneGraph) {
         models,
         proc_blocks,
         outputs,
+        delays,
+        pipelines,
         resources,
         tensors,
     } = rune;
@@ -59,9 +64,41 @@ fn print_rune(rune: &RuneGraph) {
     print_models(models, tensors);
     print_proc_blocks(proc_blocks, tensors);
     print_outputs(outputs, tensors);
+    print_delays(delays, tensors);
+    print_pipelines(pipelines);
     print_resources(resources);
 }
 
+fn print_pipelines(pipelines: &HashMap<Name, Vec<Name>>) {
+    if pipelines.is_empty() {
+        return;
+    }
+
+    println!("Pipelines:");
+
+    for (name, stages) in pipelines {
+        let stages: Vec<&str> = stages.iter().map(|s| s.as_str()).collect();
+        println!("\t{}: {}", name, stages.join(", "));
+    }
+}
+
+fn print_delays(
+    delays: &HashMap<Name, DelaySummary>,
+    tensors: &HashMap<TensorId, Shape<'static>>,
+) {
+    if delays.is_empty() {
+        return;
+    }
+
+    println!("Delays:");
+
+    for (name, delay) in delays {
+        println!("- {}: initially {}", name, delay.initial);
+        print_tensors("Inputs", &delay.inputs, tensors);
+        print_tensors("Outputs", &delay.outputs, tensors);
+    }
+}
+
 fn print_outputs(
     outputs: &HashMap<Name, OutputSummary>,
     tensors: &HashMap<TensorId, Shape<'static>>,
@@ -171,6 +208,9 @@ fn print_args(args: &HashMap<String, ResourceOrString>) {
 pub(crate) struct Metadata {
     pub(crate) version: Option<RuneVersion>,
     pub(crate) rune: Option<RuneGraph>,
+    /// The default value for each resource embedded in the Rune.
+    #[serde(skip)]
+    pub(crate) resources: BTreeMap<String, Vec<u8>>,
 }
 
 impl Metadata {
@@ -213,6 +253,23 @@ impl Metadata {
                         },
                     }
                 },
+                hotg_rune_compiler::codegen::RESOURCE_CUSTOM_SECTION => {
+                    let mut data = section.data;
+
+                    while !data.is_empty() {
+                        match hotg_rune_core::decode_inline_resource(data) {
+                            Some((name, value, rest)) => {
+                                meta.resources
+                                    .insert(name.to_string(), value.to_vec());
+                                data = rest;
+                            },
+                            None => {
+                                log::warn!("Unable to decode a resource");
+                                break;
+                            },
+                        }
+                    }
+                },
                 _ => {},
             }
         }
@@ -1,17 +1,42 @@
 pub mod build;
+mod check;
+mod config;
+mod debug;
+mod diff;
+mod extract;
+mod fmt;
 mod graph;
 mod inspect;
+mod lsp;
 mod model_info;
+mod patch;
 pub mod run;
+mod test;
 mod unstable;
 mod version;
+mod watch;
 
 use codespan_reporting::term::termcolor;
 use env_logger::WriteStyle;
 
 pub use crate::{
-    build::Build, graph::Graph, inspect::Inspect, model_info::ModelInfo,
-    run::Run, unstable::Unstable, version::Version,
+    build::Build,
+    check::Check,
+    config::{Config, ConfigCommand},
+    debug::Debugger,
+    diff::Diff,
+    extract::Extract,
+    fmt::Fmt,
+    graph::Graph,
+    inspect::Inspect,
+    lsp::Lsp,
+    model_info::ModelInfo,
+    patch::Patch,
+    run::Run,
+    test::Test,
+    unstable::Unstable,
+    version::Version,
+    watch::Watch,
 };
 
 #[derive(
@@ -0,0 +1,718 @@
+//! A Language Server Protocol implementation for Runefiles.
+
+mod outline;
+
+use std::{
+    collections::HashMap, fmt::Write as _, ops::Range, panic::AssertUnwindSafe,
+};
+
+use anyhow::{Context, Error};
+use codespan_reporting::{
+    diagnostic::{LabelStyle, Severity},
+    files::Files,
+};
+use hotg_rune_compiler::parse::{
+    Document, DocumentV1, ResourceDeclaration, SourceMap, Stage, Type,
+};
+use lsp_server::{
+    Connection, ErrorCode, Message, Notification, Request, Response,
+};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        DidSaveTextDocument, Notification as _, PublishDiagnostics,
+    },
+    request::{Completion, GotoDefinition, HoverRequest, Request as _},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams,
+    CompletionResponse, CompletionTextEdit, DiagnosticRelatedInformation,
+    DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidSaveTextDocumentParams, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, NumberOrString, OneOf, Position,
+    PublishDiagnosticsParams, SaveOptions, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, TextEdit, Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use self::outline::{CompletionContext, Outline, Reference};
+use crate::{
+    check::{self, CollectDiagnostics},
+    Unstable,
+};
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct Lsp {
+    /// Communicate over stdin and stdout (this is the default, but some
+    /// editors always pass it).
+    #[structopt(long)]
+    stdio: bool,
+}
+
+impl Lsp {
+    pub fn execute(self, unstable: Unstable) -> Result<(), Error> {
+        log::info!("Starting the language server");
+
+        let (connection, io_threads) = Connection::stdio();
+
+        let capabilities = serde_json::to_value(capabilities())
+            .context("Unable to serialize the server's capabilities")?;
+        connection
+            .initialize(capabilities)
+            .context("Unable to initialize the language server")?;
+
+        let mut server = Server::new(unstable);
+
+        for msg in &connection.receiver {
+            match msg {
+                Message::Request(req) => {
+                    if connection.handle_shutdown(&req)? {
+                        break;
+                    }
+
+                    let response = server.handle_request(req);
+                    connection.sender.send(Message::Response(response))?;
+                },
+                Message::Notification(notification) => {
+                    for msg in server.handle_notification(notification) {
+                        connection.sender.send(Message::Notification(msg))?;
+                    }
+                },
+                Message::Response(_) => {},
+            }
+        }
+
+        // Note: the IO threads won't stop until the connection is dropped
+        drop(connection);
+        io_threads
+            .join()
+            .context("Unable to shut down the language server")?;
+
+        log::info!("The language server shut down");
+
+        Ok(())
+    }
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::Full),
+                save: Some(TextDocumentSyncSaveOptions::SaveOptions(
+                    SaveOptions {
+                        include_text: Some(true),
+                    },
+                )),
+                ..Default::default()
+            },
+        )),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![
+                "$".to_string(),
+                "-".to_string(),
+                " ".to_string(),
+            ]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+struct Server {
+    unstable: Unstable,
+    documents: HashMap<Url, OpenDocument>,
+}
+
+impl Server {
+    fn new(unstable: Unstable) -> Self {
+        Server {
+            unstable,
+            documents: HashMap::new(),
+        }
+    }
+
+    fn handle_request(&mut self, req: Request) -> Response {
+        let Request { id, method, params } = req;
+
+        let result = match method.as_str() {
+            HoverRequest::METHOD => dispatch(params, |p| self.hover(p)),
+            GotoDefinition::METHOD => {
+                dispatch(params, |p| self.goto_definition(p))
+            },
+            Completion::METHOD => dispatch(params, |p| self.completion(p)),
+            _ => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("Unsupported request, \"{}\"", method),
+                );
+            },
+        };
+
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err(e) => Response::new_err(
+                id,
+                ErrorCode::InvalidParams as i32,
+                e.to_string(),
+            ),
+        }
+    }
+
+    fn handle_notification(
+        &mut self,
+        notification: Notification,
+    ) -> Vec<Notification> {
+        let Notification { method, params } = notification;
+
+        let outcome = match method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                parse_params(params).map(|p| self.did_open(p))
+            },
+            DidChangeTextDocument::METHOD => {
+                parse_params(params).map(|p| self.did_change(p))
+            },
+            DidSaveTextDocument::METHOD => {
+                parse_params(params).map(|p| self.did_save(p))
+            },
+            DidCloseTextDocument::METHOD => {
+                parse_params(params).map(|p| self.did_close(p))
+            },
+            _ => {
+                log::debug!("Ignoring the \"{}\" notification", method);
+                return Vec::new();
+            },
+        };
+
+        match outcome {
+            Ok(Some(diagnostics)) => vec![Notification::new(
+                PublishDiagnostics::METHOD.to_string(),
+                diagnostics,
+            )],
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                log::warn!("Invalid \"{}\" notification: {}", method, e);
+                Vec::new()
+            },
+        }
+    }
+
+    fn did_open(
+        &mut self,
+        params: DidOpenTextDocumentParams,
+    ) -> Option<PublishDiagnosticsParams> {
+        let uri = params.text_document.uri;
+        let doc = OpenDocument::new(params.text_document.text);
+        let diagnostics = self.check(&uri, &doc.text);
+        self.documents.insert(uri, doc);
+
+        Some(diagnostics)
+    }
+
+    fn did_change(
+        &mut self,
+        params: DidChangeTextDocumentParams,
+    ) -> Option<PublishDiagnosticsParams> {
+        // We asked for full syncs, so the last change contains everything
+        let text = params.content_changes.into_iter().last()?.text;
+
+        self.documents
+            .entry(params.text_document.uri)
+            .and_modify(|doc| doc.update(text.clone()))
+            .or_insert_with(|| OpenDocument::new(text));
+
+        None
+    }
+
+    fn did_save(
+        &mut self,
+        params: DidSaveTextDocumentParams,
+    ) -> Option<PublishDiagnosticsParams> {
+        let uri = params.text_document.uri;
+
+        if let Some(text) = params.text {
+            self.documents
+                .entry(uri.clone())
+                .and_modify(|doc| doc.update(text.clone()))
+                .or_insert_with(|| OpenDocument::new(text));
+        }
+
+        let doc = self.documents.get(&uri)?;
+        Some(self.check(&uri, &doc.text))
+    }
+
+    fn did_close(
+        &mut self,
+        params: DidCloseTextDocumentParams,
+    ) -> Option<PublishDiagnosticsParams> {
+        let uri = params.text_document.uri;
+        self.documents.remove(&uri);
+
+        // Clear any diagnostics that were left behind
+        Some(PublishDiagnosticsParams {
+            uri,
+            diagnostics: Vec::new(),
+            version: None,
+        })
+    }
+
+    /// Run the Runefile through the compiler's analysis phases.
+    fn check(&self, uri: &Url, src: &str) -> PublishDiagnosticsParams {
+        let diagnostics = match self.compiler_diagnostics(uri, src) {
+            Ok((sources, diags)) => diags
+                .iter()
+                .map(|diag| to_lsp_diagnostic(uri, &sources, diag))
+                .collect(),
+            Err(e) => {
+                log::warn!("Unable to check \"{}\": {:?}", uri, e);
+                Vec::new()
+            },
+        };
+
+        PublishDiagnosticsParams {
+            uri: uri.clone(),
+            diagnostics,
+            version: None,
+        }
+    }
+
+    fn compiler_diagnostics(
+        &self,
+        uri: &Url,
+        src: &str,
+    ) -> Result<
+        (
+            SourceMap,
+            Vec<codespan_reporting::diagnostic::Diagnostic<()>>,
+        ),
+        Error,
+    > {
+        let runefile = uri
+            .to_file_path()
+            .map_err(|_| Error::msg("Only local files can be checked"))?;
+        let ctx = check::build_context_for_source(
+            &runefile,
+            None,
+            None,
+            src.to_string(),
+        )?;
+        let features = self.unstable.feature_flags();
+
+        let mut hooks = CollectDiagnostics::default();
+
+        // A bug in the compiler shouldn't take down the whole server
+        std::panic::catch_unwind(AssertUnwindSafe(|| {
+            hotg_rune_compiler::build_with_hooks(ctx, features, &mut hooks);
+        }))
+        .map_err(|_| Error::msg("The compiler panicked"))?;
+
+        let sources = hooks.sources(&runefile, src);
+        Ok((sources, hooks.diagnostics))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let doc = self.documents.get(&position.text_document.uri)?;
+        let offset = position_to_offset(&doc.text, position.position);
+
+        let word = outline::word_at(&doc.text, offset);
+        let parsed = doc.parsed.as_ref()?;
+
+        let value = match Reference::parse(&doc.text[word.clone()])? {
+            Reference::Stage { name, index } => {
+                describe_stage(name, parsed.pipeline.get(name)?, index)
+            },
+            Reference::Resource(name) => {
+                describe_resource(name, parsed.resources.get(name)?)
+            },
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(span_to_range(&doc.text, word)),
+        })
+    }
+
+    fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let doc = self.documents.get(&uri)?;
+        let offset = position_to_offset(&doc.text, position.position);
+
+        let word = outline::word_at(&doc.text, offset);
+        let definition = match Reference::parse(&doc.text[word])? {
+            Reference::Stage { name, .. } => doc.outline.stage(name)?,
+            Reference::Resource(name) => doc.outline.resource(name)?,
+        };
+
+        Some(GotoDefinitionResponse::Scalar(Location {
+            uri,
+            range: span_to_range(&doc.text, definition.name_span.clone()),
+        }))
+    }
+
+    fn completion(
+        &self,
+        params: CompletionParams,
+    ) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let doc = self.documents.get(&position.text_document.uri)?;
+        let offset = position_to_offset(&doc.text, position.position);
+
+        let range =
+            span_to_range(&doc.text, outline::word_at(&doc.text, offset));
+        let item =
+            |label: String, kind, detail: Option<String>| CompletionItem {
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: label.clone(),
+                })),
+                label,
+                kind: Some(kind),
+                detail,
+                ..Default::default()
+            };
+
+        let items = match CompletionContext::at(&doc.text, offset)? {
+            CompletionContext::Input => {
+                let current = doc.outline.enclosing_stage(offset);
+                let mut items = Vec::new();
+
+                for stage in doc.outline.stages() {
+                    if Some(stage) == current {
+                        continue;
+                    }
+
+                    let outputs = doc
+                        .parsed
+                        .as_ref()
+                        .and_then(|d| d.pipeline.get(&stage.name))
+                        .map(|s| s.output_types());
+
+                    match outputs {
+                        // Sinks can't be used as inputs
+                        Some([]) => {},
+                        Some([single]) => items.push(item(
+                            stage.name.clone(),
+                            CompletionItemKind::Reference,
+                            Some(shape(single)),
+                        )),
+                        Some(multiple) => {
+                            for (i, ty) in multiple.iter().enumerate() {
+                                items.push(item(
+                                    format!("{}.{}", stage.name, i),
+                                    CompletionItemKind::Reference,
+                                    Some(shape(ty)),
+                                ));
+                            }
+                        },
+                        None => items.push(item(
+                            stage.name.clone(),
+                            CompletionItemKind::Reference,
+                            None,
+                        )),
+                    }
+                }
+
+                items
+            },
+            CompletionContext::Capability => {
+                hotg_rune_core::capabilities::all()
+                    .iter()
+                    .map(|(name, _)| {
+                        item(
+                            name.to_string(),
+                            CompletionItemKind::EnumMember,
+                            None,
+                        )
+                    })
+                    .collect()
+            },
+            CompletionContext::Output => hotg_rune_core::outputs::all()
+                .iter()
+                .map(|(name, _)| {
+                    item(name.to_string(), CompletionItemKind::EnumMember, None)
+                })
+                .collect(),
+            CompletionContext::Resource => doc
+                .outline
+                .resources()
+                .map(|res| {
+                    let detail = doc
+                        .parsed
+                        .as_ref()
+                        .and_then(|d| d.resources.get(&res.name))
+                        .map(|decl| describe_resource(&res.name, decl));
+
+                    item(
+                        format!("${}", res.name),
+                        CompletionItemKind::Variable,
+                        detail,
+                    )
+                })
+                .collect(),
+        };
+
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+/// A Runefile that is currently open in the editor.
+struct OpenDocument {
+    text: String,
+    outline: Outline,
+    /// The most recent version of the document that could be parsed.
+    parsed: Option<DocumentV1>,
+}
+
+impl OpenDocument {
+    fn new(text: String) -> Self {
+        let mut doc = OpenDocument {
+            text: String::new(),
+            outline: Outline::default(),
+            parsed: None,
+        };
+        doc.update(text);
+
+        doc
+    }
+
+    fn update(&mut self, text: String) {
+        self.outline = Outline::new(&text);
+
+        // Note: We keep the previous document around when parsing fails
+        // because the file is often invalid while the user is typing.
+        if let Ok(doc) = Document::parse(&text) {
+            self.parsed = Some(doc.to_v1());
+        }
+
+        self.text = text;
+    }
+}
+
+fn dispatch<P, R>(
+    params: serde_json::Value,
+    handler: impl FnOnce(P) -> R,
+) -> Result<serde_json::Value, serde_json::Error>
+where
+    P: DeserializeOwned,
+    R: Serialize,
+{
+    let params = parse_params(params)?;
+    serde_json::to_value(handler(params))
+}
+
+fn parse_params<P: DeserializeOwned>(
+    params: serde_json::Value,
+) -> Result<P, serde_json::Error> {
+    serde_json::from_value(params)
+}
+
+fn describe_stage(name: &str, stage: &Stage, index: Option<usize>) -> String {
+    let mut description = String::new();
+
+    // Note: writing to a String can't fail
+    let _ = match stage {
+        Stage::Model(m) => {
+            write!(description, "**{}** (model `{}`)", name, m.model)
+        },
+        Stage::ProcBlock(p) => {
+            write!(description, "**{}** (proc-block `{}`)", name, p.proc_block)
+        },
+        Stage::Capability(c) => {
+            write!(description, "**{}** (capability `{}`)", name, c.capability)
+        },
+        Stage::Out(out) => {
+            write!(description, "**{}** (output `{}`)", name, out.out)
+        },
+        Stage::Delay(delay) => {
+            write!(
+                description,
+                "**{}** (delay, initially `{}`)",
+                name, delay.initial
+            )
+        },
+        Stage::Template(t) => {
+            write!(description, "**{}** (template `{}`)", name, t.template)
+        },
+    };
+
+    let outputs = stage.output_types();
+    if !outputs.is_empty() {
+        let _ = write!(description, "\n\nOutputs:\n");
+
+        for (i, ty) in outputs.iter().enumerate() {
+            let selected = if index == Some(i) { " ←" } else { "" };
+            let _ =
+                writeln!(description, "- {}: `{}`{}", i, shape(ty), selected);
+        }
+    }
+
+    description
+}
+
+fn describe_resource(name: &str, decl: &ResourceDeclaration) -> String {
+    let ty = serde_json::to_value(&decl.ty)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default();
+    let mut description = format!("**${}** ({} resource)", name, ty);
+
+    if let Some(inline) = &decl.inline {
+        let _ = write!(description, "\n\nDefault value: `{}`", inline);
+    }
+    if let Some(path) = &decl.path {
+        let _ = write!(description, "\n\nLoaded from `{}`", path);
+    }
+
+    description
+}
+
+/// Format a [`Type`] the same way as [`hotg_rune_core::Shape`].
+fn shape(ty: &Type) -> String {
+    let dims: Vec<_> = ty.dimensions.iter().map(|d| d.to_string()).collect();
+    format!("{}[{}]", ty.name.to_lowercase(), dims.join(", "))
+}
+
+fn to_lsp_diagnostic(
+    uri: &Url,
+    sources: &SourceMap,
+    diag: &codespan_reporting::diagnostic::Diagnostic<()>,
+) -> lsp_types::Diagnostic {
+    let diag = sources.resolve(diag);
+    let src = sources.source(0).unwrap_or_default();
+
+    let primary = diag
+        .labels
+        .iter()
+        .find(|label| label.style == LabelStyle::Primary)
+        .or_else(|| diag.labels.first());
+    // Note: the diagnostic has to point somewhere in the Runefile, so
+    // problems in included files are reported as related information
+    let range = primary
+        .filter(|label| label.file_id == 0)
+        .map(|label| label.range.clone())
+        .unwrap_or(0..0);
+
+    let related_information: Vec<_> = diag
+        .labels
+        .iter()
+        .filter(|label| {
+            label.style == LabelStyle::Secondary || label.file_id != 0
+        })
+        .filter_map(|label| {
+            Some(DiagnosticRelatedInformation {
+                location: label_location(
+                    uri,
+                    sources,
+                    label.file_id,
+                    label.range.clone(),
+                )?,
+                message: if label.message.is_empty() {
+                    diag.message.clone()
+                } else {
+                    label.message.clone()
+                },
+            })
+        })
+        .collect();
+
+    let mut message = diag.message.clone();
+    for note in &diag.notes {
+        message.push('\n');
+        message.push_str(note);
+    }
+
+    lsp_types::Diagnostic {
+        range: span_to_range(src, range),
+        severity: Some(match diag.severity {
+            Severity::Bug | Severity::Error => DiagnosticSeverity::Error,
+            Severity::Warning => DiagnosticSeverity::Warning,
+            Severity::Note => DiagnosticSeverity::Information,
+            Severity::Help => DiagnosticSeverity::Hint,
+        }),
+        code: diag.code.clone().map(NumberOrString::String),
+        source: Some("rune".to_string()),
+        message,
+        related_information: if related_information.is_empty() {
+            None
+        } else {
+            Some(related_information)
+        },
+        ..Default::default()
+    }
+}
+
+/// Convert a LSP [`Position`] (a line number and UTF-16 column) to a byte
+/// offset.
+fn position_to_offset(src: &str, position: Position) -> usize {
+    let line_start: usize = src
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = src[line_start..].split('\n').next().unwrap_or_default();
+
+    let mut column = 0;
+    for (i, c) in line.char_indices() {
+        if column >= position.character as usize {
+            return line_start + i;
+        }
+        column += c.len_utf16();
+    }
+
+    line_start + line.len()
+}
+
+fn offset_to_position(src: &str, offset: usize) -> Position {
+    let before = &src[..offset.min(src.len())];
+    let line_start = before.rfind('\n').map_or(0, |ix| ix + 1);
+
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+/// Find where a label points, taking into account that it may be in a file
+/// included by the Runefile.
+fn label_location(
+    uri: &Url,
+    sources: &SourceMap,
+    file_id: usize,
+    range: Range<usize>,
+) -> Option<Location> {
+    let src = sources.source(file_id).ok()?;
+
+    let uri = if file_id == 0 {
+        uri.clone()
+    } else {
+        // Note: included files are named relative to the Runefile
+        let runefile = uri.to_file_path().ok()?;
+        let path = runefile.parent()?.join(sources.name(file_id).ok()?);
+        Url::from_file_path(path).ok()?
+    };
+
+    Some(Location {
+        uri,
+        range: span_to_range(src, range),
+    })
+}
+
+fn span_to_range(src: &str, span: Range<usize>) -> lsp_types::Range {
+    lsp_types::Range {
+        start: offset_to_position(src, span.start),
+        end: offset_to_position(src, span.end),
+    }
+}
@@ -0,0 +1,300 @@
+//! A lightweight, text-based view of a Runefile.
+//!
+//! The compiler only gives us a [`hotg_rune_compiler::parse::DocumentV1`]
+//! when the whole file is valid YAML, which is rarely the case while someone
+//! is typing. Instead, we look at indentation to figure out where each stage
+//! and resource is defined and what the cursor is pointing at.
+
+use std::ops::Range;
+
+/// The sections of a Runefile which contain named items.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Section {
+    Pipeline,
+    Resources,
+}
+
+impl Section {
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "pipeline" => Some(Section::Pipeline),
+            "resources" => Some(Section::Resources),
+            _ => None,
+        }
+    }
+}
+
+/// A stage or resource definition.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Definition {
+    pub(crate) name: String,
+    pub(crate) section: Section,
+    /// Where the item's name is.
+    pub(crate) name_span: Range<usize>,
+    /// The item's name and everything nested underneath it.
+    pub(crate) body: Range<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Outline {
+    pub(crate) definitions: Vec<Definition>,
+}
+
+impl Outline {
+    pub(crate) fn new(src: &str) -> Self {
+        let mut definitions: Vec<Definition> = Vec::new();
+        let mut section = None;
+        let mut item_indent = None;
+
+        for line in lines(src) {
+            if line.is_blank() {
+                continue;
+            }
+
+            if line.indent == 0 {
+                close_last(&mut definitions, line.start);
+                section =
+                    line.key().and_then(|(key, _)| Section::from_key(key));
+                item_indent = None;
+                continue;
+            }
+
+            let section = match section {
+                Some(s) => s,
+                None => continue,
+            };
+            let indent = *item_indent.get_or_insert(line.indent);
+
+            if line.indent != indent {
+                continue;
+            }
+
+            close_last(&mut definitions, line.start);
+
+            if let Some((name, name_span)) = line.key() {
+                definitions.push(Definition {
+                    name: name.to_string(),
+                    section,
+                    name_span,
+                    body: line.start..src.len(),
+                });
+            }
+        }
+
+        Outline { definitions }
+    }
+
+    pub(crate) fn stages(&self) -> impl Iterator<Item = &'_ Definition> + '_ {
+        self.definitions
+            .iter()
+            .filter(|d| d.section == Section::Pipeline)
+    }
+
+    pub(crate) fn resources(
+        &self,
+    ) -> impl Iterator<Item = &'_ Definition> + '_ {
+        self.definitions
+            .iter()
+            .filter(|d| d.section == Section::Resources)
+    }
+
+    pub(crate) fn stage(&self, name: &str) -> Option<&Definition> {
+        self.stages().find(|d| d.name == name)
+    }
+
+    pub(crate) fn resource(&self, name: &str) -> Option<&Definition> {
+        self.resources().find(|d| d.name == name)
+    }
+
+    /// The stage whose definition contains a particular byte offset.
+    pub(crate) fn enclosing_stage(&self, offset: usize) -> Option<&Definition> {
+        self.stages().find(|d| d.body.contains(&offset))
+    }
+}
+
+/// Close off the previous definition's body at the start of this line.
+fn close_last(definitions: &mut Vec<Definition>, end: usize) {
+    if let Some(last) = definitions.last_mut() {
+        if last.body.end > end {
+            last.body.end = end;
+        }
+    }
+}
+
+/// Something the cursor might be pointing at.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Reference<'a> {
+    /// A stage, possibly with an output index (e.g. `fft` or `fft.1`).
+    Stage { name: &'a str, index: Option<usize> },
+    /// A resource (e.g. `$wordlist`).
+    Resource(&'a str),
+}
+
+impl<'a> Reference<'a> {
+    pub(crate) fn parse(word: &'a str) -> Option<Self> {
+        if let Some(name) = word.strip_prefix('$') {
+            return if name.is_empty() {
+                None
+            } else {
+                Some(Reference::Resource(name))
+            };
+        }
+
+        let (name, index) = match word.rsplit_once('.') {
+            Some((name, index)) => (name, Some(index.parse().ok()?)),
+            None => (word, None),
+        };
+
+        if name.is_empty() {
+            None
+        } else {
+            Some(Reference::Stage { name, index })
+        }
+    }
+}
+
+/// What kind of completions make sense at a particular location.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum CompletionContext {
+    /// One of the names in a stage's `inputs` list.
+    Input,
+    /// A stage's `capability` field.
+    Capability,
+    /// A stage's `out` field.
+    Output,
+    /// A `$resource` reference.
+    Resource,
+}
+
+impl CompletionContext {
+    pub(crate) fn at(src: &str, offset: usize) -> Option<Self> {
+        let word = word_at(src, offset);
+        if src[word].starts_with('$') {
+            return Some(CompletionContext::Resource);
+        }
+
+        let current = lines(src).find(|l| l.contains(offset))?;
+        let before_cursor = src[current.start..offset].trim_start();
+
+        if before_cursor.starts_with("capability:") {
+            return Some(CompletionContext::Capability);
+        }
+        if before_cursor.starts_with("out:") {
+            return Some(CompletionContext::Output);
+        }
+        if before_cursor.starts_with("inputs:") {
+            // Probably a flow sequence, like "inputs: [audio, fft"
+            return Some(CompletionContext::Input);
+        }
+
+        if before_cursor.starts_with('-') {
+            // Find the key this list item belongs to. Note that YAML lets
+            // list items be indented at the same level as their key.
+            let parent = lines(&src[..current.start])
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .filter(|l| !l.is_blank())
+                .find(|l| {
+                    l.indent <= current.indent && !l.trimmed().starts_with('-')
+                })?;
+
+            if let Some(("inputs", _)) = parent.key() {
+                return Some(CompletionContext::Input);
+            }
+        }
+
+        None
+    }
+}
+
+/// Get the span of the identifier-like word surrounding a byte offset.
+pub(crate) fn word_at(src: &str, offset: usize) -> Range<usize> {
+    let is_word = |c: char| c.is_alphanumeric() || "$_-.".contains(c);
+    let offset = offset.min(src.len());
+
+    let start = src[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word(c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(offset);
+    let end = src[offset..]
+        .char_indices()
+        .find(|&(_, c)| !is_word(c))
+        .map(|(i, _)| offset + i)
+        .unwrap_or(src.len());
+
+    start..end
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Line<'a> {
+    src: &'a str,
+    /// The offset of the first byte in the line.
+    start: usize,
+    /// The line's text, without the trailing newline.
+    text: &'a str,
+    indent: usize,
+}
+
+impl<'a> Line<'a> {
+    fn trimmed(&self) -> &'a str { self.text.trim() }
+
+    fn is_blank(&self) -> bool {
+        let trimmed = self.trimmed();
+        trimmed.is_empty() || trimmed.starts_with('#')
+    }
+
+    fn contains(&self, offset: usize) -> bool {
+        // Note: the cursor may be sitting just after the last character
+        let end = self.start + self.text.len();
+        self.start <= offset && offset <= end
+    }
+
+    /// If this line starts a mapping entry (`key: ...`), get the key and
+    /// where it is.
+    fn key(&self) -> Option<(&'a str, Range<usize>)> {
+        let text = &self.text[self.indent..];
+        let colon = text.find(':')?;
+        let key = text[..colon].trim_end();
+        let quoted = key.len() >= 2
+            && (key.starts_with('"') && key.ends_with('"')
+                || key.starts_with('\'') && key.ends_with('\''));
+
+        let (key, offset) = if quoted {
+            (&key[1..key.len() - 1], 1)
+        } else {
+            (key, 0)
+        };
+
+        if key.is_empty() || key.starts_with(|c| "-#[{".contains(c)) {
+            return None;
+        }
+
+        let start = self.start + self.indent + offset;
+        debug_assert_eq!(&self.src[start..start + key.len()], key);
+
+        Some((key, start..start + key.len()))
+    }
+}
+
+fn lines(src: &str) -> impl Iterator<Item = Line<'_>> + '_ {
+    let mut start = 0;
+
+    src.split('\n').map(move |text| {
+        let line_start = start;
+        start += text.len() + 1;
+
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let indent = text.len() - text.trim_start_matches(' ').len();
+
+        Line {
+            src,
+            start: line_start,
+            text,
+            indent,
+        }
+    })
+}
@@ -0,0 +1,261 @@
+mod onnx;
+mod tensorflow;
+mod tflite;
+
+use std::{
+    fmt::{self, Display, Formatter, Write as _},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+use strum::VariantNames;
+
+use crate::Format;
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct ModelInfo {
+    #[structopt(
+        help = "The model to inspect (TensorFlow Lite, ONNX, or a frozen \
+                TensorFlow graph)",
+        parse(from_os_str)
+    )]
+    file: PathBuf,
+    #[structopt(
+        short,
+        long,
+        help = "The format to print output in",
+        default_value = "text",
+        possible_values = Format::VARIANTS,
+        parse(try_from_str)
+    )]
+    format: Format,
+    #[structopt(
+        long,
+        help = "Print a Runefile stage which uses this model instead"
+    )]
+    runefile_stage: bool,
+}
+
+impl ModelInfo {
+    pub fn execute(self) -> Result<(), Error> {
+        let raw = std::fs::read(&self.file).with_context(|| {
+            format!("Unable to read \"{}\"", &self.file.display())
+        })?;
+
+        let model_format =
+            ModelFormat::detect(&self.file, &raw).with_context(|| {
+                format!(
+                    "Unable to determine what kind of model \"{}\" is",
+                    self.file.display()
+                )
+            })?;
+        log::debug!("Treating \"{}\" as {}", self.file.display(), model_format);
+
+        let description = model_format.describe(&raw).with_context(|| {
+            format!(
+                "Unable to load \"{}\" as a {} model",
+                self.file.display(),
+                model_format
+            )
+        })?;
+
+        if self.runefile_stage {
+            print!("{}", runefile_stage(&self.file, &description));
+            return Ok(());
+        }
+
+        match self.format {
+            Format::Text => print_info(&description),
+            Format::Json => {
+                let mut stdout = std::io::stdout();
+                serde_json::to_writer_pretty(stdout.lock(), &description)
+                    .context("Unable to print to stdout")?;
+                writeln!(stdout)?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+fn print_info(model: &ModelDescription) {
+    println!("Format: {}", model.format);
+    println!("Ops: {}", model.ops);
+
+    println!("Inputs:");
+    for input in &model.inputs {
+        println!("\t{}", input);
+    }
+
+    println!("Outputs:");
+    for output in &model.outputs {
+        println!("\t{}", output);
+    }
+}
+
+/// Generate a Runefile stage which can be pasted into the `pipeline` section.
+///
+/// The stage's inputs are left commented out because they need to refer to
+/// the stages providing each tensor, which only the user knows.
+fn runefile_stage(path: &Path, model: &ModelDescription) -> String {
+    let mut stage = String::new();
+
+    // Note: writing to a String can't fail
+    let _ = writeln!(stage, "{}:", stage_name(path));
+    let _ = writeln!(
+        stage,
+        "  model: {}",
+        serde_json::to_string(&path.display().to_string()).unwrap()
+    );
+
+    if model.format != ModelFormat::TensorFlowLite {
+        let _ = writeln!(stage, "  args:");
+        let _ = writeln!(stage, "    format: {}", model.format);
+    }
+
+    if !model.inputs.is_empty() {
+        let _ = writeln!(stage, "  # inputs:");
+        for input in &model.inputs {
+            let _ = writeln!(stage, "  #   - TODO # {}", input);
+        }
+    }
+
+    if !model.outputs.is_empty() {
+        let _ = writeln!(stage, "  outputs:");
+        for output in &model.outputs {
+            if output.dims.iter().any(Option::is_none) {
+                let _ = writeln!(
+                    stage,
+                    "    # \"{}\" has dynamic dimensions, which were replaced \
+                     with 1",
+                    output.name
+                );
+            }
+
+            let _ = writeln!(
+                stage,
+                "    - type: {}",
+                output.element_kind.to_uppercase()
+            );
+            let _ = writeln!(stage, "      dimensions:");
+            for dim in &output.dims {
+                let _ = writeln!(stage, "        - {}", dim.unwrap_or(1));
+            }
+        }
+    }
+
+    stage
+}
+
+/// Turn a model's filename into something that can be used as a stage name.
+fn stage_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert_str(0, "model_");
+    }
+
+    name
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize)]
+enum ModelFormat {
+    #[serde(rename = "tensorflow-lite")]
+    TensorFlowLite,
+    #[serde(rename = "onnx")]
+    Onnx,
+    #[serde(rename = "tensorflow")]
+    TensorFlow,
+}
+
+impl ModelFormat {
+    /// Figure out what kind of model we are dealing with, using the file
+    /// extension if possible and falling back to the model's contents.
+    fn detect(path: &Path, raw: &[u8]) -> Option<Self> {
+        let from_extension = match path.extension().and_then(|e| e.to_str()) {
+            Some("tflite") => Some(ModelFormat::TensorFlowLite),
+            Some("onnx") => Some(ModelFormat::Onnx),
+            Some("pb") => Some(ModelFormat::TensorFlow),
+            _ => None,
+        };
+
+        from_extension.or_else(|| ModelFormat::from_contents(raw))
+    }
+
+    fn from_contents(raw: &[u8]) -> Option<Self> {
+        if tflite::is_tflite(raw) {
+            Some(ModelFormat::TensorFlowLite)
+        } else if onnx::is_onnx(raw) {
+            Some(ModelFormat::Onnx)
+        } else if tensorflow::is_graph_def(raw) {
+            Some(ModelFormat::TensorFlow)
+        } else {
+            None
+        }
+    }
+
+    fn describe(self, raw: &[u8]) -> Result<ModelDescription, Error> {
+        match self {
+            ModelFormat::TensorFlowLite => tflite::describe(raw),
+            ModelFormat::Onnx => onnx::describe(raw),
+            ModelFormat::TensorFlow => tensorflow::describe(raw),
+        }
+    }
+}
+
+impl Display for ModelFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Note: these are the names used by a model stage's "format" argument
+        match self {
+            ModelFormat::TensorFlowLite => write!(f, "tensorflow-lite"),
+            ModelFormat::Onnx => write!(f, "onnx"),
+            ModelFormat::TensorFlow => write!(f, "tensorflow"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+struct ModelDescription {
+    format: ModelFormat,
+    inputs: Vec<TensorInfo>,
+    outputs: Vec<TensorInfo>,
+    ops: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+struct TensorInfo {
+    name: String,
+    /// The element type, using Rune's names (e.g. `f32` or `utf8`) where
+    /// possible.
+    element_kind: String,
+    /// The tensor's dimensions, where `None` is a dimension that isn't known
+    /// until runtime.
+    dims: Vec<Option<usize>>,
+}
+
+impl Display for TensorInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}[", self.name, self.element_kind)?;
+
+        for (i, dim) in self.dims.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match dim {
+                Some(d) => write!(f, "{}", d)?,
+                None => write!(f, "?")?,
+            }
+        }
+
+        write!(f, "]")
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::{Context, Error};
+pub(crate) use hotg_rune_compiler::type_check::onnx::is_onnx;
+use hotg_rune_compiler::type_check::onnx::{self, OnnxTensor};
+
+use super::{ModelDescription, ModelFormat, TensorInfo};
+
+pub(crate) fn describe(raw: &[u8]) -> Result<ModelDescription, Error> {
+    let model = onnx::parse(raw).context("Unable to parse the ONNX model")?;
+
+    Ok(ModelDescription {
+        format: ModelFormat::Onnx,
+        inputs: model.inputs.iter().map(tensor_info).collect(),
+        outputs: model.outputs.iter().map(tensor_info).collect(),
+        ops: model.ops,
+    })
+}
+
+fn tensor_info(tensor: &OnnxTensor) -> TensorInfo {
+    let element_kind = match tensor.elem_type {
+        Some(t) => element_kind(t),
+        None => "unknown".to_string(),
+    };
+
+    TensorInfo {
+        name: tensor.name.clone(),
+        element_kind,
+        dims: tensor.dims.clone().unwrap_or_default(),
+    }
+}
+
+/// Convert a `TensorProto.DataType` to Rune's name for it.
+fn element_kind(data_type: i32) -> String {
+    match (onnx::element_type(data_type), data_type) {
+        (Some(element_type), _) => element_type.rune_name().to_string(),
+        (None, 9) => "bool".to_string(),
+        (None, 10) => "f16".to_string(),
+        (None, other) => format!("unknown ({})", other),
+    }
+}
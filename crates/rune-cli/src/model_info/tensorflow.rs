@@ -0,0 +1,196 @@
+//! Just enough of TensorFlow's [`GraphDef`][graph] protobuf schema to find a
+//! frozen graph's inputs and outputs.
+//!
+//! [graph]: https://github.com/tensorflow/tensorflow/blob/master/tensorflow/core/framework/graph.proto
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Error};
+use hotg_rune_core::ElementType;
+use prost::Message;
+
+use super::{ModelDescription, ModelFormat, TensorInfo};
+
+/// Nodes which don't do any computation.
+const NOT_OPERATIONS: &[&str] = &["Placeholder", "Const", "NoOp"];
+
+pub(crate) fn is_graph_def(raw: &[u8]) -> bool {
+    match GraphDef::decode(raw) {
+        Ok(graph) => {
+            !graph.node.is_empty()
+                && graph.node.iter().all(|n| !n.op.is_empty())
+        },
+        Err(_) => false,
+    }
+}
+
+pub(crate) fn describe(raw: &[u8]) -> Result<ModelDescription, Error> {
+    let graph = GraphDef::decode(raw)
+        .context("Unable to parse the TensorFlow graph")?;
+
+    let inputs = graph
+        .node
+        .iter()
+        .filter(|n| n.op == "Placeholder")
+        .map(tensor_info)
+        .collect();
+
+    // A frozen graph's outputs are the nodes nobody else uses.
+    let consumed: HashSet<&str> = graph
+        .node
+        .iter()
+        .flat_map(|n| &n.input)
+        .map(|input| node_name(input))
+        .collect();
+    let outputs = graph
+        .node
+        .iter()
+        .filter(|n| !consumed.contains(n.name.as_str()))
+        .filter(|n| !NOT_OPERATIONS.contains(&n.op.as_str()))
+        .map(tensor_info)
+        .collect();
+
+    let ops = graph
+        .node
+        .iter()
+        .filter(|n| !NOT_OPERATIONS.contains(&n.op.as_str()))
+        .count();
+
+    Ok(ModelDescription {
+        format: ModelFormat::TensorFlow,
+        inputs,
+        outputs,
+        ops,
+    })
+}
+
+/// Get the name of the node an input refers to, stripping the control
+/// dependency marker (`^node`) and output index (`node:1`).
+fn node_name(input: &str) -> &str {
+    let input = input.trim_start_matches('^');
+
+    match input.rfind(':') {
+        Some(index) => &input[..index],
+        None => input,
+    }
+}
+
+fn tensor_info(node: &NodeDef) -> TensorInfo {
+    let data_type = ["dtype", "T", "out_type"]
+        .iter()
+        .find_map(|key| node.attr.get(*key).and_then(|a| a.r#type));
+
+    let shape = node
+        .attr
+        .get("shape")
+        .and_then(|a| a.shape.as_ref())
+        .or_else(|| {
+            node.attr
+                .get("_output_shapes")
+                .and_then(|a| a.list.as_ref())
+                .and_then(|l| l.shape.first())
+        });
+
+    let dims = match shape {
+        Some(shape) if !shape.unknown_rank => shape
+            .dim
+            .iter()
+            .map(|d| {
+                if d.size > 0 {
+                    Some(d.size as usize)
+                } else {
+                    None
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    TensorInfo {
+        name: node.name.clone(),
+        element_kind: data_type
+            .map(element_kind)
+            .unwrap_or_else(|| "unknown".to_string()),
+        dims,
+    }
+}
+
+/// Convert a TensorFlow `DataType` to Rune's name for it.
+fn element_kind(data_type: i32) -> String {
+    // Reference types (e.g. DT_FLOAT_REF) are offset by 100
+    let data_type = if data_type > 100 {
+        data_type - 100
+    } else {
+        data_type
+    };
+
+    let element_type = match data_type {
+        1 => ElementType::F32,
+        2 => ElementType::F64,
+        3 => ElementType::I32,
+        4 => ElementType::U8,
+        5 => ElementType::I16,
+        6 => ElementType::I8,
+        7 => ElementType::String,
+        9 => ElementType::I64,
+        17 => ElementType::U16,
+        22 => ElementType::U32,
+        23 => ElementType::U64,
+        10 => return "bool".to_string(),
+        19 => return "f16".to_string(),
+        other => return format!("unknown ({})", other),
+    };
+
+    element_type.rune_name().to_string()
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct GraphDef {
+    #[prost(message, repeated, tag = "1")]
+    node: Vec<NodeDef>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct NodeDef {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    op: String,
+    #[prost(string, repeated, tag = "3")]
+    input: Vec<String>,
+    #[prost(map = "string, message", tag = "5")]
+    attr: HashMap<String, AttrValue>,
+}
+
+/// The value of a node attribute. In the real schema these fields are all
+/// part of a `oneof`.
+#[derive(Clone, PartialEq, Message)]
+struct AttrValue {
+    #[prost(message, optional, tag = "1")]
+    list: Option<ListValue>,
+    #[prost(int32, optional, tag = "6")]
+    r#type: Option<i32>,
+    #[prost(message, optional, tag = "7")]
+    shape: Option<TensorShapeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ListValue {
+    #[prost(message, repeated, tag = "7")]
+    shape: Vec<TensorShapeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorShapeProto {
+    #[prost(message, repeated, tag = "2")]
+    dim: Vec<Dim>,
+    #[prost(bool, tag = "3")]
+    unknown_rank: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Dim {
+    /// The dimension's size, or `-1` if it is unknown.
+    #[prost(int64, tag = "1")]
+    size: i64,
+}
@@ -0,0 +1,36 @@
+use anyhow::{Context, Error};
+pub(crate) use hotg_rune_compiler::type_check::tflite::is_tflite;
+use hotg_rune_compiler::type_check::tflite::{self, TfliteTensor};
+
+use super::{ModelDescription, ModelFormat, TensorInfo};
+
+pub(crate) fn describe(raw: &[u8]) -> Result<ModelDescription, Error> {
+    let model = tflite::parse(raw)
+        .context("Unable to parse the TensorFlow Lite model")?;
+
+    Ok(ModelDescription {
+        format: ModelFormat::TensorFlowLite,
+        inputs: model.inputs.iter().map(tensor_info).collect(),
+        outputs: model.outputs.iter().map(tensor_info).collect(),
+        ops: model.ops,
+    })
+}
+
+fn tensor_info(tensor: &TfliteTensor) -> TensorInfo {
+    TensorInfo {
+        name: tensor.name.clone(),
+        element_kind: element_kind(tensor.tensor_type),
+        dims: tensor.dims.clone().unwrap_or_default(),
+    }
+}
+
+/// Convert a `TensorType` to Rune's name for it.
+fn element_kind(tensor_type: i8) -> String {
+    match (tflite::element_type(tensor_type), tensor_type) {
+        (Some(element_type), _) => element_type.rune_name().to_string(),
+        (None, 1) => "f16".to_string(),
+        (None, 6) => "bool".to_string(),
+        (None, 8) => "complex64".to_string(),
+        (None, other) => format!("unknown ({})", other),
+    }
+}
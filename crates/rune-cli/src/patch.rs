@@ -0,0 +1,297 @@
+use std::{
+    collections::BTreeMap, convert::TryFrom, path::PathBuf, str::FromStr,
+};
+
+use anyhow::{Context, Error};
+use hotg_rune_compiler::{
+    codegen::{RuneGraph, RESOURCE_CUSTOM_SECTION},
+    parse::ResourceType,
+};
+
+use crate::inspect::Metadata;
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct Patch {
+    /// Where to write the patched Rune (defaults to
+    /// "<rune-name>-patched.rune").
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Set a resource's value (e.g. "--set-resource THRESHOLD=0.5").
+    #[structopt(long = "set-resource", parse(try_from_str))]
+    set_resource: Vec<Assignment>,
+    /// Set a resource's value to the contents of a file (e.g.
+    /// "--set-resource-file LABELS=labels.txt").
+    #[structopt(long = "set-resource-file", parse(try_from_str))]
+    set_resource_file: Vec<Assignment>,
+    /// The compiled Rune to patch.
+    #[structopt(parse(from_os_str))]
+    rune: PathBuf,
+}
+
+impl Patch {
+    pub fn execute(self) -> Result<(), Error> {
+        let wasm = std::fs::read(&self.rune).with_context(|| {
+            format!("Unable to read \"{}\"", self.rune.display())
+        })?;
+
+        let Metadata { rune, .. } = Metadata::from_wasm_binary(&wasm)
+            .context("Unable to parse metadata from the WebAssembly module")?;
+        let rune =
+            rune.context("Unable to find the Rune graph custom section")?;
+
+        let new_values = self.new_values(&rune)?;
+        let patched = replace_resources(&wasm, &new_values)
+            .context("Unable to patch the WebAssembly module")?;
+
+        let output = self.output()?;
+        std::fs::write(&output, &patched).with_context(|| {
+            format!("Unable to write to \"{}\"", output.display())
+        })?;
+
+        log::info!(
+            "Wrote the patched Rune to \"{}\" ({} bytes)",
+            output.display(),
+            patched.len()
+        );
+
+        Ok(())
+    }
+
+    /// Load the new value for each resource and make sure it is compatible
+    /// with what the Rune expects.
+    fn new_values(
+        &self,
+        rune: &RuneGraph,
+    ) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        let mut values = BTreeMap::new();
+
+        for Assignment { name, value } in &self.set_resource {
+            values.insert(name.clone(), value.clone().into_bytes());
+        }
+
+        for Assignment { name, value } in &self.set_resource_file {
+            let data = std::fs::read(value)
+                .with_context(|| format!("Unable to read \"{}\"", value))?;
+            values.insert(name.clone(), data);
+        }
+
+        for (name, value) in &values {
+            let resource = rune
+                .resources
+                .iter()
+                .find(|(n, _)| n.as_str() == name)
+                .map(|(_, r)| r)
+                .with_context(|| {
+                    format!("The Rune doesn't have a \"{}\" resource", name)
+                })?;
+
+            if resource.ty == ResourceType::String
+                && std::str::from_utf8(value).is_err()
+            {
+                anyhow::bail!(
+                    "The \"{}\" resource is a string, but the new value isn't \
+                     valid UTF-8",
+                    name
+                );
+            }
+        }
+
+        Ok(values)
+    }
+
+    fn output(&self) -> Result<PathBuf, Error> {
+        if let Some(output) = &self.output {
+            return Ok(output.clone());
+        }
+
+        let stem = self
+            .rune
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .context("Unable to determine the Rune's name")?;
+        let filename = format!("{}-patched.rune", stem);
+
+        Ok(match self.rune.parent() {
+            Some(parent) => parent.join(filename),
+            None => PathBuf::from(filename),
+        })
+    }
+}
+
+/// A `NAME=value` pair.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Assignment {
+    pub(crate) name: String,
+    pub(crate) value: String,
+}
+
+impl FromStr for Assignment {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .context("Expected something like \"NAME=value\"")?;
+        let name = name.trim().trim_start_matches('$');
+
+        if name.is_empty() {
+            anyhow::bail!("The name can't be empty");
+        }
+
+        Ok(Assignment {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+const WASM_HEADER_LEN: usize = 8;
+const CUSTOM_SECTION_ID: u8 = 0;
+
+/// Rewrite a WebAssembly module, replacing the resource custom sections for
+/// each of the `new_values`.
+///
+/// Resources which don't already have a custom section (i.e. they had no
+/// default value) will be added to the end of the module.
+fn replace_resources(
+    wasm: &[u8],
+    new_values: &BTreeMap<String, Vec<u8>>,
+) -> Result<Vec<u8>, Error> {
+    if wasm.len() < WASM_HEADER_LEN || &wasm[..4] != b"\0asm" {
+        anyhow::bail!("Not a WebAssembly module");
+    }
+
+    let mut patched = Vec::with_capacity(wasm.len());
+    patched.extend_from_slice(&wasm[..WASM_HEADER_LEN]);
+
+    let mut remaining_values = new_values.clone();
+    let mut rest = &wasm[WASM_HEADER_LEN..];
+
+    while !rest.is_empty() {
+        let id = rest[0];
+        let (size, after_size) =
+            read_leb128(&rest[1..]).context("Invalid section length")?;
+        let payload = after_size
+            .get(..size)
+            .context("The section extends past the end of the module")?;
+        let section_len = rest.len() - after_size.len() + size;
+        let section = &rest[..section_len];
+        rest = &rest[section_len..];
+
+        if id == CUSTOM_SECTION_ID {
+            if let Some(resource_section) =
+                patch_resource_section(payload, &mut remaining_values)?
+            {
+                patched.extend(resource_section);
+                continue;
+            }
+        }
+
+        patched.extend_from_slice(section);
+    }
+
+    for (name, value) in &remaining_values {
+        patched.extend(resource_section(name, value)?);
+    }
+
+    Ok(patched)
+}
+
+/// If this custom section contains resources that need to be patched, return
+/// the replacement custom section.
+fn patch_resource_section(
+    payload: &[u8],
+    new_values: &mut BTreeMap<String, Vec<u8>>,
+) -> Result<Option<Vec<u8>>, Error> {
+    let (name_len, rest) =
+        read_leb128(payload).context("Invalid custom section name")?;
+    let name = rest
+        .get(..name_len)
+        .context("Invalid custom section name")?;
+
+    if name != RESOURCE_CUSTOM_SECTION.as_bytes() {
+        return Ok(None);
+    }
+
+    let mut data = &rest[name_len..];
+    let mut resources = Vec::new();
+    let mut modified = false;
+
+    while !data.is_empty() {
+        let (name, value, rest) = hotg_rune_core::decode_inline_resource(data)
+            .context("Unable to decode an embedded resource")?;
+
+        match new_values.remove(name) {
+            Some(new_value) => {
+                log::debug!("Replacing the \"{}\" resource", name);
+                resources.push((name, new_value));
+                modified = true;
+            },
+            None => resources.push((name, value.to_vec())),
+        }
+
+        data = rest;
+    }
+
+    if !modified {
+        return Ok(None);
+    }
+
+    let mut section = Vec::new();
+    for (name, value) in resources {
+        section.extend(resource_section(name, &value)?);
+    }
+
+    Ok(Some(section))
+}
+
+/// Create a resource custom section, using the same format as the compiler.
+fn resource_section(name: &str, value: &[u8]) -> Result<Vec<u8>, Error> {
+    let name_len = u32::try_from(name.len())?;
+    let value_len = u32::try_from(value.len())?;
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&name_len.to_be_bytes());
+    data.extend_from_slice(name.as_bytes());
+    data.extend_from_slice(&value_len.to_be_bytes());
+    data.extend_from_slice(value);
+
+    let mut payload = Vec::new();
+    write_leb128(&mut payload, RESOURCE_CUSTOM_SECTION.len());
+    payload.extend_from_slice(RESOURCE_CUSTOM_SECTION.as_bytes());
+    payload.extend(data);
+
+    let mut section = vec![CUSTOM_SECTION_ID];
+    write_leb128(&mut section, payload.len());
+    section.extend(payload);
+
+    Ok(section)
+}
+
+fn read_leb128(bytes: &[u8]) -> Option<(usize, &[u8])> {
+    let mut value = 0_usize;
+
+    for (i, &byte) in bytes.iter().enumerate().take(5) {
+        value |= usize::from(byte & 0x7f) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            return Some((value, &bytes[i + 1..]));
+        }
+    }
+
+    None
+}
+
+fn write_leb128(buffer: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            buffer.push(byte);
+            return;
+        }
+
+        buffer.push(byte | 0x80);
+    }
+}
@@ -10,6 +10,8 @@ use regex::Regex;
 use structopt::StructOpt;
 use strum::VariantNames;
 
+use crate::config::{Config, RunConfig};
+
 #[derive(Debug, Clone, PartialEq, StructOpt)]
 pub struct Run {
     #[structopt(
@@ -47,11 +49,10 @@ pub struct Run {
     random: Option<u64>,
     #[structopt(
         long,
-        help = "The WebAssembly engine to use",
+        help = "The WebAssembly engine to use [default: wasmer]",
         possible_values = Engine::VARIANTS,
-        default_value = "wasmer",
     )]
-    engine: Engine,
+    engine: Option<Engine>,
     #[structopt(
         long = "file-resource",
         parse(try_from_str),
@@ -64,12 +65,93 @@ pub struct Run {
         help = "Use the provided string as a resource"
     )]
     string_resources: Vec<StringResource>,
+    #[structopt(
+        long,
+        help = "Only run one of the Rune's named pipelines instead of the \
+                whole thing"
+    )]
+    pipeline: Option<String>,
     #[structopt(help = "The Rune to run")]
     rune: PathBuf,
 }
 
 impl Run {
+    /// Use defaults from a `rune.toml` file for anything that wasn't
+    /// specified on the command-line.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        let RunConfig {
+            engine,
+            image,
+            sound,
+            accelerometer,
+            raw,
+            random,
+            resources,
+            file_resources,
+        } = &config.run;
+
+        self.engine = self.engine.or(*engine);
+        self.random = self.random.or(*random);
+
+        let inputs = [
+            (&mut self.image, image),
+            (&mut self.sound, sound),
+            (&mut self.accelerometer, accelerometer),
+            (&mut self.raw, raw),
+        ];
+        for (paths, defaults) in inputs {
+            if paths.is_empty() {
+                paths.extend(defaults.iter().cloned());
+            }
+        }
+
+        // Resources from the command-line are loaded last, so they'll
+        // overwrite anything with the same name.
+        let string_resources =
+            resources.iter().map(|(name, value)| StringResource {
+                name: name.clone(),
+                value: value.clone(),
+            });
+        self.string_resources.splice(0..0, string_resources);
+
+        let file_resources =
+            file_resources.iter().map(|(name, path)| FileResource {
+                name: name.clone(),
+                path: path.clone(),
+            });
+        self.file_resources.splice(0..0, file_resources);
+
+        self
+    }
+
     pub fn execute(self) -> Result<(), Error> {
+        let outputs = self.predict()?;
+        println!("{}", outputs);
+
+        Ok(())
+    }
+
+    /// Run the Rune, returning its outputs as JSON.
+    pub(crate) fn predict(self) -> Result<serde_json::Value, Error> {
+        let pipeline = self.pipeline.clone();
+        let mut runtime = self.prepare()?;
+
+        match pipeline {
+            Some(name) => {
+                runtime.predict_pipeline(&name).with_context(|| {
+                    format!("Running the \"{}\" pipeline failed", name)
+                })?
+            },
+            None => runtime.predict().context("Prediction failed")?,
+        }
+
+        serde_json::to_value(runtime.output_tensors())
+            .context("Unable to serialize the output tensors to JSON")
+    }
+
+    /// Load the Rune and provide it with all its resources and inputs so it
+    /// is ready to be run.
+    pub(crate) fn prepare(self) -> Result<Runtime, Error> {
         log::info!("Running rune: {}", self.rune.display());
 
         let rune = std::fs::read(&self.rune).with_context(|| {
@@ -86,15 +168,7 @@ impl Run {
         log::debug!("Loading capabilities {:?}", caps);
         runtime.input_tensors().extend(self.load_inputs(caps)?);
 
-        runtime.predict().context("Prediction failed")?;
-
-        let outputs = runtime.output_tensors();
-
-        let serialized = serde_json::to_string(outputs)
-            .context("Unable to serialize the output tensors to JSON")?;
-        println!("{}", serialized);
-
-        Ok(())
+        Ok(runtime)
     }
 
     fn load_inputs(
@@ -169,7 +243,7 @@ impl Run {
         &self,
         rune: &[u8],
     ) -> Result<Runtime, LoadError> {
-        match self.engine {
+        match self.engine.unwrap_or_default() {
             Engine::Wasm3 => Runtime::wasm3(rune),
             Engine::Wasmer => Runtime::wasmer(rune),
         }
@@ -234,22 +308,57 @@ impl FromStr for StringResource {
 
 fn parse_key_value_pair(s: &str) -> Result<(&str, &str), Error> {
     static PATTERN: Lazy<Regex> =
-        Lazy::new(|| Regex::new(r"([a-zA-Z_][a-zA-Z0-9]*)=(.*)").unwrap());
+        Lazy::new(|| Regex::new(r"^([a-zA-Z_][a-zA-Z0-9_]*)=(.*)$").unwrap());
 
     let captures = PATTERN
         .captures(s)
         .context("Expected a resource in the form \"NAME=value\"")?;
-    let key = captures.get(0).unwrap().as_str();
-    let value = captures.get(1).unwrap().as_str();
+    // Note: capture 0 is the entire match
+    let key = captures.get(1).unwrap().as_str();
+    let value = captures.get(2).unwrap().as_str();
 
     Ok((key, value))
 }
 
 #[derive(
-    Debug, Copy, Clone, PartialEq, strum::EnumVariantNames, strum::EnumString,
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    strum::EnumVariantNames,
+    strum::EnumString,
+    serde::Serialize,
+    serde::Deserialize,
 )]
 #[strum(serialize_all = "kebab-case")]
-enum Engine {
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Engine {
     Wasm3,
     Wasmer,
 }
+
+impl Default for Engine {
+    fn default() -> Self { Engine::Wasmer }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_resources_from_the_command_line() {
+        assert_eq!(
+            parse_key_value_pair("LABELS=cat").unwrap(),
+            ("LABELS", "cat")
+        );
+        assert_eq!(
+            parse_key_value_pair("MY_LABELS=a=b").unwrap(),
+            ("MY_LABELS", "a=b")
+        );
+        assert_eq!(parse_key_value_pair("EMPTY=").unwrap(), ("EMPTY", ""));
+
+        assert!(parse_key_value_pair("1LABELS=cat").is_err());
+        assert!(parse_key_value_pair("LA-BELS=cat").is_err());
+        assert!(parse_key_value_pair("LABELS").is_err());
+    }
+}
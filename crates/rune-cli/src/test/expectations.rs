@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// A file containing text which should appear in the output of a test.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExpectedOutput {
+    pub path: PathBuf,
+    pub stream: Stream,
+    pub text: String,
+}
+
+impl ExpectedOutput {
+    /// Load all the `*.stdout` and `*.stderr` files in a directory.
+    pub(crate) fn load_all(directory: &Path) -> Result<Vec<Self>, Error> {
+        let mut expected = Vec::new();
+
+        for entry in directory.read_dir().with_context(|| {
+            format!("Unable to read \"{}\"", directory.display())
+        })? {
+            let path = entry?.path();
+
+            let stream = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("stdout") => Stream::Stdout,
+                Some("stderr") => Stream::Stderr,
+                _ => continue,
+            };
+
+            let text = std::fs::read_to_string(&path).with_context(|| {
+                format!("Unable to read \"{}\"", path.display())
+            })?;
+
+            expected.push(ExpectedOutput { path, stream, text });
+        }
+
+        expected.sort_by(|left, right| left.path.cmp(&right.path));
+
+        Ok(expected)
+    }
+
+    pub(crate) fn check(&self, actual: &str, tolerance: f64) -> bool {
+        contains(actual, &self.text, tolerance)
+    }
+}
+
+/// Check whether the `expected` text appears somewhere in `actual`.
+///
+/// Differences in whitespace are ignored, and numbers are considered equal
+/// when they are within `tolerance` of each other (relative to the size of the
+/// number, for anything bigger than `1.0`).
+pub(crate) fn contains(actual: &str, expected: &str, tolerance: f64) -> bool {
+    let expected = expected.trim();
+
+    if actual.contains(expected) {
+        return true;
+    }
+
+    let mut pattern = String::new();
+    let mut expected_numbers = Vec::new();
+    let mut last_end = 0;
+
+    for number in NUMBER.find_iter(expected) {
+        pattern.push_str(&literal(&expected[last_end..number.start()]));
+        pattern.push_str(&format!("({})", NUMBER.as_str()));
+        expected_numbers.push(number.as_str());
+        last_end = number.end();
+    }
+    pattern.push_str(&literal(&expected[last_end..]));
+
+    let re = match Regex::new(&pattern) {
+        Ok(re) => re,
+        // The expected output was too big to turn into a regex
+        Err(_) => return false,
+    };
+
+    let mut candidates = re.captures_iter(actual);
+
+    candidates.any(|captures| {
+        captures.iter().skip(1).zip(&expected_numbers).all(
+            |(actual, expected)| match actual {
+                Some(actual) => {
+                    numbers_match(actual.as_str(), expected, tolerance)
+                },
+                None => false,
+            },
+        )
+    })
+}
+
+static NUMBER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"[-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?").unwrap()
+});
+
+/// Turn some text into a regex which matches it literally, ignoring any
+/// differences in whitespace.
+fn literal(text: &str) -> String {
+    static WHITESPACE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
+
+    WHITESPACE
+        .split(text)
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(r"\s*")
+}
+
+fn numbers_match(actual: &str, expected: &str, tolerance: f64) -> bool {
+    if actual == expected {
+        return true;
+    }
+
+    match (actual.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(actual), Ok(expected)) => {
+            let scale = actual.abs().max(expected.abs()).max(1.0);
+            (actual - expected).abs() <= tolerance * scale
+        },
+        _ => false,
+    }
+}
@@ -0,0 +1,124 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::{Context, Error};
+
+use super::{Outcome, TestResult};
+
+/// Save the test results as a JUnit XML report.
+pub(crate) fn write(path: &Path, results: &[TestResult]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| {
+            format!("Unable to create the \"{}\" directory", parent.display())
+        })?;
+    }
+
+    std::fs::write(path, render(results))
+        .with_context(|| format!("Unable to write to \"{}\"", path.display()))
+}
+
+fn render(results: &[TestResult]) -> String {
+    let failures = results
+        .iter()
+        .filter(|r| matches!(r.outcome, Outcome::Fail(_)))
+        .count();
+    let skipped = results
+        .iter()
+        .filter(|r| matches!(r.outcome, Outcome::Skipped))
+        .count();
+    let time: f64 = results.iter().map(|r| r.duration.as_secs_f64()).sum();
+
+    let mut xml = String::new();
+
+    // Note: writing to a String can't fail
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<testsuites tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+        results.len(),
+        failures,
+        skipped,
+        time
+    );
+    let _ = writeln!(
+        xml,
+        r#"  <testsuite name="rune" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+        results.len(),
+        failures,
+        skipped,
+        time
+    );
+
+    for result in results {
+        let _ = write!(
+            xml,
+            r#"    <testcase name="{}" classname="{}" time="{:.3}""#,
+            escape(&result.name),
+            escape(&result.expectation.to_string()),
+            result.duration.as_secs_f64()
+        );
+
+        match &result.outcome {
+            Outcome::Pass => {
+                let _ = writeln!(xml, " />");
+            },
+            Outcome::Skipped => {
+                let _ = writeln!(xml, ">");
+                let _ = writeln!(xml, "      <skipped />");
+                let _ = writeln!(xml, "    </testcase>");
+            },
+            Outcome::Fail(errors) => {
+                let message = errors.first().map(String::as_str).unwrap_or("");
+                let message = message.lines().next().unwrap_or("");
+
+                let _ = writeln!(xml, ">");
+                let _ = writeln!(
+                    xml,
+                    r#"      <failure message="{}">{}</failure>"#,
+                    escape(message),
+                    escape(&errors.join("\n\n"))
+                );
+
+                if let Some(output) = &result.output {
+                    let _ = writeln!(
+                        xml,
+                        "      <system-out>{}</system-out>",
+                        escape(&String::from_utf8_lossy(&output.stdout))
+                    );
+                    let _ = writeln!(
+                        xml,
+                        "      <system-err>{}</system-err>",
+                        escape(&String::from_utf8_lossy(&output.stderr))
+                    );
+                }
+
+                let _ = writeln!(xml, "    </testcase>");
+            },
+        }
+    }
+
+    let _ = writeln!(xml, "  </testsuite>");
+    let _ = writeln!(xml, "</testsuites>");
+
+    xml
+}
+
+/// Escape text so it can be used in XML attributes and elements.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters (e.g. terminal colours) aren't allowed in XML
+            '\n' | '\r' | '\t' => escaped.push(c),
+            c if c.is_control() => {},
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
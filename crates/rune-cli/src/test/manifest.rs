@@ -0,0 +1,184 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+
+/// The name of the optional file used to configure a test.
+pub(crate) const MANIFEST_FILE: &str = "test.yml";
+
+/// Per-test settings, loaded from a `test.yml` file next to the Runefile.
+#[derive(Debug, Default, Clone, PartialEq, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Manifest {
+    /// What should happen when the test is executed.
+    pub expect: Expectation,
+    /// The inputs to pass to the Rune. If not provided, inputs are
+    /// discovered based on the files in the test's directory.
+    pub inputs: Option<Inputs>,
+    /// Resources which should be set to a string value.
+    pub resources: BTreeMap<String, String>,
+    /// Resources which should be loaded from a file.
+    pub file_resources: BTreeMap<String, PathBuf>,
+    /// How close numbers in the output need to be to the expected value.
+    pub tolerance: Option<f64>,
+    /// The WebAssembly engine to run this test with.
+    pub engine: Option<String>,
+}
+
+impl Manifest {
+    pub(crate) fn load(directory: &Path) -> Result<Self, Error> {
+        let path = directory.join(MANIFEST_FILE);
+
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+
+        let src = std::fs::read_to_string(&path).with_context(|| {
+            format!("Unable to read \"{}\"", path.display())
+        })?;
+
+        serde_yaml::from_str(&src)
+            .with_context(|| format!("Unable to parse \"{}\"", path.display()))
+    }
+
+    /// The arguments to pass to `rune run`.
+    pub(crate) fn run_args(
+        &self,
+        directory: &Path,
+    ) -> Result<Vec<OsString>, Error> {
+        let inputs = match &self.inputs {
+            Some(inputs) => inputs.clone(),
+            None => Inputs::discover(directory)?,
+        };
+
+        let mut args = inputs.args(directory);
+
+        for (name, value) in &self.resources {
+            args.push("--string-resource".into());
+            args.push(format!("{}={}", name, value).into());
+        }
+
+        for (name, path) in &self.file_resources {
+            let mut arg = OsString::from(format!("{}=", name));
+            arg.push(directory.join(path));
+            args.push("--file-resource".into());
+            args.push(arg);
+        }
+
+        Ok(args)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Expectation {
+    CompilePass,
+    CompileFail,
+    RunPass,
+    RunFail,
+}
+
+impl Expectation {
+    pub(crate) fn should_run(self) -> bool {
+        matches!(self, Expectation::RunPass | Expectation::RunFail)
+    }
+
+    pub(crate) fn should_succeed(self) -> bool {
+        matches!(self, Expectation::CompilePass | Expectation::RunPass)
+    }
+}
+
+impl Default for Expectation {
+    fn default() -> Self { Expectation::RunPass }
+}
+
+impl Display for Expectation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expectation::CompilePass => write!(f, "compile-pass"),
+            Expectation::CompileFail => write!(f, "compile-fail"),
+            Expectation::RunPass => write!(f, "run-pass"),
+            Expectation::RunFail => write!(f, "run-fail"),
+        }
+    }
+}
+
+/// Inputs for the Rune's capabilities, mirroring the flags accepted by
+/// `rune run`.
+#[derive(Debug, Default, Clone, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Inputs {
+    pub image: Vec<PathBuf>,
+    pub sound: Vec<PathBuf>,
+    pub accelerometer: Vec<PathBuf>,
+    pub raw: Vec<PathBuf>,
+    pub random: Option<u64>,
+}
+
+impl Inputs {
+    /// Guess which inputs to use based on the extensions of the files in a
+    /// directory.
+    pub(crate) fn discover(directory: &Path) -> Result<Self, Error> {
+        let mut entries = Vec::new();
+
+        for entry in directory.read_dir().with_context(|| {
+            format!("Unable to read \"{}\"", directory.display())
+        })? {
+            entries.push(entry?.path());
+        }
+
+        entries.sort();
+
+        let mut inputs = Inputs::default();
+
+        for path in entries {
+            let bucket = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("png" | "jpg") => &mut inputs.image,
+                Some("wav") => &mut inputs.sound,
+                Some("csv") => &mut inputs.accelerometer,
+                Some("bin") => &mut inputs.raw,
+                _ => continue,
+            };
+            bucket.push(path);
+        }
+
+        Ok(inputs)
+    }
+
+    fn args(&self, directory: &Path) -> Vec<OsString> {
+        let Inputs {
+            image,
+            sound,
+            accelerometer,
+            raw,
+            random,
+        } = self;
+
+        let mut args = Vec::new();
+
+        let files = [
+            ("--image", image),
+            ("--sound", sound),
+            ("--accelerometer", accelerometer),
+            ("--raw", raw),
+        ];
+
+        for (flag, paths) in files {
+            for path in paths {
+                args.push(flag.into());
+                args.push(directory.join(path).into_os_string());
+            }
+        }
+
+        if let Some(seed) = random {
+            args.push("--random".into());
+            args.push(seed.to_string().into());
+        }
+
+        args
+    }
+}
@@ -0,0 +1,410 @@
+mod expectations;
+mod junit;
+mod manifest;
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error};
+use walkdir::WalkDir;
+
+use self::{
+    expectations::{ExpectedOutput, Stream},
+    manifest::{Expectation, Manifest},
+};
+use crate::Unstable;
+
+#[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
+pub struct Test {
+    /// Update the expected output files to match the actual output.
+    #[structopt(long)]
+    bless: bool,
+    /// Only run tests whose name contains one of these strings.
+    #[structopt(short, long = "filter")]
+    filters: Vec<String>,
+    /// Write a JUnit XML report to this file.
+    #[structopt(long, parse(from_os_str))]
+    junit: Option<PathBuf>,
+    /// How close numbers in the output need to be to their expected value.
+    #[structopt(long, default_value = "1e-5")]
+    tolerance: f64,
+    /// The WebAssembly engine to use when running tests.
+    #[structopt(long, default_value = "wasmer")]
+    engine: String,
+    /// The directory containing your tests (any directory with a
+    /// Runefile.yml is a test).
+    #[structopt(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+}
+
+impl Test {
+    pub fn execute(self, unstable: Unstable) -> Result<(), Error> {
+        let directory = self.directory.canonicalize().with_context(|| {
+            format!("Unable to find \"{}\"", self.directory.display())
+        })?;
+        let tests = discover(&directory)?;
+        let runner = Runner {
+            rune: std::env::current_exe()
+                .context("Unable to determine the path to \"rune\"")?,
+            global_args: unstable.args(),
+            cache_dir: directory.join("target").join("rune-test"),
+        };
+
+        let mut results = Vec::new();
+
+        for test in tests.iter().filter(|t| self.should_run(&t.name)) {
+            let result = self.run_test(&runner, test);
+
+            match &result.outcome {
+                Outcome::Pass => println!("test {} ... ok", result.name),
+                Outcome::Skipped => {
+                    println!("test {} ... ignored", result.name)
+                },
+                Outcome::Fail(_) => println!("test {} ... FAILED", result.name),
+            }
+
+            results.push(result);
+        }
+
+        let failures: Vec<_> = results
+            .iter()
+            .filter_map(|r| match &r.outcome {
+                Outcome::Fail(errors) => Some((&r.name, errors)),
+                _ => None,
+            })
+            .collect();
+
+        for (name, errors) in &failures {
+            println!();
+            println!("---- {} ----", name);
+            for error in errors.iter() {
+                println!("{}", error);
+            }
+        }
+
+        let passed = results
+            .iter()
+            .filter(|r| matches!(r.outcome, Outcome::Pass))
+            .count();
+        let ignored = results.len() - passed - failures.len();
+
+        println!();
+        println!(
+            "test result: {}. {} passed; {} failed; {} ignored",
+            if failures.is_empty() { "ok" } else { "FAILED" },
+            passed,
+            failures.len(),
+            ignored
+        );
+
+        if let Some(junit) = &self.junit {
+            junit::write(junit, &results)?;
+        }
+
+        match failures.len() {
+            0 => Ok(()),
+            1 => Err(Error::msg("1 test failed")),
+            n => Err(anyhow::anyhow!("{} tests failed", n)),
+        }
+    }
+
+    fn should_run(&self, name: &str) -> bool {
+        self.filters.is_empty() || self.filters.iter().any(|f| name.contains(f))
+    }
+
+    fn run_test(&self, runner: &Runner, test: &TestCase) -> TestResult {
+        let start = Instant::now();
+
+        let (outcome, output) = if test.is_ignored() {
+            (Outcome::Skipped, None)
+        } else {
+            match self.check(runner, test) {
+                Ok((errors, output)) if errors.is_empty() => {
+                    (Outcome::Pass, Some(output))
+                },
+                Ok((errors, output)) => (Outcome::Fail(errors), Some(output)),
+                Err(e) => (Outcome::Fail(vec![format!("{:?}", e)]), None),
+            }
+        };
+
+        TestResult {
+            name: test.name.clone(),
+            expectation: test.manifest.expect,
+            outcome,
+            duration: start.elapsed(),
+            output,
+        }
+    }
+
+    /// Execute the test, returning a list of problems and the output from
+    /// the last command that was executed.
+    fn check(
+        &self,
+        runner: &Runner,
+        test: &TestCase,
+    ) -> Result<(Vec<String>, Output), Error> {
+        let expect = test.manifest.expect;
+        let mut output = runner.build(test)?;
+
+        if expect.should_run() {
+            if !output.status.success() {
+                let errors = vec![format!(
+                    "Unable to compile the Rune\n{}",
+                    String::from_utf8_lossy(&output.stderr)
+                )];
+                return Ok((errors, output));
+            }
+
+            let engine =
+                test.manifest.engine.as_deref().unwrap_or(&self.engine);
+            output = runner.run(test, engine)?;
+        }
+
+        let mut errors = Vec::new();
+
+        if output.status.success() != expect.should_succeed() {
+            errors.push(format!(
+                "Expected the test to {}, but it {}\n{}",
+                if expect.should_succeed() {
+                    "pass"
+                } else {
+                    "fail"
+                },
+                if output.status.success() {
+                    "passed"
+                } else {
+                    "failed"
+                },
+                String::from_utf8_lossy(&output.stderr)
+            ));
+            return Ok((errors, output));
+        }
+
+        let expected_output = ExpectedOutput::load_all(&test.directory)?;
+
+        if self.bless {
+            bless(test, &expected_output, &output)?;
+            return Ok((errors, output));
+        }
+
+        let tolerance = test.manifest.tolerance.unwrap_or(self.tolerance);
+
+        for expected in &expected_output {
+            let actual = stream(&output, expected.stream);
+
+            if !expected.check(&actual, tolerance) {
+                errors.push(format!(
+                    "Unable to find the contents of \"{}\" in the \
+                     output.\n\nExpected:\n{}\n\nActual:\n{}",
+                    expected.path.display(),
+                    expected.text.trim(),
+                    actual.trim(),
+                ));
+            }
+        }
+
+        Ok((errors, output))
+    }
+}
+
+/// Overwrite the expected output files with the test's actual output.
+///
+/// If the test doesn't have any expected output files, we'll create
+/// `expected.stdout` for tests that should pass and `expected.stderr` for
+/// tests that should fail.
+fn bless(
+    test: &TestCase,
+    expected_output: &[ExpectedOutput],
+    output: &Output,
+) -> Result<(), Error> {
+    let mut files: Vec<_> = expected_output
+        .iter()
+        .map(|e| (e.path.clone(), e.stream))
+        .collect();
+
+    if files.is_empty() {
+        files.push(if test.manifest.expect.should_succeed() {
+            (test.directory.join("expected.stdout"), Stream::Stdout)
+        } else {
+            (test.directory.join("expected.stderr"), Stream::Stderr)
+        });
+    }
+
+    for (path, s) in files {
+        let actual = stream(output, s);
+        let contents = format!("{}\n", actual.trim());
+
+        log::debug!("Updating \"{}\"", path.display());
+        std::fs::write(&path, contents).with_context(|| {
+            format!("Unable to write to \"{}\"", path.display())
+        })?;
+    }
+
+    Ok(())
+}
+
+fn stream(output: &Output, stream: Stream) -> String {
+    let raw = match stream {
+        Stream::Stdout => &output.stdout,
+        Stream::Stderr => &output.stderr,
+    };
+
+    String::from_utf8_lossy(raw).into_owned()
+}
+
+/// Find all the tests in a directory.
+fn discover(directory: &Path) -> Result<Vec<TestCase>, Error> {
+    let mut tests = Vec::new();
+
+    let entries = WalkDir::new(directory)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            // Skip build artifacts and things like ".git/"
+            entry.depth() == 0 || !(name == "target" || name.starts_with('.'))
+        });
+
+    for entry in entries {
+        let entry = entry.with_context(|| {
+            format!("Unable to read \"{}\"", directory.display())
+        })?;
+
+        if entry.file_name() != "Runefile.yml" {
+            continue;
+        }
+
+        let test_dir = entry
+            .path()
+            .parent()
+            .context("The Runefile should always have a parent directory")?;
+        let test = TestCase::load(directory, test_dir).with_context(|| {
+            format!("Unable to load the test in \"{}\"", test_dir.display())
+        })?;
+
+        log::debug!("Found \"{}\"", test.name);
+        tests.push(test);
+    }
+
+    Ok(tests)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TestCase {
+    name: String,
+    directory: PathBuf,
+    manifest: Manifest,
+}
+
+impl TestCase {
+    fn load(root: &Path, directory: &Path) -> Result<Self, Error> {
+        let relative = directory.strip_prefix(root).unwrap_or(directory);
+
+        let name = if relative.as_os_str().is_empty() {
+            directory
+                .canonicalize()?
+                .file_name()
+                .context("Unable to determine the test's name")?
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            relative
+                .iter()
+                .map(|s| s.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+
+        let manifest = Manifest::load(directory)?;
+
+        Ok(TestCase {
+            name,
+            directory: directory.to_path_buf(),
+            manifest,
+        })
+    }
+
+    fn is_ignored(&self) -> bool {
+        Path::new(&self.name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map_or(false, |n| n.starts_with('_'))
+    }
+}
+
+/// Something that can invoke `rune` for us.
+#[derive(Debug)]
+struct Runner {
+    rune: PathBuf,
+    global_args: Vec<OsString>,
+    cache_dir: PathBuf,
+}
+
+impl Runner {
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.rune);
+        cmd.arg("--colour=never").args(&self.global_args);
+
+        // Avoid mixing logs into the output unless explicitly asked for
+        if std::env::var_os("RUST_LOG").is_none() {
+            cmd.env("RUST_LOG", "off");
+        }
+
+        cmd
+    }
+
+    fn rune_path(&self, test: &TestCase) -> PathBuf {
+        self.cache_dir.join(&test.name).with_extension("rune")
+    }
+
+    fn build(&self, test: &TestCase) -> Result<Output, Error> {
+        let mut cmd = self.command();
+        cmd.arg("build")
+            .arg(test.directory.join("Runefile.yml"))
+            .arg("--output")
+            .arg(self.rune_path(test))
+            .arg("--cache-dir")
+            .arg(self.cache_dir.join(&test.name));
+
+        execute(cmd)
+    }
+
+    fn run(&self, test: &TestCase, engine: &str) -> Result<Output, Error> {
+        let mut cmd = self.command();
+        cmd.arg("run")
+            .arg(self.rune_path(test))
+            .arg("--engine")
+            .arg(engine)
+            .args(test.manifest.run_args(&test.directory)?)
+            .current_dir(&test.directory);
+
+        execute(cmd)
+    }
+}
+
+fn execute(mut cmd: Command) -> Result<Output, Error> {
+    log::debug!("Executing {:?}", cmd);
+
+    cmd.output()
+        .with_context(|| format!("Unable to execute {:?}", cmd))
+}
+
+#[derive(Debug)]
+pub(crate) struct TestResult {
+    pub name: String,
+    pub expectation: Expectation,
+    pub outcome: Outcome,
+    pub duration: Duration,
+    pub output: Option<Output>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Outcome {
+    Pass,
+    Skipped,
+    Fail(Vec<String>),
+}
@@ -7,13 +7,17 @@
 //!
 //! - Set `global = true` so unstable flags can be placed anywhere on the
 //!   command-line
-//! - Set `requires = "enable_unstable"` so you can only use unstable features
-//!   after explicitly opting in.
+//! - Check the flag in [`Unstable::validate()`] so you can only use unstable
+//!   features after explicitly opting in. This is done after merging in the
+//!   `rune.toml` settings because unstable features may be enabled there.
 
-use std::path::PathBuf;
+use std::{ffi::OsString, path::PathBuf};
 
+use anyhow::Error;
 use hotg_rune_compiler::FeatureFlags;
 
+use crate::Config;
+
 #[derive(Debug, Clone, PartialEq, structopt::StructOpt)]
 pub struct Unstable {
     /// Enable unstable features.
@@ -21,17 +25,35 @@ pub struct Unstable {
     pub unstable: bool,
     /// (unstable) A path to the Rune repository. Primarily used to patch
     /// dependencies when hacking on Rune locally.
-    #[structopt(
-        long,
-        env,
-        requires = "unstable",
-        parse(from_os_str),
-        global = true
-    )]
+    #[structopt(long, env, parse(from_os_str), global = true)]
     rune_repo_dir: Option<PathBuf>,
 }
 
 impl Unstable {
+    /// Use defaults from a `rune.toml` file for anything that wasn't
+    /// specified on the command-line.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.unstable |= config.unstable.enabled.unwrap_or(false);
+        self.rune_repo_dir = self
+            .rune_repo_dir
+            .or_else(|| config.unstable.rune_repo_dir.clone());
+        self
+    }
+
+    /// Make sure unstable flags are only used when unstable features have
+    /// been enabled.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.unstable && self.rune_repo_dir.is_some() {
+            anyhow::bail!(
+                "The --rune-repo-dir flag requires unstable features to be \
+                 enabled (use --unstable or set \"enabled = true\" in the \
+                 [unstable] section of rune.toml)"
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn feature_flags(&self) -> FeatureFlags {
         let mut features = FeatureFlags::default();
 
@@ -43,4 +65,21 @@ impl Unstable {
 
         features
     }
+
+    /// The command-line arguments needed to pass these flags on to another
+    /// `rune` process.
+    pub(crate) fn args(&self) -> Vec<OsString> {
+        let mut args = Vec::new();
+
+        if self.unstable {
+            args.push("--unstable".into());
+        }
+
+        if let Some(dir) = &self.rune_repo_dir {
+            args.push("--rune-repo-dir".into());
+            args.push(dir.clone().into_os_string());
+        }
+
+        args
+    }
 }
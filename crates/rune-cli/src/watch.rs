@@ -0,0 +1,363 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    iter,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Error};
+use codespan_reporting::term::termcolor::ColorChoice;
+use hotg_rune_compiler::{
+    parse::{self, ResourceOrString, SourceMap, Stage},
+    BuildContext, Diagnostics,
+};
+use structopt::StructOpt;
+
+use crate::{Build, Config, Run, Unstable};
+
+#[derive(Debug, Clone, PartialEq, StructOpt)]
+pub struct Watch {
+    #[structopt(flatten)]
+    build: Build,
+    /// How often to check for changes, in milliseconds.
+    #[structopt(long, default_value = "500")]
+    interval: u64,
+    /// Run the Rune after every successful build, passing these arguments to
+    /// "rune run" (e.g. "rune watch -- --image cat.png").
+    #[structopt(last = true)]
+    run_args: Vec<String>,
+    #[structopt(skip)]
+    config: Config,
+}
+
+impl Watch {
+    /// Use defaults from a `rune.toml` file for anything that wasn't
+    /// specified on the command-line.
+    pub fn with_config(self, config: &Config) -> Self {
+        Watch {
+            build: self.build.with_config(config),
+            config: config.clone(),
+            ..self
+        }
+    }
+
+    pub fn execute(
+        self,
+        color: ColorChoice,
+        unstable: Unstable,
+    ) -> Result<(), Error> {
+        let run = self.run_command()?;
+        let interval = Duration::from_millis(self.interval);
+
+        let mut previous_snapshot = None;
+        let mut previous_outputs = None;
+
+        loop {
+            let snapshot = Snapshot::take(&self.watched_paths());
+
+            if previous_snapshot.as_ref() != Some(&snapshot) {
+                if previous_snapshot.is_some() {
+                    log::info!("Change detected, rebuilding");
+                }
+                previous_snapshot = Some(snapshot);
+
+                match self.build.clone().execute(color, unstable.clone()) {
+                    Ok(_) => {
+                        if let Some(run) = &run {
+                            rerun(run.clone(), &mut previous_outputs);
+                        }
+                    },
+                    Err(e) => log::error!("{:?}", e),
+                }
+
+                log::info!("Waiting for changes...");
+            }
+
+            std::thread::sleep(interval);
+        }
+    }
+
+    fn run_command(&self) -> Result<Option<Run>, Error> {
+        if self.run_args.is_empty() {
+            return Ok(None);
+        }
+
+        let rune = self.build.rune_path()?;
+        let args = iter::once(OsString::from("run"))
+            .chain(self.run_args.iter().map(OsString::from))
+            .chain(iter::once(rune.into_os_string()));
+
+        Run::from_iter_safe(args)
+            .map(|run| Some(run.with_config(&self.config)))
+            .context("Invalid arguments for \"rune run\"")
+    }
+
+    /// Figure out which files the build depends on.
+    ///
+    /// The Runefile is read each time so we pick up on any models, resources,
+    /// or proc-blocks that were added since the last build.
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        let runefile = self.build.runefile();
+
+        match self.build.build_context() {
+            Ok(ctx) => dependencies(runefile, &ctx),
+            Err(_) => vec![runefile.to_path_buf()],
+        }
+    }
+}
+
+/// Find every file a Rune depends on, resolving includes and variables the
+/// same way the compiler does.
+fn dependencies(runefile: &Path, ctx: &BuildContext) -> Vec<PathBuf> {
+    let current_dir = &ctx.current_directory;
+    let mut paths = vec![runefile.to_path_buf()];
+
+    let mut sources = SourceMap::new("Runefile.yml", ctx.runefile.as_str());
+    // Note: any problems will be reported when we try to build the Rune
+    let mut diags = Diagnostics::new();
+    let loaded =
+        parse::load_runefile(ctx, &mut sources, &mut diags, |include| {
+            // Watch includes even if they can't be read so we'll rebuild
+            // once they are created
+            let path = current_dir.join(include);
+            paths.push(path.clone());
+            std::fs::read_to_string(path)
+        });
+
+    let doc = match loaded {
+        Some((doc, _)) => doc,
+        None => return paths,
+    };
+
+    for stage in doc.pipeline.values() {
+        match stage {
+            Stage::Model(model) => {
+                if let ResourceOrString::String(path) = &model.model {
+                    paths.push(current_dir.join(path));
+                }
+            },
+            Stage::ProcBlock(proc_block) => {
+                // Only local proc-blocks can change underneath us
+                let path = &proc_block.proc_block;
+                if path.base.starts_with('.') {
+                    paths.push(current_dir.join(&path.base));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    for resource in doc.resources.values() {
+        if let Some(path) = &resource.path {
+            paths.push(current_dir.join(path));
+        }
+    }
+
+    paths
+}
+
+fn rerun(run: Run, previous_outputs: &mut Option<serde_json::Value>) {
+    let outputs = match run.predict() {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            log::error!("{:?}", e);
+            return;
+        },
+    };
+
+    match previous_outputs {
+        Some(previous) if *previous == outputs => {
+            println!("Outputs unchanged");
+        },
+        Some(previous) => print_diff(previous, &outputs),
+        None => println!("{}", outputs),
+    }
+
+    *previous_outputs = Some(outputs);
+}
+
+/// Print a line-based diff of two pretty-printed JSON values.
+fn print_diff(old: &serde_json::Value, new: &serde_json::Value) {
+    for line in diff_lines(old, new) {
+        println!("{}", line);
+    }
+}
+
+/// Compare two values line-by-line, prefixing each line with `+` if it was
+/// added, `-` if it was removed, or a space if it didn't change.
+fn diff_lines(old: &serde_json::Value, new: &serde_json::Value) -> Vec<String> {
+    let old = serde_json::to_string_pretty(old).unwrap_or_default();
+    let new = serde_json::to_string_pretty(new).unwrap_or_default();
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // The classic longest-common-subsequence table, where lcs[i][j] is the
+    // length of the LCS of old[i..] and new[j..].
+    let mut lcs = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len()
+            && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1])
+        {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+
+    lines
+}
+
+/// The last modified time for every file being watched.
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot(BTreeMap<PathBuf, Option<SystemTime>>);
+
+impl Snapshot {
+    fn take(paths: &[PathBuf]) -> Self {
+        let mut times = BTreeMap::new();
+
+        for path in paths {
+            record_modified_times(path, &mut times);
+        }
+
+        Snapshot(times)
+    }
+}
+
+fn record_modified_times(
+    path: &Path,
+    times: &mut BTreeMap<PathBuf, Option<SystemTime>>,
+) {
+    let metadata = std::fs::metadata(path).ok();
+
+    if metadata.as_ref().map_or(false, |m| m.is_dir()) {
+        let entries = match std::fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            // Skip build artifacts and things like ".git/"
+            if name == "target" || name.starts_with('.') {
+                continue;
+            }
+
+            record_modified_times(&entry.path(), times);
+        }
+    } else {
+        let modified = metadata.and_then(|m| m.modified().ok());
+        times.insert(path.to_path_buf(), modified);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn watch_nested_includes_and_variable_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let runefile = r#"
+version: 1
+image: runicos/base
+includes: [stages/common.yml]
+variables:
+  MODELS: ./models
+pipeline:
+  rand:
+    capability: RAND
+    outputs: [{type: f32, dimensions: [1]}]
+  sine:
+    model: ${MODELS}/sine.tflite
+    inputs: [rand]
+    outputs: [{type: f32, dimensions: [1]}]
+  serial:
+    out: SERIAL
+    inputs: [sine]
+"#;
+        std::fs::write(dir.join("Runefile.yml"), runefile).unwrap();
+        std::fs::create_dir(dir.join("stages")).unwrap();
+        std::fs::write(
+            dir.join("stages/common.yml"),
+            "includes: [nested.yml]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("stages/nested.yml"),
+            "resources:\n  LABELS:\n    path: labels.txt\n",
+        )
+        .unwrap();
+        let ctx = BuildContext::for_directory(dir).unwrap();
+
+        let got = dependencies(&dir.join("Runefile.yml"), &ctx);
+
+        let expected = [
+            "Runefile.yml",
+            "stages/common.yml",
+            "stages/nested.yml",
+            "models/sine.tflite",
+            "stages/labels.txt",
+        ];
+        for path in &expected {
+            assert!(got.contains(&dir.join(path)), "{} in {:?}", path, got);
+        }
+        assert_eq!(got.len(), expected.len());
+    }
+
+    #[test]
+    fn watch_includes_that_dont_exist_yet() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let runefile =
+            "version: 1\nimage: runicos/base\nincludes: [missing.yml]\n";
+        std::fs::write(dir.join("Runefile.yml"), runefile).unwrap();
+        let ctx = BuildContext::for_directory(dir).unwrap();
+
+        let got = dependencies(&dir.join("Runefile.yml"), &ctx);
+
+        assert!(got.contains(&dir.join("missing.yml")), "{:?}", got);
+    }
+
+    #[test]
+    fn diff_changed_outputs() {
+        let old = json!({ "label": "cat", "confidence": 0.5 });
+        let new = json!({ "label": "dog", "confidence": 0.5 });
+
+        let got = diff_lines(&old, &new);
+
+        assert_eq!(
+            got,
+            vec![
+                "  {",
+                "    \"confidence\": 0.5,",
+                "-   \"label\": \"cat\"",
+                "+   \"label\": \"dog\"",
+                "  }",
+            ]
+        );
+    }
+}
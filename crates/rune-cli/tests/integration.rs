@@ -59,6 +59,61 @@ fn person_detection() {
         .stdout(predicates::str::contains("\"person_prob\""));
 }
 
+#[cfg(target_os = "linux")] // See https://github.com/hotg-ai/rune/issues/131
+#[test]
+fn debug_the_intermediate_tensors_of_a_rune_with_a_utf8_stage() {
+    let person_detection_dir = example_dir().join("person_detection");
+    let runefile = person_detection_dir.join("Runefile.yml");
+    let build_dir = cache_dir().join("debug");
+    let rune = build_dir.join("person_detection.rune");
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("build")
+        .arg(&runefile)
+        .arg("--colour=never")
+        .arg("--debug-tensors")
+        .arg("--cache-dir")
+        .arg(build_dir.join("build"))
+        .arg("--output")
+        .arg(&rune)
+        .arg("--unstable")
+        .arg("--rune-repo-dir")
+        .arg(project_root())
+        .assert()
+        .success();
+
+    let image = person_detection_dir.join("image_grayscale.png");
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("debug")
+        .arg(&rune)
+        .arg("--image")
+        .arg(&image)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("most_confident_index: u32[1]"))
+        .stdout(predicates::str::contains("label: utf8[1]"))
+        .stdout(predicates::str::contains("\"person_prob\""));
+
+    let output = Command::cargo_bin("rune")
+        .unwrap()
+        .arg("debug")
+        .arg(&rune)
+        .arg("--image")
+        .arg(&image)
+        .arg("--stop-after=most_confident_index")
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("most_confident_index: u32[1]"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("label:"), "{}", stdout);
+}
+
 #[test]
 fn build_all_examples() {
     let runefiles = WalkDir::new(example_dir())
@@ -86,3 +141,859 @@ fn build_all_examples() {
             .success();
     }
 }
+
+#[test]
+fn check_all_examples() {
+    let runefiles = WalkDir::new(example_dir())
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name() == "Runefile.yml");
+
+    for runefile in runefiles {
+        let mut cmd = Command::cargo_bin("rune").unwrap();
+        cmd.arg("check")
+            .arg(runefile.path())
+            .arg("--colour=never")
+            .arg("--format=json")
+            .assert()
+            .success();
+    }
+}
+
+#[test]
+fn check_reports_errors_as_json() {
+    let temp = tempfile::tempdir().unwrap();
+    let runefile = temp.path().join("Runefile.yml");
+    std::fs::write(
+        &runefile,
+        "version: 1\nimage: runicos/base\npipeline:\n  serial:\n    out: \
+         serial\n    inputs:\n    - missing\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rune").unwrap();
+    cmd.arg("check")
+        .arg(&runefile)
+        .arg("--colour=never")
+        .arg("--format=json")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("\"severity\": \"error\""))
+        .stdout(predicates::str::contains("missing"));
+}
+
+#[test]
+fn fmt_check_detects_unformatted_runefiles() {
+    let temp = tempfile::tempdir().unwrap();
+    let runefile = temp.path().join("Runefile.yml");
+    std::fs::write(
+        &runefile,
+        "image: runicos/base\nversion: 1\npipeline:\n  rand:\n    capability: \
+         rand\n    outputs:\n    - type: F32\n      dimensions: [1]\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("fmt")
+        .arg("--check")
+        .arg(&runefile)
+        .assert()
+        .failure();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("fmt")
+        .arg(&runefile)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("fmt")
+        .arg("--check")
+        .arg(&runefile)
+        .assert()
+        .success();
+}
+
+#[test]
+fn fmt_upgrade_converts_to_version_2() {
+    let temp = tempfile::tempdir().unwrap();
+    let runefile = temp.path().join("Runefile.yml");
+    std::fs::copy(example_dir().join("sine").join("Runefile.yml"), &runefile)
+        .unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("fmt")
+        .arg("--upgrade")
+        .arg(&runefile)
+        .assert()
+        .success();
+
+    let upgraded = std::fs::read_to_string(&runefile).unwrap();
+    assert!(upgraded.starts_with("version: 2\n"), "{}", upgraded);
+    assert!(upgraded.contains("kind: model"), "{}", upgraded);
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("fmt")
+        .arg("--check")
+        .arg(&runefile)
+        .assert()
+        .success();
+}
+
+#[test]
+fn graph_a_runefile_in_every_format() {
+    let runefile = example_dir().join("sine").join("Runefile.yml");
+
+    for (format, expected) in [
+        ("dot", "digraph"),
+        ("mermaid", "flowchart TD"),
+        ("json", "\"edges\""),
+        ("text", "|-- f32[1, 1] --> sine"),
+    ] {
+        let mut cmd = Command::cargo_bin("rune").unwrap();
+        cmd.arg("graph")
+            .arg(&runefile)
+            .arg("--format")
+            .arg(format)
+            .assert()
+            .success()
+            .stdout(predicates::str::contains(expected))
+            .stdout(predicates::str::contains("f32[1, 1]"));
+    }
+}
+
+#[cfg(target_os = "linux")] // See https://github.com/hotg-ai/rune/issues/131
+#[test]
+fn extract_a_compiled_rune() {
+    let sine_dir = example_dir().join("sine");
+    let build_dir = cache_dir().join("extract");
+    let rune = build_dir.join("sine.rune");
+    let output_dir = build_dir.join("extracted");
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("build")
+        .arg(sine_dir.join("Runefile.yml"))
+        .arg("--colour=never")
+        .arg("--output")
+        .arg(&rune)
+        .arg("--unstable")
+        .arg("--rune-repo-dir")
+        .arg(project_root())
+        .assert()
+        .success();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("extract")
+        .arg(&rune)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .assert()
+        .success();
+
+    assert!(output_dir.join("metadata.json").exists());
+    let model = std::fs::read(output_dir.join("models").join("sine")).unwrap();
+    let original = std::fs::read(sine_dir.join("sinemodel.tflite")).unwrap();
+    assert_eq!(model, original);
+}
+
+/// Create a WebAssembly custom section.
+fn custom_section(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut payload = vec![name.len() as u8];
+    payload.extend_from_slice(name.as_bytes());
+    payload.extend_from_slice(data);
+
+    let mut section = vec![0];
+    let mut len = payload.len();
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            section.push(byte);
+            break;
+        }
+        section.push(byte | 0x80);
+    }
+    section.extend(payload);
+
+    section
+}
+
+fn inline_resource(name: &str, value: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&(name.len() as u32).to_be_bytes());
+    data.extend_from_slice(name.as_bytes());
+    data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    data.extend_from_slice(value);
+    data
+}
+
+/// Write a Rune containing just the custom sections `rune diff` looks at.
+fn rune_with_graph(
+    path: &Path,
+    version: &str,
+    graph: &serde_json::Value,
+    resources: &[(&str, &[u8])],
+) {
+    let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+    let version = serde_json::json!({ "version": version }).to_string();
+    wasm.extend(custom_section(".rune_version", version.as_bytes()));
+    wasm.extend(custom_section(".rune_graph", graph.to_string().as_bytes()));
+    for (name, value) in resources {
+        wasm.extend(custom_section(
+            ".rune_resource",
+            &inline_resource(name, value),
+        ));
+    }
+
+    std::fs::write(path, &wasm).unwrap();
+}
+
+#[test]
+fn diff_two_runes() {
+    let temp = tempfile::tempdir().unwrap();
+    let old = temp.path().join("old.rune");
+    let new = temp.path().join("new.rune");
+    let malformed = temp.path().join("malformed.rune");
+    let f32_1 = serde_json::json!({ "element_type": "F32", "dimensions": [1] });
+    let f32_2 = serde_json::json!({ "element_type": "F32", "dimensions": [2] });
+    let string = serde_json::json!({ "default_value": null, "ty": "string" });
+    rune_with_graph(
+        &old,
+        "0.11.0",
+        &serde_json::json!({
+            "rune": { "name": "diff" },
+            "capabilities": {
+                "rand": {
+                    "kind": { "type": "random" },
+                    "args": {},
+                    "outputs": ["rand"],
+                },
+            },
+            "proc-blocks": {
+                "normalize": {
+                    "path": "hotg-ai/proc-blocks@v0.11#normalize",
+                    "args": {},
+                    "inputs": ["rand"],
+                    "outputs": ["normalize"],
+                },
+            },
+            "outputs": {
+                "serial": {
+                    "kind": { "type": "serial" },
+                    "args": {},
+                    "inputs": ["normalize"],
+                },
+            },
+            "resources": { "LABELS": string, "THRESHOLD": string },
+            "tensors": { "rand": f32_1, "normalize": f32_1 },
+        }),
+        &[("THRESHOLD", b"0.5"), ("LABELS", b"cat\ndog")],
+    );
+    rune_with_graph(
+        &new,
+        "0.12.0",
+        &serde_json::json!({
+            "rune": { "name": "diff" },
+            "capabilities": {
+                "rand": {
+                    "kind": { "type": "random" },
+                    "args": { "n": "2" },
+                    "outputs": ["rand"],
+                },
+            },
+            "proc-blocks": {
+                "normalize": {
+                    "path": "hotg-ai/proc-blocks@v0.12#normalize",
+                    "args": {},
+                    "inputs": ["rand"],
+                    "outputs": ["normalize"],
+                },
+            },
+            "outputs": {
+                "tensor": {
+                    "kind": { "type": "tensor" },
+                    "args": {},
+                    "inputs": ["normalize"],
+                },
+            },
+            "resources": { "THRESHOLD": string },
+            "tensors": { "rand": f32_2, "normalize": f32_2 },
+        }),
+        &[("THRESHOLD", b"0.75")],
+    );
+    rune_with_graph(
+        &malformed,
+        "0.12.0",
+        &serde_json::json!({
+            "rune": { "name": "diff" },
+            "outputs": {
+                "serial": {
+                    "kind": { "type": "serial" },
+                    "args": {},
+                    "inputs": ["missing"],
+                },
+            },
+        }),
+        &[],
+    );
+
+    let expected = [
+        "~ compiled by: v0.11.0 -> v0.12.0",
+        "~ \"normalize\": version v0.11 -> v0.12",
+        "~ \"normalize\": input 0: f32[1] from rand.0 -> f32[2] from rand.0",
+        "~ \"normalize\": output 0: f32[1] -> f32[2]",
+        "+ \"rand\": arg n = \"2\"",
+        "~ \"rand\": output 0: f32[1] -> f32[2]",
+        "- output \"serial\" (serial)",
+        "+ output \"tensor\" (tensor)",
+        "- resource $LABELS",
+        "~ resource $THRESHOLD: \"0.5\" -> \"0.75\"",
+    ];
+    let output = Command::cargo_bin("rune")
+        .unwrap()
+        .arg("diff")
+        .arg(&old)
+        .arg(&new)
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("diff")
+        .arg(&old)
+        .arg(&new)
+        .arg("--format=json")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#""type": "node-added""#))
+        .stdout(predicates::str::contains(r#""type": "resource-removed""#));
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("diff")
+        .arg(&old)
+        .arg(&new)
+        .arg("--exit-code")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Found 10 changes"));
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("diff")
+        .arg(&old)
+        .arg(&old)
+        .arg("--exit-code")
+        .assert()
+        .success()
+        .stdout("");
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("diff")
+        .arg(&old)
+        .arg(&malformed)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("The new Rune is malformed"));
+}
+
+#[test]
+fn patch_the_resources_in_a_rune() {
+    let temp = tempfile::tempdir().unwrap();
+    let rune = temp.path().join("resources.rune");
+    let patched = temp.path().join("patched.rune");
+    let output_dir = temp.path().join("extracted");
+    let graph = r#"{
+        "rune": { "name": "resources" },
+        "resources": {
+            "LABELS": { "default_value": null, "ty": "string" },
+            "THRESHOLD": { "default_value": null, "ty": "string" }
+        }
+    }"#;
+    let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+    wasm.extend(custom_section(".rune_graph", graph.as_bytes()));
+    wasm.extend(custom_section(
+        ".rune_resource",
+        &inline_resource("THRESHOLD", b"0.5"),
+    ));
+    std::fs::write(&rune, &wasm).unwrap();
+    let labels = temp.path().join("labels.txt");
+    std::fs::write(&labels, "cat\ndog\n").unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("patch")
+        .arg(&rune)
+        .arg("--set-resource")
+        .arg("THRESHOLD=0.75")
+        .arg("--set-resource-file")
+        .arg(format!("LABELS={}", labels.display()))
+        .arg("--output")
+        .arg(&patched)
+        .assert()
+        .success();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("extract")
+        .arg(&patched)
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .assert()
+        .success();
+
+    let resources = output_dir.join("resources");
+    assert_eq!(
+        std::fs::read_to_string(resources.join("THRESHOLD")).unwrap(),
+        "0.75"
+    );
+    assert_eq!(
+        std::fs::read_to_string(resources.join("LABELS")).unwrap(),
+        "cat\ndog\n"
+    );
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("patch")
+        .arg(&rune)
+        .arg("--set-resource")
+        .arg("MISSING=42")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("MISSING"));
+}
+
+#[test]
+fn extract_refuses_to_write_outside_the_output_directory() {
+    let temp = tempfile::tempdir().unwrap();
+    let output_dir = temp.path().join("extracted");
+
+    for name in &["../escaped", "nested/escaped", "/tmp/escaped", ".."] {
+        let rune = temp.path().join("malicious.rune");
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend(custom_section(
+            ".rune_resource",
+            &inline_resource(name, b"gotcha"),
+        ));
+        std::fs::write(&rune, &wasm).unwrap();
+
+        Command::cargo_bin("rune")
+            .unwrap()
+            .arg("extract")
+            .arg(&rune)
+            .arg("--output-dir")
+            .arg(&output_dir)
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains("isn't a valid file name"));
+    }
+
+    assert!(!temp.path().join("escaped").exists());
+    assert!(!output_dir.exists());
+}
+
+#[test]
+fn bless_and_run_golden_output_tests() {
+    let temp = tempfile::tempdir().unwrap();
+    let broken = temp.path().join("broken");
+    std::fs::create_dir_all(&broken).unwrap();
+    std::fs::write(
+        broken.join("Runefile.yml"),
+        "version: 1\nimage: runicos/base\npipeline:\n  serial:\n    out: \
+         serial\n    inputs:\n    - missing\n",
+    )
+    .unwrap();
+    std::fs::write(broken.join("test.yml"), "expect: compile-fail\n").unwrap();
+    let ignored = temp.path().join("_ignored");
+    std::fs::create_dir_all(&ignored).unwrap();
+    std::fs::write(ignored.join("Runefile.yml"), "not a runefile").unwrap();
+    let junit = temp.path().join("junit.xml");
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("test")
+        .arg(temp.path())
+        .arg("--bless")
+        .assert()
+        .success();
+
+    let expected =
+        std::fs::read_to_string(broken.join("expected.stderr")).unwrap();
+    assert!(expected.contains("missing"), "{}", expected);
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("test")
+        .arg(temp.path())
+        .arg("--junit")
+        .arg(&junit)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("test broken ... ok"))
+        .stdout(predicates::str::contains("test _ignored ... ignored"));
+
+    let report = std::fs::read_to_string(&junit).unwrap();
+    assert!(report.contains(r#"<testcase name="broken""#));
+    assert!(report.contains("<skipped />"));
+
+    std::fs::write(broken.join("expected.stderr"), "something else").unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("test")
+        .arg(temp.path())
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("test broken ... FAILED"));
+}
+
+/// Encode a protobuf field (a varint if `value` is `Ok`, otherwise a
+/// length-delimited field).
+fn protobuf_field(tag: u64, value: Result<u64, &[u8]>) -> Vec<u8> {
+    fn varint(mut n: u64, buffer: &mut Vec<u8>) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                buffer.push(byte);
+                break;
+            }
+            buffer.push(byte | 0x80);
+        }
+    }
+
+    let mut field = Vec::new();
+
+    match value {
+        Ok(n) => {
+            varint(tag << 3, &mut field);
+            varint(n, &mut field);
+        },
+        Err(bytes) => {
+            varint(tag << 3 | 2, &mut field);
+            varint(bytes.len() as u64, &mut field);
+            field.extend_from_slice(bytes);
+        },
+    }
+
+    field
+}
+
+#[test]
+fn model_info_for_an_onnx_model() {
+    let temp = tempfile::tempdir().unwrap();
+    // Note: no extension so we need to detect the format from its contents
+    let model = temp.path().join("classifier");
+
+    let tensor = |name: &str, element_type: u64, dims: &[u64]| {
+        let dims: Vec<u8> = dims
+            .iter()
+            .flat_map(|&d| protobuf_field(1, Err(&protobuf_field(1, Ok(d)))))
+            .collect();
+        let tensor_type = [
+            protobuf_field(1, Ok(element_type)),
+            protobuf_field(2, Err(&dims)),
+        ]
+        .concat();
+        let type_proto = protobuf_field(1, Err(&tensor_type));
+
+        [
+            protobuf_field(1, Err(name.as_bytes())),
+            protobuf_field(2, Err(&type_proto)),
+        ]
+        .concat()
+    };
+    let graph = [
+        protobuf_field(1, Err(&protobuf_field(4, Err(b"Gemm")))),
+        protobuf_field(1, Err(&protobuf_field(4, Err(b"Softmax")))),
+        protobuf_field(11, Err(&tensor("image", 1, &[1, 784]))),
+        protobuf_field(12, Err(&tensor("probabilities", 1, &[1, 10]))),
+    ]
+    .concat();
+    let onnx =
+        [protobuf_field(1, Ok(7)), protobuf_field(7, Err(&graph))].concat();
+    std::fs::write(&model, onnx).unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("model-info")
+        .arg(&model)
+        .arg("--format=json")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#""format": "onnx""#))
+        .stdout(predicates::str::contains(r#""ops": 2"#))
+        .stdout(predicates::str::contains(r#""name": "probabilities""#));
+
+    let output = Command::cargo_bin("rune")
+        .unwrap()
+        .arg("model-info")
+        .arg(&model)
+        .arg("--runefile-stage")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stage = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stage.starts_with("classifier:\n"), "{}", stage);
+    assert!(stage.contains("format: onnx"), "{}", stage);
+    assert!(
+        stage.contains("#   - TODO # image: f32[1, 784]"),
+        "{}",
+        stage
+    );
+    assert!(
+        stage.contains(
+            "- type: F32\n      dimensions:\n        - 1\n        - 10"
+        ),
+        "{}",
+        stage
+    );
+}
+
+#[test]
+fn model_info_for_a_tflite_model() {
+    let model = example_dir().join("gesture").join("model.tflite");
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("model-info")
+        .arg(&model)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Format: tensorflow-lite"))
+        .stdout(predicates::str::contains("Ops: 8"))
+        .stdout(predicates::str::contains("conv2d_input: f32[?, 128, 3, 1]"))
+        .stdout(predicates::str::contains("Identity: f32[?, 4]"));
+}
+
+#[test]
+fn show_settings_from_the_project_config_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let project = temp.path().join("project");
+    let nested = project.join("nested");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(
+        project.join("rune.toml"),
+        "[run]\nengine = \"wasm3\"\nrandom = 42\nimage = [\"cat.png\"]\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("config")
+        .arg("show")
+        .arg("--format=json")
+        .current_dir(&nested)
+        // Make sure the user's own settings don't get picked up
+        .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+        .env_remove("CACHE_DIR")
+        .env_remove("RUNE_REPO_DIR")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(r#""engine": "wasm3""#))
+        .stdout(predicates::str::contains(r#""random": 42"#))
+        .stdout(predicates::str::contains("cat.png"));
+
+    // Typos should be caught instead of silently ignored
+    std::fs::write(project.join("rune.toml"), "[run]\nengin = \"wasm3\"\n")
+        .unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("config")
+        .arg("show")
+        .current_dir(&project)
+        .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Unable to parse"));
+}
+
+#[test]
+fn a_broken_config_file_only_affects_commands_that_use_it() {
+    let temp = tempfile::tempdir().unwrap();
+    std::fs::write(temp.path().join("rune.toml"), "[run\n").unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("version")
+        .current_dir(temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+        .assert()
+        .success();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("config")
+        .arg("show")
+        .current_dir(temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Unable to parse"));
+}
+
+#[test]
+fn unstable_features_can_be_enabled_by_the_config_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let repo_dir = temp.path().join("rune");
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("config")
+        .arg("show")
+        .arg("--rune-repo-dir")
+        .arg(&repo_dir)
+        .current_dir(temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+        .env_remove("RUNE_REPO_DIR")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("requires unstable features"));
+
+    std::fs::write(
+        temp.path().join("rune.toml"),
+        "[unstable]\nenabled = true\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin("rune")
+        .unwrap()
+        .arg("config")
+        .arg("show")
+        .arg("--rune-repo-dir")
+        .arg(&repo_dir)
+        .current_dir(temp.path())
+        .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+        .env_remove("RUNE_REPO_DIR")
+        .assert()
+        .success();
+}
+
+/// Encode a JSON-RPC message using the Language Server Protocol's framing.
+fn lsp_message(msg: serde_json::Value) -> String {
+    let body = msg.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+#[test]
+fn language_server_hover_and_completion() {
+    let temp = tempfile::tempdir().unwrap();
+    let runefile = temp.path().join("Runefile.yml");
+    let src =
+        "version: 1\nimage: runicos/base\n\npipeline:\n  rand:\n    \
+         capability: RAND\n    outputs:\n    - type: F32\n      dimensions: \
+         [1, 4]\n  serial:\n    out: SERIAL\n    inputs:\n    - rand\n";
+    std::fs::write(&runefile, src).unwrap();
+    let uri = format!("file://{}", runefile.display());
+    let position = |line: u32, character: u32| {
+        serde_json::json!({
+            "textDocument": { "uri": &uri },
+            "position": { "line": line, "character": character },
+        })
+    };
+
+    let session: String = vec![
+        serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "initialize",
+            "params": { "capabilities": {} },
+        }),
+        serde_json::json!({
+            "jsonrpc": "2.0", "method": "initialized", "params": {},
+        }),
+        serde_json::json!({
+            "jsonrpc": "2.0", "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": &uri, "languageId": "yaml", "version": 1, "text": src,
+                },
+            },
+        }),
+        serde_json::json!({
+            "jsonrpc": "2.0", "id": 2, "method": "textDocument/hover",
+            "params": position(12, 7),
+        }),
+        serde_json::json!({
+            "jsonrpc": "2.0", "id": 3, "method": "textDocument/completion",
+            "params": position(5, 16),
+        }),
+        serde_json::json!({
+            "jsonrpc": "2.0", "id": 4, "method": "textDocument/definition",
+            "params": position(12, 7),
+        }),
+        serde_json::json!({ "jsonrpc": "2.0", "id": 5, "method": "shutdown" }),
+        serde_json::json!({ "jsonrpc": "2.0", "method": "exit" }),
+    ]
+    .into_iter()
+    .map(lsp_message)
+    .collect();
+
+    let output = Command::cargo_bin("rune")
+        .unwrap()
+        .arg("lsp")
+        .write_stdin(session)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let messages =
+        parse_lsp_messages(&String::from_utf8(output.stdout).unwrap());
+    let response = |id: u64| {
+        messages
+            .iter()
+            .find(|msg| msg["id"] == id)
+            .map(|msg| msg["result"].clone())
+            .unwrap()
+    };
+
+    assert!(messages
+        .iter()
+        .any(|msg| msg["method"] == "textDocument/publishDiagnostics"));
+
+    let hover = response(2);
+    let hover_text = hover["contents"]["value"].as_str().unwrap();
+    assert!(hover_text.contains("`f32[1, 4]`"), "{}", hover_text);
+
+    let completions = response(3);
+    let labels: Vec<_> = completions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    assert!(labels.contains(&"FLOAT_IMAGE"), "{:?}", labels);
+
+    let definition = response(4);
+    assert_eq!(
+        definition["range"],
+        serde_json::json!({
+            "start": { "line": 4, "character": 2 },
+            "end": { "line": 4, "character": 6 },
+        })
+    );
+}
+
+fn parse_lsp_messages(mut stdout: &str) -> Vec<serde_json::Value> {
+    let mut messages = Vec::new();
+
+    while let Some(header_end) = stdout.find("\r\n\r\n") {
+        let length: usize = stdout[..header_end]
+            .trim_start_matches("Content-Length: ")
+            .parse()
+            .unwrap();
+        let body = &stdout[header_end + 4..header_end + 4 + length];
+        messages.push(serde_json::from_str(body).unwrap());
+        stdout = &stdout[header_end + 4 + length..];
+    }
+
+    messages
+}
@@ -0,0 +1,294 @@
+//! The arguments accepted by each of the builtin capabilities and outputs.
+//!
+//! These descriptors are the single source of truth for what a Runefile may
+//! pass to a capability or output. The compiler uses them to validate a
+//! stage's `args` and the runtime uses them when reading its inputs.
+
+use core::fmt::{self, Display, Formatter};
+
+use crate::{capabilities, outputs};
+
+/// Metadata describing a single argument.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ArgumentDescriptor {
+    /// The argument's name, as written in `snake_case`.
+    pub name: &'static str,
+    /// A human-friendly description of what the argument does.
+    pub description: &'static str,
+    pub ty: ArgumentType,
+    /// Must this argument always be provided?
+    pub required: bool,
+    /// The value used when the argument isn't provided, if there is one.
+    pub default: Option<&'static str>,
+    /// The only values this argument may take. An empty list means anything
+    /// of the right [`ArgumentType`] is accepted.
+    pub allowed_values: &'static [&'static str],
+}
+
+impl ArgumentDescriptor {
+    /// Check that a value is acceptable for this argument.
+    ///
+    /// Values starting with `@` are arbitrary expressions which get evaluated
+    /// inside the Rune, so they are only checked against the
+    /// [`ArgumentDescriptor::allowed_values`].
+    pub fn validate(&self, value: &str) -> Result<(), InvalidArgument> {
+        if !self.allowed_values.is_empty() {
+            return if self.allowed_values.contains(&value) {
+                Ok(())
+            } else {
+                Err(InvalidArgument::NotAllowed {
+                    allowed_values: self.allowed_values,
+                })
+            };
+        }
+
+        match self.ty {
+            ArgumentType::Integer
+                if !value.starts_with('@') && value.parse::<u64>().is_err() =>
+            {
+                Err(InvalidArgument::NotAnInteger)
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The kind of value an argument accepts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ArgumentType {
+    /// A non-negative integer.
+    Integer,
+    /// Any string.
+    String,
+}
+
+impl Display for ArgumentType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgumentType::Integer => write!(f, "integer"),
+            ArgumentType::String => write!(f, "string"),
+        }
+    }
+}
+
+/// The reason a value was rejected by [`ArgumentDescriptor::validate()`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InvalidArgument {
+    NotAnInteger,
+    NotAllowed {
+        allowed_values: &'static [&'static str],
+    },
+}
+
+impl Display for InvalidArgument {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidArgument::NotAnInteger => {
+                write!(f, "expected a non-negative integer")
+            },
+            InvalidArgument::NotAllowed { allowed_values } => {
+                write!(f, "expected one of ")?;
+
+                for (i, value) in allowed_values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", value)?;
+                }
+
+                Ok(())
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidArgument {}
+
+/// Get the arguments accepted by a capability (see [`capabilities`]),
+/// returning `None` if they aren't known.
+pub fn capability_arguments(
+    capability: u32,
+) -> Option<&'static [ArgumentDescriptor]> {
+    match capability {
+        capabilities::RAND => Some(&[random::AMOUNT]),
+        capabilities::SOUND => {
+            Some(&[SOURCE, sound::HZ, sound::SAMPLE_DURATION_MS])
+        },
+        capabilities::ACCEL => Some(&[SOURCE, accelerometer::SAMPLES]),
+        capabilities::IMAGE => {
+            Some(&[SOURCE, image::WIDTH, image::HEIGHT, image::PIXEL_FORMAT])
+        },
+        capabilities::RAW => Some(&[SOURCE, raw::LENGTH]),
+        _ => None,
+    }
+}
+
+/// Get the arguments accepted by an output (see [`outputs`]), returning
+/// `None` if they aren't known.
+pub fn output_arguments(output: u32) -> Option<&'static [ArgumentDescriptor]> {
+    match output {
+        outputs::SERIAL | outputs::TENSOR => Some(&[]),
+        _ => None,
+    }
+}
+
+/// Which of the inputs provided to the runtime should be used.
+pub const SOURCE: ArgumentDescriptor = ArgumentDescriptor {
+    name: "source",
+    description: "The index of the input to read from when the runtime has \
+                  been given more than one",
+    ty: ArgumentType::Integer,
+    required: false,
+    default: Some("0"),
+    allowed_values: &[],
+};
+
+pub mod random {
+    use super::*;
+
+    pub const AMOUNT: ArgumentDescriptor = ArgumentDescriptor {
+        name: "amount",
+        description: "How many random numbers to generate",
+        ty: ArgumentType::Integer,
+        required: false,
+        default: Some("1"),
+        allowed_values: &[],
+    };
+}
+
+pub mod sound {
+    use super::*;
+
+    pub const HZ: ArgumentDescriptor = ArgumentDescriptor {
+        name: "hz",
+        description: "The sample rate, in Hertz",
+        ty: ArgumentType::Integer,
+        required: true,
+        default: None,
+        allowed_values: &[],
+    };
+
+    pub const SAMPLE_DURATION_MS: ArgumentDescriptor = ArgumentDescriptor {
+        name: "sample_duration_ms",
+        description: "How much audio to provide, in milliseconds",
+        ty: ArgumentType::Integer,
+        required: true,
+        default: None,
+        allowed_values: &[],
+    };
+}
+
+pub mod accelerometer {
+    use super::*;
+
+    pub const SAMPLES: ArgumentDescriptor = ArgumentDescriptor {
+        name: "samples",
+        description: "How many samples to read, defaulting to all of them",
+        ty: ArgumentType::Integer,
+        required: false,
+        default: None,
+        allowed_values: &[],
+    };
+}
+
+pub mod image {
+    use super::*;
+
+    pub const WIDTH: ArgumentDescriptor = ArgumentDescriptor {
+        name: "width",
+        description: "The width the image will be resized to, in pixels",
+        ty: ArgumentType::Integer,
+        required: true,
+        default: None,
+        allowed_values: &[],
+    };
+
+    pub const HEIGHT: ArgumentDescriptor = ArgumentDescriptor {
+        name: "height",
+        description: "The height the image will be resized to, in pixels",
+        ty: ArgumentType::Integer,
+        required: true,
+        default: None,
+        allowed_values: &[],
+    };
+
+    pub const PIXEL_FORMAT: ArgumentDescriptor = ArgumentDescriptor {
+        name: "pixel_format",
+        description: "How each pixel should be represented",
+        ty: ArgumentType::String,
+        required: false,
+        default: Some("@PixelFormat::RGB"),
+        allowed_values: &[
+            "@PixelFormat::RGB",
+            "@PixelFormat::BGR",
+            "@PixelFormat::GrayScale",
+            "0",
+            "1",
+            "2",
+        ],
+    };
+}
+
+pub mod raw {
+    use super::*;
+
+    pub const LENGTH: ArgumentDescriptor = ArgumentDescriptor {
+        name: "length",
+        description: "How many bytes to read, defaulting to all of them",
+        ty: ArgumentType::Integer,
+        required: false,
+        default: None,
+        allowed_values: &[],
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_default_is_valid() {
+        let all = capabilities::all()
+            .iter()
+            .filter_map(|&(_, id)| capability_arguments(id))
+            .flatten();
+
+        for arg in all {
+            if arg.required {
+                assert_eq!(arg.default, None, "{} is required", arg.name);
+            }
+
+            if let Some(default) = arg.default {
+                assert_eq!(arg.validate(default), Ok(()), "{}", arg.name);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_integers() {
+        assert_eq!(image::WIDTH.validate("128"), Ok(()));
+        assert_eq!(image::WIDTH.validate("@WIDTH * 2"), Ok(()));
+        assert_eq!(
+            image::WIDTH.validate("-1"),
+            Err(InvalidArgument::NotAnInteger)
+        );
+        assert_eq!(
+            image::WIDTH.validate("wide"),
+            Err(InvalidArgument::NotAnInteger)
+        );
+    }
+
+    #[test]
+    fn validate_allowed_values() {
+        assert_eq!(image::PIXEL_FORMAT.validate("@PixelFormat::BGR"), Ok(()));
+
+        let err = image::PIXEL_FORMAT.validate("RGBA").unwrap_err();
+
+        assert_eq!(
+            format!("{}", err),
+            "expected one of \"@PixelFormat::RGB\", \"@PixelFormat::BGR\", \
+             \"@PixelFormat::GrayScale\", \"0\", \"1\", \"2\""
+        );
+    }
+}
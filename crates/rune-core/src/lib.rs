@@ -16,6 +16,7 @@ extern crate std;
 
 extern crate alloc;
 
+pub mod arguments;
 mod element_type;
 mod logging;
 mod pixel_format;
@@ -45,6 +46,10 @@ pub const ONNX_MIMETYPE: &str = "application/onnx-model";
 /// The mimetype used for a TensorFlow JS model.
 pub const TFJS_MIMETYPE: &str = "application/tfjs-model";
 
+/// The prefix added to the name of the function a Rune exports for each of
+/// its named pipelines (e.g. `_call_pipeline_fast`).
+pub const PIPELINE_EXPORT_PREFIX: &str = "_call_pipeline_";
+
 /// The version number for this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
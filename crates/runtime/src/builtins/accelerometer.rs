@@ -9,6 +9,7 @@ use std::{
 
 use anyhow::Error;
 use csv::{Position, StringRecord};
+use hotg_rune_core::arguments::accelerometer::SAMPLES;
 
 use crate::{builtins::Arguments, Tensor};
 
@@ -18,7 +19,7 @@ pub fn accelerometer(
     samples: &AccelerometerSamples,
 ) -> Result<Tensor, Error> {
     let requested_samples: usize =
-        args.parse_or_default("samples", samples.len())?;
+        args.parse_or_default(SAMPLES.name, samples.len())?;
 
     if requested_samples > samples.len() {
         anyhow::bail!(
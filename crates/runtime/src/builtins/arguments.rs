@@ -1,6 +1,7 @@
 use std::{collections::HashMap, str::FromStr};
 
 use anyhow::{Context, Error};
+use hotg_rune_core::arguments::ArgumentDescriptor;
 
 /// Helper methods for reading arguments.
 #[derive(Debug, Clone, PartialEq)]
@@ -45,4 +46,25 @@ impl Arguments {
             None => Ok(default),
         }
     }
+
+    /// Read an argument, falling back to its [`ArgumentDescriptor::default`]
+    /// when it isn't set.
+    pub fn parse_argument<T>(
+        &self,
+        descriptor: &ArgumentDescriptor,
+    ) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        match (self.0.contains_key(descriptor.name), descriptor.default) {
+            (false, Some(default)) => default.parse::<T>().with_context(|| {
+                format!(
+                    "Unable to parse {:?} as the default \"{}\" argument",
+                    default, descriptor.name
+                )
+            }),
+            _ => self.parse(descriptor.name),
+        }
+    }
 }
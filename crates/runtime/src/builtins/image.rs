@@ -1,6 +1,7 @@
 use std::{num::NonZeroUsize, str::FromStr};
 
 use anyhow::Error;
+use hotg_rune_core::arguments::image::{HEIGHT, PIXEL_FORMAT, WIDTH};
 use image::{imageops::FilterType, DynamicImage};
 
 use crate::{builtins::Arguments, ElementType, Tensor};
@@ -8,10 +9,9 @@ use crate::{builtins::Arguments, ElementType, Tensor};
 /// Load an input tensor from an image, applying any transformations requested
 /// by the Rune.
 pub fn image(args: &Arguments, img: &DynamicImage) -> Result<Tensor, Error> {
-    let width: u32 = args.parse("width")?;
-    let height: u32 = args.parse("height")?;
-    let pixel_format: PixelFormat =
-        args.parse_or_default("pixel_format", PixelFormat::RGB8)?;
+    let width: u32 = args.parse_argument(&WIDTH)?;
+    let height: u32 = args.parse_argument(&HEIGHT)?;
+    let pixel_format: PixelFormat = args.parse_argument(&PIXEL_FORMAT)?;
 
     Ok(transform(img, width, height, pixel_format))
 }
@@ -86,3 +86,15 @@ impl FromStr for PixelFormat {
 #[derive(Debug, Copy, Clone, PartialEq, thiserror::Error)]
 #[error("Unknown pixel format")]
 pub struct UnknownPixelFormat;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_allowed_pixel_format_is_understood() {
+        for value in PIXEL_FORMAT.allowed_values {
+            assert!(value.parse::<PixelFormat>().is_ok(), "{}", value);
+        }
+    }
+}
@@ -8,6 +8,7 @@ mod raw;
 mod sound;
 
 use anyhow::Error;
+use hotg_rune_core::arguments::SOURCE;
 
 pub use self::{
     accelerometer::{
@@ -26,7 +27,7 @@ pub fn source<'src, T>(
     sources: &'src [T],
     args: &Arguments,
 ) -> Result<&'src T, Error> {
-    let index: usize = args.parse_or_default("source", 0)?;
+    let index: usize = args.parse_argument(&SOURCE)?;
 
     match sources.get(index) {
         Some(source) => Ok(source),
@@ -1,17 +1,18 @@
 use anyhow::Error;
+use hotg_rune_core::arguments::random::AMOUNT;
 use rand::{Rng, SeedableRng};
 
 use crate::{builtins::Arguments, Tensor};
 
 pub fn random(args: &Arguments) -> Result<Tensor, Error> {
-    let count: usize = args.parse_or_default("amount", 1)?;
+    let count: usize = args.parse_argument(&AMOUNT)?;
 
     let rng = rand::thread_rng();
     random_tensor(count, rng)
 }
 
 pub fn seeded_random(args: &Arguments, seed: u64) -> Result<Tensor, Error> {
-    let count: usize = args.parse_or_default("amount", 1)?;
+    let count: usize = args.parse_argument(&AMOUNT)?;
 
     let rng = rand::rngs::SmallRng::seed_from_u64(seed);
     random_tensor(count, rng)
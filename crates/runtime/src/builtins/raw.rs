@@ -1,9 +1,10 @@
 use anyhow::Error;
+use hotg_rune_core::arguments::raw::LENGTH;
 
 use crate::{builtins::Arguments, Tensor};
 
 pub fn raw(args: &Arguments, bytes: &[u8]) -> Result<Tensor, Error> {
-    let length: usize = args.parse_or_default("length", bytes.len())?;
+    let length: usize = args.parse_or_default(LENGTH.name, bytes.len())?;
 
     if bytes.len() < length {
         anyhow::bail!(
@@ -8,6 +8,7 @@ use std::{
 };
 
 use anyhow::{Context, Error};
+use hotg_rune_core::arguments::sound::{HZ, SAMPLE_DURATION_MS};
 use hound::{WavReader, WavSpec};
 
 use crate::{builtins::Arguments, Tensor};
@@ -15,8 +16,8 @@ use crate::{builtins::Arguments, Tensor};
 /// Load an input from a sound clip, applying any transformations requested by
 /// the Rune.
 pub fn sound(args: &Arguments, clip: &AudioClip) -> Result<Tensor, Error> {
-    let sample_rate: u32 = args.parse("hz")?;
-    let sample_duration_ms = args.parse("sample_duration_ms")?;
+    let sample_rate: u32 = args.parse_argument(&HZ)?;
+    let sample_duration_ms = args.parse_argument(&SAMPLE_DURATION_MS)?;
     let duration = Duration::from_millis(sample_duration_ms);
 
     let AudioClip { spec, samples } = clip;
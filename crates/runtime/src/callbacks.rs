@@ -36,6 +36,10 @@ pub(crate) trait Callbacks: Send + Sync + 'static {
     /// Get the value of a global resource.
     fn get_resource(&self, name: &str) -> Option<&[u8]>;
 
+    /// Inspect the tensors produced by a node in the pipeline, returning
+    /// `true` if the pipeline should stop after this node.
+    fn debug_tensor(&self, node: &str, data: &[u8]) -> Result<bool, Error>;
+
     fn log(&self, _record: &Record<'_>);
 }
 
@@ -222,6 +222,19 @@ impl HostFunctions {
         Ok(())
     }
 
+    pub fn rune_debug_tensor(
+        &mut self,
+        node: &str,
+        data: &[u8],
+    ) -> Result<u32, Error> {
+        let should_stop =
+            self.callbacks.debug_tensor(node, data).with_context(|| {
+                format!("Unable to inspect the output of \"{}\"", node)
+            })?;
+
+        Ok(should_stop as u32)
+    }
+
     pub fn rune_resource_open(&mut self, name: &str) -> Result<u32, Error> {
         let resource = self
             .callbacks
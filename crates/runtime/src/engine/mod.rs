@@ -27,6 +27,9 @@ pub(crate) trait WebAssemblyEngine {
 
     /// Call the `_call()` function to run the Rune.
     fn predict(&mut self) -> Result<(), Error>;
+
+    /// Call the function exported for one of the Rune's named pipelines.
+    fn predict_pipeline(&mut self, name: &str) -> Result<(), Error>;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -99,7 +99,8 @@ impl WebAssemblyEngine for Wasm3Engine {
             .link("consume_output", consume_output)?
             .link("rune_resource_open", rune_resource_open)?
             .link("rune_resource_read", rune_resource_read)?
-            .link("rune_resource_close", rune_resource_close)?;
+            .link("rune_resource_close", rune_resource_close)?
+            .link("rune_debug_tensor", rune_debug_tensor)?;
 
         Ok(Wasm3Engine {
             runtime,
@@ -132,6 +133,14 @@ impl WebAssemblyEngine for Wasm3Engine {
 
         Ok(())
     }
+
+    fn predict_pipeline(&mut self, name: &str) -> Result<(), Error> {
+        let export =
+            format!("{}{}", hotg_rune_core::PIPELINE_EXPORT_PREFIX, name);
+        let _: i32 = self.call(&export, (), |f, _| f.call())?;
+
+        Ok(())
+    }
 }
 
 struct Linker<'rt> {
@@ -425,6 +434,16 @@ fn rune_resource_close(
     Ok(0)
 }
 
+fn rune_debug_tensor(
+    cc: CallContext<'_>,
+    host: &mut HostFunctions,
+    (name, name_len, data, data_len): (u32, u32, u32, u32),
+) -> Result<u32, Error> {
+    let name = cc.read_string(name, name_len)?;
+    let data = unsafe { cc.array(data, data_len)? };
+    host.rune_debug_tensor(name, data)
+}
+
 trait Wasm3ResultExt<T> {
     fn to_anyhow(self) -> Result<T, Error>;
 }
@@ -581,6 +600,14 @@ mod tests {
 
         fn get_resource(&self, _name: &str) -> Option<&[u8]> { Some(&[]) }
 
+        fn debug_tensor(
+            &self,
+            _node: &str,
+            _data: &[u8],
+        ) -> Result<bool, Error> {
+            Ok(false)
+        }
+
         fn log(&self, _record: &Record<'_>) {}
 
         fn loaded(&self, _rune: &RuneGraph<'_>) -> Result<(), Error> {
@@ -55,6 +55,7 @@ impl WebAssemblyEngine for WasmerEngine {
                 "rune_resource_open" => Function::new_native_with_env(&store, env.clone(), rune_resource_open),
                 "rune_resource_read" => Function::new_native_with_env(&store, env.clone(), rune_resource_read),
                 "rune_resource_close" => Function::new_native_with_env(&store, env.clone(), rune_resource_close),
+                "rune_debug_tensor" => Function::new_native_with_env(&store, env.clone(), rune_debug_tensor),
             }
         };
 
@@ -92,6 +93,22 @@ impl WebAssemblyEngine for WasmerEngine {
 
         Ok(())
     }
+
+    fn predict_pipeline(&mut self, name: &str) -> Result<(), Error> {
+        let export =
+            format!("{}{}", hotg_rune_core::PIPELINE_EXPORT_PREFIX, name);
+        let call: NativeFunc<(), i32> = self
+            .instance
+            .exports
+            .get_native_function(&export)
+            .with_context(|| {
+                format!("Unable to get the \"{}\" function", export)
+            })?;
+
+        call.call().map_err(unwrap_anyhow_error)?;
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -214,6 +231,40 @@ fn rune_resource_read(
     Ok(bytes_written)
 }
 
+fn rune_debug_tensor(
+    env: &Env,
+    name: WasmPtr<u8, Array>,
+    name_len: u32,
+    data: WasmPtr<u8, Array>,
+    data_len: u32,
+) -> Result<u32, RuntimeError> {
+    let memory = env
+        .memory
+        .get_ref()
+        .context("The memory isn't initialized")
+        .map_err(runtime_error)?;
+
+    // Safety: this function isn't reentrant, so we don't need to worry about
+    // concurrent mutations.
+    let name = unsafe {
+        name.get_utf8_str(memory, name_len)
+            .context("Invalid name pointer")
+            .map_err(runtime_error)?
+    };
+
+    let data = data
+        .deref(memory, 0, data_len)
+        .context("Invalid data pointer")
+        .map_err(runtime_error)?;
+    let data: Vec<u8> = data.into_iter().map(|c| c.get()).collect();
+
+    env.host_functions
+        .lock()
+        .unwrap()
+        .rune_debug_tensor(name, &data)
+        .map_err(runtime_error)
+}
+
 fn rune_resource_close(env: &Env, id: u32) -> Result<(), RuntimeError> {
     env.host_functions
         .lock()
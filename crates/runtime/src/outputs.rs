@@ -1,8 +1,11 @@
+use std::{convert::TryInto, num::NonZeroUsize};
+
 use anyhow::{Context, Error};
+use hotg_rune_core::Shape;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{Map, Value};
 
-use crate::{NodeMetadata, Tensor, TensorElement};
+use crate::{ElementType, NodeMetadata, Tensor, TensorElement};
 
 #[derive(Debug)]
 pub enum OutputTensor {
@@ -143,6 +146,196 @@ pub(crate) fn parse_outputs(
 ) -> Result<Vec<OutputTensor>, Error> {
     match meta.kind.as_str() {
         "SERIAL" => crate::outputs::parse_serial(data),
+        "TENSOR" => crate::outputs::parse_tensors(data),
         _ => anyhow::bail!("Unknown output type"),
     }
 }
+
+/// Parse a buffer of tensors in the format used by the `TENSOR` output.
+///
+/// Each tensor is encoded as a little-endian `u32` length, that many bytes
+/// containing the tensor's shape (e.g. `"f32[1, 3]"`), then the tensor's
+/// elements. String elements are each written as a little-endian `u32`
+/// length followed by that many bytes of UTF-8.
+pub(crate) fn parse_tensors(
+    mut data: &[u8],
+) -> Result<Vec<OutputTensor>, Error> {
+    let mut tensors = Vec::new();
+
+    while !data.is_empty() {
+        let (shape, rest) = split_shape(data)?;
+
+        let (tensor, rest) =
+            if shape.element_type() == hotg_rune_core::ElementType::String {
+                split_strings(&shape, rest)?
+            } else {
+                split_numeric(&shape, rest)?
+            };
+
+        tensors.push(tensor);
+        data = rest;
+    }
+
+    Ok(tensors)
+}
+
+fn split_numeric<'a>(
+    shape: &Shape<'_>,
+    data: &'a [u8],
+) -> Result<(OutputTensor, &'a [u8]), Error> {
+    let element_type = element_type(shape.element_type())
+        .with_context(|| format!("Unable to decode a {} tensor", shape))?;
+    let dimensions = shape
+        .dimensions()
+        .iter()
+        .map(|&d| NonZeroUsize::new(d))
+        .collect::<Option<Vec<_>>>()
+        .with_context(|| {
+            format!("The {} tensor has a zero dimension", shape)
+        })?;
+
+    let num_elements: usize = shape.dimensions().iter().product();
+    let byte_length = num_elements * element_type.byte_size();
+    anyhow::ensure!(
+        data.len() >= byte_length,
+        "A {} tensor needs {} bytes, but only {} bytes are left",
+        shape,
+        byte_length,
+        data.len()
+    );
+    let (buffer, rest) = data.split_at(byte_length);
+
+    let tensor = Tensor::new_raw(element_type, dimensions, buffer.to_vec());
+
+    Ok((tensor.into(), rest))
+}
+
+fn split_strings<'a>(
+    shape: &Shape<'_>,
+    mut data: &'a [u8],
+) -> Result<(OutputTensor, &'a [u8]), Error> {
+    let num_elements: usize = shape.dimensions().iter().product();
+    let mut strings = Vec::with_capacity(num_elements);
+
+    for i in 0..num_elements {
+        let (string, rest) = split_string(data).with_context(|| {
+            format!("Unable to read element {} of a {} tensor", i, shape)
+        })?;
+        strings.push(string);
+        data = rest;
+    }
+
+    let tensor = OutputTensor::StringTensor {
+        dimensions: shape.dimensions().to_vec(),
+        strings,
+    };
+
+    Ok((tensor, data))
+}
+
+fn split_string(data: &[u8]) -> Result<(String, &[u8]), Error> {
+    anyhow::ensure!(data.len() >= 4, "Unable to read the string's length");
+    let (length, rest) = data.split_at(4);
+    let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+
+    anyhow::ensure!(rest.len() >= length, "The string was truncated");
+    let (string, rest) = rest.split_at(length);
+    let string = std::str::from_utf8(string)
+        .context("The string isn't valid UTF-8")?
+        .to_string();
+
+    Ok((string, rest))
+}
+
+fn split_shape(data: &[u8]) -> Result<(Shape<'static>, &[u8]), Error> {
+    anyhow::ensure!(data.len() >= 4, "Unable to read the shape's length");
+    let (length, rest) = data.split_at(4);
+    let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+
+    anyhow::ensure!(rest.len() >= length, "The shape was truncated");
+    let (shape, rest) = rest.split_at(length);
+    let shape = std::str::from_utf8(shape)
+        .context("The shape isn't valid UTF-8")?
+        .parse()
+        .context("Unable to parse the shape")?;
+
+    Ok((shape, rest))
+}
+
+fn element_type(
+    element_type: hotg_rune_core::ElementType,
+) -> Result<ElementType, Error> {
+    use hotg_rune_core::ElementType as E;
+
+    match element_type {
+        E::U8 => Ok(ElementType::U8),
+        E::I8 => Ok(ElementType::I8),
+        E::U16 => Ok(ElementType::U16),
+        E::I16 => Ok(ElementType::I16),
+        E::U32 => Ok(ElementType::U32),
+        E::I32 => Ok(ElementType::I32),
+        E::F32 => Ok(ElementType::F32),
+        E::U64 => Ok(ElementType::U64),
+        E::I64 => Ok(ElementType::I64),
+        E::F64 => Ok(ElementType::F64),
+        E::String => {
+            Err(Error::msg("String tensors can't be sent as raw bytes"))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(shape: &str, elements: &[u8]) -> Vec<u8> {
+        let mut buffer = (shape.len() as u32).to_le_bytes().to_vec();
+        buffer.extend(shape.as_bytes());
+        buffer.extend(elements);
+        buffer
+    }
+
+    fn encode_strings(shape: &str, strings: &[&str]) -> Vec<u8> {
+        let mut elements = Vec::new();
+        for s in strings {
+            elements.extend(&(s.len() as u32).to_le_bytes());
+            elements.extend(s.as_bytes());
+        }
+
+        encode(shape, &elements)
+    }
+
+    #[test]
+    fn parse_numeric_and_string_tensors() {
+        let mut data = encode("u8[2]", &[1, 2]);
+        data.extend(encode_strings("utf8[1, 2]", &["up", "down"]));
+
+        let tensors = parse_tensors(&data).unwrap();
+
+        assert_eq!(tensors.len(), 2);
+        match &tensors[0] {
+            OutputTensor::Tensor(t) => {
+                assert_eq!(t.shape().to_string(), "u8[2]")
+            },
+            other => panic!("Expected a numeric tensor, found {:?}", other),
+        }
+        match &tensors[1] {
+            OutputTensor::StringTensor {
+                dimensions,
+                strings,
+            } => {
+                assert_eq!(dimensions, &[1, 2]);
+                assert_eq!(strings, &["up", "down"]);
+            },
+            other => panic!("Expected a string tensor, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_string_tensor() {
+        let mut data = encode_strings("utf8[2]", &["up", "down"]);
+        data.truncate(data.len() - 1);
+
+        assert!(parse_tensors(&data).is_err());
+    }
+}
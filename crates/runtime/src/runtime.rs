@@ -47,7 +47,7 @@ use wasmparser::{Parser, Payload};
 use crate::{
     callbacks::{Callbacks, Model, ModelMetadata, RuneGraph},
     engine::{LoadError, WebAssemblyEngine},
-    outputs::{parse_outputs, OutputTensor},
+    outputs::{parse_outputs, parse_tensors, OutputTensor},
     NodeMetadata, Tensor,
 };
 
@@ -55,6 +55,7 @@ use crate::{
 pub struct Runtime {
     state: Arc<State>,
     engine: Box<dyn WebAssemblyEngine>,
+    pipelines: Vec<String>,
 }
 
 impl Runtime {
@@ -84,13 +85,57 @@ impl Runtime {
         Ok(Runtime {
             state,
             engine: Box::new(engine),
+            pipelines: named_pipelines(rune),
         })
     }
 }
 
+/// Find the named pipelines a Rune exports a function for.
+fn named_pipelines(wasm: &[u8]) -> Vec<String> {
+    let mut pipelines = Vec::new();
+
+    for payload in Parser::default().parse_all(wasm) {
+        if let Ok(Payload::ExportSection(exports)) = payload {
+            for export in exports.into_iter().flatten() {
+                if let Some(name) = export
+                    .field
+                    .strip_prefix(hotg_rune_core::PIPELINE_EXPORT_PREFIX)
+                {
+                    pipelines.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    pipelines
+}
+
 impl Runtime {
     /// Run the Rune.
-    pub fn predict(&mut self) -> Result<(), Error> { self.engine.predict() }
+    pub fn predict(&mut self) -> Result<(), Error> {
+        unsafe { self.state.intermediate_tensors().clear() };
+        self.engine.predict()
+    }
+
+    /// Run one of the Rune's named pipelines, only executing the stages it
+    /// depends on.
+    pub fn predict_pipeline(&mut self, name: &str) -> Result<(), Error> {
+        if !self.pipelines.iter().any(|p| p == name) {
+            anyhow::bail!(
+                "The Rune doesn't have a \"{}\" pipeline (available \
+                 pipelines: {:?})",
+                name,
+                self.pipelines
+            );
+        }
+
+        unsafe { self.state.intermediate_tensors().clear() };
+        self.engine.predict_pipeline(name)
+    }
+
+    /// The names of the pipelines which can be run using
+    /// [`Runtime::predict_pipeline()`].
+    pub fn pipelines(&self) -> &[String] { &self.pipelines }
 
     /// Get all input tensors, keyed by capability ID.
     pub fn input_tensors(&mut self) -> &mut HashMap<u32, Tensor> {
@@ -102,6 +147,24 @@ impl Runtime {
         unsafe { self.state.output_tensors() }
     }
 
+    /// Get the tensors produced by each node in the pipeline during the last
+    /// call to [`Runtime::predict()`], in the order they were executed.
+    ///
+    /// This will be empty unless the Rune was compiled with intermediate
+    /// tensor reporting enabled (e.g. `rune build --debug-tensors`). Nodes
+    /// which were skipped because their condition didn't hold won't have any
+    /// tensors.
+    pub fn intermediate_tensors(&self) -> &[(String, Vec<OutputTensor>)] {
+        unsafe { self.state.intermediate_tensors() }
+    }
+
+    /// Ask the Rune to stop executing its pipeline once this node has run.
+    ///
+    /// This only works for Runes that report their intermediate tensors.
+    pub fn stop_after(&mut self, node: impl Into<Option<String>>) {
+        unsafe { *self.state.stop_after.get() = node.into() }
+    }
+
     /// Get a mapping from each capability's ID to its metadata.
     pub fn capabilities(&self) -> &HashMap<u32, NodeMetadata> {
         unsafe { self.state.capabilities() }
@@ -152,6 +215,8 @@ struct State {
     >,
     log: UnsafeCell<Box<dyn Fn(&Record<'_>) + Send + Sync>>,
     resources: UnsafeCell<HashMap<String, Vec<u8>>>,
+    intermediate_tensors: UnsafeCell<Vec<(String, Vec<OutputTensor>)>>,
+    stop_after: UnsafeCell<Option<String>>,
 }
 
 impl State {
@@ -199,6 +264,12 @@ impl State {
         &mut *self.resources.get()
     }
 
+    unsafe fn intermediate_tensors(
+        &self,
+    ) -> &mut Vec<(String, Vec<OutputTensor>)> {
+        &mut *self.intermediate_tensors.get()
+    }
+
     unsafe fn set_logger<L>(&self, log: L)
     where
         L: Fn(&Record<'_>),
@@ -228,6 +299,8 @@ impl Default for State {
             )),
             log: UnsafeCell::new(Box::new(|_| {})),
             resources: UnsafeCell::default(),
+            intermediate_tensors: UnsafeCell::default(),
+            stop_after: UnsafeCell::default(),
         }
     }
 }
@@ -317,6 +390,17 @@ impl Callbacks for State {
         resources.get(name).map(|s| s.as_slice())
     }
 
+    fn debug_tensor(&self, node: &str, data: &[u8]) -> Result<bool, Error> {
+        // Safety: see the safety comments on State
+        let intermediate_tensors = unsafe { self.intermediate_tensors() };
+        let stop_after = unsafe { &*self.stop_after.get() };
+
+        let tensors = parse_tensors(data)?;
+        intermediate_tensors.push((node.to_string(), tensors));
+
+        Ok(stop_after.as_deref() == Some(node))
+    }
+
     fn log(&self, record: &Record<'_>) {
         // Safety: see the safety comments on State
         let log = unsafe { &*self.log.get() };
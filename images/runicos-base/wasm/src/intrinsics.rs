@@ -168,4 +168,17 @@ extern "C" {
     ///
     /// Invalid parameters will be ignored.
     pub fn rune_resource_close(resource_id: u32);
+
+    /// Report the tensors produced by a node in the pipeline so they can be
+    /// inspected (e.g. by `rune debug`).
+    ///
+    /// The `data` buffer uses the same encoding as the `TENSOR` output. A
+    /// non-zero return value means the runtime would like the pipeline to
+    /// stop after this node.
+    pub fn rune_debug_tensor(
+        name: *const u8,
+        name_len: u32,
+        data: *const u8,
+        data_len: u32,
+    ) -> u32;
 }
@@ -1,4 +1,4 @@
-use alloc::{string::ToString, vec::Vec};
+use alloc::{borrow::Cow, string::ToString, vec::Vec};
 
 use hotg_rune_core::{outputs, AsElementType, Tensor};
 
@@ -35,13 +35,41 @@ impl Default for TensorOutput {
     fn default() -> Self { TensorOutput::new() }
 }
 
+/// Send the tensors produced by a pipeline node to the runtime for
+/// inspection, returning `true` if the pipeline should stop here.
+pub fn report_intermediate(node: &str, tensors: impl Writable) -> bool {
+    let mut buffer = Vec::new();
+    tensors.encode(&mut buffer);
+
+    unsafe {
+        crate::intrinsics::rune_debug_tensor(
+            node.as_ptr(),
+            node.len() as u32,
+            buffer.as_ptr(),
+            buffer.len() as u32,
+        ) != 0
+    }
+}
+
 pub trait Writable {
     fn encode(&self, buffer: &mut Vec<u8>);
 }
 
+impl<W> Writable for &W
+where
+    W: Writable + ?Sized,
+{
+    fn encode(&self, buffer: &mut Vec<u8>) { (**self).encode(buffer); }
+}
+
+/// Nothing, used when reporting a node which was skipped.
+impl Writable for () {
+    fn encode(&self, _buffer: &mut Vec<u8>) {}
+}
+
 impl<E> Writable for Tensor<E>
 where
-    E: AsElementType,
+    E: AsElementType + WritableElement,
 {
     fn encode(&self, buffer: &mut Vec<u8>) {
         let shape = self.shape().to_string();
@@ -49,9 +77,39 @@ where
         buffer.extend(&shape_len);
         buffer.extend(shape.as_bytes());
 
-        let (ptr, len) = self.as_ptr_and_byte_length();
-        let data = unsafe { core::slice::from_raw_parts(ptr, len) };
-        buffer.extend(data);
+        E::encode_elements(self.elements(), buffer);
+    }
+}
+
+/// An element type which can be written to a [`Writable`] buffer.
+pub trait WritableElement: Sized {
+    fn encode_elements(elements: &[Self], buffer: &mut Vec<u8>);
+}
+
+macro_rules! numeric_writable_element {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl WritableElement for $ty {
+                fn encode_elements(elements: &[Self], buffer: &mut Vec<u8>) {
+                    for element in elements {
+                        buffer.extend(&element.to_le_bytes());
+                    }
+                }
+            }
+        )*
+    };
+}
+
+numeric_writable_element!(u8, i8, u16, i16, u32, i32, f32, u64, i64, f64);
+
+/// Strings can't be sent as raw bytes, so each element is written as a
+/// little-endian `u32` length followed by that many bytes of UTF-8.
+impl WritableElement for Cow<'static, str> {
+    fn encode_elements(elements: &[Self], buffer: &mut Vec<u8>) {
+        for element in elements {
+            buffer.extend(&(element.len() as u32).to_le_bytes());
+            buffer.extend(element.as_bytes());
+        }
     }
 }
 